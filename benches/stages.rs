@@ -0,0 +1,115 @@
+//! Benchmarks of the analysis stages, on synthetic inputs:
+//!
+//!   cargo bench                  every benchmark
+//!   cargo bench -- parse         those whose name contains "parse"
+//!
+//! Each benchmark reports the median time per iteration over its samples,
+//! with the fastest and slowest.  The harness is a small one of our own, so
+//! the benchmarks build with the crate's dependencies alone.
+
+#![allow(clippy::needless_return)]
+
+mod synthetic;
+
+use std::hint::black_box;
+use std::sync::Arc;
+use std::time::{Duration, Instant};
+
+use clap::Parser;
+use git_author_stats::analysis::{blame_snapshots, SnapshotSpec};
+use git_author_stats::backend::{count_blame, Backend, CliBackend};
+use git_author_stats::cache::BlameCache;
+use git_author_stats::cli_args::Args;
+use git_author_stats::filter::FileFilter;
+use git_author_stats::identity::IdentityResolver;
+use git_author_stats::report::{render_json, Report, Table};
+use git_author_stats::teams::{AuthorOrder, Teams};
+use git_author_stats::Repo;
+
+use synthetic::SyntheticRepo;
+
+/// Time to spend sampling each benchmark
+const BUDGET: Duration = Duration::from_secs(2);
+const MIN_SAMPLES: usize = 5;
+const MAX_SAMPLES: usize = 50;
+
+struct Bencher {
+    filter: Option<String>,
+}
+
+impl Bencher {
+    fn wanted(&self, name: &str) -> bool {
+        return self.filter.as_ref().is_none_or(|filter| name.contains(filter.as_str()));
+    }
+
+    fn bench<T>(&self, name: &str, mut f: impl FnMut() -> T) {
+        if !self.wanted(name) {
+            return;
+        }
+        // One run to warm up, and to size the samples
+        let start = Instant::now();
+        black_box(f());
+        let once = start.elapsed().max(Duration::from_nanos(1));
+        let per_sample = BUDGET / MAX_SAMPLES as u32;
+        let iterations = (per_sample.as_nanos() / once.as_nanos()).clamp(1, 1_000_000) as u32;
+        let samples = ((BUDGET.as_nanos() / (once.as_nanos() * iterations as u128)) as usize).clamp(MIN_SAMPLES, MAX_SAMPLES);
+
+        let mut times: Vec<Duration> = (0..samples).map(|_| {
+            let start = Instant::now();
+            for _ in 0..iterations {
+                black_box(f());
+            }
+            start.elapsed() / iterations
+        }).collect();
+        times.sort();
+        println!("{name:<44} {:>12?}  [{:?} .. {:?}]  {samples} x {iterations}", times[samples / 2], times[0], times[samples - 1]);
+    }
+}
+
+fn main() {
+    // cargo bench passes --bench, and whatever follows `--`
+    let filter = std::env::args().skip(1).find(|arg| !arg.starts_with('-'));
+    let b = Bencher { filter };
+
+    // Blame output to counts per author
+    for (lines, authors) in [(1_000, 5), (100_000, 50)] {
+        let text = synthetic::porcelain(lines, 500, authors);
+        b.bench(&format!("blame-parse/{lines} lines, {authors} authors"), || count_blame(&text, "src/file.rs", &[]).unwrap());
+    }
+
+    // Raw counts to the table of resolved authors
+    let opt = Args::parse_from(["git-author-stats"]);
+    let resolver = IdentityResolver::default();
+    let teams = Teams::default();
+    let order = AuthorOrder::default();
+    for (dates, authors) in [(12, 10), (120, 500)] {
+        let perf = synthetic::performance(dates, authors);
+        b.bench(&format!("reduce/{dates} dates, {authors} authors"), || Table::build(&opt, &perf, None, &resolver, &teams, &order, None));
+    }
+
+    // The table to output
+    for (dates, authors) in [(12, 10), (120, 500)] {
+        let table = Table::build(&opt, &synthetic::performance(dates, authors), None, &resolver, &teams, &order, None);
+        let report = Report { table, groups: Vec::new(), categories: Vec::new(), fingerprint: Vec::new(), coupling: Vec::new() };
+        b.bench(&format!("format/json, {dates} dates, {authors} authors"), || render_json(&opt, &report));
+        b.bench(&format!("format/cells, {dates} dates, {authors} authors"), || {
+            report.table.rows.iter().map(|row| (0..row.counts.len()).map(|i| report.table.cell(&opt, &row.counts, i, ",").len()).sum::<usize>()).sum::<usize>()
+        });
+    }
+
+    // Everything but the output, blaming a repo of N files x M commits x K authors
+    for (files, commits, authors) in [(50, 200, 5), (100, 500, 10)] {
+        let name = format!("snapshots/{files} files, {commits} commits, {authors} authors");
+        if !b.wanted(&name) {
+            continue;
+        }
+        let synthetic = SyntheticRepo::new(files, commits, authors);
+        let backend: Arc<dyn Backend> = Arc::new(CliBackend::new(Arc::new(Repo::discover(synthetic.path()).unwrap())));
+        let spec = SnapshotSpec::new(synthetic.dates.clone());
+        b.bench(&name, || {
+            let stats = blame_snapshots(&backend, &spec, &FileFilter::default(), &mut BlameCache::in_memory()).unwrap();
+            assert!(synthetic::total(&stats.authors) > 0);
+            stats
+        });
+    }
+}
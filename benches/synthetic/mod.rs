@@ -0,0 +1,121 @@
+use std::fmt::Write as _;
+use std::fs;
+use std::io::Write as _;
+use std::path::PathBuf;
+use std::process::{Command, Stdio};
+
+use chrono::{Days, NaiveDate};
+use git_author_stats::identity::Author;
+use git_author_stats::{AuthorCount, AuthorPerformance};
+
+//----
+// Synthetic inputs
+//
+// Repos and tool outputs of a given size, the same on every run: N files
+// changed by M commits of K authors.  Each commit appends lines to a few files
+// and rewrites one line of each, so blame has history to walk.
+
+/// Name and email of author `k`
+fn author(k: usize) -> (String, String) {
+    return (format!("Author {k}"), format!("author{k}@example.com"));
+}
+
+/// Seconds since the epoch of commit `c`: one a day from 2020-01-01
+fn commit_time(c: usize) -> i64 {
+    return 1_577_836_800 + c as i64 * 86_400;
+}
+
+/// `git blame --line-porcelain` output of a file with `lines` lines, last
+/// changed by `commits` commits of `authors` authors
+pub fn porcelain(lines: usize, commits: usize, authors: usize) -> String {
+    let mut out = String::new();
+    for i in 0..lines {
+        let c = i * 7 % commits;
+        let (name, email) = author(c % authors);
+        let time = commit_time(c);
+        writeln!(out, "{c:040x} {} {}", i + 1, i + 1).unwrap();
+        writeln!(out, "author {name}\nauthor-mail <{email}>\nauthor-time {time}\nauthor-tz +0000").unwrap();
+        writeln!(out, "committer {name}\ncommitter-mail <{email}>\ncommitter-time {time}\ncommitter-tz +0000").unwrap();
+        writeln!(out, "summary {}\nfilename src/file.rs", ["Fix overflow", "Add parser", "Refactor tables"][c % 3]).unwrap();
+        writeln!(out, "\tlet line_{i} = {c};").unwrap();
+    }
+    return out;
+}
+
+/// Lines per author per date, for `dates` monthly dates and `authors` authors
+pub fn performance(dates: usize, authors: usize) -> AuthorPerformance {
+    let mut perf = AuthorPerformance::new();
+    for d in 0..dates {
+        let date = format!("{}-{:02}-01", 2000 + d / 12, d % 12 + 1);
+        let mut acnt = AuthorCount::new();
+        for k in 0..authors {
+            let (name, email) = author(k);
+            acnt.insert(Author::new(&name, &email), ((k * 37 + d * 11) % 5000) as i32);
+        }
+        perf.insert(date, acnt);
+    }
+    return perf;
+}
+
+/// A git repo in the temp dir, removed when dropped
+pub struct SyntheticRepo {
+    pub dir: PathBuf,
+    /// The date after each tenth of the history, YYYY-MM-DD
+    pub dates: Vec<String>,
+}
+
+impl SyntheticRepo {
+    /// `files` files changed by `commits` commits of `authors` authors, written
+    /// with git fast-import
+    pub fn new(files: usize, commits: usize, authors: usize) -> SyntheticRepo {
+        let dir = std::env::temp_dir().join(format!("git-author-stats-bench-{}-{files}x{commits}x{authors}", std::process::id()));
+        let _ = fs::remove_dir_all(&dir);
+        fs::create_dir_all(&dir).unwrap();
+        let git = |args: &[&str]| Command::new("git").arg("-C").arg(&dir).args(args).stdin(Stdio::piped()).stdout(Stdio::null()).spawn().unwrap();
+        assert!(git(&["init", "-q", "-b", "main"]).wait().unwrap().success());
+
+        let mut contents: Vec<Vec<String>> = vec![Vec::new(); files];
+        let mut stream = String::new();
+        for c in 0..commits {
+            let (name, email) = author(c % authors);
+            let time = commit_time(c);
+            writeln!(stream, "commit refs/heads/main\nauthor {name} <{email}> {time} +0000\ncommitter {name} <{email}> {time} +0000").unwrap();
+            let message = format!("Change {c}");
+            writeln!(stream, "data {}\n{message}", message.len()).unwrap();
+            for j in 0..3.min(files) {
+                let f = (c * 7 + j) % files;
+                let lines = &mut contents[f];
+                if let Some(line) = lines.get_mut(c % 10) {
+                    *line = format!("rewritten by {c}");
+                }
+                lines.extend((0..5).map(|i| format!("line {i} of commit {c}")));
+                let data = lines.iter().map(|line| format!("{line}\n")).collect::<String>();
+                writeln!(stream, "M 644 inline src/{}/file{f}.rs\ndata {}\n{data}", f % 10, data.len()).unwrap();
+            }
+        }
+
+        let mut import = git(&["fast-import", "--quiet"]);
+        import.stdin.take().unwrap().write_all(stream.as_bytes()).unwrap();
+        assert!(import.wait().unwrap().success());
+        let first = NaiveDate::from_ymd_opt(2020, 1, 1).unwrap();
+        let dates = (1..=10)
+            .map(|tenth| (first + Days::new((commits * tenth / 10) as u64)).format("%Y-%m-%d").to_string())
+            .collect();
+        return SyntheticRepo { dir, dates };
+    }
+
+    pub fn path(&self) -> &str {
+        return self.dir.to_str().unwrap();
+    }
+}
+
+impl Drop for SyntheticRepo {
+    fn drop(&mut self) {
+        let _ = fs::remove_dir_all(&self.dir);
+    }
+}
+
+/// Lines of every author at every date, for checking a run did something
+pub fn total(stats: &AuthorPerformance) -> i64 {
+    return stats.values().flat_map(|acnt| acnt.values()).map(|&count| count as i64).sum();
+}
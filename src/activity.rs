@@ -0,0 +1,157 @@
+use chrono::{Local, NaiveDate, TimeZone};
+use hashbrown::HashMap;
+
+use crate::backend::{Backend, CommitInfo, FileChange};
+use crate::breakdown::{self, Breakdown};
+use crate::analysis::SnapshotSpec;
+use crate::cli_args::Metric;
+use crate::error::{Error, Result};
+use crate::filter::FileFilter;
+use crate::{AuthorCount, AuthorPerformance, Count};
+
+//----
+// Activity metrics
+//
+// Where blame measures the lines that survive at each date, these count what
+// each author did in the period leading up to it: the column for a date
+// covers commits authored after the previous sampled date and before this
+// one (the first column covers all earlier history).
+//
+// The experimental effort metric estimates hours of work: each author's
+// commits are clustered into sessions, a pause longer than the session gap
+// starting a new one.  A commit stands for the time since the previous one of
+// its session, or SESSION_LEAD for the work before a session's first commit.
+
+/// Work assumed before the first commit of a session, in seconds
+pub const SESSION_LEAD: i64 = 30 * 60;
+
+/// Local midnight at the start of a YYYY-MM-DD date, as seconds since the epoch
+pub fn date_timestamp(date: &str) -> Option<i64> {
+    let day = NaiveDate::parse_from_str(date, "%Y-%m-%d").ok()?;
+    let midnight = day.and_hms_opt(0, 0, 0)?;
+    return Local.from_local_datetime(&midnight).earliest().map(|t| t.timestamp());
+}
+
+/// How often each file changed, for --weight hotspot
+#[derive(Debug, Clone, Default)]
+pub struct ChangeFrequency {
+    /// Author dates of the commits changing each path, oldest first
+    times: HashMap<String, Vec<i64>>,
+}
+
+impl ChangeFrequency {
+    pub fn from_log(commits: &[CommitInfo]) -> ChangeFrequency {
+        let mut frequency = ChangeFrequency::default();
+        for commit in commits {
+            for f in &commit.files {
+                frequency.times.entry_ref(&f.path).or_default().push(commit.time);
+            }
+        }
+        frequency.times.values_mut().for_each(|times| times.sort());
+        return frequency;
+    }
+
+    /// Number of commits that changed `path` before `time`
+    pub fn before(&self, path: &str, time: i64) -> Count {
+        match self.times.get(path) {
+            Some(times) => return times.partition_point(|&t| t < time) as Count,
+            None => return 0,
+        }
+    }
+}
+
+/// Seconds of work each commit stands for, given the `gap` that ends a session.
+/// Commits only touching filtered out files don't count, nor take part in
+/// sessions.
+fn session_effort(commits: &[CommitInfo], filter: &FileFilter, gap: i64) -> Vec<i64> {
+    let mut order: Vec<usize> = (0..commits.len()).collect();
+    order.sort_by_key(|&i| commits[i].time);
+    let mut previous = HashMap::new();
+    let mut effort = vec![0; commits.len()];
+    for i in order {
+        let commit = &commits[i];
+        if commit.files.iter().all(|f| filter.reason_to_skip(&f.path).is_some() || filter.automated(&f.path, &commit.author)) {
+            continue;
+        }
+        effort[i] = match previous.insert(&commit.author, commit.time) {
+            Some(time) if commit.time - time <= gap => commit.time - time,
+            _ => SESSION_LEAD,
+        };
+    }
+    return effort;
+}
+
+/// Round seconds to whole hours
+fn to_hours(perf: &mut AuthorPerformance) {
+    for count in perf.values_mut().flat_map(|acnt| acnt.values_mut()) {
+        *count = (*count + 1800) / 3600;
+    }
+}
+
+/// Activity per author at the spec's dates, overall and per --group-by group,
+/// for each of `metrics` from one pass over the log
+pub fn sample_activity(spec: &SnapshotSpec, metrics: &[Metric], backend: &dyn Backend, filter: &FileFilter)
+    -> Result<Vec<(AuthorPerformance, Breakdown)>>
+{
+    let dates = &spec.dates;
+    let mut perf = AuthorPerformance::new();
+    let bounds = dates.iter()
+        .map(|d| date_timestamp(d).ok_or_else(|| Error::Config(format!("invalid date '{d}', expected YYYY-MM-DD"))))
+        .collect::<Result<Vec<i64>>>()?;
+    for date in dates {
+        perf.insert(date.clone(), AuthorCount::new());
+    }
+    let mut samples: Vec<(AuthorPerformance, Breakdown)> = metrics.iter().map(|_| (perf.clone(), Breakdown::new())).collect();
+
+    let commits = backend.commit_log(&spec.branch, &spec.scope)?;
+    let effort = match metrics.contains(&Metric::Effort) {
+        true => session_effort(&commits, filter, spec.session_gap),
+        false => Vec::new(),
+    };
+    for (i, commit) in commits.iter().enumerate() {
+        // First sampled date after the commit; later commits fall outside every period
+        let Some(period) = bounds.iter().position(|&b| commit.time < b) else {
+            continue;
+        };
+        let files: Vec<_> = commit.files.iter()
+            .filter(|f| filter.reason_to_skip(&f.path).is_none() && !filter.automated(&f.path, &commit.author))
+            .collect();
+        if files.is_empty() {
+            continue;
+        }
+        let count = |metric: Metric, files: &[&FileChange]| -> Count {
+            match metric {
+                Metric::Commits => return 1,
+                Metric::Added => return files.iter().map(|f| f.added).sum(),
+                Metric::Deleted => return files.iter().map(|f| f.deleted).sum(),
+                Metric::Churn => return files.iter().map(|f| f.added + f.deleted).sum(),
+                // Seconds, rounded to hours per author and period below
+                Metric::Effort => return effort[i] as Count,
+                Metric::Lines => unreachable!("lines are counted by blame"),
+            }
+        };
+        let date = &dates[period];
+
+        // A commit touching several groups counts once in each
+        let mut by_group: HashMap<String, Vec<&FileChange>> = HashMap::new();
+        for f in &files {
+            if let Some(group) = spec.group_of(spec.group_by, &f.path) {
+                by_group.entry(group).or_default().push(f);
+            }
+        }
+        for (&metric, (perf, groups)) in metrics.iter().zip(samples.iter_mut()) {
+            for (group, group_files) in &by_group {
+                breakdown::add(groups, group, date, &commit.author, count(metric, group_files));
+            }
+            *perf.get_mut(date).unwrap().entry(commit.author.clone()).or_insert(0) += count(metric, &files);
+        }
+    }
+    for (&metric, (perf, groups)) in metrics.iter().zip(samples.iter_mut()) {
+        if metric == Metric::Effort {
+            to_hours(perf);
+            groups.values_mut().for_each(to_hours);
+        }
+        breakdown::fill_dates(groups, perf);
+    }
+    return Ok(samples);
+}
@@ -0,0 +1,1173 @@
+use chrono::{Days, Months, NaiveDate};
+use hashbrown::{HashMap, HashSet};
+use std::fmt::Write;
+use std::path::Path;
+use std::sync::mpsc::channel;
+use std::sync::Arc;
+use std::time::{Duration, Instant};
+use threadpool::ThreadPool;
+
+use crate::activity;
+use crate::at_rest::{self, AtRest};
+use crate::backend::{Backend, FileBlame, TreeEntry};
+use crate::breakdown::{self, Breakdown, Fingerprint, Languages};
+use crate::cache::{BlameCache, CachedBlame};
+use crate::category::{add_categories, AuthorCategories};
+use crate::activity::ChangeFrequency;
+use crate::cli_args::{BinaryMetric, GroupBy, Interval, Metric, MissingSnapshot, Weight};
+use crate::error::{Error, Result};
+use crate::filter::{attribute_skip_reason, DocFiles, FileFilter, SkipReason, SKIP_ATTRIBUTES};
+use crate::health::Staleness;
+use crate::identity::Author;
+use crate::log::Progress;
+use crate::ownership::{file_entropy, is_dominated, Concentration, EntropyTotal};
+use crate::report::json_string;
+use crate::sampling::{self, Estimate};
+use crate::{csv_field, debug, info, AuthorCount, AuthorPerformance, Count, Date};
+
+//----
+// Snapshot analysis
+//
+// The entry points for embedding: sample a repo at a series of dates, either
+// by blaming every file (surviving lines) or by scanning the log (activity),
+// and collect the counts per raw author identity.  Identities are merged and
+// the counts arranged for display by `report`.
+
+type FileCosts = HashMap<String, Duration>;
+
+/// What to sample
+#[derive(Debug, Clone)]
+pub struct SnapshotSpec {
+    /// Branch to analyze; HEAD if None
+    pub branch: Option<String>,
+    /// Snapshot dates, YYYY-MM-DD, oldest first
+    pub dates: Vec<Date>,
+    /// Analyze this revision at the dates, instead of the branch's latest
+    /// commit before each
+    pub revision: Option<String>,
+    /// Repo-relative paths to limit the analysis to; the whole repo if empty
+    pub scope: Vec<String>,
+    /// What to do at dates where the branch has no commits yet
+    pub missing_snapshot: MissingSnapshot,
+    /// Skip files .gitattributes marks binary, generated or vendored
+    pub gitattributes: bool,
+    /// Also sum the counts per directory, extension or language
+    pub group_by: GroupBy,
+    /// Levels of directories GroupBy::Dir groups files by
+    pub dir_depth: usize,
+    /// Languages of files, for grouping them by language
+    pub languages: Languages,
+    /// Count the lines of these documentation files apart from the others
+    pub docs: Option<DocFiles>,
+    /// Keep the per-file counts of the latest snapshot
+    pub keep_files: bool,
+    /// Also count lines per commit category
+    pub categories: bool,
+    /// Write blame wall time per file per snapshot to this CSV file
+    pub profile_files: Option<String>,
+    /// Write every file skipped at each snapshot, and why, to this JSON Lines file
+    pub exclusion_log: Option<String>,
+    /// Encrypt the profile_files CSV and exclusion_log with this
+    pub at_rest: Option<AtRest>,
+    /// Number of files to blame at once
+    pub jobs: usize,
+    /// Seconds between commits that end a work session, for Metric::Effort
+    pub session_gap: i64,
+    /// How much each line counts in the author counts, their groups and the
+    /// fingerprint
+    pub weight: Weight,
+    /// Also sum each author's lines per group at the latest date
+    pub fingerprint: GroupBy,
+    /// Also total the ownership entropy of each snapshot's files
+    pub entropy: bool,
+    /// Also count each snapshot's files where one author owns more than this
+    /// share (0..1) of the lines
+    pub dominance: Option<f64>,
+    /// Also count each snapshot's lines last changed more than this many days
+    /// before it
+    pub stale_after: Option<u32>,
+    /// Also count the lines of the files skipped at the latest snapshot
+    pub coverage: bool,
+    /// Blame only this fraction (0..1] of each snapshot's files and scale the
+    /// counts up, for a quick estimate
+    pub sample: Option<f64>,
+    /// How binary files count
+    pub binary_metric: BinaryMetric,
+}
+
+impl SnapshotSpec {
+    pub fn new(dates: Vec<Date>) -> SnapshotSpec {
+        return SnapshotSpec {
+            branch: None,
+            dates,
+            revision: None,
+            scope: Vec::new(),
+            missing_snapshot: MissingSnapshot::Skip,
+            gitattributes: false,
+            group_by: GroupBy::None,
+            dir_depth: 1,
+            languages: Languages::default(),
+            docs: None,
+            keep_files: false,
+            categories: false,
+            profile_files: None,
+            exclusion_log: None,
+            at_rest: None,
+            jobs: default_jobs(),
+            session_gap: 2 * 3600,
+            weight: Weight::Lines,
+            fingerprint: GroupBy::None,
+            entropy: false,
+            dominance: None,
+            stale_after: None,
+            coverage: false,
+            sample: None,
+            binary_metric: BinaryMetric::Skip,
+        };
+    }
+
+    /// Group a file belongs to, or None when not grouping
+    pub fn group_of(&self, group_by: GroupBy, path: &str) -> Option<String> {
+        match group_by {
+            GroupBy::Path => return breakdown::path_group(&self.scope, path),
+            GroupBy::Dir => return Some(breakdown::dir_group(path, self.dir_depth)),
+            _ => return breakdown::group_of(group_by, &self.languages, path),
+        }
+    }
+}
+
+/// The first of every month from January `first_year` through December `last_year`
+pub fn monthly_dates(first_year: i32, last_year: i32) -> Vec<Date> {
+    let mut dates = Vec::new();
+    for year in first_year..=last_year {
+        for month in 1..=12 {
+            dates.push(format!("{year:4}-{month:02}-01"));
+        }
+    }
+    return dates;
+}
+
+/// Dates from `since` through `until`, `interval` apart
+pub fn interval_dates(since: NaiveDate, until: NaiveDate, interval: Interval) -> Vec<Date> {
+    let mut dates = Vec::new();
+    for n in 0.. {
+        let date = match interval {
+            Interval::Week => since.checked_add_days(Days::new(7 * n)),
+            Interval::Month => since.checked_add_months(Months::new(n as u32)),
+            Interval::Quarter => since.checked_add_months(Months::new(3 * n as u32)),
+            Interval::Year => since.checked_add_months(Months::new(12 * n as u32)),
+        };
+        match date {
+            Some(date) if date <= until => dates.push(date.format("%Y-%m-%d").to_string()),
+            _ => break,
+        }
+    }
+    return dates;
+}
+
+/// Seconds since the epoch at the start of a YYYY-MM-DD date, in UTC
+fn start_of(date: &str) -> i64 {
+    return NaiveDate::parse_from_str(date, "%Y-%m-%d").ok()
+        .and_then(|day| day.and_hms_opt(0, 0, 0))
+        .map(|time| time.timestamp())
+        .unwrap_or(0);
+}
+
+/// Blame counts per file at one snapshot
+#[derive(Debug, Clone, Default)]
+pub struct FileOwnership {
+    pub date: Date,
+    pub revision: String,
+    pub files: Vec<(String, AuthorCount)>,
+}
+
+/// How much of a snapshot's lines the counts cover: the lines of the files
+/// counted and of those skipped by the excludes
+#[derive(Debug, Clone, Default, PartialEq, Eq)]
+pub struct Coverage {
+    pub date: Date,
+    pub counted: i64,
+    pub excluded: i64,
+}
+
+impl Coverage {
+    /// Share of the lines counted, in percent; all of none
+    pub fn percent(&self) -> f64 {
+        match self.counted + self.excluded {
+            0 => return 100.0,
+            total => return self.counted as f64 * 100.0 / total as f64,
+        }
+    }
+
+    /// An error if less than `min` percent of the lines are counted
+    pub fn check(&self, min: f64) -> Result<()> {
+        match self.percent() < min {
+            true => return Err(Error::LowCoverage { date: self.date.clone(), percent: self.percent(), min }),
+            false => return Ok(()),
+        }
+    }
+}
+
+/// Counts per raw author per snapshot date
+#[derive(Debug, Clone, Default)]
+pub struct AuthorStats {
+    pub authors: AuthorPerformance,
+    /// Lines in SnapshotSpec::docs files, which `authors` leaves out
+    pub docs: AuthorPerformance,
+    /// Every file skipped at any snapshot, with the latest reason why
+    pub excluded: HashMap<String, SkipReason>,
+    /// Per-file counts at the latest snapshot, if SnapshotSpec::keep_files
+    pub latest: Option<FileOwnership>,
+    /// The same counts per SnapshotSpec::group_by group
+    pub groups: Breakdown,
+    /// Lines per author per commit category, if SnapshotSpec::categories
+    pub categories: HashMap<Date, AuthorCategories>,
+    /// Lines per author per SnapshotSpec::fingerprint group at the latest snapshot
+    pub fingerprint: Fingerprint,
+    /// Ownership entropy of the files per date, if SnapshotSpec::entropy
+    pub entropy: HashMap<Date, EntropyTotal>,
+    /// Files with a dominant author per date, if SnapshotSpec::dominance
+    pub concentration: HashMap<Date, Concentration>,
+    /// Lines and stale lines per date, if SnapshotSpec::stale_after
+    pub staleness: HashMap<Date, Staleness>,
+    /// Lines counted and skipped at the latest snapshot, if SnapshotSpec::coverage
+    pub coverage: Option<Coverage>,
+    /// Dates left out for having no revision on the branch
+    pub missing: Vec<Date>,
+    /// Dates analyzed on the default branch instead, with MissingSnapshot::Fallback
+    pub fallbacks: Vec<Date>,
+    /// Dates whose every file was skipped, with the rules that skipped them;
+    /// they count no lines
+    pub ineligible: Vec<(Date, Vec<String>)>,
+    /// How good the estimated counts of each date are, if SnapshotSpec::sample
+    pub estimates: HashMap<Date, Estimate>,
+}
+
+impl AuthorStats {
+    /// Add the counts of another repository's analysis to these.  Its
+    /// excluded files are listed under `name/`; per-file counts aren't kept,
+    /// since they can't be blamed in one place.
+    pub fn merge(&mut self, name: &str, other: AuthorStats) {
+        for (date, acnt) in other.authors {
+            let sum = self.authors.entry(date).or_default();
+            for (author, count) in acnt {
+                *sum.entry(author).or_insert(0) += count;
+            }
+        }
+        for (date, acnt) in other.docs {
+            let sum = self.docs.entry(date).or_default();
+            for (author, count) in acnt {
+                *sum.entry(author).or_insert(0) += count;
+            }
+        }
+        for (group, perf) in other.groups {
+            for (date, acnt) in perf {
+                acnt.iter().for_each(|(author, count)| breakdown::add(&mut self.groups, &group, &date, author, *count));
+            }
+        }
+        for (date, acat) in other.categories {
+            add_categories(self.categories.entry(date).or_default(), &acat);
+        }
+        breakdown::add_fingerprint(&mut self.fingerprint, &other.fingerprint);
+        for (date, total) in other.entropy {
+            self.entropy.entry(date).or_default().add(total);
+        }
+        for (date, files) in other.concentration {
+            self.concentration.entry(date).or_default().add(files);
+        }
+        for (date, lines) in other.staleness {
+            self.staleness.entry(date).or_default().add(lines);
+        }
+        for (date, estimate) in other.estimates {
+            self.estimates.entry(date).or_insert_with(|| Estimate::new(estimate.fraction, 0, 0)).add_estimate(&estimate);
+        }
+        for (path, skip) in other.excluded {
+            self.excluded.insert(format!("{name}/{path}"), skip);
+        }
+        // A date stays ineligible only if no repo counts anything at it
+        for (date, rules) in other.ineligible {
+            match self.ineligible.iter_mut().find(|(d, _)| *d == date) {
+                Some((_, known)) => rules.into_iter().for_each(|rule| if !known.contains(&rule) { known.push(rule) }),
+                None => self.ineligible.push((date, rules)),
+            }
+        }
+        let authors = &self.authors;
+        self.ineligible.retain(|(date, _)| authors.get(date).is_none_or(|acnt| acnt.is_empty()));
+        self.ineligible.sort();
+        self.latest = None;
+        breakdown::fill_dates(&mut self.groups, &self.authors);
+    }
+
+    /// Add the snapshots of an analysis of later dates of the same repository.
+    /// Its latest files and fingerprint replace these, if it has them.
+    pub fn extend(&mut self, later: AuthorStats) {
+        self.authors.extend(later.authors);
+        self.docs.extend(later.docs);
+        for (group, perf) in later.groups {
+            self.groups.entry(group).or_default().extend(perf);
+        }
+        self.categories.extend(later.categories);
+        self.entropy.extend(later.entropy);
+        self.concentration.extend(later.concentration);
+        self.staleness.extend(later.staleness);
+        self.excluded.extend(later.excluded);
+        self.missing.extend(later.missing);
+        self.fallbacks.extend(later.fallbacks);
+        self.ineligible.extend(later.ineligible);
+        self.estimates.extend(later.estimates);
+        if later.latest.is_some() {
+            self.latest = later.latest;
+        }
+        if !later.fingerprint.is_empty() {
+            self.fingerprint = later.fingerprint;
+        }
+        breakdown::fill_dates(&mut self.groups, &self.authors);
+    }
+
+    /// Drop the counts of authors for which `keep` is false
+    pub fn retain_authors<F: Fn(&Author) -> bool>(&mut self, keep: F) {
+        self.authors.values_mut().chain(self.docs.values_mut()).for_each(|acnt| acnt.retain(|a, _| keep(a)));
+        self.groups.values_mut().flat_map(|perf| perf.values_mut()).for_each(|acnt| acnt.retain(|a, _| keep(a)));
+        self.categories.values_mut().for_each(|acat| acat.retain(|a, _| keep(a)));
+        self.fingerprint.values_mut().for_each(|acnt| acnt.retain(|a, _| keep(a)));
+        if let Some(latest) = self.latest.as_mut() {
+            latest.files.iter_mut().for_each(|(_, acnt)| acnt.retain(|a, _| keep(a)));
+        }
+    }
+
+    /// Move the counts of authors for which `fold` is true onto `into`, so
+    /// no output can tell them apart
+    pub fn fold_authors<F: Fn(&Author) -> bool>(&mut self, fold: F, into: &Author) {
+        self.authors.values_mut().chain(self.docs.values_mut()).for_each(|acnt| fold_counts(acnt, &fold, into));
+        self.groups.values_mut().flat_map(|perf| perf.values_mut()).for_each(|acnt| fold_counts(acnt, &fold, into));
+        for acat in self.categories.values_mut() {
+            let folded: Vec<_> = acat.drain_filter(|a, _| fold(a)).map(|(_, counts)| (into.clone(), counts)).collect();
+            folded.into_iter().for_each(|counts| add_categories(acat, &AuthorCategories::from([counts])));
+        }
+        self.fingerprint.values_mut().for_each(|acnt| fold_counts(acnt, &fold, into));
+        if let Some(latest) = self.latest.as_mut() {
+            latest.files.iter_mut().for_each(|(_, acnt)| fold_counts(acnt, &fold, into));
+        }
+    }
+}
+
+fn fold_counts<F: Fn(&Author) -> bool>(acnt: &mut AuthorCount, fold: &F, into: &Author) {
+    let mut folded = None;
+    for (_, count) in acnt.drain_filter(|a, _| fold(a)) {
+        *folded.get_or_insert(0) += count;
+    }
+    if let Some(count) = folded {
+        *acnt.entry(into.clone()).or_insert(0) += count;
+    }
+}
+
+/// Files to analyze at one date, as indexes into the run's distinct files
+struct Snapshot {
+    date: Date,
+    revision: String,
+    files: Vec<usize>,
+    /// Binary files skipped by the filters, counted with BinaryMetric::Files
+    binaries: Vec<String>,
+}
+
+/// Whether `skip` is for a binary file, for BinaryMetric::Files to count it anyway
+fn skipped_as_binary(skip: &SkipReason) -> bool {
+    return matches!(skip.reason.as_str(), "binary extension" | "binary attribute");
+}
+
+/// The author of the latest commit before `revision` changing each of `paths`
+fn last_committers(backend: &dyn Backend, revision: &str, paths: &[String]) -> Result<HashMap<String, Author>> {
+    let mut authors = HashMap::new();
+    for commit in backend.commit_log(&Some(revision.to_string()), paths)? {
+        for f in commit.files {
+            authors.entry(f.path).or_insert_with(|| commit.author.clone());
+        }
+    }
+    authors.retain(|path, _| paths.contains(path));
+    return Ok(authors);
+}
+
+/// A file to blame, at the first snapshot it appears in
+struct WorkItem {
+    file: usize,
+    date: Date,
+    revision: String,
+}
+
+/// Number of blame jobs to run at once by default: one per logical core
+pub fn default_jobs() -> usize {
+    return std::thread::available_parallelism().map(|n| n.get()).unwrap_or(1);
+}
+
+/// Resolve the revision to analyze at `date`, or None to skip the date
+fn snapshot_revision(backend: &dyn Backend, spec: &SnapshotSpec, date: &Date, stats: &mut AuthorStats) -> Result<Option<String>> {
+    if let Some(revision) = &spec.revision {
+        return Ok(Some(revision.clone()));
+    }
+    let at_date = Some(date.to_string());
+    if let Some(revision) = backend.revision(&spec.branch, &at_date)? {
+        return Ok(Some(revision));
+    }
+    match spec.missing_snapshot {
+        MissingSnapshot::Error => {
+            let branch = spec.branch.clone().unwrap_or("HEAD".to_string());
+            return Err(Error::NoRevision { branch, date: date.clone() });
+        },
+        MissingSnapshot::Fallback if spec.branch.is_some() => {
+            let revision = backend.revision(&Some(backend.default_branch()?), &at_date)?;
+            match revision {
+                Some(_) => stats.fallbacks.push(date.clone()),
+                None => stats.missing.push(date.clone()),
+            }
+            return Ok(revision);
+        },
+        _ => {
+            stats.missing.push(date.clone());
+            return Ok(None);
+        },
+    }
+}
+
+/// Blame every eligible file at each date, counting the lines each author
+/// owns.  Files found in `cache` aren't blamed again.
+///
+/// All snapshots are listed first, so that the blames of every date share one
+/// pool of `spec.jobs` threads: each distinct file content is blamed once,
+/// the most expensive ones first.
+pub fn blame_snapshots(backend: &Arc<dyn Backend>, spec: &SnapshotSpec, filter: &FileFilter, cache: &mut BlameCache) -> Result<AuthorStats> {
+    let mut stats = AuthorStats::default();
+
+    // Blame wall time of each file, as measured at the latest snapshot
+    let costs: FileCosts = cache.costs();
+
+    let mut profile = spec.profile_files.as_ref().map(|_| String::from("date,revision,file,seconds\n"));
+    let mut exclusion_log = spec.exclusion_log.as_ref().map(|_| String::new());
+
+    // Distinct (blob, path) pairs over all snapshots
+    let mut distinct: Vec<TreeEntry> = Vec::new();
+    let mut index: HashMap<(String, String), usize> = HashMap::new();
+    let mut snapshots = Vec::new();
+    let mut work = Vec::new();
+    // Date, revision and skipped files of the latest snapshot, for spec.coverage
+    let mut skipped = None;
+
+    let mut listing = Progress::new("listing snapshots", spec.dates.len());
+    for date in spec.dates.iter() {
+        listing.inc();
+        let Some(revision) = snapshot_revision(backend.as_ref(), spec, date, &mut stats)? else {
+            info!("{date}: no revision");
+            continue;
+        };
+        info!("{date}: {revision}");
+        let files = backend.files(&revision, &spec.scope)?;
+        let listed = match spec.coverage {
+            true => files.clone(),
+            false => Vec::new(),
+        };
+
+        let listed_any = !files.is_empty();
+        let excluded = &mut stats.excluded;
+        let mut logged = Vec::new();
+        let mut exclude = |f: &TreeEntry, skip: SkipReason| {
+            if !excluded.contains_key(&f.path) {
+                info!("skipped {}: {skip}", f.path);
+            }
+            logged.push((f.clone(), skip.clone()));
+            excluded.insert(f.path.clone(), skip);
+        };
+        let count_binaries = spec.binary_metric == BinaryMetric::Files;
+        let mut binaries = Vec::new();
+        let mut generated = filter.generated_files(&files.iter().map(|f| f.path.as_str()).collect::<Vec<_>>());
+        let mut files: Vec<TreeEntry> = files.into_iter().filter(|f| {
+            match filter.reason_to_skip(&f.path).or_else(|| generated.remove(&f.path)) {
+                None => true,
+                Some(skip) if count_binaries && skipped_as_binary(&skip) => {
+                    binaries.push(f.path.clone());
+                    false
+                },
+                Some(skip) => {
+                    exclude(f, skip);
+                    false
+                },
+            }
+        }).collect();
+        if spec.gitattributes && !files.is_empty() {
+            let paths: Vec<String> = files.iter().map(|f| f.path.clone()).collect();
+            let attrs = backend.attributes(&revision, &paths, &SKIP_ATTRIBUTES)?;
+            files.retain(|f| match attrs.get(&f.path).and_then(|a| attribute_skip_reason(a)) {
+                None => true,
+                Some(skip) if count_binaries && skipped_as_binary(&skip) => {
+                    binaries.push(f.path.clone());
+                    false
+                },
+                Some(skip) => {
+                    exclude(f, skip);
+                    false
+                },
+            });
+        }
+        if let Some(out) = exclusion_log.as_mut() {
+            let entries: Vec<TreeEntry> = logged.iter().map(|(f, _)| f.clone()).collect();
+            let lines = backend.line_counts(&revision, &entries)?;
+            let bytes = backend.sizes(&revision, &entries)?;
+            for (((f, skip), lines), bytes) in logged.iter().zip(lines).zip(bytes) {
+                let rule = match skip.pattern.is_empty() {
+                    true => &skip.reason,
+                    false => &skip.pattern,
+                };
+                writeln!(out, "{{\"date\": {}, \"revision\": {}, \"path\": {}, \"rule\": {}, \"reason\": {}, \"source\": \"{}\", \"lines\": {lines}, \"bytes\": {bytes}}}",
+                    json_string(date), json_string(&revision), json_string(&f.path), json_string(rule), json_string(&skip.reason), skip.source).unwrap();
+            }
+        }
+        // Every file skipped still makes a column, of zeros
+        let ineligible = listed_any && files.is_empty() && binaries.is_empty();
+        if ineligible {
+            let mut rules: Vec<String> = Vec::new();
+            for (_, skip) in &logged {
+                if !rules.contains(&skip.to_string()) {
+                    rules.push(skip.to_string());
+                }
+            }
+            info!("{date}: no eligible files");
+            stats.ineligible.push((date.clone(), rules));
+        }
+        if spec.coverage {
+            let kept: HashSet<&str> = files.iter().map(|f| f.path.as_str()).collect();
+            let excluded: Vec<TreeEntry> = listed.into_iter().filter(|f| !kept.contains(f.path.as_str())).collect();
+            skipped = Some((date.clone(), revision.clone(), excluded));
+        }
+        if let Some(fraction) = spec.sample {
+            let listed = files.len();
+            files.retain(|f| sampling::sampled(&f.path, fraction));
+            binaries.retain(|path| sampling::sampled(path, fraction));
+            stats.estimates.insert(date.clone(), Estimate::new(fraction, files.len(), listed));
+        }
+        if files.is_empty() && binaries.is_empty() && !ineligible { continue; }
+
+        // Only blame files whose content changed since a cached or earlier snapshot
+        let mut snapshot = Snapshot { date: date.clone(), revision: revision.clone(), files: Vec::new(), binaries };
+        for f in files {
+            let key = (f.blob.clone(), f.path.clone());
+            let file = match index.get(&key) {
+                Some(&file) => {
+                    // Blamed (or found in the cache) for an earlier snapshot
+                    cache.hits += 1;
+                    file
+                },
+                None => {
+                    let file = distinct.len();
+                    if cache.get(&f).is_none() {
+                        work.push(WorkItem { file, date: date.clone(), revision: revision.clone() });
+                    }
+                    index.insert(key, file);
+                    distinct.push(f);
+                    file
+                },
+            };
+            snapshot.files.push(file);
+        }
+        snapshots.push(snapshot);
+    };
+    drop(listing);
+
+    if !work.is_empty() {
+        work.sort_by_key(|item| std::cmp::Reverse(costs.get(&distinct[item.file].path).copied().unwrap_or(Duration::MAX)));
+        let pool = ThreadPool::new(spec.jobs.max(1).min(work.len()));
+        let (tx, rx) = channel();
+        for item in work.iter() {
+            let tbackend = backend.clone();
+            let trevision = item.revision.clone();
+            let tpath = distinct[item.file].path.clone();
+            let tfile = item.file;
+            let ttx = tx.clone();
+            pool.execute(move || {
+                let start = Instant::now();
+                let fblame = tbackend.blame(&trevision, &tpath);
+                // The receiver is gone if another file failed
+                let _ = ttx.send((tfile, fblame, start.elapsed()));
+            });
+        };
+
+        let dates: HashMap<usize, &WorkItem> = work.iter().map(|item| (item.file, item)).collect();
+        let mut blaming = Progress::new("blaming files", work.len());
+        for (file, fblame, elapsed) in rx.iter().take(work.len()) {
+            let FileBlame { authors, categories, times } = fblame?;
+            let f = &distinct[file];
+            blaming.inc();
+            debug!("blamed {} at {} in {:.3}s", f.path, dates[&file].date, elapsed.as_secs_f64());
+            if let Some(out) = profile.as_mut() {
+                let item = dates[&file];
+                writeln!(out, "{},{},{},{:.6}", item.date, item.revision, csv_field(&f.path), elapsed.as_secs_f64()).unwrap();
+            }
+            cache.insert(f, CachedBlame { authors, categories, times, cost: elapsed });
+        }
+    }
+    if let (Some(path), Some(profile)) = (&spec.profile_files, &profile) {
+        at_rest::write(Path::new(path), profile, spec.at_rest.as_ref())?;
+    }
+    if let (Some(path), Some(log)) = (&spec.exclusion_log, &exclusion_log) {
+        at_rest::write(Path::new(path), log, spec.at_rest.as_ref())?;
+    }
+
+    // Lines of a file at a date count once per commit that changed it before
+    let frequency = match spec.weight {
+        Weight::Lines => None,
+        Weight::Hotspot => Some(ChangeFrequency::from_log(&backend.commit_log(&spec.branch, &spec.scope)?)),
+    };
+    let weight = |path: &str, date: &str| -> Count {
+        match &frequency {
+            None => return 1,
+            Some(frequency) => return frequency.before(path, activity::date_timestamp(date).unwrap_or(i64::MAX)),
+        }
+    };
+
+    // Every file is in the cache now.  The lines of automated authors in the
+    // files of their [automated] rules don't count.
+    let mut automated: HashMap<usize, CachedBlame> = HashMap::new();
+    for (file, f) in distinct.iter().enumerate().filter(|(_, f)| filter.has_automated(&f.path)) {
+        let mut blame = cache.peek(f).expect("every snapshot file is blamed").clone();
+        blame.authors.retain(|author, _| !filter.automated(&f.path, author));
+        blame.categories.retain(|author, _| !filter.automated(&f.path, author));
+        automated.insert(file, blame);
+    }
+    let blame_of = |file: usize| match automated.get(&file) {
+        Some(blame) => blame,
+        None => cache.peek(&distinct[file]).expect("every snapshot file is blamed"),
+    };
+    // Binary files blame to no lines; BinaryMetric::Files credits each to its
+    // last committer instead
+    let mut binary: HashMap<usize, bool> = HashMap::new();
+    let mut binary_authors: HashMap<Date, Vec<(String, Author)>> = HashMap::new();
+    if spec.binary_metric == BinaryMetric::Files {
+        for snapshot in &snapshots {
+            let unknown: Vec<usize> = snapshot.files.iter().copied()
+                .filter(|file| !binary.contains_key(file) && blame_of(*file).authors.is_empty())
+                .collect();
+            let entries: Vec<TreeEntry> = unknown.iter().map(|&file| distinct[file].clone()).collect();
+            binary.extend(unknown.into_iter().zip(backend.binary(&snapshot.revision, &entries)?));
+            let mut paths = snapshot.binaries.clone();
+            paths.extend(snapshot.files.iter().filter(|file| binary.get(*file) == Some(&true)).map(|&file| distinct[file].path.clone()));
+            if paths.is_empty() {
+                continue;
+            }
+            let authors = last_committers(backend.as_ref(), &snapshot.revision, &paths)?;
+            info!("{}: {} binary files counted", snapshot.date, authors.len());
+            binary_authors.insert(snapshot.date.clone(), authors.into_iter().collect());
+        }
+    }
+    if let Some((date, revision, excluded)) = skipped {
+        let counted = snapshots.iter().filter(|snapshot| snapshot.date == date).flat_map(|snapshot| &snapshot.files)
+            .flat_map(|&file| blame_of(file).authors.values())
+            .map(|&count| count as i64)
+            .sum::<i64>();
+        let counted = match stats.estimates.get(&date) {
+            Some(estimate) => (counted as f64 / estimate.fraction).round() as i64,
+            None => counted,
+        };
+        let excluded = backend.line_counts(&revision, &excluded)?.iter().map(|&count| count as i64).sum();
+        info!("{date}: {counted} lines counted, {excluded} in skipped files");
+        stats.coverage = Some(Coverage { date, counted, excluded });
+    }
+    if let Some(latest) = snapshots.last() {
+        for &file in &latest.files {
+            let f = &distinct[file];
+            let Some(group) = spec.group_of(spec.fingerprint, &f.path) else {
+                break;
+            };
+            let blame = blame_of(file);
+            let acnt = stats.fingerprint.entry(group).or_default();
+            let weight = weight(&f.path, &latest.date);
+            blame.authors.iter().for_each(|(author, count)| {
+                *acnt.entry(author.clone()).or_insert(0) += count * weight;
+            });
+        }
+    }
+    for snapshot in snapshots {
+        let date = &snapshot.date;
+        let mut dauth = AuthorCount::new();
+        let mut ddocs = AuthorCount::new();
+        let mut dcat = AuthorCategories::new();
+        let mut dentropy = EntropyTotal::default();
+        let mut dconcentration = Concentration::default();
+        let mut dstaleness = Staleness::default();
+        let cutoff = spec.stale_after.map(|days| start_of(date) - days as i64 * 86400);
+        let mut file_counts = Vec::new();
+        for file in snapshot.files {
+            let f = &distinct[file];
+            let blame = blame_of(file);
+            let weight = weight(&f.path, date);
+            let counts = match spec.docs.as_ref().is_some_and(|docs| docs.is_doc(&f.path)) {
+                true => &mut ddocs,
+                false => &mut dauth,
+            };
+            blame.authors.iter().for_each(|(author, count)| {
+                *counts.entry(author.clone()).or_insert(0) += count * weight;
+            });
+            if let Some(estimate) = stats.estimates.get_mut(date) {
+                estimate.add(&blame.authors, weight);
+            }
+            if let Some(group) = spec.group_of(spec.group_by, &f.path) {
+                blame.authors.iter().for_each(|(author, count)| breakdown::add(&mut stats.groups, &group, date, author, count * weight));
+            }
+            if spec.keep_files {
+                file_counts.push((f.path.clone(), blame.authors.clone()));
+            }
+            if spec.categories {
+                add_categories(&mut dcat, &blame.categories);
+            }
+            if spec.entropy {
+                dentropy.add(EntropyTotal { sum: file_entropy(&blame.authors), files: 1 });
+            }
+            if let Some(threshold) = spec.dominance {
+                dconcentration.add(Concentration { files: 1, dominated: is_dominated(&blame.authors, threshold) as usize });
+            }
+            if let Some(cutoff) = cutoff {
+                dstaleness.add(Staleness::of(&blame.times, cutoff));
+            }
+        }
+
+        for (path, author) in binary_authors.remove(date).unwrap_or_default() {
+            let acnt = AuthorCount::from([(author.clone(), 1)]);
+            let counts = match spec.docs.as_ref().is_some_and(|docs| docs.is_doc(&path)) {
+                true => &mut ddocs,
+                false => &mut dauth,
+            };
+            *counts.entry(author.clone()).or_insert(0) += 1;
+            if let Some(estimate) = stats.estimates.get_mut(date) {
+                estimate.add(&acnt, 1);
+            }
+            if let Some(group) = spec.group_of(spec.group_by, &path) {
+                breakdown::add(&mut stats.groups, &group, date, &author, 1);
+            }
+            if spec.keep_files {
+                file_counts.retain(|(kept, _)| *kept != path);
+                file_counts.push((path, acnt));
+            }
+        }
+        if let Some(estimate) = stats.estimates.get(date) {
+            estimate.extrapolate(&mut dauth);
+            estimate.extrapolate(&mut ddocs);
+            dcat.values_mut().flatten().for_each(|count| *count = estimate.scale(*count));
+        }
+        stats.authors.insert(date.to_string(), dauth);
+        if spec.docs.is_some() {
+            stats.docs.insert(date.to_string(), ddocs);
+        }
+        if spec.categories {
+            stats.categories.insert(date.to_string(), dcat);
+        }
+        if spec.entropy {
+            stats.entropy.insert(date.to_string(), dentropy);
+        }
+        if spec.dominance.is_some() {
+            stats.concentration.insert(date.to_string(), dconcentration);
+        }
+        if cutoff.is_some() {
+            stats.staleness.insert(date.to_string(), dstaleness);
+        }
+        if spec.keep_files {
+            stats.latest = Some(FileOwnership { date: date.clone(), revision: snapshot.revision, files: file_counts });
+        }
+    }
+
+    if spec.sample.is_some() {
+        for (date, acnt) in stats.groups.values_mut().flatten() {
+            stats.estimates[date].extrapolate(acnt);
+        }
+        if let Some(estimate) = stats.latest.as_ref().and_then(|latest| stats.estimates.get(&latest.date)) {
+            stats.fingerprint.values_mut().for_each(|acnt| estimate.extrapolate(acnt));
+        }
+    }
+    breakdown::fill_dates(&mut stats.groups, &stats.authors);
+    return Ok(stats);
+}
+
+/// Count each author's activity in the period before each date, once per
+/// metric in `metrics`, sharing one pass over the log
+pub fn activity_snapshots(backend: &dyn Backend, spec: &SnapshotSpec, metrics: &[Metric], filter: &FileFilter) -> Result<Vec<AuthorStats>> {
+    let samples = activity::sample_activity(spec, metrics, backend, filter)?;
+    return Ok(samples.into_iter().map(|(authors, groups)| AuthorStats { authors, groups, ..Default::default() }).collect());
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::backend::{STAGED, WORKING_TREE};
+    use crate::config::Config;
+    use crate::fixture::{Fixture, ALICE, BOB, DATES};
+    use crate::identity::Special;
+    use std::fs;
+
+    fn spec() -> SnapshotSpec {
+        return SnapshotSpec::new(DATES.iter().map(|d| d.to_string()).collect());
+    }
+
+    fn count(stats: &AuthorStats, date: &str, (name, email): (&str, &str)) -> i32 {
+        return stats.authors[date].get(&Author::new(name, email)).copied().unwrap_or(0);
+    }
+
+    #[test]
+    fn steps_dates_by_interval() {
+        let day = |text: &str| NaiveDate::parse_from_str(text, "%Y-%m-%d").unwrap();
+        assert_eq!(interval_dates(day("2020-01-31"), day("2020-04-30"), Interval::Month), ["2020-01-31", "2020-02-29", "2020-03-31", "2020-04-30"]);
+        assert_eq!(interval_dates(day("2020-01-01"), day("2020-01-20"), Interval::Week), ["2020-01-01", "2020-01-08", "2020-01-15"]);
+        assert_eq!(interval_dates(day("2019-11-01"), day("2020-06-01"), Interval::Quarter), ["2019-11-01", "2020-02-01", "2020-05-01"]);
+        assert!(interval_dates(day("2021-01-01"), day("2020-01-01"), Interval::Year).is_empty());
+    }
+
+    #[test]
+    fn blames_each_snapshot() {
+        let fixture = Fixture::new();
+        let stats = blame_snapshots(&fixture.backend(), &spec(), &FileFilter::default(), &mut BlameCache::in_memory()).unwrap();
+
+        assert_eq!(stats.missing, vec!["2020-01-01".to_string()]);
+        assert_eq!(count(&stats, "2020-02-01", ALICE), 5);
+        assert_eq!(count(&stats, "2020-02-01", BOB), 0);
+        assert_eq!(count(&stats, "2020-03-01", ALICE), 5);
+        assert_eq!(count(&stats, "2020-03-01", BOB), 3);
+        assert_eq!(count(&stats, "2020-04-01", ALICE), 4);
+        assert_eq!(count(&stats, "2020-04-01", BOB), 3);
+    }
+
+    #[test]
+    fn counts_doc_lines_apart() {
+        let fixture = Fixture::new();
+        let spec = SnapshotSpec { docs: Some(DocFiles::new(&Config::default(), &[]).unwrap()), ..spec() };
+        let stats = blame_snapshots(&fixture.backend(), &spec, &FileFilter::default(), &mut BlameCache::in_memory()).unwrap();
+        let alice = Author::new(ALICE.0, ALICE.1);
+        // README.md's lines
+        assert_eq!(count(&stats, "2020-04-01", ALICE), 3);
+        assert_eq!(stats.docs["2020-04-01"][&alice], 1);
+        assert_eq!(stats.docs["2020-02-01"][&alice], 2);
+        assert!(!stats.docs["2020-04-01"].contains_key(&Author::new(BOB.0, BOB.1)));
+    }
+
+    #[test]
+    fn reuses_blame_of_unchanged_files() {
+        let fixture = Fixture::new();
+        let mut cache = BlameCache::in_memory();
+        blame_snapshots(&fixture.backend(), &spec(), &FileFilter::default(), &mut cache).unwrap();
+        // Every file is blamed once per distinct content: 5 blobs, 3 reused
+        assert_eq!((cache.hits, cache.misses), (3, 5));
+    }
+
+    #[test]
+    fn merges_repositories() {
+        let (first, second) = (Fixture::new(), Fixture::new());
+        let spec = SnapshotSpec { group_by: GroupBy::Dir, ..spec() };
+        let mut stats = AuthorStats::default();
+        for fixture in [&first, &second] {
+            let filter = FileFilter::new(&Config::default(), false, &["vendor/".to_string()], &[]).unwrap();
+            let repo_stats = blame_snapshots(&fixture.backend(), &spec, &filter, &mut BlameCache::in_memory()).unwrap();
+            stats.merge("app", repo_stats);
+        }
+
+        assert_eq!(count(&stats, "2020-04-01", ALICE), 8);
+        assert_eq!(count(&stats, "2020-04-01", BOB), 4);
+        assert_eq!(stats.groups["src/"]["2020-04-01"][&Author::new(BOB.0, BOB.1)], 4);
+        assert!(stats.excluded.contains_key("app/vendor/lib.js"));
+    }
+
+    #[test]
+    fn groups_by_given_path() {
+        let fixture = Fixture::new();
+        let scope = vec!["src".to_string(), "src/main.rs".to_string(), "README.md".to_string()];
+        let spec = SnapshotSpec { group_by: GroupBy::Path, scope, ..spec() };
+        let stats = blame_snapshots(&fixture.backend(), &spec, &FileFilter::default(), &mut BlameCache::in_memory()).unwrap();
+        let mut groups: Vec<&String> = stats.groups.keys().collect();
+        groups.sort();
+        assert_eq!(groups, ["README.md", "src/main.rs"]);
+        assert_eq!(stats.groups["src/main.rs"]["2020-04-01"][&Author::new(BOB.0, BOB.1)], 2);
+        assert_eq!(stats.groups["README.md"]["2020-04-01"][&Author::new(ALICE.0, ALICE.1)], 1);
+        assert_eq!(breakdown::path_group(&spec.scope, "srcs/x.rs"), None);
+    }
+
+    #[test]
+    fn folds_opted_out_authors() {
+        let fixture = Fixture::new();
+        let spec = SnapshotSpec { group_by: GroupBy::Dir, fingerprint: GroupBy::Ext, keep_files: true, ..spec() };
+        let mut stats = blame_snapshots(&fixture.backend(), &spec, &FileFilter::default(), &mut BlameCache::in_memory()).unwrap();
+        let bob = Author::new(BOB.0, BOB.1);
+        let folded = Author::new("Opted-out", "");
+        stats.fold_authors(|author| *author == bob, &folded);
+
+        assert_eq!(count(&stats, "2020-02-01", ("Opted-out", "")), 0);
+        assert_eq!(count(&stats, "2020-04-01", ("Opted-out", "")), 3);
+        assert_eq!(count(&stats, "2020-04-01", ALICE), 4);
+        assert_eq!(stats.groups["src/"]["2020-04-01"][&folded], 2);
+        assert_eq!(stats.fingerprint[".js"][&folded], 1);
+        let latest = stats.latest.unwrap();
+        assert!(latest.files.iter().all(|(_, acnt)| !acnt.contains_key(&bob)));
+        assert!(stats.authors.values().all(|acnt| !acnt.contains_key(&bob)));
+    }
+
+    #[test]
+    fn extends_with_later_snapshots() {
+        let fixture = Fixture::new();
+        let backend = fixture.backend();
+        let all = SnapshotSpec { group_by: GroupBy::Dir, ..spec() };
+        let whole = blame_snapshots(&backend, &all, &FileFilter::default(), &mut BlameCache::in_memory()).unwrap();
+        let (early, late) = all.dates.split_at(2);
+        let mut stats = AuthorStats::default();
+        for dates in [early, late] {
+            let spec = SnapshotSpec { dates: dates.to_vec(), ..all.clone() };
+            stats.extend(blame_snapshots(&backend, &spec, &FileFilter::default(), &mut BlameCache::in_memory()).unwrap());
+        }
+
+        assert_eq!(stats.authors, whole.authors);
+        assert_eq!(stats.groups, whole.groups);
+        assert_eq!(count(&stats, "2020-04-01", ALICE), 4);
+    }
+
+    #[test]
+    fn blames_a_given_revision() {
+        let fixture = Fixture::new();
+        let backend = fixture.backend();
+        let first = backend.revision(&None, &Some("2020-02-01".to_string())).unwrap().unwrap();
+        assert_eq!(backend.commit_date(&first).unwrap(), "2020-01-15");
+        let spec = SnapshotSpec { revision: Some(first), ..SnapshotSpec::new(vec!["2020-04-01".to_string()]) };
+        let stats = blame_snapshots(&backend, &spec, &FileFilter::default(), &mut BlameCache::in_memory()).unwrap();
+
+        assert_eq!(count(&stats, "2020-04-01", ALICE), 5);
+        assert_eq!(count(&stats, "2020-04-01", BOB), 0);
+    }
+
+    #[test]
+    fn blames_the_working_tree() {
+        let fixture = Fixture::new();
+        fixture.write("src/main.rs", "a\nb\nc\nd\ne\nf\n");
+        fs::remove_file(fixture.dir.join("vendor/lib.js")).unwrap();
+        let backend = fixture.backend();
+        let files = backend.files(WORKING_TREE, &[]).unwrap();
+        let blobs: Vec<(&str, bool)> = files.iter().map(|f| (f.path.as_str(), f.blob.is_empty())).collect();
+        assert_eq!(blobs, [("README.md", false), ("src/main.rs", true)]);
+
+        let spec = SnapshotSpec { revision: Some(WORKING_TREE.to_string()), ..SnapshotSpec::new(vec!["2020-04-01".to_string()]) };
+        let stats = blame_snapshots(&backend, &spec, &FileFilter::default(), &mut BlameCache::in_memory()).unwrap();
+        assert_eq!(count(&stats, "2020-04-01", BOB), 2);
+        assert_eq!(stats.authors["2020-04-01"][&Special::Uncommitted.author()], 1);
+
+        // What is staged: unstaged changes and deletions don't count
+        fixture.write("README.md", "x\nstaged\n");
+        fixture.stage("README.md");
+        let spec = SnapshotSpec { revision: Some(STAGED.to_string()), ..spec };
+        let stats = blame_snapshots(&backend, &spec, &FileFilter::default(), &mut BlameCache::in_memory()).unwrap();
+        assert_eq!(count(&stats, "2020-04-01", BOB), 3);
+        assert_eq!(stats.authors["2020-04-01"][&Special::Uncommitted.author()], 1);
+    }
+
+    #[test]
+    fn fingerprints_latest_snapshot() {
+        let fixture = Fixture::new();
+        let spec = SnapshotSpec { fingerprint: GroupBy::Ext, ..spec() };
+        let stats = blame_snapshots(&fixture.backend(), &spec, &FileFilter::default(), &mut BlameCache::in_memory()).unwrap();
+
+        let alice = Author::new(ALICE.0, ALICE.1);
+        let bob = Author::new(BOB.0, BOB.1);
+        assert_eq!(stats.fingerprint[".rs"][&alice], 3);
+        assert_eq!(stats.fingerprint[".md"][&alice], 1);
+        assert_eq!(stats.fingerprint[".rs"][&bob], 2);
+        assert_eq!(stats.fingerprint[".js"][&bob], 1);
+    }
+
+    #[test]
+    fn averages_ownership_entropy() {
+        let fixture = Fixture::new();
+        let spec = SnapshotSpec { entropy: true, ..spec() };
+        let stats = blame_snapshots(&fixture.backend(), &spec, &FileFilter::default(), &mut BlameCache::in_memory()).unwrap();
+
+        assert_eq!(stats.entropy["2020-02-01"], EntropyTotal { sum: 0.0, files: 2 });
+        // main.rs is shared 3:2 by then; README.md and vendor/lib.js have one owner
+        let total = stats.entropy["2020-04-01"];
+        assert_eq!(total.files, 3);
+        assert!((total.sum - 0.9710).abs() < 1e-4);
+    }
+
+    #[test]
+    fn counts_dominated_files() {
+        let fixture = Fixture::new();
+        let spec = SnapshotSpec { dominance: Some(0.5), ..spec() };
+        let stats = blame_snapshots(&fixture.backend(), &spec, &FileFilter::default(), &mut BlameCache::in_memory()).unwrap();
+
+        assert_eq!(stats.concentration["2020-02-01"], Concentration { files: 2, dominated: 2 });
+        // main.rs is shared 3:2, so Alice's 60% is over the threshold
+        assert_eq!(stats.concentration["2020-04-01"], Concentration { files: 3, dominated: 3 });
+        let spec = SnapshotSpec { dominance: Some(0.6), ..spec };
+        let stats = blame_snapshots(&fixture.backend(), &spec, &FileFilter::default(), &mut BlameCache::in_memory()).unwrap();
+        assert_eq!(stats.concentration["2020-04-01"], Concentration { files: 3, dominated: 2 });
+    }
+
+    #[test]
+    fn counts_stale_lines() {
+        let fixture = Fixture::new();
+        let spec = SnapshotSpec { stale_after: Some(30), ..spec() };
+        let stats = blame_snapshots(&fixture.backend(), &spec, &FileFilter::default(), &mut BlameCache::in_memory()).unwrap();
+
+        // By March, only Bob's February lines are recent
+        assert_eq!(stats.staleness["2020-03-01"], Staleness { lines: 8, stale: 5 });
+        assert_eq!(stats.staleness["2020-04-01"], Staleness { lines: 7, stale: 7 });
+    }
+
+    #[test]
+    fn handles_unicode_paths_and_binary_files() {
+        let fixture = Fixture::new();
+        let files: [(&str, &[u8]); 2] = [("docs/naïve café.md", b"one\ntwo\n"), ("logo.png", b"\x89PNG\0\0\n\0\x01\n")];
+        fixture.commit_files(&files, ALICE, "2020-04-15T12:00:00", "Add docs and logo");
+        let spec = SnapshotSpec::new(vec!["2020-05-01".to_string()]);
+        let stats = blame_snapshots(&fixture.backend(), &spec, &FileFilter::default(), &mut BlameCache::in_memory()).unwrap();
+        assert_eq!(count(&stats, "2020-05-01", ALICE), 4 + 2);
+    }
+
+    #[test]
+    fn counts_binary_files_by_file() {
+        let fixture = Fixture::new();
+        fixture.commit_files(&[("logo.png", b"\x89PNG\0\0\n\0\x01\n"), ("art/icon.png", b"\0\0")], BOB, "2020-04-15T12:00:00", "Add art");
+        let spec = SnapshotSpec { binary_metric: BinaryMetric::Files, ..SnapshotSpec::new(vec!["2020-05-01".to_string()]) };
+        let stats = blame_snapshots(&fixture.backend(), &spec, &FileFilter::default(), &mut BlameCache::in_memory()).unwrap();
+        // Bob's 3 lines and a line per image
+        assert_eq!(count(&stats, "2020-05-01", BOB), 3 + 2);
+
+        // Also when they're excluded as binary
+        let filter = FileFilter::new(&Config::default(), true, &[], &[]).unwrap();
+        let stats = blame_snapshots(&fixture.backend(), &spec, &filter, &mut BlameCache::in_memory()).unwrap();
+        assert_eq!(count(&stats, "2020-05-01", BOB), 3 + 2);
+        assert!(!stats.excluded.contains_key("logo.png"));
+    }
+
+    #[test]
+    fn skips_excluded_files() {
+        let fixture = Fixture::new();
+        let filter = FileFilter::new(&Config::default(), false, &["vendor/".to_string()], &[]).unwrap();
+        let stats = blame_snapshots(&fixture.backend(), &spec(), &filter, &mut BlameCache::in_memory()).unwrap();
+
+        assert_eq!(count(&stats, "2020-04-01", BOB), 2);
+        assert!(stats.excluded.contains_key("vendor/lib.js"));
+    }
+
+    #[test]
+    fn keeps_dates_without_eligible_files() {
+        let fixture = Fixture::new();
+        let filter = FileFilter::new(&Config::default(), false, &["src/".to_string(), "README.md".to_string()], &[]).unwrap();
+        let spec = SnapshotSpec::new(vec!["2020-02-01".to_string(), "2020-03-01".to_string()]);
+        let stats = blame_snapshots(&fixture.backend(), &spec, &filter, &mut BlameCache::in_memory()).unwrap();
+
+        // Only Bob's vendored lib is left by the second date
+        assert!(stats.authors["2020-02-01"].is_empty());
+        assert_eq!(count(&stats, "2020-03-01", BOB), 1);
+        let rules = vec!["excluded by --exclude (cli README.md)".to_string(), "excluded by --exclude (cli src/)".to_string()];
+        assert_eq!(stats.ineligible, [("2020-02-01".to_string(), rules)]);
+    }
+
+    #[test]
+    fn logs_exclusions() {
+        let fixture = Fixture::new();
+        let log = fixture.dir.join("exclusions.jsonl").to_string_lossy().to_string();
+        let config = Config::parse("[files]\nexclude = [\"vendor/\"]").unwrap();
+        let filter = FileFilter::new(&config, false, &["README.md".to_string()], &[]).unwrap();
+        let spec = SnapshotSpec { exclusion_log: Some(log.clone()), ..SnapshotSpec::new(vec!["2020-02-01".to_string(), "2020-03-01".to_string()]) };
+        blame_snapshots(&fixture.backend(), &spec, &filter, &mut BlameCache::in_memory()).unwrap();
+
+        let text = std::fs::read_to_string(&log).unwrap();
+        let lines: Vec<&str> = text.lines().collect();
+        assert_eq!(lines.len(), 3);
+        assert!(lines[0].starts_with("{\"date\": \"2020-02-01\", \"revision\": \""));
+        assert!(lines[0].ends_with("\"path\": \"README.md\", \"rule\": \"README.md\", \"reason\": \"excluded by --exclude\", \"source\": \"cli\", \"lines\": 2, \"bytes\": 4}"));
+        assert!(lines[1].starts_with("{\"date\": \"2020-03-01\"") && lines[1].contains("\"path\": \"README.md\""));
+        assert!(lines[2].ends_with("\"path\": \"vendor/lib.js\", \"rule\": \"vendor/\", \"reason\": \"excluded by config\", \"source\": \"config\", \"lines\": 1, \"bytes\": 2}"));
+    }
+
+    #[test]
+    fn measures_coverage() {
+        let fixture = Fixture::new();
+        let filter = FileFilter::new(&Config::default(), false, &["vendor/".to_string(), "*.png".to_string()], &[]).unwrap();
+        fixture.commit_files(&[("logo.png", b"\x89PNG\0\0\n\0\x01\n")], ALICE, "2020-04-15T12:00:00", "Add logo");
+        let spec = SnapshotSpec { coverage: true, ..SnapshotSpec::new(vec!["2020-03-01".to_string(), "2020-05-01".to_string()]) };
+        let stats = blame_snapshots(&fixture.backend(), &spec, &filter, &mut BlameCache::in_memory()).unwrap();
+
+        // The latest snapshot's: 5 lines of main.rs and 1 of the README, 1 of
+        // vendor/lib.js and none of the binary
+        let coverage = stats.coverage.unwrap();
+        assert_eq!(coverage, Coverage { date: "2020-05-01".to_string(), counted: 6, excluded: 1 });
+        assert!(coverage.check(85.0).is_ok());
+        assert!(matches!(coverage.check(90.0), Err(Error::LowCoverage { .. })));
+    }
+
+    #[test]
+    fn limits_to_scope() {
+        let fixture = Fixture::new();
+        let spec = SnapshotSpec { scope: vec!["src".to_string()], ..spec() };
+        let stats = blame_snapshots(&fixture.backend(), &spec, &FileFilter::default(), &mut BlameCache::in_memory()).unwrap();
+
+        assert_eq!(count(&stats, "2020-04-01", ALICE), 3);
+        assert_eq!(count(&stats, "2020-04-01", BOB), 2);
+    }
+
+    #[test]
+    fn missing_snapshot_error() {
+        let fixture = Fixture::new();
+        let spec = SnapshotSpec { missing_snapshot: MissingSnapshot::Error, ..spec() };
+        let result = blame_snapshots(&fixture.backend(), &spec, &FileFilter::default(), &mut BlameCache::in_memory());
+        assert!(matches!(result, Err(Error::NoRevision { date, .. }) if date == "2020-01-01"));
+    }
+
+    #[test]
+    fn counts_commits_per_period() {
+        let fixture = Fixture::new();
+        let metrics = [Metric::Commits, Metric::Churn];
+        let [commits, churn] = &activity_snapshots(fixture.backend().as_ref(), &spec(), &metrics, &FileFilter::default()).unwrap()[..] else {
+            panic!("expected one result per metric");
+        };
+
+        assert_eq!(count(commits, "2020-01-01", ALICE), 0);
+        assert_eq!(count(commits, "2020-02-01", ALICE), 1);
+        assert_eq!(count(commits, "2020-03-01", BOB), 1);
+        assert_eq!(count(commits, "2020-04-01", BOB), 1);
+        assert_eq!(count(churn, "2020-02-01", ALICE), 5);
+        assert_eq!(count(churn, "2020-03-01", BOB), 3);
+        assert_eq!(count(churn, "2020-04-01", BOB), 1);
+    }
+
+    #[test]
+    fn estimates_effort_from_sessions() {
+        let fixture = Fixture::new();
+        let backend = fixture.backend();
+        let [effort] = &activity_snapshots(backend.as_ref(), &spec(), &[Metric::Effort], &FileFilter::default()).unwrap()[..] else {
+            panic!("expected one result per metric");
+        };
+        // Commits a month apart are sessions of their own
+        assert_eq!(count(effort, "2020-02-01", ALICE), 1);
+        assert_eq!(count(effort, "2020-04-01", BOB), 1);
+
+        // A pause shorter than the gap counts as work
+        let spec = SnapshotSpec { session_gap: 40 * 86400, ..spec() };
+        let [effort] = &activity_snapshots(backend.as_ref(), &spec, &[Metric::Effort], &FileFilter::default()).unwrap()[..] else {
+            panic!("expected one result per metric");
+        };
+        assert_eq!(count(effort, "2020-03-01", BOB), 1);
+        assert!((695..=697).contains(&count(effort, "2020-04-01", BOB)));
+    }
+
+    #[test]
+    fn weights_lines_by_change_frequency() {
+        let fixture = Fixture::new();
+        let spec = SnapshotSpec { weight: Weight::Hotspot, group_by: GroupBy::Dir, ..spec() };
+        let stats = blame_snapshots(&fixture.backend(), &spec, &FileFilter::default(), &mut BlameCache::in_memory()).unwrap();
+
+        // src/main.rs and README.md changed twice by 2020-04-01, vendor/lib.js once
+        assert_eq!(count(&stats, "2020-02-01", ALICE), 5);
+        assert_eq!(count(&stats, "2020-04-01", ALICE), 3 * 2 + 2);
+        assert_eq!(count(&stats, "2020-04-01", BOB), 2 * 2 + 1);
+        assert_eq!(stats.groups["vendor/"]["2020-04-01"][&Author::new(BOB.0, BOB.1)], 1);
+    }
+
+    #[test]
+    fn groups_by_directory() {
+        let fixture = Fixture::new();
+        let spec = SnapshotSpec { group_by: GroupBy::Dir, ..spec() };
+        let stats = blame_snapshots(&fixture.backend(), &spec, &FileFilter::default(), &mut BlameCache::in_memory()).unwrap();
+
+        let src = &stats.groups["src/"]["2020-04-01"];
+        assert_eq!(src.get(&Author::new(BOB.0, BOB.1)), Some(&2));
+        assert_eq!(stats.groups["vendor/"]["2020-02-01"].len(), 0);
+    }
+}
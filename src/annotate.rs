@@ -0,0 +1,152 @@
+use chrono::{Local, TimeZone};
+
+use crate::backend::Backend;
+use crate::error::{Error, Result};
+use crate::identity::{IdentityResolver, OptOut};
+use crate::report::json_string;
+
+//----
+// Line ownership
+//
+// `annotate` tells who owns each line of one file at one revision, and how old
+// the line is, for review bots that ping the owners of the regions a change
+// touches.  Runs of consecutive lines with the same owner are also listed as
+// ranges.  Owners are resolved like in the reports, opt-outs included.
+
+/// The owner of one line, numbered from 1
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct LineOwner {
+    pub line: usize,
+    pub author: String,
+    /// Canonical email; empty for opted-out authors
+    pub email: String,
+    pub commit: String,
+    /// Author date of the commit, seconds since the epoch
+    pub time: i64,
+}
+
+/// Consecutive lines with the same owner
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct OwnedRange {
+    pub start: usize,
+    pub end: usize,
+    pub author: String,
+    pub email: String,
+    /// Latest author date of the range's lines
+    pub time: i64,
+}
+
+#[derive(Debug, Clone, Default)]
+pub struct Annotation {
+    /// Full commit id that `rev` resolved to
+    pub revision: String,
+    pub file: String,
+    pub lines: Vec<LineOwner>,
+}
+
+/// Blame `file` (relative to the repo's top level) at `rev`
+pub fn annotate(backend: &dyn Backend, rev: &str, file: &str, resolver: &IdentityResolver, opt_out: &OptOut) -> Result<Annotation> {
+    let revision = backend.revision(&Some(rev.to_string()), &None)?
+        .ok_or_else(|| Error::Config(format!("--rev: unknown revision {rev}")))?;
+    let mut blamed = backend.blame_lines(&revision, file)?;
+    for line in &mut blamed {
+        if opt_out.opted_out(&line.author, resolver) {
+            line.author = OptOut::aggregate();
+        }
+    }
+    let names = resolver.display_names(blamed.iter().map(|line| (&line.author, 1)));
+    let lines = blamed.into_iter().enumerate().map(|(i, line)| {
+        let canonical = resolver.resolve(&line.author).author;
+        LineOwner {
+            line: i + 1,
+            author: names.get(&canonical.key()).cloned().unwrap_or(canonical.name.clone()),
+            email: canonical.email,
+            commit: line.commit,
+            time: line.time,
+        }
+    }).collect();
+    return Ok(Annotation { revision, file: file.to_string(), lines });
+}
+
+impl Annotation {
+    pub fn ranges(&self) -> Vec<OwnedRange> {
+        let mut ranges: Vec<OwnedRange> = Vec::new();
+        for line in &self.lines {
+            match ranges.last_mut() {
+                Some(range) if range.author == line.author && range.email == line.email => {
+                    range.end = line.line;
+                    range.time = range.time.max(line.time);
+                },
+                _ => ranges.push(OwnedRange {
+                    start: line.line,
+                    end: line.line,
+                    author: line.author.clone(),
+                    email: line.email.clone(),
+                    time: line.time,
+                }),
+            }
+        }
+        return ranges;
+    }
+}
+
+fn format_day(time: i64) -> String {
+    match Local.timestamp_opt(time, 0).single() {
+        Some(t) => return t.format("%Y-%m-%d").to_string(),
+        None => return String::new(),
+    }
+}
+
+/// Days from `time` to `now`
+fn age_days(time: i64, now: i64) -> i64 {
+    return (now - time).max(0) / 86400;
+}
+
+/// The annotation as JSON, with ages as of `now` (seconds since the epoch)
+pub fn render_json(annotation: &Annotation, now: i64) -> String {
+    let lines: Vec<String> = annotation.lines.iter().map(|line| {
+        format!("    {{\"line\": {}, \"author\": {}, \"email\": {}, \"commit\": {}, \"date\": {}, \"age_days\": {}}}",
+            line.line, json_string(&line.author), json_string(&line.email), json_string(&line.commit),
+            json_string(&format_day(line.time)), age_days(line.time, now))
+    }).collect();
+    let ranges: Vec<String> = annotation.ranges().iter().map(|range| {
+        format!("    {{\"start\": {}, \"end\": {}, \"author\": {}, \"email\": {}, \"last_changed\": {}, \"age_days\": {}}}",
+            range.start, range.end, json_string(&range.author), json_string(&range.email),
+            json_string(&format_day(range.time)), age_days(range.time, now))
+    }).collect();
+    let mut out = String::from("{\n");
+    out.push_str(&format!("  \"revision\": {},\n", json_string(&annotation.revision)));
+    out.push_str(&format!("  \"file\": {},\n", json_string(&annotation.file)));
+    out.push_str(&format!("  \"lines\": [\n{}\n  ],\n", lines.join(",\n")));
+    out.push_str(&format!("  \"ranges\": [\n{}\n  ]\n", ranges.join(",\n")));
+    out.push('}');
+    return out;
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::fixture::{Fixture, ALICE};
+
+    #[test]
+    fn owns_each_line() {
+        let fixture = Fixture::new();
+        let backend = fixture.backend();
+        let resolver = IdentityResolver::default();
+        let annotation = annotate(backend.as_ref(), "HEAD", "src/main.rs", &resolver, &OptOut::default()).unwrap();
+
+        assert_eq!(annotation.revision.len(), 40);
+        assert_eq!(annotation.lines.len(), 5);
+        assert_eq!((annotation.lines[0].author.as_str(), annotation.lines[0].email.as_str()), ALICE);
+        assert_eq!(format_day(annotation.lines[4].time), "2020-02-15");
+        let ranges = annotation.ranges();
+        let ranges: Vec<(usize, usize, &str)> = ranges.iter().map(|r| (r.start, r.end, r.author.as_str())).collect();
+        assert_eq!(ranges, [(1, 3, "Alice"), (4, 5, "Bob")]);
+        assert!(render_json(&annotation, annotation.lines[0].time + 86400 * 10).contains("\"age_days\": 10"));
+
+        let opt_out = OptOut::new(&["bob@example.com".to_string()]).unwrap();
+        let annotation = annotate(backend.as_ref(), "HEAD", "src/main.rs", &resolver, &opt_out).unwrap();
+        assert_eq!(annotation.ranges()[1].author, "Opted-out");
+        assert!(annotate(backend.as_ref(), "no-such-rev", "src/main.rs", &resolver, &opt_out).is_err());
+    }
+}
@@ -0,0 +1,134 @@
+use std::env;
+use std::fs;
+use std::io::{self, Write};
+use std::path::Path;
+use std::process::{Command, Stdio};
+
+//----
+// Encryption at rest
+//
+// With --encrypt-at-rest, every file written through `write` is encrypted, so
+// artifacts left on shared build machines don't reveal who wrote what; a new
+// output file gets that by being written through it, and `read` takes both.
+// The `openssl` command line tool does the work: ChaCha20 with a key derived
+// from a passphrase (PBKDF2, salted), read from a key file or the
+// GIT_AUTHOR_STATS_KEY environment variable.  That is confidentiality only:
+// there is no MAC, so a file changed on disk decrypts to garbage without an
+// error, unless the change hits the check line.
+
+/// Environment variable holding the passphrase, when no key file is given
+pub const KEY_ENV: &str = "GIT_AUTHOR_STATS_KEY";
+
+/// What `openssl enc -salt` starts its output with
+const MAGIC: &[u8] = b"Salted__";
+
+/// Encrypted ahead of the data, to tell a wrong key from damaged data
+const CHECK: &[u8] = b"git-author-stats\n";
+
+#[derive(Debug, Clone)]
+pub struct AtRest {
+    /// Where openssl reads the passphrase: file:PATH or env:KEY_ENV
+    pass: String,
+}
+
+impl AtRest {
+    /// Passphrase from the first line of `key_file`, or else from KEY_ENV
+    pub fn new(key_file: Option<&str>) -> Result<AtRest, String> {
+        if let Some(path) = key_file {
+            if !Path::new(path).is_file() {
+                return Err(format!("key file {path} doesn't exist"));
+            }
+            return Ok(AtRest { pass: format!("file:{path}") });
+        }
+        match env::var_os(KEY_ENV).filter(|key| !key.is_empty()) {
+            Some(_) => return Ok(AtRest { pass: format!("env:{KEY_ENV}") }),
+            None => return Err(format!("--encrypt-at-rest needs --key-file or {KEY_ENV}")),
+        }
+    }
+
+    fn openssl(&self, decrypt: bool, data: &[u8]) -> io::Result<Vec<u8>> {
+        let mut command = Command::new("openssl");
+        command.args(["enc", "-chacha20", "-pbkdf2", "-salt", "-pass", &self.pass]);
+        if decrypt {
+            command.arg("-d");
+        }
+        let mut child = command.stdin(Stdio::piped()).stdout(Stdio::piped()).stderr(Stdio::piped()).spawn()
+            .map_err(|e| io::Error::new(e.kind(), format!("failed to run openssl: {e}")))?;
+        // Feed stdin from another thread, so a full stdout pipe can't deadlock
+        let mut stdin = child.stdin.take().unwrap();
+        let input = data.to_vec();
+        let feeder = std::thread::spawn(move || stdin.write_all(&input));
+        let output = child.wait_with_output()?;
+        let fed = feeder.join().unwrap_or(Ok(()));
+        if !output.status.success() {
+            let action = if decrypt { "decrypt" } else { "encrypt" };
+            let stderr = String::from_utf8_lossy(&output.stderr);
+            return Err(io::Error::other(format!("openssl failed to {action}: {}", stderr.lines().next().unwrap_or(""))));
+        }
+        fed?;
+        return Ok(output.stdout);
+    }
+
+    pub fn encrypt(&self, data: &[u8]) -> io::Result<Vec<u8>> {
+        return self.openssl(false, &[CHECK, data].concat());
+    }
+
+    pub fn decrypt(&self, data: &[u8]) -> io::Result<Vec<u8>> {
+        match self.openssl(true, data)?.strip_prefix(CHECK) {
+            Some(plain) => return Ok(plain.to_vec()),
+            None => return Err(io::Error::new(io::ErrorKind::InvalidData, "can't decrypt, wrong key")),
+        }
+    }
+}
+
+pub fn is_encrypted(data: &[u8]) -> bool {
+    return data.starts_with(MAGIC);
+}
+
+/// Write `data` to `path`, encrypted if `at_rest` is given
+pub fn write<C: AsRef<[u8]>>(path: &Path, data: C, at_rest: Option<&AtRest>) -> io::Result<()> {
+    match at_rest {
+        Some(at_rest) => return fs::write(path, at_rest.encrypt(data.as_ref())?),
+        None => return fs::write(path, data),
+    }
+}
+
+/// Read `path`, decrypting it if it is encrypted.  Encrypted files can't be
+/// read without `at_rest`.
+pub fn read(path: &Path, at_rest: Option<&AtRest>) -> io::Result<Vec<u8>> {
+    let data = fs::read(path)?;
+    match (is_encrypted(&data), at_rest) {
+        (false, _) => return Ok(data),
+        (true, Some(at_rest)) => return at_rest.decrypt(&data),
+        (true, None) => return Err(io::Error::other(format!("{} is encrypted, see --encrypt-at-rest", path.display()))),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::fixture::Fixture;
+
+    #[test]
+    fn round_trip() {
+        let fixture = Fixture::new();
+        let dir = Path::new(fixture.path());
+        fs::write(dir.join("key"), "correct horse\n").unwrap();
+        fs::write(dir.join("other-key"), "battery staple\n").unwrap();
+        let at_rest = AtRest::new(Some(&dir.join("key").to_string_lossy())).unwrap();
+        let path = dir.join("data");
+
+        write(&path, "Jane Roe <jane@example.com>", Some(&at_rest)).unwrap();
+        let stored = fs::read(&path).unwrap();
+        assert!(is_encrypted(&stored));
+        assert!(!String::from_utf8_lossy(&stored).contains("jane"));
+        assert_eq!(read(&path, Some(&at_rest)).unwrap(), b"Jane Roe <jane@example.com>");
+        assert!(read(&path, None).is_err());
+        let other = AtRest::new(Some(&dir.join("other-key").to_string_lossy())).unwrap();
+        assert!(other.decrypt(&stored).is_err());
+
+        write(&path, "plain", None).unwrap();
+        assert_eq!(read(&path, Some(&at_rest)).unwrap(), b"plain");
+        assert!(AtRest::new(Some(&dir.join("missing").to_string_lossy())).is_err());
+    }
+}
@@ -0,0 +1,143 @@
+use hashbrown::HashMap;
+
+use crate::analysis::FileOwnership;
+use crate::identity::IdentityResolver;
+use crate::report::group_digits;
+use crate::Count;
+
+//----
+// Ownership change attribution
+//
+// `explain FROM TO` blames two snapshots and splits each author's change in
+// lines between them by file, so a surprising jump in the matrix comes with
+// the files that made it: a library vendored in, a module someone else
+// rewrote, code moved to a file the author never touched.
+
+/// An author's lines in one file at both snapshots
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct FileDelta {
+    pub path: String,
+    pub before: Count,
+    pub after: Count,
+}
+
+impl FileDelta {
+    pub fn change(&self) -> i64 {
+        return self.after as i64 - self.before as i64;
+    }
+}
+
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct AuthorDelta {
+    pub author: String,
+    pub before: Count,
+    pub after: Count,
+    /// Files whose count changed, largest change first
+    pub files: Vec<FileDelta>,
+}
+
+impl AuthorDelta {
+    pub fn change(&self) -> i64 {
+        return self.after as i64 - self.before as i64;
+    }
+}
+
+/// Each resolved author's change from `before` to `after`, split by file.
+/// Authors with the largest change come first; those whose files didn't
+/// change are left out.
+pub fn attribute(before: &FileOwnership, after: &FileOwnership, resolver: &IdentityResolver) -> Vec<AuthorDelta> {
+    let raw = before.files.iter().chain(&after.files).flat_map(|(_, acnt)| acnt.iter().map(|(author, count)| (author, *count)));
+    let names = resolver.display_names(raw);
+    // Author -> path -> lines before and after
+    let mut counts: HashMap<String, HashMap<&str, [Count; 2]>> = HashMap::new();
+    for (side, snapshot) in [before, after].into_iter().enumerate() {
+        for (path, acnt) in &snapshot.files {
+            for (author, count) in acnt {
+                let key = resolver.resolve(author).author.key();
+                let name = names.get(&key).cloned().unwrap_or(key);
+                counts.entry(name).or_default().entry(path.as_str()).or_insert([0, 0])[side] += count;
+            }
+        }
+    }
+
+    let mut deltas: Vec<AuthorDelta> = counts.into_iter().filter_map(|(author, files)| {
+        let before = files.values().map(|counts| counts[0]).sum();
+        let after = files.values().map(|counts| counts[1]).sum();
+        let mut files: Vec<FileDelta> = files.into_iter()
+            .filter(|(_, [before, after])| before != after)
+            .map(|(path, [before, after])| FileDelta { path: path.to_string(), before, after })
+            .collect();
+        if files.is_empty() {
+            return None;
+        }
+        files.sort_by(|a, b| b.change().abs().cmp(&a.change().abs()).then(a.path.cmp(&b.path)));
+        return Some(AuthorDelta { author, before, after, files });
+    }).collect();
+    deltas.sort_by(|a, b| b.change().abs().cmp(&a.change().abs()).then(a.author.cmp(&b.author)));
+    return deltas;
+}
+
+fn signed(n: i64, separator: &str) -> String {
+    match n > 0 {
+        true => return format!("+{}", group_digits(n, separator)),
+        false => return group_digits(n, separator),
+    }
+}
+
+/// The report: each author's change, then the `top` files that changed it most
+pub fn render_text(deltas: &[AuthorDelta], from: &str, to: &str, top: usize, separator: &str) -> String {
+    let mut out = format!("Lines from {from} to {to}\n");
+    if deltas.is_empty() {
+        out.push_str("No author's lines changed.\n");
+        return out;
+    }
+    let width = deltas.iter().map(|delta| delta.author.chars().count()).max().unwrap_or(0);
+    for delta in deltas {
+        out.push_str(&format!("\n{:<width$}  {} -> {}  {}\n", delta.author,
+            group_digits(delta.before as i64, separator), group_digits(delta.after as i64, separator), signed(delta.change(), separator)));
+        for file in delta.files.iter().take(top) {
+            out.push_str(&format!("  {:>8}  {}\n", signed(file.change(), separator), file.path));
+        }
+        let rest = &delta.files[top.min(delta.files.len())..];
+        if !rest.is_empty() {
+            let change = rest.iter().map(|file| file.change()).sum();
+            let files = match rest.len() {
+                1 => "1 more file".to_string(),
+                n => format!("{n} more files"),
+            };
+            out.push_str(&format!("  {:>8}  ({files})\n", signed(change, separator)));
+        }
+    }
+    return out;
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::analysis::{blame_snapshots, SnapshotSpec};
+    use crate::cache::BlameCache;
+    use crate::filter::FileFilter;
+    use crate::fixture::Fixture;
+
+    #[test]
+    fn explains_changes_by_file() {
+        let fixture = Fixture::new();
+        let backend = fixture.backend();
+        let snapshot = |date: &str| {
+            let spec = SnapshotSpec { keep_files: true, ..SnapshotSpec::new(vec![date.to_string()]) };
+            return blame_snapshots(&backend, &spec, &FileFilter::default(), &mut BlameCache::in_memory()).unwrap().latest.unwrap();
+        };
+        let deltas = attribute(&snapshot("2020-02-01"), &snapshot("2020-04-01"), &IdentityResolver::default());
+        let bob = &deltas[0];
+        assert_eq!((bob.author.as_str(), bob.before, bob.after), ("Bob", 0, 3));
+        assert_eq!(bob.files, [
+            FileDelta { path: "src/main.rs".to_string(), before: 0, after: 2 },
+            FileDelta { path: "vendor/lib.js".to_string(), before: 0, after: 1 },
+        ]);
+        assert_eq!((deltas[1].author.as_str(), deltas[1].change()), ("Alice", -1));
+
+        let text = render_text(&deltas, "2020-02-01", "2020-04-01", 1, ",");
+        assert!(text.starts_with("Lines from 2020-02-01 to 2020-04-01\n\nBob    0 -> 3  +3\n        +2  src/main.rs\n        +1  (1 more file)\n"));
+        assert!(text.contains("\nAlice  5 -> 4  -1\n        -1  README.md\n"));
+    }
+}
@@ -0,0 +1,681 @@
+use hashbrown::HashMap;
+use std::path::Path;
+use std::fs;
+use std::process::Output;
+use std::sync::Arc;
+
+use crate::category::{classify, AuthorCategories, CATEGORIES};
+use crate::error::{Error, Result};
+use crate::identity::{Author, Special};
+use crate::notebook;
+use crate::porcelain;
+use crate::recording;
+use crate::repo::{run, spawn_error, Repo};
+use crate::{debug, warn, AuthorCount, Count};
+
+//----
+// Git backends
+//
+// Everything the analysis needs from git goes through a Backend, so that the
+// subprocess based implementation can be swapped for a native one.  Only the
+// `git` command line one exists: the libgit2 one, and a --backend option to
+// pick it, are still to do and need the git2 crate.
+
+/// Stands for the files as they are on disk, uncommitted changes included,
+/// wherever a backend takes a revision to list, blame or read attributes at
+pub const WORKING_TREE: &str = "working tree";
+
+/// Likewise for the files as they are staged in the index
+pub const STAGED: &str = "index";
+
+pub trait Backend: Send + Sync {
+    /// Latest commit on `branch` (default: HEAD) before `date`; None if there
+    /// is none, or no such branch
+    fn revision(&self, branch: &Option<String>, date: &Option<String>) -> Result<Option<String>>;
+
+    /// The repo's default branch: origin's HEAD if known, otherwise the local HEAD
+    fn default_branch(&self) -> Result<String>;
+
+    /// Short names of the local and remote-tracking branches, sorted
+    fn branches(&self) -> Result<Vec<String>>;
+
+    /// Committer date of `revision`, YYYY-MM-DD in local time like --before
+    fn commit_date(&self, revision: &str) -> Result<String>;
+
+    /// All files tracked at `revision` under the `scope` paths (everything if empty)
+    fn files(&self, revision: &str, scope: &[String]) -> Result<Vec<TreeEntry>>;
+
+    /// Values of the git attributes `attrs` for `paths` as of `revision`.
+    /// Unspecified attributes are left out.
+    fn attributes(&self, revision: &str, paths: &[String], attrs: &[&str]) -> Result<HashMap<String, Vec<(String, String)>>>;
+
+    /// Number of lines of each of `files` at `revision`, without blaming
+    /// them; binary files have none
+    fn line_counts(&self, revision: &str, files: &[TreeEntry]) -> Result<Vec<Count>>;
+
+    /// Whether each of `files` at `revision` looks binary to git
+    fn binary(&self, revision: &str, files: &[TreeEntry]) -> Result<Vec<bool>>;
+
+    /// Size in bytes of each of `files` at `revision`
+    fn sizes(&self, revision: &str, files: &[TreeEntry]) -> Result<Vec<u64>>;
+
+    /// Number of surviving lines per author of `file_path` at `revision`
+    fn blame(&self, revision: &str, file_path: &str) -> Result<FileBlame>;
+
+    /// The commit that last touched each line of `file_path` at `revision`,
+    /// in line order; empty for binary files
+    fn blame_lines(&self, revision: &str, file_path: &str) -> Result<Vec<BlameLine>>;
+
+    /// Non-merge commits reachable from `branch` (default: HEAD) touching the
+    /// `scope` paths, newest first, with the lines each one added and deleted per file
+    fn commit_log(&self, branch: &Option<String>, scope: &[String]) -> Result<Vec<CommitInfo>>;
+
+    /// Renames and copies in the non-merge commits reachable from `branch`
+    /// (default: HEAD) touching the `scope` paths, newest first
+    fn renames(&self, branch: &Option<String>, scope: &[String]) -> Result<Vec<Rename>>;
+
+    /// The merge commit that brought `commit` into the first-parent history of
+    /// `branch`; none if it was committed there directly
+    fn merge_of(&self, commit: &str, branch: &str) -> Result<Option<String>>;
+}
+
+/// Surviving lines of a file per author, also split by the category of the
+/// commit that last touched each line
+#[derive(Debug, Clone, Default)]
+pub struct FileBlame {
+    pub authors: AuthorCount,
+    pub categories: AuthorCategories,
+    /// Lines per author date of their commit (seconds since the epoch), oldest first
+    pub times: Vec<(i64, Count)>,
+}
+
+/// Who last touched one line of a file, and when
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct BlameLine {
+    pub commit: String,
+    pub author: Author,
+    /// Author date, seconds since the epoch
+    pub time: i64,
+}
+
+/// One line of `git blame --line-porcelain` output
+#[derive(Debug, Clone, PartialEq, Eq)]
+struct PorcelainLine<'a> {
+    commit: &'a str,
+    author: Author,
+    time: i64,
+    summary: &'a str,
+    /// The line itself
+    text: &'a str,
+}
+
+/// Lines of `git blame --line-porcelain` output (see `porcelain`), or what's
+/// wrong with it.  Lines without a regular author get a special identity:
+///   - lines of the `shallow` commits, which git marks `boundary` (so does it
+///     the root commit, which is a regular author's)
+///   - lines not committed yet, whose commit is all zeros
+///   - lines whose commit has neither author name nor email
+fn parse_porcelain<'a>(text: &'a str, shallow: &[String]) -> std::result::Result<Vec<PorcelainLine<'a>>, String> {
+    let lines = porcelain::parse(text)?.into_iter().map(|record| {
+        let author = if record.sha.bytes().all(|b| b == b'0') {
+            Special::Uncommitted.author()
+        } else if record.boundary && shallow.iter().any(|c| c == record.sha) {
+            Special::Boundary.author()
+        } else if record.name.is_empty() && record.mail.is_empty() {
+            Special::Unknown.author()
+        } else {
+            Author::new(record.name, record.mail)
+        };
+        PorcelainLine { commit: record.sha, author, time: record.time, summary: record.summary, text: record.text }
+    });
+    return Ok(lines.collect());
+}
+
+/// Whether a file's content looks binary to git: a NUL byte early on
+fn looks_binary(content: &[u8]) -> bool {
+    return content[..content.len().min(8000)].contains(&0);
+}
+
+/// Lines of a file's content, as blame would count them: none if it looks
+/// binary
+fn line_count(content: &[u8]) -> Count {
+    if looks_binary(content) {
+        return 0;
+    }
+    let newlines = content.iter().filter(|&&b| b == b'\n').count();
+    let unterminated = !content.is_empty() && !content.ends_with(b"\n");
+    return (newlines + unterminated as usize) as Count;
+}
+
+/// Blame output that doesn't parse, as a failure of the command
+fn bad_blame(file_path: &str, message: String) -> Error {
+    return Error::Git { command: "blame".to_string(), message: format!("{file_path}: unexpected output, {message}") };
+}
+
+/// Count the lines of `git blame --line-porcelain` output of `file_path`,
+/// per author, category and commit time
+pub fn count_blame(text: &str, file_path: &str, shallow: &[String]) -> Result<FileBlame> {
+    let mut blame = FileBlame::default();
+
+    // Binary content has no lines to speak of
+    if text.lines().any(|x| x.starts_with('\t') && x.contains('\0')) {
+        debug!("{file_path} is binary, not counted");
+        return Ok(blame);
+    }
+
+    let mut lines = parse_porcelain(text, shallow).map_err(|e| bad_blame(file_path, e))?;
+    if notebook::is_notebook(file_path) {
+        let mut keep = notebook::source_lines(lines.iter().map(|line| line.text)).into_iter();
+        lines.retain(|_| keep.next().unwrap_or(false));
+    }
+    let mut times: HashMap<i64, Count> = HashMap::new();
+    for line in lines {
+        blame.categories.entry(line.author.clone()).or_insert([0; CATEGORIES.len()])[classify(line.summary) as usize] += 1;
+        *blame.authors.entry(line.author).or_insert(0) += 1;
+        *times.entry(line.time).or_insert(0) += 1;
+    }
+    blame.times = times.into_iter().collect();
+    blame.times.sort();
+    return Ok(blame);
+}
+
+/// A commit and the files it changed, from `git log --numstat`
+#[derive(Debug, Clone)]
+pub struct CommitInfo {
+    pub author: Author,
+    /// Author date, seconds since the epoch
+    pub time: i64,
+    pub files: Vec<FileChange>,
+}
+
+#[derive(Debug, Clone)]
+pub struct FileChange {
+    pub path: String,
+    /// Lines added and deleted; zero for binary files
+    pub added: Count,
+    pub deleted: Count,
+}
+
+/// A file renamed or copied by a commit, as git's similarity detection sees it
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct Rename {
+    pub commit: String,
+    /// Author date, seconds since the epoch
+    pub time: i64,
+    /// Copied rather than renamed: `from` stays
+    pub copy: bool,
+    /// Percent of the content the two files share
+    pub similarity: u32,
+    pub from: String,
+    pub to: String,
+}
+
+/// The renames and copies in `git log --name-status -z` output with
+/// `%x01%H%x00%at` headers
+fn parse_renames(text: &str) -> Vec<Rename> {
+    let mut renames = Vec::new();
+    let mut commit = ("", 0);
+    let mut fields = text.split('\0').map(|field| field.trim_start_matches('\n'));
+    while let Some(field) = fields.next() {
+        if let Some(hash) = field.strip_prefix('\x01') {
+            // The time, then the status of the first file if there is one
+            let next = fields.next().unwrap_or_default();
+            let (time, status) = next.split_once('\n').unwrap_or((next, ""));
+            commit = (hash, time.parse().unwrap_or(0));
+            if status.is_empty() {
+                continue;
+            }
+            renames.extend(rename(commit, status, &mut fields));
+        } else if !field.is_empty() {
+            renames.extend(rename(commit, field, &mut fields));
+        }
+    }
+    return renames;
+}
+
+/// The rename or copy `status` (R100, C075, ...) of `commit`, reading its
+/// paths from `fields`; other statuses have one path
+fn rename<'a>(commit: (&str, i64), status: &str, fields: &mut impl Iterator<Item = &'a str>) -> Option<Rename> {
+    let copy = match status.chars().next() {
+        Some('R') => false,
+        Some('C') => true,
+        _ => {
+            fields.next();
+            return None;
+        },
+    };
+    let (from, to) = (fields.next()?, fields.next()?);
+    return Some(Rename {
+        commit: commit.0.to_string(),
+        time: commit.1,
+        copy,
+        similarity: status[1..].parse().unwrap_or(0),
+        from: from.to_string(),
+        to: to.to_string(),
+    });
+}
+
+/// A file in a revision's tree
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct TreeEntry {
+    pub path: String,
+    /// Object id of the file's content; empty for a working tree file with
+    /// uncommitted changes, which isn't cached
+    pub blob: String,
+}
+
+/// Runs the git command line tool for every query
+pub struct CliBackend {
+    repo: Arc<Repo>,
+}
+
+impl CliBackend {
+    pub fn new(repo: Arc<Repo>) -> CliBackend {
+        return CliBackend { repo };
+    }
+
+    /// `measure` of the content of each of `files` at `revision`.  Blobs are
+    /// read in one cat-file pass; changed files of the working tree from disk
+    fn measure<T: Clone + Default>(&self, revision: &str, files: &[TreeEntry], measure: fn(&[u8]) -> T) -> Result<Vec<T>> {
+        let mut measures = vec![T::default(); files.len()];
+        let mut batch = Vec::new();
+        for (i, f) in files.iter().enumerate() {
+            match (f.blob.is_empty(), revision) {
+                (false, _) => batch.push((i, f.blob.clone())),
+                (true, STAGED) => batch.push((i, format!(":{}", f.path))),
+                (true, _) => measures[i] = measure(&fs::read(Path::new(&self.repo.root).join(&f.path))?),
+            }
+        }
+        if batch.is_empty() {
+            return Ok(measures);
+        }
+        let input: String = batch.iter().map(|(_, object)| format!("{object}\n")).collect();
+        let cat_file_out = recording::output(self.repo.git().arg("cat-file").arg("--batch"), Some(input.into_bytes()))
+            .map_err(|e| spawn_error("cat-file", e))?;
+        if !cat_file_out.status.success() {
+            return Err(Error::Git {
+                command: "cat-file".to_string(),
+                message: String::from_utf8_lossy(&cat_file_out.stderr).trim().to_string(),
+            });
+        }
+
+        // <object> SP <type> SP <size> LF <contents> LF, or <object> SP missing LF
+        let mut out = &cat_file_out.stdout[..];
+        for (i, _) in batch {
+            let Some(end) = out.iter().position(|&b| b == b'\n') else {
+                break;
+            };
+            let header = String::from_utf8_lossy(&out[..end]).to_string();
+            out = &out[end + 1..];
+            let Some(size) = header.rsplit(' ').next().and_then(|size| size.parse::<usize>().ok()) else {
+                continue;
+            };
+            let size = size.min(out.len());
+            measures[i] = measure(&out[..size]);
+            out = &out[(size + 1).min(out.len())..];
+        }
+        return Ok(measures);
+    }
+
+    /// `git blame` of `file_path` at `revision`, of the file on disk, or of
+    /// its staged content
+    fn blame_output(&self, revision: &str, file_path: &str) -> Result<Output> {
+        let mut cmd = self.repo.git();
+        cmd.arg("blame").arg("--line-porcelain");
+        match revision {
+            WORKING_TREE => {},
+            STAGED => {
+                // blame takes the staged content from a file
+                let content = run(self.repo.git().arg("cat-file").arg("blob").arg(format!(":{file_path}")), "cat-file")?;
+                let contents = self.repo.scratch()?.path("staged");
+                fs::write(&contents, content.stdout)?;
+                let out = run(cmd.arg("--contents").arg(&contents).arg("--").arg(file_path), "blame");
+                let _ = fs::remove_file(&contents);
+                return out;
+            },
+            revision => {
+                cmd.arg(revision);
+            },
+        }
+        return run(cmd.arg("--").arg(file_path), "blame");
+    }
+
+    /// The files of the index, still on disk unless `staged`: HEAD's blob for
+    /// the ones without uncommitted (or with `staged`, staged) changes, so
+    /// they can be cached, and no blob for the others
+    fn uncommitted_files(&self, scope: &[String], staged: bool) -> Result<Vec<TreeEntry>> {
+        let head: HashMap<String, String> = match self.revision(&None, &None)? {
+            Some(head) => self.files(&head, scope)?.into_iter().map(|f| (f.path, f.blob)).collect(),
+            None => HashMap::new(),
+        };
+        let mut diff = self.repo.git();
+        diff.arg("diff").arg("--name-only").arg("-z");
+        if staged {
+            diff.arg("--cached");
+        }
+        let diff_out = run(diff.arg("HEAD").arg("--").args(scope), "diff");
+        let changed: Vec<String> = match diff_out {
+            Ok(out) => String::from_utf8_lossy(&out.stdout).split('\0').filter(|p| !p.is_empty()).map(|p| p.to_string()).collect(),
+            // No commits yet: everything is uncommitted
+            Err(_) => Vec::new(),
+        };
+        let ls_files_out = run(self.repo.git().arg("ls-files").arg("-s").arg("-z").arg("--").args(scope), "ls-files")?;
+        // <mode> SP <object> SP <stage> TAB <path> NUL
+        let mut entries: Vec<TreeEntry> = Vec::new();
+        for record in String::from_utf8_lossy(&ls_files_out.stdout).split('\0') {
+            let Some((meta, path)) = record.split_once('\t') else {
+                continue;
+            };
+            // Submodules can't be blamed; conflicted files are listed once per stage
+            if meta.starts_with("160000") || entries.last().is_some_and(|last| last.path == path) {
+                continue;
+            }
+            if !staged && !Path::new(&self.repo.root).join(path).is_file() {
+                continue;
+            }
+            let blob = match head.get(path) {
+                Some(blob) if !changed.iter().any(|c| c == path) => blob.clone(),
+                _ => String::new(),
+            };
+            entries.push(TreeEntry { path: path.to_string(), blob });
+        }
+        return Ok(entries);
+    }
+}
+
+impl Backend for CliBackend {
+    fn revision(&self, branch: &Option<String>, date: &Option<String>) -> Result<Option<String>> {
+        // git log --format=format:"%H" --before=2023-01-01
+        let mut cmd = self.repo.git();
+        cmd.arg("log");
+        cmd.arg("-n1").arg("--format=format:%H");
+        if let Some(date) = date {
+            cmd.arg(format!("--before={date}"));
+        }
+        if let Some(branch) = branch {
+           cmd.arg(branch);
+        }
+        let cmd_out = recording::output(&mut cmd, None).map_err(|e| spawn_error("log", e))?;
+        match cmd_out.status.success() {
+            // Unknown branch
+            false => return Ok(None),
+            true => {
+                let revision = String::from_utf8_lossy(&cmd_out.stdout).trim().to_string();
+                // No commits before the date
+                if revision.is_empty() {
+                    return Ok(None);
+                }
+                return Ok(Some(revision));
+            },
+        }
+    }
+
+    fn commit_date(&self, revision: &str) -> Result<String> {
+        let log_out = run(self.repo.git().arg("log").arg("-n1").arg("--format=format:%cd").arg("--date=short-local").arg(revision), "log")?;
+        return Ok(String::from_utf8_lossy(&log_out.stdout).trim().to_string());
+    }
+
+    fn default_branch(&self) -> Result<String> {
+        let symref_out = recording::output(
+            self.repo.git()
+                    .arg("symbolic-ref")
+                    .arg("--quiet")
+                    .arg("--short")
+                    .arg("refs/remotes/origin/HEAD"),
+            None,
+        ).map_err(|e| spawn_error("symbolic-ref", e))?;
+        match symref_out.status.success() {
+            false => return Ok("HEAD".to_string()),
+            true => return Ok(String::from_utf8_lossy(&symref_out.stdout).trim().to_string()),
+        }
+    }
+
+    fn branches(&self) -> Result<Vec<String>> {
+        let refs_out = run(
+            self.repo.git()
+                    .arg("for-each-ref")
+                    .arg("--format=%(refname:short)")
+                    .arg("refs/heads")
+                    .arg("refs/remotes"),
+            "for-each-ref",
+        )?;
+        let mut names: Vec<String> = String::from_utf8_lossy(&refs_out.stdout).lines()
+            // origin/HEAD only points at another branch
+            .filter(|name| !name.is_empty() && !name.ends_with("/HEAD"))
+            .map(|name| name.to_string())
+            .collect();
+        names.sort();
+        return Ok(names);
+    }
+
+    fn files(&self, revision: &str, scope: &[String]) -> Result<Vec<TreeEntry>> {
+        match revision {
+            WORKING_TREE => return self.uncommitted_files(scope, false),
+            STAGED => return self.uncommitted_files(scope, true),
+            _ => {},
+        }
+        let ls_tree_out = run(
+            self.repo.git()
+                    .arg("ls-tree")
+                    .arg("-r")
+                    .arg("-z")
+                    .arg(revision)
+                    .arg("--")
+                    .args(scope),
+            "ls-tree",
+        )?;
+        // <mode> SP <type> SP <object> TAB <path> NUL, with paths unquoted
+        let mut entries = Vec::new();
+        for record in ls_tree_out.stdout.split(|&b| b == 0).filter(|r| !r.is_empty()) {
+            let Some(tab) = record.iter().position(|&b| b == b'\t') else {
+                continue;
+            };
+            let meta = String::from_utf8_lossy(&record[..tab]);
+            let mut meta = meta.split(' ');
+            // Submodules show up as "commit" entries and can't be blamed
+            if meta.nth(1) != Some("blob") {
+                continue;
+            }
+            let Some(blob) = meta.next() else {
+                continue;
+            };
+            match String::from_utf8(record[tab + 1..].to_vec()) {
+                Ok(path) => entries.push(TreeEntry { path, blob: blob.to_string() }),
+                Err(e) => warn!("skipping {}: path is not valid UTF-8", String::from_utf8_lossy(e.as_bytes())),
+            }
+        }
+        return Ok(entries);
+    }
+
+    fn attributes(&self, revision: &str, paths: &[String], attrs: &[&str]) -> Result<HashMap<String, Vec<(String, String)>>> {
+        let mut found: HashMap<String, Vec<(String, String)>> = HashMap::new();
+
+        // check-attr reads .gitattributes from the index, so give it a private
+        // index of the snapshot rather than touching the repo's own.  The
+        // working tree's are read from disk, the staged ones from the index.
+        let mut cmd = match revision {
+            WORKING_TREE | STAGED => self.repo.git(),
+            revision => self.repo.git_with_index(revision)?,
+        };
+        cmd.arg("check-attr");
+        if revision != WORKING_TREE {
+            cmd.arg("--cached");
+        }
+        let input: Vec<u8> = paths.iter().flat_map(|p| p.bytes().chain(std::iter::once(0))).collect();
+        let check_attr_out = recording::output(cmd.arg("--stdin").arg("-z").args(attrs), Some(input))
+            .map_err(|e| spawn_error("check-attr", e))?;
+        if !check_attr_out.status.success() {
+            return Err(Error::Git {
+                command: "check-attr".to_string(),
+                message: String::from_utf8_lossy(&check_attr_out.stderr).trim().to_string(),
+            });
+        }
+
+        // <path> NUL <attribute> NUL <info> NUL
+        let out = String::from_utf8_lossy(&check_attr_out.stdout);
+        let fields: Vec<&str> = out.split('\0').collect();
+        for triple in fields.chunks_exact(3) {
+            if triple[2] != "unspecified" {
+                found.entry(triple[0].to_string()).or_default().push((triple[1].to_string(), triple[2].to_string()));
+            }
+        }
+        return Ok(found);
+    }
+
+    fn line_counts(&self, revision: &str, files: &[TreeEntry]) -> Result<Vec<Count>> {
+        return self.measure(revision, files, line_count);
+    }
+
+    fn binary(&self, revision: &str, files: &[TreeEntry]) -> Result<Vec<bool>> {
+        return self.measure(revision, files, looks_binary);
+    }
+
+    fn sizes(&self, revision: &str, files: &[TreeEntry]) -> Result<Vec<u64>> {
+        return self.measure(revision, files, |content| content.len() as u64);
+    }
+
+    fn blame(&self, revision: &str, file_path: &str) -> Result<FileBlame> {
+        let blame_out = self.blame_output(revision, file_path)?;
+        return count_blame(&String::from_utf8_lossy(&blame_out.stdout), file_path, &self.repo.shallow_commits);
+    }
+
+    fn blame_lines(&self, revision: &str, file_path: &str) -> Result<Vec<BlameLine>> {
+        let blame_out = self.blame_output(revision, file_path)?;
+        let auth_lines = String::from_utf8_lossy(&blame_out.stdout);
+        if auth_lines.lines().any(|x| x.starts_with('\t') && x.contains('\0')) {
+            return Ok(Vec::new());
+        }
+
+        let lines = parse_porcelain(&auth_lines, &self.repo.shallow_commits).map_err(|e| bad_blame(file_path, e))?.into_iter()
+            .map(|line| BlameLine { commit: line.commit.to_string(), author: line.author, time: line.time })
+            .collect();
+        return Ok(lines);
+    }
+
+    fn commit_log(&self, branch: &Option<String>, scope: &[String]) -> Result<Vec<CommitInfo>> {
+        let mut cmd = self.repo.git();
+        cmd.arg("-c").arg("core.quotepath=off");
+        cmd.arg("log").arg("--no-merges").arg("--no-renames").arg("--numstat");
+        // \x01 marks the start of each commit's header
+        cmd.arg("--format=format:%x01%aN%x00%aE%x00%at");
+        if let Some(branch) = branch {
+            cmd.arg(branch);
+        }
+        cmd.arg("--").args(scope);
+        let log_out = run(&mut cmd, "log")?;
+
+        let mut commits: Vec<CommitInfo> = Vec::new();
+        for line in String::from_utf8_lossy(&log_out.stdout).lines() {
+            if let Some(header) = line.strip_prefix('\x01') {
+                let fields: Vec<&str> = header.split('\0').collect();
+                if let [name, email, time] = fields[..] {
+                    commits.push(CommitInfo {
+                        author: Author::new(name, email),
+                        time: time.parse().unwrap_or(0),
+                        files: Vec::new(),
+                    });
+                }
+            } else if let Some(commit) = commits.last_mut() {
+                // <added> TAB <deleted> TAB <path>, with "-" counts for binary files
+                let mut fields = line.splitn(3, '\t');
+                if let (Some(added), Some(deleted), Some(path)) = (fields.next(), fields.next(), fields.next()) {
+                    commit.files.push(FileChange {
+                        path: path.to_string(),
+                        added: added.parse().unwrap_or(0),
+                        deleted: deleted.parse().unwrap_or(0),
+                    });
+                }
+            }
+        }
+        return Ok(commits);
+    }
+
+    fn renames(&self, branch: &Option<String>, scope: &[String]) -> Result<Vec<Rename>> {
+        let mut cmd = self.repo.git();
+        // Copies of files the same commit changed; --find-copies-harder would
+        // compare every file of every commit
+        cmd.arg("log").arg("--no-merges").arg("-M").arg("-C").arg("--diff-filter=RC").arg("--name-status").arg("-z");
+        cmd.arg("--format=format:%x01%H%x00%at");
+        if let Some(branch) = branch {
+            cmd.arg(branch);
+        }
+        cmd.arg("--").args(scope);
+        let log_out = run(&mut cmd, "log")?;
+        return Ok(parse_renames(&String::from_utf8_lossy(&log_out.stdout)));
+    }
+
+    fn merge_of(&self, commit: &str, branch: &str) -> Result<Option<String>> {
+        let mut cmd = self.repo.git();
+        // The oldest first-parent descendant has `commit` as its first parent
+        // if it was committed on the branch, or else merged it
+        cmd.arg("rev-list").arg("--first-parent").arg("--ancestry-path").arg("--reverse").arg("--parents");
+        cmd.arg(format!("{commit}..{branch}"));
+        let list_out = run(&mut cmd, "rev-list")?;
+        let text = String::from_utf8_lossy(&list_out.stdout);
+        let Some(oldest) = text.lines().next() else {
+            return Ok(None);
+        };
+        let ids: Vec<&str> = oldest.split(' ').collect();
+        match ids.get(1) {
+            Some(&parent) if parent != commit && ids.len() > 2 => return Ok(Some(ids[0].to_string())),
+            _ => return Ok(None),
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn parses_special_lines() {
+        let root = "1".repeat(40);
+        let shallow = "2".repeat(40);
+        let text = format!("\
+{root} 1 1 1
+author Alice
+author-mail <alice@example.com>
+author-time 100
+summary Initial commit
+boundary
+filename f
+\ta
+{shallow} 2 2 1
+author Bob
+author-mail <bob@example.com>
+author-time 200
+summary Squashed history
+boundary
+filename f
+\tb
+{zeros} 3 3 1
+author Not Committed Yet
+author-mail <not.committed.yet>
+author-time 300
+summary Version of f from f
+filename f
+\tc
+{root} 4 4 1
+author 
+author-mail <>
+author-time 100
+summary Initial commit
+filename f
+\t
+", zeros = "0".repeat(40));
+        let lines = parse_porcelain(&text, std::slice::from_ref(&shallow)).unwrap();
+        let authors: Vec<Author> = lines.iter().map(|line| line.author.clone()).collect();
+        assert_eq!(authors, [Author::new("Alice", "alice@example.com"), Special::Boundary.author(), Special::Uncommitted.author(), Special::Unknown.author()]);
+        assert_eq!((lines[1].commit, lines[1].time, lines[1].summary), (shallow.as_str(), 200, "Squashed history"));
+        assert_eq!(parse_porcelain(&text, &[]).unwrap()[1].author, Author::new("Bob", "bob@example.com"));
+    }
+
+    #[test]
+    fn parses_renames() {
+        let text = "\x01abc\x00200\nR100\0a.txt\0b.txt\0C075\0b.txt\0c.txt\0\n\x01def\x00100\0\n\x01fed\x0050\nR090\0x\0y\0";
+        let renames = parse_renames(text);
+        assert_eq!(renames.len(), 3);
+        assert_eq!(renames[1], Rename { commit: "abc".to_string(), time: 200, copy: true, similarity: 75, from: "b.txt".to_string(), to: "c.txt".to_string() });
+        assert_eq!((renames[2].commit.as_str(), renames[2].time, renames[2].from.as_str()), ("fed", 50, "x"));
+    }
+}
@@ -0,0 +1,162 @@
+use hashbrown::HashSet;
+use std::path::Path;
+
+use crate::html::escape;
+use crate::identity::IdentityResolver;
+use crate::ownership::{self, BUS_FACTOR_SHARE};
+use crate::report::Table;
+use crate::AuthorCount;
+
+//----
+// Badges
+//
+// --badge bus-factor.svg writes a shields.io-style badge of one headline
+// number at the latest date, to embed in a README and regenerate from CI:
+// the bus factor, the top contributor's share of the lines, or the authors
+// with commits in the period before the date.  The file's name picks the
+// number unless it's given as KIND=FILE.  Colors go from green to red as
+// ownership concentrates.
+
+const GREEN: &str = "#4c1";
+const YELLOW: &str = "#dfb317";
+const RED: &str = "#e05d44";
+const BLUE: &str = "#007ec6";
+const LABEL_COLOR: &str = "#555";
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum BadgeKind {
+    BusFactor,
+    TopShare,
+    ActiveAuthors,
+}
+
+const KINDS: [(&str, BadgeKind); 3] = [
+    ("bus-factor", BadgeKind::BusFactor),
+    ("top-share", BadgeKind::TopShare),
+    ("active-authors", BadgeKind::ActiveAuthors),
+];
+
+/// `KIND=FILE`, or a FILE named after its kind such as `bus-factor.svg`
+pub fn parse_badge(text: &str) -> Result<(BadgeKind, String), String> {
+    let kind_of = |name: &str| KINDS.iter().find(|(kind, _)| *kind == name).map(|&(_, kind)| kind);
+    let names = || KINDS.map(|(kind, _)| kind).join(", ");
+    if let Some((name, file)) = text.split_once('=') {
+        let kind = kind_of(name).ok_or_else(|| format!("unknown badge {name:?}, expected one of {}", names()))?;
+        return Ok((kind, file.to_string()));
+    }
+    let stem = Path::new(text).file_stem().map(|stem| stem.to_string_lossy().to_string()).unwrap_or_default();
+    match kind_of(&stem) {
+        Some(kind) => return Ok((kind, text.to_string())),
+        None => return Err(format!("can't tell the badge from {text:?}: name the file {}.svg, or give KIND=FILE", KINDS.map(|(kind, _)| kind).join(".svg, "))),
+    }
+}
+
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct Badge {
+    pub label: String,
+    pub value: String,
+    pub color: &'static str,
+}
+
+/// Canonical authors with a count in `acnt`
+pub fn active_authors(acnt: &AuthorCount, resolver: &IdentityResolver) -> usize {
+    return acnt.iter().filter(|(_, &count)| count > 0).map(|(author, _)| resolver.resolve(author).author.key()).collect::<HashSet<String>>().len();
+}
+
+/// The `kind` badge of the latest date of the lines `table`, with `active`
+/// authors in the period before it
+pub fn badge(kind: BadgeKind, table: &Table, active: usize) -> Badge {
+    let counts: Vec<_> = match table.dates.len().checked_sub(1) {
+        Some(last) => table.rows.iter().map(|row| row.counts[last]).collect(),
+        None => Vec::new(),
+    };
+    match kind {
+        BadgeKind::BusFactor => {
+            let authors = ownership::bus_factor(counts.iter().copied(), BUS_FACTOR_SHARE);
+            let color = match authors {
+                0 | 1 => RED,
+                2 => YELLOW,
+                _ => GREEN,
+            };
+            return Badge { label: "bus factor".to_string(), value: authors.to_string(), color };
+        },
+        BadgeKind::TopShare => {
+            let total: i64 = counts.iter().map(|&count| count as i64).sum();
+            let top = counts.iter().copied().max().unwrap_or(0) as i64;
+            let share = if total > 0 { 100.0 * top as f64 / total as f64 } else { 0.0 };
+            let color = match share {
+                share if share > 50.0 => RED,
+                share if share > 30.0 => YELLOW,
+                _ => GREEN,
+            };
+            return Badge { label: "top contributor".to_string(), value: format!("{share:.0}%"), color };
+        },
+        BadgeKind::ActiveAuthors => return Badge { label: "active authors".to_string(), value: active.to_string(), color: BLUE },
+    }
+}
+
+/// Rough width of `text` in 11px Verdana, as shields.io sizes it
+fn text_width(text: &str) -> usize {
+    return text.chars().count() * 7 + 10;
+}
+
+/// The badge as a standalone SVG document, in shields.io's flat style
+pub fn render_svg(badge: &Badge) -> String {
+    let (label, value) = (escape(&badge.label), escape(&badge.value));
+    let left = text_width(&badge.label);
+    let right = text_width(&badge.value);
+    let width = left + right;
+    let mut out = String::new();
+    out.push_str(&format!("<svg xmlns=\"http://www.w3.org/2000/svg\" width=\"{width}\" height=\"20\" role=\"img\" aria-label=\"{label}: {value}\">\n"));
+    out.push_str(&format!("<title>{label}: {value}</title>\n"));
+    out.push_str("<linearGradient id=\"s\" x2=\"0\" y2=\"100%\"><stop offset=\"0\" stop-color=\"#bbb\" stop-opacity=\".1\"/><stop offset=\"1\" stop-opacity=\".1\"/></linearGradient>\n");
+    out.push_str(&format!("<clipPath id=\"r\"><rect width=\"{width}\" height=\"20\" rx=\"3\" fill=\"#fff\"/></clipPath>\n"));
+    out.push_str("<g clip-path=\"url(#r)\">\n");
+    out.push_str(&format!("<rect width=\"{left}\" height=\"20\" fill=\"{LABEL_COLOR}\"/>\n"));
+    out.push_str(&format!("<rect x=\"{left}\" width=\"{right}\" height=\"20\" fill=\"{}\"/>\n", badge.color));
+    out.push_str(&format!("<rect width=\"{width}\" height=\"20\" fill=\"url(#s)\"/>\n"));
+    out.push_str("</g>\n");
+    out.push_str("<g fill=\"#fff\" text-anchor=\"middle\" font-family=\"Verdana,Geneva,DejaVu Sans,sans-serif\" font-size=\"11\">\n");
+    out.push_str(&format!("<text x=\"{}\" y=\"15\" fill=\"#010101\" fill-opacity=\".3\">{label}</text><text x=\"{}\" y=\"14\">{label}</text>\n", left / 2, left / 2));
+    out.push_str(&format!("<text x=\"{}\" y=\"15\" fill=\"#010101\" fill-opacity=\".3\">{value}</text><text x=\"{}\" y=\"14\">{value}</text>\n", left + right / 2, left + right / 2));
+    out.push_str("</g>\n</svg>\n");
+    return out;
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::report::Row;
+
+    #[test]
+    fn badges_the_latest_date() {
+        assert_eq!(parse_badge("docs/bus-factor.svg"), Ok((BadgeKind::BusFactor, "docs/bus-factor.svg".to_string())));
+        assert_eq!(parse_badge("top-share=owners.svg"), Ok((BadgeKind::TopShare, "owners.svg".to_string())));
+        assert!(parse_badge("owners.svg").unwrap_err().contains("bus-factor.svg, top-share.svg, active-authors.svg"));
+        assert!(parse_badge("size=owners.svg").is_err());
+
+        let row = |author: &str, counts: Vec<i32>| Row { author: author.to_string(), keys: Vec::new(), counts, docs: Vec::new(), margins: Vec::new() };
+        let table = Table {
+            dates: vec!["2020-03-01".to_string(), "2020-04-01".to_string()],
+            rows: vec![row("Alice", vec![10, 55]), row("Bob", vec![0, 30]), row("Carol", vec![5, 15])],
+            totals: vec![15, 100],
+            doc_totals: Vec::new(),
+            groups: Vec::new(),
+            files: Vec::new(),
+            files_at: None,
+            entropy: Vec::new(),
+            bus_factor: Vec::new(),
+            health: Vec::new(),
+            ineligible: Vec::new(),
+            margins: Vec::new(),
+        };
+        assert_eq!(badge(BadgeKind::BusFactor, &table, 0), Badge { label: "bus factor".to_string(), value: "1".to_string(), color: RED });
+        assert_eq!(badge(BadgeKind::TopShare, &table, 0).value, "55%");
+        let active = badge(BadgeKind::ActiveAuthors, &table, 3);
+        assert_eq!((active.value.as_str(), active.color), ("3", BLUE));
+
+        let svg = render_svg(&badge(BadgeKind::BusFactor, &table, 0));
+        assert!(svg.starts_with("<svg xmlns=\"http://www.w3.org/2000/svg\" width=\"97\" height=\"20\" role=\"img\" aria-label=\"bus factor: 1\">"));
+        assert!(svg.contains("<rect x=\"80\" width=\"17\" height=\"20\" fill=\"#e05d44\"/>"));
+    }
+}
@@ -0,0 +1,148 @@
+use std::fmt::Write;
+use std::path::{Path, PathBuf};
+use std::process::Command;
+
+use crate::cache::fingerprint;
+use crate::error::Result;
+use crate::history::Entry;
+use crate::html::{escape, STYLE};
+use crate::i18n::{self, t, tf};
+use crate::repo::run;
+
+//----
+// Batch runs
+//
+// --batch reads repositories from stdin, one path or URL per line like a
+// --repos-file, and writes each one's report as a static site to
+// --output-dir/NAME/, with an index.html linking them all and their headline
+// numbers, so an organization's nightly job is one invocation.  URLs are
+// mirrored into the cache directory and fetched again on later runs.  A repo
+// that fails is listed as failed in the index, and the run still goes on.
+
+/// Whether a --batch line is a URL to clone rather than a local path
+pub fn is_url(entry: &str) -> bool {
+    // scp-like "git@host:org/repo.git" too, but not a Windows drive
+    return entry.contains("://") || entry.split_once(':').is_some_and(|(host, _)| host.contains('@'));
+}
+
+/// The last part of a path or URL, without `.git`, safe as a directory name
+fn base_name(entry: &str) -> String {
+    let last = entry.trim_end_matches(['/', '\\']).rsplit(['/', '\\', ':']).next().unwrap_or("");
+    let name: String = last.strip_suffix(".git").unwrap_or(last).chars()
+        .map(|c| if c.is_alphanumeric() || "-_.".contains(c) { c } else { '_' })
+        .collect();
+    match name.trim_matches('.').is_empty() {
+        true => return "repo".to_string(),
+        false => return name,
+    }
+}
+
+/// The directory under --output-dir of each of `entries`, told apart by a
+/// number where two have the same name
+pub fn output_names(entries: &[String]) -> Vec<String> {
+    let mut names: Vec<String> = Vec::new();
+    for entry in entries {
+        let base = base_name(entry);
+        let mut name = base.clone();
+        let mut n = 1;
+        while names.contains(&name) {
+            n += 1;
+            name = format!("{base}-{n}");
+        }
+        names.push(name);
+    }
+    return names;
+}
+
+/// A local mirror of `url` in `dir`, cloned the first time and fetched after
+pub fn mirror(url: &str, dir: &Path) -> Result<PathBuf> {
+    let path = dir.join(format!("{}-{}.git", base_name(url), &fingerprint(url.as_bytes())[..8]));
+    match path.is_dir() {
+        true => run(Command::new("git").arg("-C").arg(&path).arg("remote").arg("update").arg("--prune"), "remote update")?,
+        false => run(Command::new("git").arg("clone").arg("--quiet").arg("--mirror").arg(url).arg(&path), "clone")?,
+    };
+    return Ok(path);
+}
+
+/// One repository of a batch: its headline numbers, or why it failed
+#[derive(Debug, Clone, PartialEq)]
+pub struct BatchRepo {
+    /// Directory of its report under --output-dir
+    pub name: String,
+    /// Path or URL as given
+    pub source: String,
+    pub outcome: std::result::Result<Option<Entry>, String>,
+}
+
+/// The index page of a batch, one row per repository
+pub fn render_index(repos: &[BatchRepo]) -> String {
+    let mut out = String::new();
+    writeln!(out, "<!DOCTYPE html>\n<html>\n<head>\n<meta charset=\"utf-8\">").unwrap();
+    writeln!(out, "<title>git-author-stats</title>").unwrap();
+    writeln!(out, "<style>\n{STYLE}</style>").unwrap();
+    writeln!(out, "</head>\n<body>").unwrap();
+    writeln!(out, "<h2>{}</h2>\n<table>", escape(&t("repositories-heading"))).unwrap();
+    let columns = ["repository", "date", "lines", "authors", "bus-factor", "top-author"];
+    let headings: Vec<String> = columns.iter().map(|&id| format!("<th>{}</th>", escape(&t(id)))).collect();
+    writeln!(out, "<tr>{}</tr>", headings.join("")).unwrap();
+    for repo in repos {
+        let name = format!("<a href=\"{0}/index.html\">{0}</a>", escape(&repo.name));
+        match &repo.outcome {
+            Ok(Some(entry)) => {
+                let top = match entry.top_author.is_empty() {
+                    true => String::new(),
+                    false => format!("{} ({:.1}%)", escape(&entry.top_author), entry.top_share),
+                };
+                writeln!(out, "<tr><td title=\"{}\">{name}</td><td>{}</td><td class=\"n\">{}</td><td class=\"n\">{}</td><td class=\"n\">{}</td><td>{top}</td></tr>",
+                    escape(&repo.source), escape(&i18n::date_label(&entry.date)), entry.total, entry.authors, entry.bus_factor).unwrap();
+            },
+            Ok(None) => writeln!(out, "<tr><td title=\"{}\">{name}</td><td colspan=\"5\"></td></tr>", escape(&repo.source)).unwrap(),
+            Err(e) => writeln!(out, "<tr><td title=\"{}\">{}</td><td colspan=\"5\">{}</td></tr>",
+                escape(&repo.source), escape(&repo.name), escape(&tf("failed", &[("error", e)]))).unwrap(),
+        }
+    }
+    writeln!(out, "</table>\n</body>\n</html>").unwrap();
+    return out;
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::fixture::Fixture;
+
+    #[test]
+    fn names_and_mirrors_the_batch() {
+        assert!(is_url("https://github.com/org/app.git") && is_url("git@github.com:org/app.git"));
+        assert!(!is_url("/srv/git/app") && !is_url("C:\\repos\\app"));
+        let entries: Vec<String> = ["https://github.com/org/app.git", "/srv/git/app/", "git@host:other/app", "/srv/git/my lib", "/"]
+            .map(|entry| entry.to_string()).to_vec();
+        assert_eq!(output_names(&entries), ["app", "app-2", "app-3", "my_lib", "repo"]);
+
+        let fixture = Fixture::new();
+        let url = format!("file://{}", fixture.dir.display());
+        let clones = fixture.dir.join("clones");
+        let path = mirror(&url, &clones).unwrap();
+        assert!(path.join("HEAD").is_file());
+        assert_eq!(mirror(&url, &clones).unwrap(), path);
+
+        let entry = Entry {
+            time: 0,
+            repo: "app".to_string(),
+            metric: "lines".to_string(),
+            date: "2020-03-01".to_string(),
+            total: 6,
+            authors: 2,
+            bus_factor: 1,
+            top_author: "Alice".to_string(),
+            top_share: 66.7,
+        };
+        let repos = [
+            BatchRepo { name: "app".to_string(), source: entries[0].clone(), outcome: Ok(Some(entry)) },
+            BatchRepo { name: "app-2".to_string(), source: entries[1].clone(), outcome: Err("not a git repository".to_string()) },
+        ];
+        let page = render_index(&repos);
+        assert!(page.contains("<tr><td title=\"https://github.com/org/app.git\"><a href=\"app/index.html\">app</a></td><td>2020-03-01</td>\
+            <td class=\"n\">6</td><td class=\"n\">2</td><td class=\"n\">1</td><td>Alice (66.7%)</td></tr>"));
+        assert!(page.contains("<td title=\"/srv/git/app/\">app-2</td><td colspan=\"5\">failed: not a git repository</td>"));
+    }
+}
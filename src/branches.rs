@@ -0,0 +1,272 @@
+use hashbrown::HashMap;
+use regex::Regex;
+use std::io::{self, Write};
+
+use crate::cli_args::{Args, OutputFormat};
+use crate::html;
+use crate::i18n::{self, t, tf};
+use crate::msgpack;
+use crate::report::{csv_lines, digit_separator, display_tables, group_digits, json_string, render_json, Report};
+use crate::yaml;
+use crate::{csv_field, Count, Date};
+
+//----
+// Branch comparison
+//
+// --branches main,develop,'release/*' analyzes each branch on the same dates
+// and reports them side by side, branch × author × date, to follow how long
+// lived release branches drift apart.  A `*` matches any part of a branch name
+// but a `/`, among the local and remote-tracking branches; other names are
+// taken as given.  The comparison view holds every author's count at the
+// latest date on each branch, and how far it is from the first branch's.
+
+/// The branches `patterns` name among `available`, in the order of the
+/// patterns, each once
+pub fn expand(patterns: &[String], available: &[String]) -> Result<Vec<String>, String> {
+    let mut names: Vec<String> = Vec::new();
+    for pattern in patterns.iter().map(|pattern| pattern.trim()).filter(|pattern| !pattern.is_empty()) {
+        let matched: Vec<String> = match pattern.contains('*') {
+            false => vec![pattern.to_string()],
+            true => {
+                let regex = Regex::new(&format!("^{}$", regex::escape(pattern).replace("\\*", "[^/]*"))).expect("escaped patterns are valid");
+                available.iter().filter(|name| regex.is_match(name)).cloned().collect()
+            },
+        };
+        if matched.is_empty() {
+            return Err(format!("no branch matches {pattern}"));
+        }
+        for name in matched {
+            if !names.contains(&name) {
+                names.push(name);
+            }
+        }
+    }
+    if names.len() < 2 {
+        return Err(format!("expected at least two branches to compare, got {}", names.join(", ")));
+    }
+    return Ok(names);
+}
+
+/// One author's count at the comparison's date on each branch
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct ComparisonRow {
+    pub author: String,
+    pub counts: Vec<Count>,
+}
+
+impl ComparisonRow {
+    /// How far each branch's count is from the first branch's
+    pub fn differences(&self) -> Vec<Count> {
+        return self.counts.iter().map(|&count| count - self.counts[0]).collect();
+    }
+}
+
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct Comparison {
+    pub date: Date,
+    pub branches: Vec<String>,
+    /// Largest count on any branch first
+    pub rows: Vec<ComparisonRow>,
+}
+
+/// Each author's count at the latest date of any of the `branches`;
+/// none if no branch has a date
+pub fn compare(branches: &[(String, Report)]) -> Option<Comparison> {
+    let date = branches.iter().filter_map(|(_, report)| report.table.dates.last()).max()?.clone();
+    let mut rows: Vec<ComparisonRow> = Vec::new();
+    // By canonical key, which is the same on every branch even when the
+    // displayed name isn't
+    let mut by_key: HashMap<String, usize> = HashMap::new();
+    for (i, (_, report)) in branches.iter().enumerate() {
+        let table = &report.table;
+        let Some(column) = table.dates.iter().position(|d| *d == date) else {
+            continue;
+        };
+        for row in &table.rows {
+            let key = row.keys.first().cloned().unwrap_or_else(|| row.author.clone());
+            let index = *by_key.entry(key).or_insert_with(|| {
+                rows.push(ComparisonRow { author: row.author.clone(), counts: vec![0; branches.len()] });
+                rows.len() - 1
+            });
+            rows[index].counts[i] += row.counts[column];
+        }
+    }
+    rows.retain(|row| row.counts.iter().any(|&count| count != 0));
+    rows.sort_by(|a, b| b.counts.iter().max().cmp(&a.counts.iter().max()).then_with(|| a.author.cmp(&b.author)));
+    return Some(Comparison { date, branches: branches.iter().map(|(name, _)| name.clone()).collect(), rows });
+}
+
+/// The text of each cell of `row`: the count, and its difference from the
+/// first branch's if there is one
+pub fn cells(row: &ComparisonRow, separator: &str) -> Vec<String> {
+    return row.counts.iter().zip(row.differences()).map(|(&count, difference)| match difference {
+        0 => group_digits(count as i64, separator),
+        _ => format!("{} ({difference:+})", group_digits(count as i64, separator)),
+    }).collect();
+}
+
+fn display_comparison(opt: &Args, comparison: &Comparison, separator: &str) {
+    let (date, base) = (i18n::date_label(&comparison.date), &comparison.branches[0]);
+    let rows: Vec<(&str, Vec<String>)> = comparison.rows.iter().map(|row| (row.author.as_str(), cells(row, separator))).collect();
+    match opt.format {
+        OutputFormat::Markdown => {
+            println!("## {}\n", tf("branches-heading", &[("date", &date), ("base", base)]));
+            let names: Vec<String> = comparison.branches.iter().map(|name| name.replace('|', "\\|")).collect();
+            println!("| {} | {} |", t("author"), names.join(" | "));
+            println!("|---|{}", "---:|".repeat(names.len()));
+            for (author, cells) in rows {
+                println!("| {} | {} |", author.replace('|', "\\|"), cells.join(" | "));
+            }
+        },
+        _ => {
+            println!("== {} ==", tf("branches-at", &[("date", &date), ("base", base)]));
+            let long_auth = rows.iter().map(|(author, _)| author.chars().count()).max().unwrap_or(0).max(t("author").chars().count());
+            let widths: Vec<usize> = comparison.branches.iter().enumerate()
+                .map(|(i, name)| rows.iter().map(|(_, cells)| cells[i].chars().count()).chain([name.chars().count()]).max().unwrap_or(0))
+                .collect();
+            print!("{:<long_auth$}, ", t("author"));
+            for (name, width) in comparison.branches.iter().zip(&widths) {
+                print!("{name:>width$}, ");
+            }
+            println!();
+            for (author, cells) in rows {
+                print!("{author:<long_auth$}, ");
+                for (cell, width) in cells.iter().zip(&widths) {
+                    print!("{cell:>width$}, ");
+                }
+                println!();
+            }
+            println!();
+        },
+    }
+}
+
+/// The CSV of every branch, its rows prefixed by the branch, then the
+/// comparison's counts
+fn comparison_csv(opt: &Args, branches: &[(String, Report)], comparison: Option<&Comparison>) -> String {
+    let mut out = String::new();
+    for (i, (name, report)) in branches.iter().enumerate() {
+        let mut lines = csv_lines(opt, report).into_iter();
+        if i > 0 {
+            out.push('\n');
+        }
+        out.push_str(&format!("branch,{}\n", lines.next().unwrap_or_default()));
+        lines.for_each(|line| out.push_str(&format!("{},{line}\n", csv_field(name))));
+    }
+    if let Some(comparison) = comparison {
+        let names: Vec<String> = comparison.branches.iter().map(|name| csv_field(name)).collect();
+        out.push_str(&format!("\ndate,author,{}\n", names.join(",")));
+        for row in &comparison.rows {
+            let counts: Vec<String> = row.counts.iter().map(|count| count.to_string()).collect();
+            out.push_str(&format!("{},{},{}\n", comparison.date, csv_field(&row.author), counts.join(",")));
+        }
+    }
+    return out;
+}
+
+/// One JSON document holding the report of each branch, by branch name, and
+/// the comparison
+pub fn render_json_document(opt: &Args, branches: &[(String, Report)], comparison: Option<&Comparison>) -> String {
+    let documents: Vec<String> = branches.iter().map(|(name, report)| {
+        let document = render_json(opt, report).trim_end().replace('\n', "\n    ");
+        format!("    {}: {document}", json_string(name))
+    }).collect();
+    let mut out = format!("{{\n  \"branches\": {{\n{}\n  }}", documents.join(",\n"));
+    if let Some(comparison) = comparison {
+        let names: Vec<String> = comparison.branches.iter().map(|name| json_string(name)).collect();
+        let rows: Vec<String> = comparison.rows.iter().map(|row| {
+            let numbers = |values: &[Count]| values.iter().map(|value| value.to_string()).collect::<Vec<String>>().join(", ");
+            format!("      {{\"author\": {}, \"counts\": [{}], \"differences\": [{}]}}", json_string(&row.author), numbers(&row.counts), numbers(&row.differences()))
+        }).collect();
+        let authors = match rows.is_empty() {
+            true => "[]".to_string(),
+            false => format!("[\n{}\n    ]", rows.join(",\n")),
+        };
+        out.push_str(&format!(",\n  \"comparison\": {{\n    \"date\": {},\n    \"base\": {},\n    \"branches\": [{}],\n    \"authors\": {authors}\n  }}",
+            json_string(&comparison.date), names[0], names.join(", ")));
+    }
+    out.push_str("\n}\n");
+    return out;
+}
+
+/// Print the report of each of `branches`, then the comparison
+pub fn display_branches(opt: &Args, branches: &[(String, Report)]) {
+    let separator = digit_separator(opt);
+    let comparison = compare(branches);
+    match opt.format {
+        OutputFormat::Html => print!("{}", html::render_branches(opt, branches, comparison.as_ref(), &separator)),
+        OutputFormat::Json => print!("{}", render_json_document(opt, branches, comparison.as_ref())),
+        OutputFormat::Yaml => print!("{}", yaml::from_json(&render_json_document(opt, branches, comparison.as_ref()))),
+        OutputFormat::Msgpack => {
+            let json = render_json_document(opt, branches, comparison.as_ref());
+            io::stdout().write_all(&msgpack::from_json(&json)).expect("failed printing to stdout");
+        },
+        OutputFormat::Csv => print!("{}", comparison_csv(opt, branches, comparison.as_ref())),
+        OutputFormat::CodeMaat => unreachable!("--branches refuses --format code-maat"),
+        OutputFormat::Table | OutputFormat::Markdown => {
+            for (name, report) in branches {
+                match opt.format {
+                    OutputFormat::Markdown => println!("## {name}\n"),
+                    _ => println!("=== {name} ==="),
+                }
+                display_tables(opt, report, &separator);
+                if opt.format == OutputFormat::Markdown {
+                    println!();
+                }
+            }
+            if let Some(comparison) = &comparison {
+                display_comparison(opt, comparison, &separator);
+            }
+        },
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::report::{Row, Table};
+
+    #[test]
+    fn compares_branches_at_the_latest_date() {
+        let available: Vec<String> = ["develop", "main", "origin/release/2.0", "release/1.0", "release/1.1", "release/old/0.9"]
+            .map(|name| name.to_string()).to_vec();
+        let patterns = |text: &str| text.split(',').map(|name| name.to_string()).collect::<Vec<String>>();
+        assert_eq!(expand(&patterns("main,release/*,main"), &available), Ok(vec!["main".to_string(), "release/1.0".to_string(), "release/1.1".to_string()]));
+        assert_eq!(expand(&patterns("main,v1.0"), &available), Ok(vec!["main".to_string(), "v1.0".to_string()]));
+        assert_eq!(expand(&patterns("main,hotfix/*"), &available), Err("no branch matches hotfix/*".to_string()));
+        assert!(expand(&patterns("main,main"), &available).is_err());
+
+        let report = |dates: &[&str], rows: Vec<(&str, &str, Vec<Count>)>| Report {
+            table: Table {
+                dates: dates.iter().map(|date| date.to_string()).collect(),
+                rows: rows.into_iter().map(|(author, key, counts)| Row { author: author.to_string(), keys: vec![key.to_string()], counts, docs: Vec::new(), margins: Vec::new() }).collect(),
+                totals: Vec::new(),
+                doc_totals: Vec::new(),
+                groups: Vec::new(),
+                files: Vec::new(),
+                files_at: None,
+                entropy: Vec::new(),
+                bus_factor: Vec::new(),
+                health: Vec::new(),
+                ineligible: Vec::new(),
+                margins: Vec::new(),
+            },
+            groups: Vec::new(),
+            categories: Vec::new(),
+            fingerprint: Vec::new(),
+            coupling: Vec::new(),
+        };
+        let branches = vec![
+            ("main".to_string(), report(&["2020-01-01", "2020-02-01"], vec![("Alice", "alice@example.com", vec![3, 5]), ("Bob", "bob@example.com", vec![1, 0])])),
+            ("release/1.0".to_string(), report(&["2020-01-01", "2020-02-01"], vec![("Alice A.", "alice@example.com", vec![3, 3]), ("Carol", "carol@example.com", vec![0, 8])])),
+            // Without a snapshot at the latest date
+            ("develop".to_string(), report(&["2020-01-01"], vec![("Alice", "alice@example.com", vec![9])])),
+        ];
+        let comparison = compare(&branches).unwrap();
+        assert_eq!(comparison.date, "2020-02-01");
+        let rows: Vec<(&str, &[Count])> = comparison.rows.iter().map(|row| (row.author.as_str(), row.counts.as_slice())).collect();
+        assert_eq!(rows, [("Carol", &[0, 8, 0][..]), ("Alice", &[5, 3, 0][..])]);
+        assert_eq!(cells(&comparison.rows[0], ","), ["0", "8 (+8)", "0"]);
+        assert_eq!(comparison.rows[1].differences(), [0, -2, -5]);
+    }
+}
@@ -0,0 +1,206 @@
+use hashbrown::HashMap;
+use std::path::Path;
+
+use crate::cli_args::GroupBy;
+use crate::identity::Author;
+use crate::{AuthorCount, AuthorPerformance, Count, Date};
+
+//----
+// Breakdown by area of the codebase
+//
+// With --group-by, the per-file counts that make up each snapshot are also
+// summed per top-level directory, extension or language, reusing the same
+// blame and log results.  The config can add languages, or move extensions
+// from one to another.  --auto-depth groups directories deeper down, as deep
+// as it takes to get a useful number of groups.
+
+/// Counts per author per date, per group name
+pub type Breakdown = HashMap<String, AuthorPerformance>;
+
+/// Lines per author per group, at one date
+pub type Fingerprint = HashMap<String, AuthorCount>;
+
+/// Languages of files known by name
+const NAMED_FILES: [(&str, &str); 4] = [
+    ("Makefile", "Makefile"),
+    ("GNUmakefile", "Makefile"),
+    ("Dockerfile", "Dockerfile"),
+    ("CMakeLists.txt", "CMake"),
+];
+
+/// Languages by (lowercase) file extension
+const EXTENSIONS: [(&str, &[&str]); 21] = [
+    ("Rust", &["rs"]),
+    ("Python", &["py"]),
+    ("C", &["c", "h"]),
+    ("C++", &["cc", "cpp", "cxx", "hh", "hpp", "hxx"]),
+    ("C#", &["cs"]),
+    ("Go", &["go"]),
+    ("Java", &["java"]),
+    ("Kotlin", &["kt", "kts"]),
+    ("Swift", &["swift"]),
+    ("JavaScript", &["js", "mjs", "cjs", "jsx"]),
+    ("TypeScript", &["ts", "tsx"]),
+    ("Ruby", &["rb"]),
+    ("PHP", &["php"]),
+    ("Shell", &["sh", "bash", "zsh"]),
+    ("Verilog", &["v", "sv", "svh", "vh"]),
+    ("VHDL", &["vhd", "vhdl"]),
+    ("Tcl", &["tcl", "xdc"]),
+    ("HTML", &["html", "htm"]),
+    ("CSS", &["css", "scss"]),
+    ("Text", &["md", "rst", "txt"]),
+    ("Config", &["toml", "yaml", "yml", "json", "ini", "cfg"]),
+];
+
+/// Maps files to languages: the config's `[languages]` table, e.g.
+/// `SystemVerilog = ["sv", "svh"]`, then the built-in names and extensions
+#[derive(Debug, Clone, Default)]
+pub struct Languages {
+    /// Language and its extensions, lowercase without the dot
+    custom: Vec<(String, Vec<String>)>,
+}
+
+impl Languages {
+    pub fn new(custom: &[(String, Vec<String>)]) -> Languages {
+        let custom = custom.iter()
+            .map(|(name, exts)| (name.clone(), exts.iter().map(|ext| ext.trim().trim_start_matches('.').to_lowercase()).collect()))
+            .collect();
+        return Languages { custom };
+    }
+
+    /// Language of a file, from its name or extension
+    pub fn of(&self, path: &str) -> String {
+        let path = Path::new(path);
+        let name = path.file_name().map(|x| x.to_string_lossy().to_string()).unwrap_or_default();
+        let ext = path.extension().map(|x| x.to_string_lossy().to_lowercase()).unwrap_or_default();
+        if let Some((language, _)) = self.custom.iter().find(|(_, exts)| exts.contains(&ext)) {
+            return language.clone();
+        }
+        if let Some((_, language)) = NAMED_FILES.iter().find(|(file, _)| *file == name) {
+            return language.to_string();
+        }
+        let language = EXTENSIONS.iter().find(|(_, exts)| exts.contains(&ext.as_str())).map(|(language, _)| *language);
+        return language.unwrap_or("Other").to_string();
+    }
+
+    /// Whether a language is called `name`, ignoring case
+    pub fn knows(&self, name: &str) -> bool {
+        let builtin = EXTENSIONS.iter().map(|(language, _)| *language).chain(NAMED_FILES.iter().map(|(_, language)| *language));
+        return self.custom.iter().map(|(language, _)| language.as_str()).chain(builtin).chain(["Other"])
+            .any(|language| language.eq_ignore_ascii_case(name));
+    }
+}
+
+/// Group a file belongs to, or None when not grouping
+pub fn group_of(group_by: GroupBy, languages: &Languages, path: &str) -> Option<String> {
+    match group_by {
+        GroupBy::None => return None,
+        GroupBy::Dir => return Some(dir_group(path, 1)),
+        GroupBy::Ext => {
+            match Path::new(path).extension() {
+                Some(ext) => return Some(format!(".{}", ext.to_string_lossy().to_lowercase())),
+                None => return Some("(no extension)".to_string()),
+            }
+        },
+        GroupBy::Language => return Some(languages.of(path)),
+        // Author attributes group rows, not files; the paths come from the
+        // spec, see path_group
+        GroupBy::Title | GroupBy::Location | GroupBy::CostCenter | GroupBy::Path => return None,
+    }
+}
+
+/// The directory of `path` at most `depth` levels down, or "(top level)"
+pub fn dir_group(path: &str, depth: usize) -> String {
+    let dirs: Vec<&str> = path.split('/').collect();
+    match dirs.len() {
+        1 => return "(top level)".to_string(),
+        n => return format!("{}/", dirs[..depth.clamp(1, n - 1)].join("/")),
+    }
+}
+
+/// Parse a --auto-depth range of group counts, "MIN-MAX"
+pub fn parse_group_range(text: &str) -> Result<(usize, usize), String> {
+    let range = text.split_once('-').and_then(|(min, max)| Some((min.trim().parse::<usize>().ok()?, max.trim().parse::<usize>().ok()?)));
+    match range {
+        Some((min, max)) if 0 < min && min <= max => return Ok((min, max)),
+        _ => return Err(format!("expected a range of group counts such as 5-20, not {text}")),
+    }
+}
+
+/// The shallowest directory depth giving `paths` from `min` to `max` groups,
+/// or else the one coming closest
+pub fn auto_depth(paths: &[&str], (min, max): (usize, usize)) -> usize {
+    let mut best = (usize::MAX, 1);
+    let mut previous = 0;
+    for depth in 1.. {
+        let mut groups: Vec<String> = paths.iter().map(|path| dir_group(path, depth)).collect();
+        groups.sort();
+        groups.dedup();
+        // Every path is already in its own directory's group
+        if groups.len() == previous {
+            break;
+        }
+        previous = groups.len();
+        let off = match groups.len() {
+            n if n < min => min - n,
+            n if n > max => n - max,
+            _ => return depth,
+        };
+        if off < best.0 {
+            best = (off, depth);
+        }
+    }
+    return best.1;
+}
+
+/// Which of the analyzed `paths` a file is in, the deepest one if they nest
+pub fn path_group(paths: &[String], path: &str) -> Option<String> {
+    let within = |scope: &&String| path == scope.as_str() || path.strip_prefix(scope.trim_end_matches('/')).is_some_and(|rest| rest.starts_with('/'));
+    return paths.iter().filter(within).max_by_key(|scope| scope.len()).cloned();
+}
+
+pub fn add(breakdown: &mut Breakdown, group: &str, date: &Date, author: &Author, count: Count) {
+    let perf = breakdown.entry_ref(group).or_default();
+    *perf.entry_ref(date).or_default().entry(author.clone()).or_insert(0) += count;
+}
+
+/// Add the counts of `other` into `into`
+pub fn add_fingerprint(into: &mut Fingerprint, other: &Fingerprint) {
+    for (group, acnt) in other {
+        let sum = into.entry_ref(group).or_default();
+        for (author, count) in acnt {
+            *sum.entry(author.clone()).or_insert(0) += count;
+        }
+    }
+}
+
+/// Give every group a column for every date in `perf`, so group tables line
+/// up with each other
+pub fn fill_dates(breakdown: &mut Breakdown, perf: &AuthorPerformance) {
+    for group_perf in breakdown.values_mut() {
+        for date in perf.keys() {
+            group_perf.entry_ref(date).or_insert_with(AuthorCount::new);
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn picks_a_depth() {
+        let paths = ["README.md", "services/api/src/main.rs", "services/api/README.md", "services/web/index.js",
+            "services/auth/lib.rs", "libs/util/a.rs", "libs/log/b.rs"];
+        assert_eq!(dir_group(paths[1], 2), "services/api/");
+        assert_eq!(dir_group(paths[1], 9), "services/api/src/");
+        assert_eq!(dir_group(paths[0], 2), "(top level)");
+        assert_eq!(auto_depth(&paths, (1, 3)), 1);
+        assert_eq!(auto_depth(&paths, (4, 8)), 2);
+        // Never more than 7 groups: the deepest comes closest
+        assert_eq!(auto_depth(&paths, (10, 20)), 3);
+        assert_eq!(parse_group_range("3-12"), Ok((3, 12)));
+        assert!(parse_group_range("12-3").is_err() && parse_group_range("0-3").is_err() && parse_group_range("5").is_err());
+    }
+}
@@ -0,0 +1,531 @@
+use hashbrown::HashMap;
+use std::env;
+use std::fs;
+use std::io::{BufRead, Write};
+use std::path::{Path, PathBuf};
+use std::time::{Duration, SystemTime, UNIX_EPOCH};
+
+use crate::at_rest::{self, AtRest};
+use crate::backend::TreeEntry;
+use crate::category::{AuthorCategories, CategoryCounts};
+use crate::identity::Author;
+use crate::{warn, AuthorCount, Count};
+
+//----
+// Blame cache
+//
+// Most files don't change between snapshots, so blame results are cached by
+// the file's blob id (and path, since identical content at two paths has
+// different histories).  The cache can be persisted to disk so repeated runs
+// on the same repo only blame files that changed since the last run.
+//
+// Each repo's cache is a directory under the cache dir holding the cache file
+// and a stats file with the hits and misses of every run.  `cache ls` and
+// `cache stats` read them; `cache gc` drops the entries runs used least
+// recently until the caches fit a size budget.
+
+const CACHE_HEADER: &str = "git-author-stats blame cache v6";
+const CACHE_FILE_NAME: &str = "blame.cache";
+const STATS_FILE_NAME: &str = "stats";
+
+#[derive(Debug, Clone)]
+pub struct CachedBlame {
+    pub authors: AuthorCount,
+    pub categories: AuthorCategories,
+    /// Lines per commit author date, oldest first
+    pub times: Vec<(i64, Count)>,
+    /// Wall time it took to blame the file
+    pub cost: Duration,
+}
+
+/// A cached blame, and when a run last used it
+#[derive(Debug, Clone)]
+struct Entry {
+    blame: CachedBlame,
+    /// Seconds since the epoch
+    used: u64,
+}
+
+/// Blob id and path of a cached file
+type Key = (String, String);
+
+#[derive(Debug, Default)]
+pub struct BlameCache {
+    entries: HashMap<Key, Entry>,
+    /// File the cache is persisted to, if any
+    file: Option<PathBuf>,
+    /// Fingerprint of the .mailmap used while blaming; git applies it to blame output
+    mailmap: String,
+    /// Start of this run, stamped on the entries it uses
+    now: u64,
+    /// Encrypt the file with this, with --encrypt-at-rest
+    at_rest: Option<AtRest>,
+    pub hits: usize,
+    pub misses: usize,
+}
+
+/// Default location of the on-disk caches: $XDG_CACHE_HOME or ~/.cache
+pub fn default_cache_dir() -> PathBuf {
+    if let Some(dir) = env::var_os("XDG_CACHE_HOME").filter(|d| !d.is_empty()) {
+        return PathBuf::from(dir).join("git-author-stats");
+    }
+    let home = env::var_os("HOME").unwrap_or_else(|| ".".into());
+    return PathBuf::from(home).join(".cache").join("git-author-stats");
+}
+
+/// Directory name for a repo's cache, readable enough to tell repos apart
+fn repo_dir_name(repo_root: &str) -> String {
+    let name: String = repo_root
+        .chars()
+        .map(|c| if c.is_ascii_alphanumeric() || c == '-' || c == '.' { c } else { '_' })
+        .collect();
+    return name.trim_matches('_').to_string();
+}
+
+fn now() -> u64 {
+    return SystemTime::now().duration_since(UNIX_EPOCH).map(|d| d.as_secs()).unwrap_or(0);
+}
+
+/// FNV-1a, stable across runs and platforms unlike std's hasher
+pub fn fingerprint(data: &[u8]) -> String {
+    let mut hash: u64 = 0xcbf29ce484222325;
+    for b in data {
+        hash ^= *b as u64;
+        hash = hash.wrapping_mul(0x100000001b3);
+    }
+    return format!("{hash:016x}");
+}
+
+fn escape(field: &str) -> String {
+    return field.replace('\\', "\\\\").replace('\t', "\\t").replace('\n', "\\n");
+}
+
+fn unescape(field: &str) -> String {
+    let mut out = String::with_capacity(field.len());
+    let mut chars = field.chars();
+    while let Some(c) = chars.next() {
+        match c {
+            '\\' => match chars.next() {
+                Some('t') => out.push('\t'),
+                Some('n') => out.push('\n'),
+                Some(c) => out.push(c),
+                None => {},
+            },
+            c => out.push(c),
+        }
+    }
+    return out;
+}
+
+impl BlameCache {
+    /// A cache that only lives for this run
+    pub fn in_memory() -> BlameCache {
+        return BlameCache::default();
+    }
+
+    /// Load the repo's on-disk cache from `cache_dir`.  A missing or outdated
+    /// cache file just starts an empty cache; one that can't be read (or
+    /// decrypted) is left alone, and this run isn't cached.
+    pub fn open(cache_dir: &Path, repo_root: &str, mailmap: &str, at_rest: Option<&AtRest>) -> BlameCache {
+        let file = cache_dir.join(repo_dir_name(repo_root)).join(CACHE_FILE_NAME);
+        let mut cache = BlameCache {
+            file: Some(file.clone()),
+            mailmap: mailmap.to_string(),
+            now: now(),
+            at_rest: at_rest.cloned(),
+            ..Default::default()
+        };
+        match read_cache(&file, at_rest) {
+            // Git baked the old mailmap into the cached names
+            Ok(Some((cached_mailmap, entries))) if cached_mailmap == mailmap => cache.entries = entries,
+            Ok(_) => {},
+            Err(e) if e.kind() == std::io::ErrorKind::NotFound => {},
+            Err(e) => {
+                warn!("not using the blame cache: {e}");
+                cache.file = None;
+            },
+        }
+        return cache;
+    }
+
+    pub fn get(&mut self, entry: &TreeEntry) -> Option<&CachedBlame> {
+        match self.entries.get_mut(&(entry.blob.clone(), entry.path.clone())) {
+            Some(found) => {
+                self.hits += 1;
+                found.used = self.now;
+                return Some(&found.blame);
+            },
+            None => {
+                self.misses += 1;
+                return None;
+            },
+        }
+    }
+
+    /// Like get, without counting a hit or miss
+    pub fn peek(&self, entry: &TreeEntry) -> Option<&CachedBlame> {
+        return self.entries.get(&(entry.blob.clone(), entry.path.clone())).map(|found| &found.blame);
+    }
+
+    pub fn insert(&mut self, entry: &TreeEntry, blame: CachedBlame) {
+        self.entries.insert((entry.blob.clone(), entry.path.clone()), Entry { blame, used: self.now });
+    }
+
+    /// Most recently measured blame cost of each path, for scheduling
+    pub fn costs(&self) -> HashMap<String, Duration> {
+        let mut costs = HashMap::new();
+        for ((_, path), found) in &self.entries {
+            costs.insert(path.clone(), found.blame.cost);
+        }
+        return costs;
+    }
+
+    /// Write the cache back to disk, if it is persistent, and add this run's
+    /// hits and misses to the repo's stats
+    pub fn save(&self) -> std::io::Result<()> {
+        let Some(file) = &self.file else {
+            return Ok(());
+        };
+        write_cache(file, &self.mailmap, &self.entries, self.at_rest.as_ref())?;
+        let stats_file = file.with_file_name(STATS_FILE_NAME);
+        let mut stats = read_stats(&stats_file);
+        stats.add(CacheStats { runs: 1, hits: self.hits as u64, misses: self.misses as u64 });
+        fs::write(stats_file, format!("runs\t{}\nhits\t{}\nmisses\t{}\n", stats.runs, stats.hits, stats.misses))?;
+        return Ok(());
+    }
+}
+
+/// One entry as written to the cache file
+fn write_entry<W: Write>(out: &mut W, (blob, path): &Key, found: &Entry) -> std::io::Result<()> {
+    let blame = &found.blame;
+    let times: Vec<String> = blame.times.iter().map(|(time, count)| format!("{time}:{count}")).collect();
+    writeln!(out, "{blob}\t{}\t{}\t{}\t{}\t{}", escape(path), blame.cost.as_micros(), found.used, blame.authors.len(), times.join(" "))?;
+    for (author, count) in &blame.authors {
+        let categories = blame.categories.get(author).copied().unwrap_or_default();
+        let categories: Vec<String> = categories.iter().map(|c| c.to_string()).collect();
+        writeln!(out, "{count}\t{}\t{}\t{}", escape(&author.name), escape(&author.email), categories.join(" "))?;
+    }
+    return Ok(());
+}
+
+fn write_cache(file: &Path, mailmap: &str, entries: &HashMap<Key, Entry>, at_rest: Option<&AtRest>) -> std::io::Result<()> {
+    fs::create_dir_all(file.parent().unwrap())?;
+    let mut out = Vec::new();
+    writeln!(out, "{CACHE_HEADER}")?;
+    writeln!(out, "mailmap\t{mailmap}")?;
+    // Working tree files with uncommitted changes have no blob to key them by
+    for (key, found) in entries.iter().filter(|((blob, _), _)| !blob.is_empty()) {
+        write_entry(&mut out, key, found)?;
+    }
+    // Write to a temporary file first so an interrupted run can't leave a truncated cache
+    let tmp = file.with_extension(format!("tmp{}", std::process::id()));
+    at_rest::write(&tmp, out, at_rest)?;
+    fs::rename(&tmp, file)?;
+    return Ok(());
+}
+
+/// The mailmap fingerprint and entries of a cache file, None if it is from
+/// another version or damaged
+fn read_cache(file: &Path, at_rest: Option<&AtRest>) -> std::io::Result<Option<(String, HashMap<Key, Entry>)>> {
+    return Ok(parse_cache(&at_rest::read(file, at_rest)?));
+}
+
+fn parse_cache(data: &[u8]) -> Option<(String, HashMap<Key, Entry>)> {
+    let mut lines = data.lines();
+    if lines.next()?.ok()? != CACHE_HEADER {
+        return None;
+    }
+    let mailmap = lines.next()?.ok()?.strip_prefix("mailmap\t")?.to_string();
+
+    let mut entries = HashMap::new();
+    while let Some(line) = lines.next() {
+        let line = line.ok()?;
+        let fields: Vec<&str> = line.split('\t').collect();
+        let [blob, path, cost, used, count, times] = fields[..] else {
+            return None;
+        };
+        let times = times.split(' ').filter(|t| !t.is_empty()).map(|t| {
+            let (time, count) = t.split_once(':')?;
+            return Some((time.parse().ok()?, count.parse().ok()?));
+        }).collect::<Option<Vec<(i64, Count)>>>()?;
+        let mut authors = AuthorCount::new();
+        let mut categories = AuthorCategories::new();
+        for _ in 0..count.parse::<usize>().ok()? {
+            let line = lines.next()?.ok()?;
+            let fields: Vec<&str> = line.split('\t').collect();
+            let [lines, name, email, category_lines] = fields[..] else {
+                return None;
+            };
+            let author = Author::new(&unescape(name), &unescape(email));
+            let mut counts = CategoryCounts::default();
+            let mut category_lines = category_lines.split(' ');
+            for count in counts.iter_mut() {
+                *count = category_lines.next()?.parse().ok()?;
+            }
+            categories.insert(author.clone(), counts);
+            authors.insert(author, lines.parse().ok()?);
+        }
+        let cost = Duration::from_micros(cost.parse().ok()?);
+        let blame = CachedBlame { authors, categories, times, cost };
+        entries.insert((blob.to_string(), unescape(path)), Entry { blame, used: used.parse().ok()? });
+    }
+    return Some((mailmap, entries));
+}
+
+/// Runs, hits and misses totalled over every run using a cache
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq)]
+pub struct CacheStats {
+    pub runs: u64,
+    pub hits: u64,
+    pub misses: u64,
+}
+
+impl CacheStats {
+    pub fn add(&mut self, other: CacheStats) {
+        self.runs += other.runs;
+        self.hits += other.hits;
+        self.misses += other.misses;
+    }
+
+    /// Share of lookups found in the cache, None before any lookup
+    pub fn hit_ratio(&self) -> Option<f64> {
+        match self.hits + self.misses {
+            0 => return None,
+            lookups => return Some(self.hits as f64 / lookups as f64),
+        }
+    }
+}
+
+/// A missing or unreadable stats file counts nothing
+fn read_stats(file: &Path) -> CacheStats {
+    let mut stats = CacheStats::default();
+    for line in fs::read_to_string(file).unwrap_or_default().lines() {
+        let (name, value) = line.split_once('\t').unwrap_or((line, ""));
+        let value = value.parse().unwrap_or(0);
+        match name {
+            "runs" => stats.runs = value,
+            "hits" => stats.hits = value,
+            "misses" => stats.misses = value,
+            _ => {},
+        }
+    }
+    return stats;
+}
+
+/// One repo's on-disk cache
+#[derive(Debug, Clone)]
+pub struct RepoCache {
+    /// Directory name, derived from the repo's path
+    pub name: String,
+    pub bytes: u64,
+    /// None if the cache file is from another version, damaged or locked
+    pub entries: Option<usize>,
+    /// Encrypted, and no key (or another key) was given to read it
+    pub locked: bool,
+    /// Latest time a run used an entry, in seconds since the epoch
+    pub last_used: Option<u64>,
+    pub stats: CacheStats,
+}
+
+/// The repo caches in `cache_dir`, by name; none if it doesn't exist
+pub fn list(cache_dir: &Path, at_rest: Option<&AtRest>) -> std::io::Result<Vec<RepoCache>> {
+    let mut caches = Vec::new();
+    let dirs = match fs::read_dir(cache_dir) {
+        Ok(dirs) => dirs,
+        Err(e) if e.kind() == std::io::ErrorKind::NotFound => return Ok(caches),
+        Err(e) => return Err(e),
+    };
+    for dir in dirs {
+        let dir = dir?.path();
+        let file = dir.join(CACHE_FILE_NAME);
+        let Ok(metadata) = fs::metadata(&file) else {
+            continue;
+        };
+        let read = read_cache(&file, at_rest);
+        let locked = read.is_err();
+        let entries = read.ok().flatten().map(|(_, entries)| entries);
+        caches.push(RepoCache {
+            name: dir.file_name().unwrap_or_default().to_string_lossy().to_string(),
+            bytes: metadata.len(),
+            entries: entries.as_ref().map(|entries| entries.len()),
+            last_used: entries.as_ref().and_then(|entries| entries.values().map(|found| found.used).max()),
+            locked,
+            stats: read_stats(&dir.join(STATS_FILE_NAME)),
+        });
+    }
+    caches.sort_by(|a, b| a.name.cmp(&b.name));
+    return Ok(caches);
+}
+
+/// What a gc removed
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq)]
+pub struct Collected {
+    pub entries: usize,
+    pub bytes: u64,
+}
+
+/// Drop the least recently used entries, across every repo's cache, until
+/// the cache files total at most `max_bytes`.  Outdated and damaged cache
+/// files are removed first, since no run can use them; locked ones are
+/// left alone.
+pub fn gc(cache_dir: &Path, max_bytes: u64, at_rest: Option<&AtRest>) -> std::io::Result<Collected> {
+    let mut collected = Collected::default();
+    let mut caches = Vec::new();
+    let mut total = 0;
+    for cache in list(cache_dir, at_rest)? {
+        let file = cache_dir.join(&cache.name).join(CACHE_FILE_NAME);
+        match read_cache(&file, at_rest) {
+            Ok(Some((mailmap, entries))) => {
+                total += cache.bytes;
+                caches.push((file, mailmap, entries));
+            },
+            Ok(None) => {
+                fs::remove_file(&file)?;
+                collected.bytes += cache.bytes;
+            },
+            Err(e) => warn!("skipping cache {}: {e}", cache.name),
+        }
+    }
+
+    // Oldest first, with the size each entry takes in its file
+    let mut candidates = Vec::new();
+    for (i, (_, _, entries)) in caches.iter().enumerate() {
+        for (key, found) in entries {
+            let mut text = Vec::new();
+            write_entry(&mut text, key, found)?;
+            candidates.push((found.used, text.len() as u64, i, key.clone()));
+        }
+    }
+    candidates.sort_by(|a, b| a.0.cmp(&b.0).then(a.3.cmp(&b.3)));
+
+    let mut changed = vec![false; caches.len()];
+    for (_, bytes, i, key) in candidates {
+        if total <= max_bytes {
+            break;
+        }
+        caches[i].2.remove(&key);
+        changed[i] = true;
+        total = total.saturating_sub(bytes);
+        collected.entries += 1;
+        collected.bytes += bytes;
+    }
+    for ((file, mailmap, entries), changed) in caches.iter().zip(changed) {
+        if changed {
+            write_cache(file, mailmap, entries, at_rest)?;
+        }
+    }
+    return Ok(collected);
+}
+
+/// A size like 500M or 2G (powers of 1024), or a number of bytes
+pub fn parse_size(text: &str) -> Result<u64, String> {
+    let text = text.trim();
+    let digits = text.find(|c: char| !c.is_ascii_digit()).unwrap_or(text.len());
+    let (number, unit) = text.split_at(digits);
+    let number: u64 = number.parse().map_err(|_| format!("invalid size: {text}"))?;
+    let unit = unit.trim().to_ascii_uppercase();
+    let scale: u64 = match unit.strip_suffix('B').unwrap_or(&unit) {
+        "" => 1,
+        "K" => 1 << 10,
+        "M" => 1 << 20,
+        "G" => 1 << 30,
+        "T" => 1 << 40,
+        _ => return Err(format!("invalid size: {text}, expected a number with an optional K, M, G or T suffix")),
+    };
+    return number.checked_mul(scale).ok_or(format!("size too large: {text}"));
+}
+
+/// A byte count the way parse_size reads it back, e.g. 1.5G or 100B
+pub fn format_size(bytes: u64) -> String {
+    let units = [("T", 1u64 << 40), ("G", 1 << 30), ("M", 1 << 20), ("K", 1 << 10)];
+    for (suffix, scale) in units {
+        if bytes >= scale {
+            return format!("{:.1}{suffix}", bytes as f64 / scale as f64);
+        }
+    }
+    return format!("{bytes}B");
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::fixture::Fixture;
+
+    fn entry(blob: &str, path: &str) -> TreeEntry {
+        return TreeEntry { blob: blob.to_string(), path: path.to_string() };
+    }
+
+    fn blame(lines: Count) -> CachedBlame {
+        let authors = AuthorCount::from([(Author::new("alice", "a@x.com"), lines)]);
+        return CachedBlame { authors, categories: AuthorCategories::new(), times: vec![(1_600_000_000, lines)], cost: Duration::from_millis(1) };
+    }
+
+    #[test]
+    fn sizes() {
+        assert_eq!(parse_size("512"), Ok(512));
+        assert_eq!(parse_size("2K"), Ok(2048));
+        assert_eq!(parse_size("3mb"), Ok(3 << 20));
+        assert!(parse_size("1 GiB").is_err());
+        assert!(parse_size("G").is_err());
+        assert_eq!(format_size(1536), "1.5K");
+        assert_eq!(format_size(100), "100B");
+    }
+
+    #[test]
+    fn persists_stats_and_collects_oldest_entries() {
+        let fixture = Fixture::new();
+        let dir = Path::new(fixture.path()).join("cache");
+
+        let mut cache = BlameCache::open(&dir, "/repo", "m", None);
+        cache.now = 100;
+        cache.insert(&entry("old", "a.rs"), blame(1));
+        cache.now = 200;
+        cache.insert(&entry("new", "b.rs"), blame(2));
+        assert!(cache.get(&entry("new", "b.rs")).is_some());
+        assert!(cache.get(&entry("gone", "c.rs")).is_none());
+        cache.save().unwrap();
+
+        let caches = list(&dir, None).unwrap();
+        assert_eq!(caches.len(), 1);
+        assert_eq!(caches[0].name, "repo");
+        assert_eq!(caches[0].entries, Some(2));
+        assert_eq!(caches[0].last_used, Some(200));
+        assert_eq!(caches[0].stats, CacheStats { runs: 1, hits: 1, misses: 1 });
+
+        // Another mailmap starts over
+        let other = BlameCache::open(&dir, "/repo", "changed", None);
+        assert!(other.peek(&entry("new", "b.rs")).is_none());
+
+        let collected = gc(&dir, caches[0].bytes - 1, None).unwrap();
+        assert_eq!(collected.entries, 1);
+        let mut cache = BlameCache::open(&dir, "/repo", "m", None);
+        assert!(cache.get(&entry("old", "a.rs")).is_none());
+        assert!(cache.get(&entry("new", "b.rs")).is_some());
+        assert_eq!(gc(&dir, 0, None).unwrap().entries, 1);
+    }
+
+    #[test]
+    fn encrypted_cache() {
+        let fixture = Fixture::new();
+        let dir = Path::new(fixture.path()).join("cache");
+        let key = Path::new(fixture.path()).join("key");
+        fs::write(&key, "secret\n").unwrap();
+        let at_rest = AtRest::new(Some(&key.to_string_lossy())).unwrap();
+
+        let mut cache = BlameCache::open(&dir, "/repo", "m", Some(&at_rest));
+        cache.insert(&entry("blob", "a.rs"), blame(3));
+        cache.save().unwrap();
+        let stored = fs::read(dir.join("repo").join(CACHE_FILE_NAME)).unwrap();
+        assert!(at_rest::is_encrypted(&stored));
+
+        let mut cache = BlameCache::open(&dir, "/repo", "m", Some(&at_rest));
+        assert!(cache.get(&entry("blob", "a.rs")).is_some());
+        // Without the key the cache is neither used, overwritten nor collected
+        let cache = BlameCache::open(&dir, "/repo", "m", None);
+        assert!(cache.peek(&entry("blob", "a.rs")).is_none());
+        cache.save().unwrap();
+        assert!(list(&dir, None).unwrap()[0].locked);
+        assert_eq!(gc(&dir, 0, None).unwrap(), Collected::default());
+        assert_eq!(list(&dir, Some(&at_rest)).unwrap()[0].entries, Some(1));
+    }
+}
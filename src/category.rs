@@ -0,0 +1,86 @@
+use hashbrown::HashMap;
+use lazy_static::lazy_static;
+use regex::Regex;
+use std::fmt;
+
+use crate::identity::Author;
+use crate::Count;
+
+//----
+// Commit categories
+//
+// Blamed lines are classified by the conventional-commit prefix of the
+// commit that last touched them ("feat: ...", "fix(parser)!: ...").
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash, PartialOrd, Ord)]
+pub enum Category {
+    Feature,
+    Fix,
+    Refactor,
+    Docs,
+    /// No prefix, or one of the other types (test, chore, build, ...)
+    Other,
+}
+
+pub const CATEGORIES: [Category; 5] = [Category::Feature, Category::Fix, Category::Refactor, Category::Docs, Category::Other];
+
+/// Lines per category, indexed by `Category as usize`
+pub type CategoryCounts = [Count; CATEGORIES.len()];
+
+/// Lines per author per category
+pub type AuthorCategories = HashMap<Author, CategoryCounts>;
+
+impl fmt::Display for Category {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        let name = match self {
+            Category::Feature => "feature",
+            Category::Fix => "fix",
+            Category::Refactor => "refactor",
+            Category::Docs => "docs",
+            Category::Other => "other",
+        };
+        return write!(f, "{name}");
+    }
+}
+
+/// Category of a commit from its summary line
+pub fn classify(summary: &str) -> Category {
+    lazy_static! {
+        static ref RE_PREFIX: Regex = Regex::new(r"^\s*([A-Za-z]+)(?:\([^)]*\))?!?:").unwrap();
+    };
+    let Some(caps) = RE_PREFIX.captures(summary) else {
+        return Category::Other;
+    };
+    match caps[1].to_lowercase().as_str() {
+        "feat" | "feature" => return Category::Feature,
+        "fix" | "bugfix" | "hotfix" => return Category::Fix,
+        "refactor" | "perf" | "style" => return Category::Refactor,
+        "docs" | "doc" => return Category::Docs,
+        _ => return Category::Other,
+    }
+}
+
+/// Add `from` into `into`
+pub fn add_categories(into: &mut AuthorCategories, from: &AuthorCategories) {
+    for (author, counts) in from {
+        let sum = into.entry(author.clone()).or_insert([0; CATEGORIES.len()]);
+        for (total, count) in sum.iter_mut().zip(counts) {
+            *total += count;
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn conventional_prefixes() {
+        assert_eq!(classify("feat: add --jobs"), Category::Feature);
+        assert_eq!(classify("fix(parser)!: handle empty input"), Category::Fix);
+        assert_eq!(classify("perf: faster blame"), Category::Refactor);
+        assert_eq!(classify("Docs: typo"), Category::Docs);
+        assert_eq!(classify("chore: bump deps"), Category::Other);
+        assert_eq!(classify("Fix the thing"), Category::Other);
+    }
+}
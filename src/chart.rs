@@ -0,0 +1,145 @@
+use std::fmt::Write;
+
+use crate::cli_args::{Args, OutputFormat};
+use crate::html::escape;
+use crate::i18n::{self, t};
+use crate::report::Table;
+use crate::Count;
+
+//----
+// Charts
+//
+// --chart draws each author's counts over the sampled dates instead of
+// listing the numbers: a sparkline per row in the terminal formats, and a
+// stacked area chart (SVG) in html.  --chart-svg writes that chart to a file.
+
+const SPARKS: [char; 8] = ['▁', '▂', '▃', '▄', '▅', '▆', '▇', '█'];
+
+/// Fill colors of the chart layers, reused from the start past the end
+pub const PALETTE: [&str; 10] = [
+    "#4e79a7", "#f28e2b", "#e15759", "#76b7b2", "#59a14f",
+    "#edc948", "#b07aa1", "#ff9da7", "#9c755f", "#bab0ac",
+];
+
+const WIDTH: f64 = 800.0;
+const HEIGHT: f64 = 300.0;
+const MARGIN: f64 = 40.0;
+const LEGEND_WIDTH: f64 = 200.0;
+
+/// Values charted per row: the counts, or shares of each date's total with --percent
+fn values(opt: &Args, table: &Table, counts: &[Count]) -> Vec<f64> {
+    return counts.iter().zip(&table.totals).map(|(&count, &total)| {
+        match (opt.as_percent, total) {
+            (false, _) => count as f64,
+            (true, 0) => 0.0,
+            (true, total) => 100.0 * count as f64 / total as f64,
+        }
+    }).collect();
+}
+
+/// One character per value, scaled so `max` is a full block.  Zero is blank.
+pub fn sparkline(values: &[f64], max: f64) -> String {
+    return values.iter().map(|&value| {
+        match value <= 0.0 || max <= 0.0 {
+            true => ' ',
+            false => {
+                let level = ((value / max) * SPARKS.len() as f64).ceil() as usize;
+                SPARKS[level.clamp(1, SPARKS.len()) - 1]
+            },
+        }
+    }).collect();
+}
+
+/// Print one sparkline per author, on a common scale, with the latest value
+pub fn display_sparklines(opt: &Args, table: &Table, separator: &str) {
+    let rows: Vec<Vec<f64>> = table.rows.iter().map(|row| values(opt, table, &row.counts)).collect();
+    let max = rows.iter().flatten().copied().fold(0.0, f64::max);
+    let first = i18n::date_label(table.dates.first().map(|d| d.as_str()).unwrap_or_default());
+    let last = i18n::date_label(table.dates.last().map(|d| d.as_str()).unwrap_or_default());
+    let latest = |i: usize| match table.dates.is_empty() {
+        true => String::new(),
+        false => table.cell(opt, &table.rows[i].counts, table.dates.len() - 1, separator),
+    };
+
+    match opt.format {
+        OutputFormat::Markdown => {
+            println!("| {} | {first} .. {last} | {last} |", t("author"));
+            println!("|---|---|---:|");
+            for (i, row) in table.rows.iter().enumerate() {
+                println!("| {} | `{}` | {} |", row.author.replace('|', "\\|"), sparkline(&rows[i], max), latest(i));
+            }
+        },
+        _ => {
+            let long_auth = table.rows.iter().map(|row| row.author.len()).max().unwrap_or(0).max(t("date").chars().count());
+            println!("{:<long_auth$}  {first} .. {last}", t("date"));
+            for (i, row) in table.rows.iter().enumerate() {
+                println!("{:<long_auth$}  {}  {}", row.author, sparkline(&rows[i], max), latest(i));
+            }
+        },
+    }
+    println!();
+}
+
+/// Stacked area chart of the table's rows, as a standalone SVG document
+pub fn svg(opt: &Args, table: &Table) -> String {
+    let layers: Vec<Vec<f64>> = table.rows.iter().map(|row| values(opt, table, &row.counts)).collect();
+    let columns = table.dates.len();
+    let mut tops = vec![0.0; columns];
+    let mut bands = Vec::new();
+    for layer in &layers {
+        let bottoms = tops.clone();
+        tops.iter_mut().zip(layer).for_each(|(top, value)| *top += value);
+        bands.push((bottoms, tops.clone()));
+    }
+    let max = tops.iter().copied().fold(0.0, f64::max);
+
+    let plot_width = WIDTH - 2.0 * MARGIN;
+    let plot_height = HEIGHT - 2.0 * MARGIN;
+    let x = |i: usize| MARGIN + match columns {
+        0 | 1 => 0.0,
+        n => plot_width * i as f64 / (n - 1) as f64,
+    };
+    let y = |value: f64| HEIGHT - MARGIN - if max > 0.0 { plot_height * value / max } else { 0.0 };
+
+    let mut out = String::new();
+    writeln!(out, "<svg xmlns=\"http://www.w3.org/2000/svg\" width=\"{}\" height=\"{HEIGHT}\" font-family=\"sans-serif\" font-size=\"11\">",
+        WIDTH + LEGEND_WIDTH).unwrap();
+    for (i, (bottoms, tops)) in bands.iter().enumerate() {
+        let upper = (0..columns).map(|c| format!("{:.1},{:.1}", x(c), y(tops[c])));
+        let lower = (0..columns).rev().map(|c| format!("{:.1},{:.1}", x(c), y(bottoms[c])));
+        let points: Vec<String> = upper.chain(lower).collect();
+        writeln!(out, "<polygon fill=\"{}\" points=\"{}\"><title>{}</title></polygon>",
+            PALETTE[i % PALETTE.len()], points.join(" "), escape(&table.rows[i].author)).unwrap();
+    }
+
+    // Axes, with the first and last date and the top of the scale
+    writeln!(out, "<line x1=\"{MARGIN}\" y1=\"{0}\" x2=\"{1}\" y2=\"{0}\" stroke=\"#333\"/>", HEIGHT - MARGIN, WIDTH - MARGIN).unwrap();
+    writeln!(out, "<line x1=\"{MARGIN}\" y1=\"{MARGIN}\" x2=\"{MARGIN}\" y2=\"{}\" stroke=\"#333\"/>", HEIGHT - MARGIN).unwrap();
+    let unit = if opt.as_percent { "%" } else { "" };
+    writeln!(out, "<text x=\"{}\" y=\"{}\" text-anchor=\"end\">{max:.0}{unit}</text>", MARGIN - 4.0, MARGIN + 4.0).unwrap();
+    if let (Some(first), Some(last)) = (table.dates.first(), table.dates.last()) {
+        writeln!(out, "<text x=\"{MARGIN}\" y=\"{}\">{}</text>", HEIGHT - MARGIN + 16.0, escape(first)).unwrap();
+        writeln!(out, "<text x=\"{}\" y=\"{}\" text-anchor=\"end\">{}</text>", WIDTH - MARGIN, HEIGHT - MARGIN + 16.0, escape(last)).unwrap();
+    }
+
+    // Legend, top layer first to match the stacking
+    for (n, i) in (0..table.rows.len()).rev().enumerate() {
+        let top = MARGIN + 16.0 * n as f64;
+        writeln!(out, "<rect x=\"{WIDTH}\" y=\"{}\" width=\"10\" height=\"10\" fill=\"{}\"/>", top - 9.0, PALETTE[i % PALETTE.len()]).unwrap();
+        writeln!(out, "<text x=\"{}\" y=\"{top}\">{}</text>", WIDTH + 16.0, escape(&table.rows[i].author)).unwrap();
+    }
+    writeln!(out, "</svg>").unwrap();
+    return out;
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn sparkline_levels() {
+        assert_eq!(sparkline(&[0.0, 1.0, 4.0, 8.0], 8.0), " ▁▄█");
+        assert_eq!(sparkline(&[0.1, 7.9], 8.0), "▁█");
+        assert_eq!(sparkline(&[0.0, 0.0], 0.0), "  ");
+    }
+}
@@ -1,36 +1,795 @@
-use clap::Parser;
+use clap::parser::ValueSource;
+use clap::{ArgAction, ArgMatches, Parser, Subcommand, ValueEnum};
+use std::ffi::OsString;
+
+use crate::badge::{parse_badge, BadgeKind};
+use crate::breakdown::parse_group_range;
+use crate::cache::parse_size;
+use crate::sampling::parse_sample;
 
 //----
 // Command Line Parsing
 
-#[derive(Debug, Parser)]
+#[derive(Debug, Clone, Parser)]
 #[command(
     name = "git-author-stats",
     author = "Matt Mahin",
     about = "tool to track how much code is being authored by each developer",
 )]
 pub struct Args {
-    /// Sort alphabetically by author name, instead of by number of lines
+    #[command(subcommand)]
+    pub command: Option<Command>,
+
+    /// Sort alphabetically by author name, instead of by number of lines (same as --sort alphabetical)
     #[arg(short, long)]
     pub alphabetical: bool,
 
+    /// What to count per author: surviving lines (git blame at each date), or activity in the period ending at each date (git log).  Several, comma separated, give one report each
+    #[arg(short, long = "metric", value_enum, value_delimiter = ',', default_value = "lines")]
+    pub metrics: Vec<Metric>,
+
+    /// How much each blamed line counts, for --metric lines
+    #[arg(long, value_enum, default_value_t = Weight::Lines)]
+    pub weight: Weight,
+
+    /// How binary files (images, design files, documents) count, for --metric lines
+    #[arg(long = "binary-metric", value_enum, default_value_t = BinaryMetric::Skip, value_name = "METRIC")]
+    pub binary_metric: BinaryMetric,
+
+    /// For --metric effort: a pause between an author's commits longer than this many hours ends a work session
+    #[arg(long = "session-gap", value_name = "HOURS", default_value_t = 2.0)]
+    pub session_gap: f64,
+
+    /// Also break the counts down by top-level directory, file extension or language, with one table per group; or group the author rows by title, location or cost center from --directory
+    #[arg(short, long = "group-by", value_enum, default_value_t = GroupBy::None)]
+    pub group_by: GroupBy,
+
+    /// With --group-by dir, group by directories as many levels down as it takes to get from MIN to MAX groups at the latest date
+    #[arg(long = "auto-depth", value_name = "MIN-MAX", num_args = 0..=1, require_equals = true, default_missing_value = "5-20", value_parser = parse_group_range)]
+    pub auto_depth: Option<(usize, usize)>,
+
+    /// CSV file (or, with the ldap feature, an ldap:// URL) giving each author email's title, location and cost center, for --group-by title|location|cost-center
+    #[arg(long, value_name = "FILE")]
+    pub directory: Option<String>,
+
+    /// Summarize each author's share of lines per directory, extension or language at the latest date
+    #[arg(long, value_enum, value_name = "GROUP", default_value_t = FingerprintBy::None)]
+    pub fingerprint: FingerprintBy,
+
+    /// Add a row with the average ownership entropy of the files (in bits: 0 when each file has a single author) per date
+    #[arg(long)]
+    pub entropy: bool,
+
+    /// Add rows with the bus factor (fewest authors owning half the lines) and the number of files one author dominates, per date
+    #[arg(long = "bus-factor")]
+    pub bus_factor: bool,
+
+    /// Share of a file's lines, in percent, above which its top author dominates it, for --bus-factor
+    #[arg(long, value_name = "PCT", default_value_t = 80.0)]
+    pub dominance: f64,
+
+    /// Add a row with a 0-100 health score per date, weighting the bus factor, ownership entropy, stale code share and active author trend per the config's [health] table
+    #[arg(long = "health-score")]
+    pub health_score: bool,
+
+    /// List files that often change in the same commits, with their owners at the latest date, flagging pairs owned by different people or teams
+    #[arg(long)]
+    pub coupling: bool,
+
+    /// Write the renames and copies of the analyzed files, with the snapshot each first shows in, as JSON to this file
+    #[arg(long = "rename-graph", value_name = "FILE")]
+    pub rename_graph: Option<String>,
+
+    /// Classify blamed lines by the conventional-commit type of their commit (feat, fix, refactor, docs) into an author x category x date cube in the JSON output
+    #[arg(long)]
+    pub categories: bool,
+
+    /// Count the lines of documentation files (*.md, *.rst and docs/, or the config's [files] docs) in a column of their own beside each date's, instead of with the code
+    #[arg(long = "split-docs", conflicts_with_all = ["group_by", "per_path", "per_repo"])]
+    pub split_docs: bool,
+
+    /// Also count files matching this glob (or `lang:NAME`) as documentation for --split-docs, or with a `!` prefix not; may be repeated
+    #[arg(long = "doc-files", value_name = "PATTERN")]
+    pub doc_files: Vec<String>,
+
+    /// Row order
+    #[arg(long, value_enum, default_value_t = SortOrder::Latest)]
+    pub sort: SortOrder,
+
+    /// File listing authors to pin to the top of the tables in its order, one name, email or "Name <email>" per line; the rest follow in the --sort order
+    #[arg(long = "author-order", value_name = "FILE")]
+    pub author_order: Option<String>,
+
+    /// Number of latest snapshots summed by --sort recent
+    #[arg(long, value_name = "N", default_value_t = 3)]
+    pub recent: usize,
+
+    /// Row label template, from the fields {name}, {email}, {email_user} and {email_domain}, e.g. "{name} ({email_domain})"
+    #[arg(long = "author-format", value_name = "TEMPLATE")]
+    pub author_format: Option<String>,
+
+    /// Only count authors whose name, email or "Name <email>" matches this glob (or regex, with a `re:` prefix); may be repeated
+    #[arg(long = "author", value_name = "PATTERN")]
+    pub authors: Vec<String>,
+
+    /// Leave out authors matching this glob (or `re:` regex); may be repeated
+    #[arg(long = "exclude-author", value_name = "PATTERN")]
+    pub exclude_authors: Vec<String>,
+
+    /// Leave out bot accounts such as `dependabot[bot]` or `renovate-bot`
+    #[arg(long = "exclude-bots")]
+    pub exclude_bots: bool,
+
+    /// Keep the N largest authors (in the sort order) and sum the rest into one "Other" row
+    #[arg(long, value_name = "N")]
+    pub top: Option<usize>,
+
+    /// Authors listed under each heading of the movers section after the table: biggest gains and losses at the latest date; 0 to leave it out
+    #[arg(long, value_name = "N", default_value_t = 3)]
+    pub movers: usize,
+
     /// Display counts as percentages
     #[arg(short = 'p', long = "percent")]
     pub as_percent: bool,
 
+    /// Display each count with its share of the date's total: `1234 (12%)`, or a second field in csv and json
+    #[arg(long = "with-percent", conflicts_with = "as_percent")]
+    pub with_percent: bool,
+
+    /// Draw each author's counts over time: sparklines in the terminal formats, a stacked area chart in html
+    #[arg(long)]
+    pub chart: bool,
+
+    /// Also write the stacked area chart to this SVG file
+    #[arg(long = "chart-svg", value_name = "FILE")]
+    pub chart_svg: Option<String>,
+
+    /// Also write a README badge of the latest bus factor, top contributor share or active authors to this SVG file, named bus-factor.svg, top-share.svg or active-authors.svg, or given as KIND=FILE; may be repeated
+    #[arg(long = "badge", value_name = "[KIND=]FILE", value_parser = parse_badge)]
+    pub badges: Vec<(BadgeKind, String)>,
+
+    /// Output format
+    #[arg(short, long, value_enum, default_value_t = OutputFormat::Table)]
+    pub format: OutputFormat,
+
+    /// Digit group separator for human readable formats: "auto" (from the locale), "none", or the separator itself
+    #[arg(long = "thousands-separator", value_name = "SEP", default_value = "auto")]
+    pub thousands_separator: String,
+
+    /// Base URL of the repo on GitHub or GitLab (e.g. https://github.com/org/repo), to link authors and files in --format html
+    #[arg(long = "repo-url", value_name = "URL")]
+    pub repo_url: Option<String>,
+
+    /// Also write the report as a static site (index.html, data.json and assets) to this directory
+    #[arg(long = "emit-static-site", value_name = "DIR")]
+    pub emit_static_site: Option<String>,
+
+    /// Also write a markdown "people to ask" page to this file: the top owners of each directory, with their last activity there
+    #[arg(long = "knowledge-map", value_name = "FILE")]
+    pub knowledge_map: Option<String>,
+
+    /// Also write each author's share of the lines against the months since their first commit to this CSV file
+    #[arg(long = "seniority-csv", value_name = "FILE")]
+    pub seniority_csv: Option<String>,
+
+    /// Also draw each author's share of the lines against the months since their first commit to this SVG file
+    #[arg(long = "seniority-svg", value_name = "FILE")]
+    pub seniority_svg: Option<String>,
+
+    /// Report the revision each date resolved to and every skipped file; twice to also list each file blamed
+    #[arg(short, long, action = ArgAction::Count, global = true, conflicts_with = "quiet")]
+    pub verbose: u8,
+
+    /// Only report errors: no warnings or progress
+    #[arg(short, long, global = true)]
+    pub quiet: bool,
+
+    /// How to print a failure on stderr: a message, or a JSON object with its code, phase, failed git command and a hint
+    #[arg(long = "error-format", global = true, value_enum, default_value_t = ErrorFormat::Text)]
+    pub error_format: ErrorFormat,
+
     /// Show excluded files
     #[arg(long = "show-excluded")]
     pub show_excluded: bool,
 
     /// branch to analyze
-    #[arg(short, long)]
+    #[arg(short, long, global = true)]
     pub branch: Option<String>,
 
-    /// Only look at commits before this date: YYYY-MM-DD.  Defaults to all commits
+    /// Branches to analyze side by side, with a comparison of each author's lines on them at the latest date; `*` matches within a branch name, e.g. main,develop,'release/*'
+    #[arg(long, value_name = "BRANCH", value_delimiter = ',')]
+    pub branches: Vec<String>,
+
+    /// First date to sample: YYYY-MM-DD.  Defaults to 2016-01-01
+    #[arg(long, global = true, value_name = "DATE")]
+    pub since: Option<String>,
+
+    /// Time between sampled dates.  Without --since or --interval, the first of every month from 2016 through this year
+    #[arg(long, global = true, value_enum)]
+    pub interval: Option<Interval>,
+
+    /// What to do at dates where the branch has no commits yet
+    #[arg(long = "missing-snapshot", value_enum, default_value_t = MissingSnapshot::Skip)]
+    pub missing_snapshot: MissingSnapshot,
+
+    /// Sample no later than this date: YYYY-MM-DD.  Defaults to today
     #[arg(short, long)]
     pub date: Option<String>,
 
-    /// Path of folder within the git repo to analyze
+    /// Alias file merging extra names/emails into one author: `Name <email> = alias, alias@email, ...`
+    #[arg(long)]
+    pub aliases: Option<String>,
+
+    /// Show how each step of identity resolution maps the authors of the branch's commits matching this name or email, and exit
+    #[arg(long, value_name = "NAME")]
+    pub explain_identity: Option<String>,
+
+    /// Config file to use instead of .git-author-stats.toml at the repo root
+    #[arg(long)]
+    pub config: Option<String>,
+
+    /// Policy file bounding the repositories, options and author fields this run may use, for shared services
+    #[arg(long, value_name = "FILE")]
+    pub policy: Option<String>,
+
+    /// Skip files matching this gitignore-style pattern, or lang:NAME; may be repeated
+    #[arg(short = 'x', long, value_name = "PATTERN")]
+    pub exclude: Vec<String>,
+
+    /// Only analyze files matching this gitignore-style pattern, or lang:NAME; may be repeated
+    #[arg(short = 'i', long, value_name = "PATTERN")]
+    pub include: Vec<String>,
+
+    /// Also apply the built-in exclude list (Vivado/FPGA binary and generated files)
+    #[arg(long = "builtin-excludes")]
+    pub builtin_excludes: bool,
+
+    /// Number of files to blame at once, across all snapshot dates.  Defaults to the number of logical cores
+    #[arg(short, long, global = true, value_name = "N")]
+    pub jobs: Option<usize>,
+
+    /// Blame only a pseudo-random P% of the files, the same ones every run, and scale the line counts up: a quick estimate, with its 95% confidence interval, before a full run
+    #[arg(long, value_name = "P%", value_parser = parse_sample, conflicts_with = "isolate_snapshots")]
+    pub sample: Option<f64>,
+
+    /// Keep blame results on disk between runs, so unchanged files are never blamed twice
+    #[arg(long)]
+    pub cache: bool,
+
+    /// Directory for the on-disk cache (implies --cache).  Defaults to ~/.cache/git-author-stats
+    #[arg(long = "cache-dir", global = true, value_name = "DIR")]
+    pub cache_dir: Option<String>,
+
+    /// Encrypt the blame cache and every file written (not stdout), with the passphrase from --key-file or $GIT_AUTHOR_STATS_KEY; needs openssl.  Confidentiality only: tampering isn't detected
+    #[arg(long = "encrypt-at-rest", global = true)]
+    pub encrypt_at_rest: bool,
+
+    /// File whose first line is the --encrypt-at-rest passphrase
+    #[arg(long = "key-file", global = true, value_name = "FILE")]
+    pub key_file: Option<String>,
+
+    /// Rank the authors by the lines they own in the working tree, uncommitted changes included, to preview how they shift ownership
+    #[arg(long = "working-tree", conflicts_with_all = ["since", "interval", "isolate_snapshots"])]
+    pub working_tree: bool,
+
+    /// Rank the authors by the lines they own in the index, counting staged changes as uncommitted, e.g. in a pre-commit hook
+    #[arg(long, conflicts_with_all = ["since", "interval", "isolate_snapshots", "working_tree"])]
+    pub staged: bool,
+
+    /// Save every git command run, with its output, and the repo files read to this directory, to reproduce the run elsewhere with --replay-git
+    #[arg(long = "record-git", global = true, value_name = "DIR", conflicts_with = "replay_git")]
+    pub record_git: Option<String>,
+
+    /// Answer git from a --record-git directory instead of running it, to re-run a recorded analysis without the repo
+    #[arg(long = "replay-git", global = true, value_name = "DIR")]
+    pub replay_git: Option<String>,
+
+    /// Blame each snapshot in a child process of its own, so one that runs out of memory or crashes git is skipped instead of ending the run
+    #[arg(long = "isolate-snapshots")]
+    pub isolate_snapshots: bool,
+
+    /// Internal: the one date a --isolate-snapshots child blames
+    #[arg(long = "isolated-snapshot", hide = true, value_name = "DATE", requires = "isolated_output")]
+    pub isolated_snapshot: Option<String>,
+
+    /// Internal: where a --isolate-snapshots child saves its model
+    #[arg(long = "isolated-output", hide = true, value_name = "FILE")]
+    pub isolated_output: Option<String>,
+
+    /// Internal: the paths a --isolate-snapshots child analyzes
+    #[arg(long = "isolated-path", hide = true, value_name = "PATH")]
+    pub isolated_paths: Vec<String>,
+
+    /// Skip files marked binary, -diff, linguist-generated or linguist-vendored in .gitattributes
+    #[arg(long)]
+    pub gitattributes: bool,
+
+    /// Write blame wall time per file per snapshot to this CSV file
+    #[arg(long = "profile-files", value_name = "CSV")]
+    pub profile_files: Option<String>,
+
+    /// Write every file skipped at each snapshot to this JSON Lines file, with the rule, its source and the file's lines and bytes
+    #[arg(long = "exclusion-log", value_name = "JSONL")]
+    pub exclusion_log: Option<String>,
+
+    /// Also save the analysis results to this file, for --load-model
+    #[arg(long = "save-model", value_name = "FILE")]
+    pub save_model: Option<String>,
+
+    /// Also upsert the counts into PostgreSQL tables at this connection URL, creating or migrating them, via psql
+    #[arg(long = "export-postgres", value_name = "URL")]
+    pub export_postgres: Option<String>,
+
+    /// Also push each author's counts at the latest snapshot to this Prometheus Pushgateway, via curl
+    #[arg(long = "push-gateway", value_name = "URL")]
+    pub push_gateway: Option<String>,
+
+    /// Also write each date's issues (from --issues) next to each metric's counts, per author, to this CSV file
+    #[arg(long = "workload-csv", value_name = "FILE", requires = "issues")]
+    pub workload_csv: Option<String>,
+
+    /// CSV of issues per author and date from an issue tracker, with email, date and issues columns, for --workload-csv
+    #[arg(long, value_name = "FILE")]
+    pub issues: Option<String>,
+
+    /// Repository name to export or push the counts under; the repository's directory name by default
+    #[arg(long = "export-name", value_name = "NAME")]
+    pub export_name: Option<String>,
+
+    /// Add the run's headline numbers (total, authors, bus factor and top author at the latest date) to --history-file, under the --export-name, for `trends`
+    #[arg(long = "record-history")]
+    pub record_history: bool,
+
+    /// History of runs for --record-history and `trends`.  Defaults to ~/.local/share/git-author-stats/history.jsonl
+    #[arg(long = "history-file", global = true, value_name = "FILE")]
+    pub history_file: Option<String>,
+
+    /// Language of the human-facing report text and the dates labeling its columns.  CSV, JSON and the other machine formats are unchanged
+    #[arg(long = "lang", global = true, value_enum, value_name = "LANG")]
+    pub lang: Option<Lang>,
+
+    /// Fluent (.ftl) catalog adding or overriding report messages, over the --lang one
+    #[arg(long = "lang-file", global = true, value_name = "FILE.ftl")]
+    pub lang_file: Option<String>,
+
+    /// Build the reports from a file written by --save-model instead of analyzing a repository
+    #[arg(long = "load-model", value_name = "FILE", conflicts_with_all = ["save_model", "repos", "repos_file"])]
+    pub load_model: Option<String>,
+
+    /// Repository to analyze instead of the one holding the paths; may be repeated, summing the counts per author across repositories
+    #[arg(long = "repo", value_name = "PATH")]
+    pub repos: Vec<String>,
+
+    /// File listing repositories to analyze together, one path per line
+    #[arg(long = "repos-file", value_name = "FILE")]
+    pub repos_file: Option<String>,
+
+    /// Read repository paths or URLs from stdin, one per line, and write each one's report as a static site to --output-dir/NAME/, with an index.html of them all
+    #[arg(long)]
+    pub batch: bool,
+
+    /// Directory --batch writes the reports to
+    #[arg(long = "output-dir", value_name = "DIR")]
+    pub output_dir: Option<String>,
+
+    /// With several repositories, also show one table per repository
+    #[arg(long = "per-repo")]
+    pub per_repo: bool,
+
+    /// Fail (exit status 6) if less than this percentage of the repository's lines, at the latest snapshot, are in files counted rather than excluded
+    #[arg(long = "min-coverage", value_name = "PCT", conflicts_with_all = ["load_model", "isolate_snapshots"])]
+    pub min_coverage: Option<f64>,
+
+    /// With several paths, also show one table per path
+    #[arg(long = "per-path", conflicts_with = "group_by")]
+    pub per_path: bool,
+
+    /// Folders or files within the git repo to analyze; the whole repo if the top level is given
     #[arg(index = 1, default_value = ".")]
-    pub path: String,
+    pub paths: Vec<String>,
+}
+
+#[derive(Debug, Clone, Subcommand)]
+pub enum Command {
+    /// Manage the on-disk blame cache
+    #[command(subcommand)]
+    Cache(CacheCommand),
+    /// Check for and install new releases of git-author-stats
+    #[command(name = "self", subcommand)]
+    Tool(ToolCommand),
+    /// Rank the authors by the lines they own at one date (YYYY-MM-DD) or revision, blaming only that snapshot
+    At {
+        /// Date or revision to blame
+        target: String,
+        /// Folders or files within the git repo to analyze; the whole repo if the top level is given
+        #[arg(default_value = ".")]
+        paths: Vec<String>,
+    },
+    /// Print a Markdown "Contributors" section for release notes: the commits and lines of each author after --since-ref up to --until, and where first-time contributors started
+    Contributors {
+        /// The previous release's tag or revision; its commits aren't counted
+        #[arg(long = "since-ref", value_name = "REF")]
+        since_ref: String,
+        /// Last revision of the release
+        #[arg(long, value_name = "REF", default_value = "HEAD")]
+        until: String,
+        /// Folders or files within the git repo to credit; the whole repo if the top level is given
+        #[arg(default_value = ".")]
+        paths: Vec<String>,
+    },
+    /// Explain how each author's lines changed between two dates (YYYY-MM-DD) or revisions: the files that changed them most
+    Explain {
+        /// Earlier date or revision
+        from: String,
+        /// Later date or revision
+        to: String,
+        /// Files to list per author; the rest are summed on one line
+        #[arg(long, default_value_t = 5)]
+        top: usize,
+        /// Folders or files within the git repo to analyze; the whole repo if the top level is given
+        #[arg(default_value = ".")]
+        paths: Vec<String>,
+    },
+    /// Answer HTTP requests for the JSON reports of the repo: GET /ownership?at=REV&path=PATH and /history?path=PATH
+    Serve {
+        /// Address to listen on; only a loopback one without --tokens
+        #[arg(long, value_name = "ADDR", default_value = "127.0.0.1:8080")]
+        listen: String,
+        /// File of `name token` lines, one per client; requests then need `Authorization: Bearer TOKEN`
+        #[arg(long, value_name = "FILE")]
+        tokens: Option<String>,
+        /// Requests each client (token, or address without --tokens) may make per minute
+        #[arg(long, value_name = "N", default_value_t = 10)]
+        rate_limit: u32,
+        /// Requests analyzed at once
+        #[arg(long, default_value_t = 2)]
+        workers: usize,
+    },
+    /// Print the owner and age of each line of one file at one revision, for review bots
+    Annotate {
+        /// Revision to blame
+        #[arg(long, default_value = "HEAD")]
+        rev: String,
+        /// File to annotate, relative to the repo's top level
+        #[arg(long)]
+        file: String,
+        /// Output format
+        #[arg(short, long, value_enum, default_value_t = AnnotateFormat::Json)]
+        format: AnnotateFormat,
+    },
+    /// Rank who should review a change to some files by the lines they own in them and their directories, for CI bots
+    SuggestReviewers {
+        /// Revision to blame
+        #[arg(long, default_value = "HEAD")]
+        rev: String,
+        /// Changed file, relative to the repo's top level; may be repeated
+        #[arg(long, required_unless_present = "patch")]
+        file: Vec<String>,
+        /// Take the changed files from a unified diff against --rev (`-` for stdin), and estimate each reviewer's review time
+        #[arg(long, value_name = "FILE")]
+        patch: Option<String>,
+        /// Leave out the change's author, by glob (or `re:` regex) over names and emails; may be repeated
+        #[arg(long, value_name = "AUTHOR")]
+        exclude: Vec<String>,
+        /// Number of reviewers to suggest
+        #[arg(long, default_value_t = 3)]
+        top: usize,
+        /// Output format
+        #[arg(short, long, value_enum, default_value_t = ReviewersFormat::Text)]
+        format: ReviewersFormat,
+    },
+    /// Show how the runs recorded with --record-history changed month by month, per repository and metric
+    Trends {
+        /// Only the repositories recorded under this name; may be repeated
+        #[arg(long, value_name = "NAME")]
+        name: Vec<String>,
+        /// Output format
+        #[arg(short, long, value_enum, default_value_t = TrendsFormat::Text)]
+        format: TrendsFormat,
+    },
+    /// Tell who owns the line ranges listed on stdin as PATH:START-END, all together, e.g. the uncovered code of a coverage report
+    Regions {
+        /// Revision to blame
+        #[arg(long, default_value = "HEAD")]
+        rev: String,
+        /// Output format
+        #[arg(short, long, value_enum, default_value_t = RegionsFormat::Text)]
+        format: RegionsFormat,
+    },
+}
+
+/// The options (as "--name") and subcommands (as "cache gc") given on the
+/// command line, for --policy
+pub fn used_options(command: &clap::Command, matches: &ArgMatches) -> Vec<String> {
+    let mut used = Vec::new();
+    collect_used(command, matches, "", &mut used);
+    return used;
+}
+
+/// The top-level options given on the command line, rebuilt as "--name=VALUE"
+/// arguments, for the analysis runs of `serve`
+pub fn given_options(command: &clap::Command, matches: &ArgMatches) -> Vec<OsString> {
+    let mut given = Vec::new();
+    for arg in command.get_arguments() {
+        let id = arg.get_id().as_str();
+        let (Some(long), Some(ValueSource::CommandLine)) = (arg.get_long(), matches.value_source(id)) else {
+            continue;
+        };
+        match arg.get_action() {
+            ArgAction::SetTrue | ArgAction::SetFalse => given.push(OsString::from(format!("--{long}"))),
+            ArgAction::Count => given.extend((0..matches.get_count(id)).map(|_| OsString::from(format!("--{long}")))),
+            _ => {
+                for value in matches.get_raw(id).into_iter().flatten() {
+                    let mut option = OsString::from(format!("--{long}="));
+                    option.push(value);
+                    given.push(option);
+                }
+            },
+        }
+    }
+    return given;
+}
+
+/// Every option (as "--name") and subcommand (as "cache gc") of `command`
+pub fn all_options(command: &clap::Command) -> Vec<String> {
+    let mut all: Vec<String> = command.get_arguments().filter_map(|arg| arg.get_long()).map(|long| format!("--{long}")).collect();
+    for sub in command.get_subcommands() {
+        all.push(sub.get_name().to_string());
+        all.extend(all_options(sub).into_iter().map(|name| match name.starts_with("--") {
+            true => name,
+            false => format!("{} {name}", sub.get_name()),
+        }));
+    }
+    return all;
+}
+
+fn collect_used(command: &clap::Command, matches: &ArgMatches, prefix: &str, used: &mut Vec<String>) {
+    for arg in command.get_arguments() {
+        let given = matches.value_source(arg.get_id().as_str()) == Some(ValueSource::CommandLine);
+        if let (Some(long), true) = (arg.get_long(), given) {
+            used.push(format!("--{long}"));
+        }
+    }
+    if let Some((name, sub_matches)) = matches.subcommand() {
+        let path = format!("{prefix}{name}");
+        used.push(path.clone());
+        if let Some(sub) = command.find_subcommand(name) {
+            collect_used(sub, sub_matches, &format!("{path} "), used);
+        }
+    }
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, ValueEnum)]
+pub enum Lang {
+    En,
+    De,
+    Fr,
+    Es,
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, ValueEnum)]
+pub enum ErrorFormat {
+    Text,
+    /// {"error": {"code", "exit_status", "phase", "message", "git_command", "hint"}} on one line
+    Json,
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, ValueEnum)]
+pub enum ReviewersFormat {
+    /// A line per reviewer with their score and lines
+    Text,
+    /// JSON document with the revision, the files and the reviewers
+    Json,
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, ValueEnum)]
+pub enum TrendsFormat {
+    /// A table per repository and metric of the last run of each month
+    Text,
+    /// Every run, one row each
+    Csv,
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, ValueEnum)]
+pub enum RegionsFormat {
+    /// A line per owner with their lines and share
+    Text,
+    /// JSON document with the revision, the owners and the regions not found
+    Json,
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, ValueEnum)]
+pub enum AnnotateFormat {
+    /// JSON document with a record per line and per range of lines with the same owner
+    Json,
+}
+
+#[derive(Debug, Clone, Subcommand)]
+pub enum CacheCommand {
+    /// Blame every file at the sampled dates into the on-disk cache, without printing a report
+    Warm {
+        /// Folders or files within the git repo to blame; the whole repo if the top level is given
+        #[arg(default_value = ".")]
+        paths: Vec<String>,
+    },
+    /// List each repository's cache with its size, entries and when a run last used it
+    Ls,
+    /// Show the hits, misses and hit ratio of each repository's cache over all runs
+    Stats,
+    /// Remove the entries runs used least recently until the caches fit in --max-size
+    Gc {
+        /// Total size to shrink the caches to, e.g. 500M or 2G
+        #[arg(long = "max-size", value_name = "SIZE", value_parser = parse_size)]
+        max_size: u64,
+    },
+}
+
+#[derive(Debug, Clone, Subcommand)]
+pub enum ToolCommand {
+    /// Print this version and the latest release
+    Check,
+    /// Replace this binary with the latest release after checking its SHA-256; needs a build with the self-update feature.  The checksum comes from the same release, so it only proves the download is intact, not that the release is genuine, and --public-key is needed for that unless --skip-signature is given
+    Update {
+        /// PEM public key to also check the release's signature with, proving it was signed by the key's owner
+        #[arg(long, value_name = "FILE", required_unless_present = "skip_signature")]
+        public_key: Option<String>,
+        /// Install without checking the release's signature, trusting whoever can change the release or GIT_AUTHOR_STATS_RELEASE_URL
+        #[arg(long, conflicts_with = "public_key")]
+        skip_signature: bool,
+        /// Reinstall the latest release even when it isn't newer
+        #[arg(long)]
+        force: bool,
+    },
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, ValueEnum)]
+pub enum Interval {
+    Week,
+    Month,
+    Quarter,
+    Year,
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, ValueEnum)]
+pub enum Metric {
+    /// Lines each author owns at the date, per git blame
+    Lines,
+    /// Non-merge commits authored in the period
+    Commits,
+    /// Lines added in the period
+    Added,
+    /// Lines deleted in the period
+    Deleted,
+    /// Lines added plus lines deleted in the period
+    Churn,
+    /// Experimental: estimated hours of work in the period, from commits clustered into sessions (see --session-gap)
+    Effort,
+}
+
+impl Metric {
+    pub fn name(&self) -> &'static str {
+        match self {
+            Metric::Lines => return "lines",
+            Metric::Commits => return "commits",
+            Metric::Added => return "added",
+            Metric::Deleted => return "deleted",
+            Metric::Churn => return "churn",
+            Metric::Effort => return "effort",
+        }
+    }
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, ValueEnum)]
+pub enum Weight {
+    /// Every line counts once
+    Lines,
+    /// Each line counts once per commit that changed its file before the date, so often changed files weigh more
+    Hotspot,
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, ValueEnum)]
+pub enum BinaryMetric {
+    /// Binary files have no lines, so they don't count
+    Skip,
+    /// Each binary file counts as one line of whoever last committed it, even if excluded as binary
+    Files,
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, ValueEnum)]
+pub enum GroupBy {
+    /// The whole repo (or the given paths) as one table
+    None,
+    /// Top-level directory
+    Dir,
+    /// File extension
+    Ext,
+    /// Language, guessed from the file name
+    Language,
+    /// Author's title, from --directory
+    Title,
+    /// Author's location, from --directory
+    Location,
+    /// Author's cost center, from --directory
+    CostCenter,
+    /// Which of the given paths the file is in, with --per-path
+    #[value(skip)]
+    Path,
+}
+
+impl GroupBy {
+    /// The --directory column of a grouping by author attribute
+    pub fn attribute(&self) -> Option<&'static str> {
+        match self {
+            GroupBy::Title => return Some("title"),
+            GroupBy::Location => return Some("location"),
+            GroupBy::CostCenter => return Some("cost_center"),
+            GroupBy::None | GroupBy::Dir | GroupBy::Ext | GroupBy::Language | GroupBy::Path => return None,
+        }
+    }
+}
+
+/// The file groupings of --group-by, which are all --fingerprint can use
+#[derive(Debug, Clone, Copy, PartialEq, Eq, ValueEnum)]
+pub enum FingerprintBy {
+    /// No fingerprint
+    None,
+    /// Top-level directory
+    Dir,
+    /// File extension
+    Ext,
+    /// Language, guessed from the file name
+    Language,
+}
+
+impl FingerprintBy {
+    pub fn group_by(&self) -> GroupBy {
+        match self {
+            FingerprintBy::None => return GroupBy::None,
+            FingerprintBy::Dir => return GroupBy::Dir,
+            FingerprintBy::Ext => return GroupBy::Ext,
+            FingerprintBy::Language => return GroupBy::Language,
+        }
+    }
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, ValueEnum)]
+pub enum SortOrder {
+    /// Most lines at the latest date first
+    Latest,
+    /// Most lines summed over all dates first
+    Total,
+    /// Most lines summed over the last --recent dates first
+    Recent,
+    /// By author name
+    Alphabetical,
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, ValueEnum)]
+pub enum OutputFormat {
+    /// Aligned columns for the terminal
+    Table,
+    /// Markdown table
+    Markdown,
+    /// Comma separated values, raw numbers
+    Csv,
+    /// Self-contained HTML page, with per-file owners at the latest date
+    Html,
+    /// JSON document, raw numbers
+    Json,
+    /// The JSON document as YAML
+    Yaml,
+    /// The JSON document as MessagePack, binary
+    Msgpack,
+    /// code-maat's main-dev CSV: each file's top owner at the latest date, for code-maat pipelines
+    CodeMaat,
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, ValueEnum)]
+pub enum MissingSnapshot {
+    /// Leave the date out, with a warning
+    Skip,
+    /// Analyze the default branch at that date instead
+    Fallback,
+    /// Stop with an error
+    Error,
 }
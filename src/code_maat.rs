@@ -0,0 +1,49 @@
+use crate::csv_field;
+use crate::report::FileRow;
+
+//----
+// code-maat compatibility
+//
+// --format code-maat writes the per-file owners at the latest date as the CSV
+// of code-maat's main-dev analysis, so pipelines and visualizations built on
+// code-maat can read this tool's cached blame instead.  code-maat counts the
+// lines each author added over the log; here `added` is the lines each owns,
+// still in the file, and `ownership` the main developer's share of them.
+
+/// The header of code-maat's main-dev CSV
+pub const MAIN_DEV_HEADER: &str = "entity,main-dev,added,total-added,ownership";
+
+/// `files` as code-maat's main-dev CSV, one line per file with any owned
+/// lines, by path
+pub fn render_main_dev(files: &[FileRow]) -> String {
+    let mut files: Vec<&FileRow> = files.iter().filter(|file| file.total > 0).collect();
+    files.sort_by(|a, b| a.path.cmp(&b.path));
+    let mut out = format!("{MAIN_DEV_HEADER}\n");
+    for file in files {
+        let Some((author, lines)) = file.owners.first() else {
+            continue;
+        };
+        let ownership = *lines as f64 / file.total as f64;
+        out.push_str(&format!("{},{},{lines},{},{ownership:.2}\n", csv_field(&file.path), csv_field(author), file.total));
+    }
+    return out;
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn writes_main_dev_csv() {
+        let file = |path: &str, owners: &[(&str, i32)]| FileRow {
+            path: path.to_string(),
+            owners: owners.iter().map(|&(author, count)| (author.to_string(), count)).collect(),
+            total: owners.iter().map(|(_, count)| count).sum(),
+            entropy: 0.0,
+        };
+        let files = [file("src/main.rs", &[("Alice", 3), ("Bob", 2)]), file("README.md", &[("Smith, Jo", 1)]), file("empty.txt", &[])];
+        assert_eq!(render_main_dev(&files), "entity,main-dev,added,total-added,ownership\n\
+            README.md,\"Smith, Jo\",1,1,1.00\n\
+            src/main.rs,Alice,3,5,0.60\n");
+    }
+}
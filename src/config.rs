@@ -0,0 +1,569 @@
+use hashbrown::HashMap;
+use std::path::Path;
+
+use crate::health::{HealthWeights, DEFAULT_STALE_AFTER_DAYS};
+use crate::identity::SpecialLabels;
+use crate::recording;
+
+//----
+// Configuration file
+//
+// Settings are read from `.git-author-stats.toml` at the repo root (or the
+// file given by --config).  Only the subset of TOML needed here is
+// supported: tables, strings, integers, floats, booleans, arrays and inline
+// tables.
+
+pub const CONFIG_FILE_NAME: &str = ".git-author-stats.toml";
+
+#[derive(Debug, Clone, PartialEq)]
+pub enum TomlValue {
+    String(String),
+    Integer(i64),
+    Float(f64),
+    Boolean(bool),
+    Array(Vec<TomlValue>),
+    Table(TomlTable),
+}
+
+pub type TomlTable = HashMap<String, TomlValue>;
+
+impl TomlValue {
+    fn type_name(&self) -> &'static str {
+        match self {
+            TomlValue::String(_) => "string",
+            TomlValue::Integer(_) => "integer",
+            TomlValue::Float(_) => "float",
+            TomlValue::Boolean(_) => "boolean",
+            TomlValue::Array(_) => "array",
+            TomlValue::Table(_) => "table",
+        }
+    }
+}
+
+struct Parser<'a> {
+    text: &'a [u8],
+    pos: usize,
+    line: usize,
+}
+
+impl<'a> Parser<'a> {
+    fn error(&self, msg: &str) -> String {
+        return format!("line {}: {msg}", self.line);
+    }
+
+    fn peek(&self) -> Option<u8> {
+        return self.text.get(self.pos).copied();
+    }
+
+    fn bump(&mut self) -> Option<u8> {
+        let c = self.peek()?;
+        self.pos += 1;
+        if c == b'\n' {
+            self.line += 1;
+        }
+        return Some(c);
+    }
+
+    /// Skip spaces and tabs, plus newlines and comments when `newlines` is set
+    fn skip_ws(&mut self, newlines: bool) {
+        while let Some(c) = self.peek() {
+            match c {
+                b' ' | b'\t' | b'\r' => { self.bump(); },
+                b'\n' if newlines => { self.bump(); },
+                b'#' => {
+                    while !matches!(self.peek(), None | Some(b'\n')) {
+                        self.bump();
+                    }
+                },
+                _ => break,
+            }
+        }
+    }
+
+    fn expect(&mut self, c: u8) -> Result<(), String> {
+        match self.bump() {
+            Some(x) if x == c => return Ok(()),
+            _ => return Err(self.error(&format!("expected '{}'", c as char))),
+        }
+    }
+
+    fn end_of_line(&mut self) -> Result<(), String> {
+        self.skip_ws(false);
+        match self.peek() {
+            None | Some(b'\n') => return Ok(()),
+            _ => return Err(self.error("unexpected text after value")),
+        }
+    }
+
+    fn key(&mut self) -> Result<Vec<String>, String> {
+        let mut parts = Vec::new();
+        loop {
+            self.skip_ws(false);
+            let part = match self.peek() {
+                Some(b'"') | Some(b'\'') => self.string()?,
+                _ => {
+                    let start = self.pos;
+                    while matches!(self.peek(), Some(c) if c.is_ascii_alphanumeric() || c == b'_' || c == b'-') {
+                        self.bump();
+                    }
+                    if start == self.pos {
+                        return Err(self.error("expected a key"));
+                    }
+                    String::from_utf8_lossy(&self.text[start..self.pos]).to_string()
+                },
+            };
+            parts.push(part);
+            self.skip_ws(false);
+            if self.peek() != Some(b'.') {
+                return Ok(parts);
+            }
+            self.bump();
+        }
+    }
+
+    fn string(&mut self) -> Result<String, String> {
+        let quote = self.bump().unwrap();
+        let mut out = Vec::new();
+        loop {
+            match self.bump() {
+                None | Some(b'\n') => return Err(self.error("unterminated string")),
+                Some(c) if c == quote => break,
+                Some(b'\\') if quote == b'"' => {
+                    let escaped = match self.bump() {
+                        Some(b'n') => b'\n',
+                        Some(b't') => b'\t',
+                        Some(b'r') => b'\r',
+                        Some(b'\\') => b'\\',
+                        Some(b'"') => b'"',
+                        _ => return Err(self.error("unsupported escape sequence")),
+                    };
+                    out.push(escaped);
+                },
+                Some(c) => out.push(c),
+            }
+        }
+        return Ok(String::from_utf8_lossy(&out).to_string());
+    }
+
+    fn value(&mut self) -> Result<TomlValue, String> {
+        self.skip_ws(false);
+        match self.peek() {
+            Some(b'"') | Some(b'\'') => return Ok(TomlValue::String(self.string()?)),
+            Some(b'[') => {
+                self.bump();
+                let mut items = Vec::new();
+                loop {
+                    self.skip_ws(true);
+                    if self.peek() == Some(b']') {
+                        self.bump();
+                        return Ok(TomlValue::Array(items));
+                    }
+                    items.push(self.value()?);
+                    self.skip_ws(true);
+                    match self.peek() {
+                        Some(b',') => { self.bump(); },
+                        Some(b']') => {},
+                        _ => return Err(self.error("expected ',' or ']' in array")),
+                    }
+                }
+            },
+            Some(b'{') => {
+                self.bump();
+                let mut table = TomlTable::new();
+                loop {
+                    self.skip_ws(false);
+                    if self.peek() == Some(b'}') {
+                        self.bump();
+                        return Ok(TomlValue::Table(table));
+                    }
+                    let key = self.key()?;
+                    self.expect(b'=')?;
+                    let value = self.value()?;
+                    insert(&mut table, &key, value).map_err(|e| self.error(&e))?;
+                    self.skip_ws(false);
+                    match self.peek() {
+                        Some(b',') => { self.bump(); },
+                        Some(b'}') => {},
+                        _ => return Err(self.error("expected ',' or '}' in inline table")),
+                    }
+                }
+            },
+            _ => {
+                let start = self.pos;
+                while matches!(self.peek(), Some(c) if c.is_ascii_alphanumeric() || b"+-._".contains(&c)) {
+                    self.bump();
+                }
+                let word = String::from_utf8_lossy(&self.text[start..self.pos]).replace('_', "");
+                return match word.as_str() {
+                    "true" => Ok(TomlValue::Boolean(true)),
+                    "false" => Ok(TomlValue::Boolean(false)),
+                    _ => {
+                        if let Ok(i) = word.parse::<i64>() {
+                            Ok(TomlValue::Integer(i))
+                        } else if let Ok(f) = word.parse::<f64>() {
+                            Ok(TomlValue::Float(f))
+                        } else {
+                            Err(self.error("expected a value"))
+                        }
+                    },
+                };
+            },
+        }
+    }
+}
+
+fn insert(table: &mut TomlTable, key: &[String], value: TomlValue) -> Result<(), String> {
+    let (last, parents) = key.split_last().unwrap();
+    let table = descend(table, parents)?;
+    if table.contains_key(last) {
+        return Err(format!("duplicate key '{}'", key.join(".")));
+    }
+    table.insert(last.clone(), value);
+    return Ok(());
+}
+
+fn descend<'t>(mut table: &'t mut TomlTable, key: &[String]) -> Result<&'t mut TomlTable, String> {
+    for part in key {
+        let entry = table.entry(part.clone()).or_insert_with(|| TomlValue::Table(TomlTable::new()));
+        table = match entry {
+            TomlValue::Table(t) => t,
+            // [[array]] tables: keys go into the latest element
+            TomlValue::Array(items) => match items.last_mut() {
+                Some(TomlValue::Table(t)) => t,
+                _ => return Err(format!("'{part}' is not a table")),
+            },
+            _ => return Err(format!("'{part}' is not a table")),
+        };
+    }
+    return Ok(table);
+}
+
+pub fn parse_toml(text: &str) -> Result<TomlTable, String> {
+    let mut root = TomlTable::new();
+    let mut current: Vec<String> = Vec::new();
+    let mut p = Parser { text: text.as_bytes(), pos: 0, line: 1 };
+    loop {
+        p.skip_ws(true);
+        match p.peek() {
+            None => return Ok(root),
+            Some(b'[') => {
+                p.bump();
+                let array = p.peek() == Some(b'[');
+                if array {
+                    p.bump();
+                }
+                current = p.key()?;
+                p.expect(b']')?;
+                if array {
+                    p.expect(b']')?;
+                    let (last, parents) = current.split_last().unwrap();
+                    let parent = descend(&mut root, parents).map_err(|e| p.error(&e))?;
+                    let entry = parent.entry(last.clone()).or_insert_with(|| TomlValue::Array(Vec::new()));
+                    match entry {
+                        TomlValue::Array(items) => items.push(TomlValue::Table(TomlTable::new())),
+                        _ => return Err(p.error(&format!("'{last}' is not an array of tables"))),
+                    }
+                } else {
+                    descend(&mut root, &current).map_err(|e| p.error(&e))?;
+                }
+                p.end_of_line()?;
+            },
+            Some(_) => {
+                let key = p.key()?;
+                p.expect(b'=')?;
+                let value = p.value()?;
+                p.end_of_line()?;
+                let table = descend(&mut root, &current).map_err(|e| p.error(&e))?;
+                insert(table, &key, value).map_err(|e| p.error(&e))?;
+            },
+        }
+    }
+}
+
+//----
+// Typed settings
+
+#[derive(Debug, Clone)]
+pub struct Config {
+    /// Use the built-in exclude list
+    pub builtin_excludes: bool,
+    /// Gitignore-style patterns of files to skip
+    pub exclude: Vec<String>,
+    /// Gitignore-style patterns of files to analyze; empty means all files
+    pub include: Vec<String>,
+    /// Gitignore-style patterns of documentation files, for --split-docs;
+    /// empty means the built-in ones
+    pub docs: Vec<String>,
+    /// Team name and its members (emails or names), sorted by team name
+    pub teams: Vec<(String, Vec<String>)>,
+    /// Patterns of authors whose counts are only reported as one "Opted-out" row
+    pub opt_out: Vec<String>,
+    /// Weight of each signal in the --health-score
+    pub health_weights: HealthWeights,
+    /// Age in days after which --health-score counts a line as stale
+    pub stale_after_days: u32,
+    /// Rows of the blamed lines without a regular author
+    pub labels: SpecialLabels,
+    /// Languages and their file extensions, sorted by language
+    pub languages: Vec<(String, Vec<String>)>,
+    /// Patterns of source files (e.g. IDL) and of the files generated from
+    /// them, sorted by source pattern
+    pub generated: Vec<(String, Vec<String>)>,
+    /// Patterns of files and of the automated authors whose lines in them
+    /// don't count, sorted by file pattern
+    pub automated: Vec<(String, Vec<String>)>,
+    /// Email domains and the domain each stands for, sorted by domain
+    pub domains: Vec<(String, String)>,
+    /// Apply the identity heuristics
+    pub heuristics: bool,
+}
+
+impl Default for Config {
+    fn default() -> Config {
+        return Config {
+            builtin_excludes: false,
+            exclude: Vec::new(),
+            include: Vec::new(),
+            docs: Vec::new(),
+            teams: Vec::new(),
+            opt_out: Vec::new(),
+            health_weights: HealthWeights::default(),
+            stale_after_days: DEFAULT_STALE_AFTER_DAYS,
+            labels: SpecialLabels::default(),
+            languages: Vec::new(),
+            generated: Vec::new(),
+            automated: Vec::new(),
+            domains: Vec::new(),
+            heuristics: true,
+        };
+    }
+}
+
+pub fn get<'t>(table: &'t TomlTable, key: &str) -> Option<&'t TomlValue> {
+    let mut parts = key.split('.').peekable();
+    let mut table = table;
+    while let Some(part) = parts.next() {
+        let value = table.get(part)?;
+        if parts.peek().is_none() {
+            return Some(value);
+        }
+        match value {
+            TomlValue::Table(t) => table = t,
+            _ => return None,
+        }
+    }
+    return None;
+}
+
+fn type_error(key: &str, expected: &str, found: &TomlValue) -> String {
+    return format!("'{key}' should be {expected}, found {}", found.type_name());
+}
+
+fn get_bool(table: &TomlTable, key: &str) -> Result<Option<bool>, String> {
+    match get(table, key) {
+        None => return Ok(None),
+        Some(TomlValue::Boolean(b)) => return Ok(Some(*b)),
+        Some(v) => return Err(type_error(key, "a boolean", v)),
+    }
+}
+
+fn get_float(table: &TomlTable, key: &str) -> Result<Option<f64>, String> {
+    match get(table, key) {
+        None => return Ok(None),
+        Some(TomlValue::Float(f)) => return Ok(Some(*f)),
+        Some(TomlValue::Integer(i)) => return Ok(Some(*i as f64)),
+        Some(v) => return Err(type_error(key, "a number", v)),
+    }
+}
+
+fn get_string(table: &TomlTable, key: &str) -> Result<Option<String>, String> {
+    match get(table, key) {
+        None => return Ok(None),
+        Some(TomlValue::String(s)) if !s.trim().is_empty() => return Ok(Some(s.trim().to_string())),
+        Some(v) => return Err(type_error(key, "a non-empty string", v)),
+    }
+}
+
+fn get_days(table: &TomlTable, key: &str) -> Result<Option<u32>, String> {
+    match get(table, key) {
+        None => return Ok(None),
+        Some(TomlValue::Integer(i)) if *i > 0 => return Ok(Some((*i).min(u32::MAX as i64) as u32)),
+        Some(v) => return Err(type_error(key, "a positive integer", v)),
+    }
+}
+
+pub fn get_str_list(table: &TomlTable, key: &str) -> Result<Vec<String>, String> {
+    return str_list(key, get(table, key));
+}
+
+fn str_list(key: &str, value: Option<&TomlValue>) -> Result<Vec<String>, String> {
+    match value {
+        None => return Ok(Vec::new()),
+        Some(TomlValue::Array(items)) => {
+            return items.iter().map(|item| match item {
+                TomlValue::String(s) => Ok(s.clone()),
+                v => Err(type_error(key, "an array of strings", v)),
+            }).collect();
+        },
+        Some(v) => return Err(type_error(key, "an array of strings", v)),
+    }
+}
+
+/// A table of string lists, e.g. `[teams]`, sorted by key
+fn get_str_lists(table: &TomlTable, key: &str) -> Result<Vec<(String, Vec<String>)>, String> {
+    match get(table, key) {
+        None => return Ok(Vec::new()),
+        Some(TomlValue::Table(t)) => {
+            let mut lists = Vec::new();
+            for name in t.keys() {
+                // Keys may be quoted, with dots in them
+                lists.push((name.clone(), str_list(name, t.get(name)).map_err(|e| format!("{key}.{e}"))?));
+            }
+            lists.sort();
+            return Ok(lists);
+        },
+        Some(v) => return Err(type_error(key, "a table", v)),
+    }
+}
+
+/// A table of strings, e.g. `[identity.domains]`, sorted by key
+fn get_str_table(table: &TomlTable, key: &str) -> Result<Vec<(String, String)>, String> {
+    match get(table, key) {
+        None => return Ok(Vec::new()),
+        Some(TomlValue::Table(t)) => {
+            let mut strings = Vec::new();
+            for (name, value) in t {
+                match value {
+                    TomlValue::String(s) if !s.trim().is_empty() => strings.push((name.clone(), s.trim().to_string())),
+                    v => return Err(format!("{key}.{}", type_error(name, "a non-empty string", v))),
+                }
+            }
+            strings.sort();
+            return Ok(strings);
+        },
+        Some(v) => return Err(type_error(key, "a table", v)),
+    }
+}
+
+impl Config {
+    pub fn parse(text: &str) -> Result<Config, String> {
+        let doc = parse_toml(text)?;
+        let defaults = HealthWeights::default();
+        let health_weights = HealthWeights {
+            bus_factor: get_float(&doc, "health.bus_factor")?.unwrap_or(defaults.bus_factor),
+            entropy: get_float(&doc, "health.entropy")?.unwrap_or(defaults.entropy),
+            stale: get_float(&doc, "health.stale")?.unwrap_or(defaults.stale),
+            active_authors: get_float(&doc, "health.active_authors")?.unwrap_or(defaults.active_authors),
+        };
+        health_weights.validate()?;
+        let default_labels = SpecialLabels::default();
+        let labels = SpecialLabels {
+            boundary: get_string(&doc, "authors.boundary")?.unwrap_or(default_labels.boundary),
+            uncommitted: get_string(&doc, "authors.uncommitted")?.unwrap_or(default_labels.uncommitted),
+            unknown: get_string(&doc, "authors.unknown")?.unwrap_or(default_labels.unknown),
+        };
+        return Ok(Config {
+            builtin_excludes: get_bool(&doc, "files.builtin_excludes")?.unwrap_or(false),
+            exclude: get_str_list(&doc, "files.exclude")?,
+            include: get_str_list(&doc, "files.include")?,
+            docs: get_str_list(&doc, "files.docs")?,
+            teams: get_str_lists(&doc, "teams")?,
+            opt_out: get_str_list(&doc, "authors.opt_out")?,
+            health_weights,
+            stale_after_days: get_days(&doc, "health.stale_after_days")?.unwrap_or(DEFAULT_STALE_AFTER_DAYS),
+            labels,
+            languages: get_str_lists(&doc, "languages")?,
+            generated: get_str_lists(&doc, "generated")?,
+            automated: get_str_lists(&doc, "automated")?,
+            domains: get_str_table(&doc, "identity.domains")?,
+            heuristics: get_bool(&doc, "identity.heuristics")?.unwrap_or(true),
+        });
+    }
+
+    /// Load the config given on the command line, or the repo's own config
+    /// file if there is one
+    pub fn load(repo_root: &str, path: &Option<String>) -> Result<Config, String> {
+        let (path, required) = match path {
+            Some(path) => (Path::new(path).to_path_buf(), true),
+            None => (Path::new(repo_root).join(CONFIG_FILE_NAME), false),
+        };
+        match recording::read_to_string(&path) {
+            Ok(text) => return Config::parse(&text).map_err(|e| format!("{}: {e}", path.display())),
+            Err(_) if !required => return Ok(Config::default()),
+            Err(e) => return Err(format!("{}: {e}", path.display())),
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn parses_settings() {
+        let config = Config::parse(r#"
+# comment
+[files]
+builtin_excludes = true
+exclude = [
+    "vendor/",  # trailing comma and comments
+    '*.min.js',
+]
+docs = ["*.md", "handbook/"]
+
+[teams]
+web = ["bob@example.com"]
+core = ["Alice"]
+
+[authors]
+opt_out = ["jane@example.com"]
+uncommitted = "Work in progress"
+
+[languages]
+SystemVerilog = ["sv", "svh"]
+
+[generated]
+"*.proto" = ["*.pb.go", "*_pb2.py"]
+
+[automated]
+"generated/" = ["build-bot@example.com", "re:.*\\[bot\\]"]
+
+[identity]
+heuristics = false
+
+[identity.domains]
+"old-company.com" = "example.com"
+
+[health]
+stale = 2
+entropy = 0.5
+stale_after_days = 180
+"#).unwrap();
+        assert!(config.builtin_excludes);
+        assert_eq!(config.exclude, vec!["vendor/", "*.min.js"]);
+        assert!(config.include.is_empty());
+        assert_eq!(config.docs, vec!["*.md", "handbook/"]);
+        assert_eq!(config.teams[0], ("core".to_string(), vec!["Alice".to_string()]));
+        assert_eq!(config.opt_out, vec!["jane@example.com"]);
+        assert_eq!(config.health_weights, HealthWeights { stale: 2.0, entropy: 0.5, ..HealthWeights::default() });
+        assert_eq!(config.stale_after_days, 180);
+        assert_eq!(config.languages, [("SystemVerilog".to_string(), vec!["sv".to_string(), "svh".to_string()])]);
+        assert_eq!(config.generated, [("*.proto".to_string(), vec!["*.pb.go".to_string(), "*_pb2.py".to_string()])]);
+        assert_eq!(config.automated, [("generated/".to_string(), vec!["build-bot@example.com".to_string(), "re:.*\\[bot\\]".to_string()])]);
+        assert_eq!(config.domains, [("old-company.com".to_string(), "example.com".to_string())]);
+        assert!(!config.heuristics && Config::default().heuristics);
+        assert_eq!(config.labels, SpecialLabels { uncommitted: "Work in progress".to_string(), ..SpecialLabels::default() });
+    }
+
+    #[test]
+    fn reports_errors() {
+        assert!(Config::parse("[files]\nexclude = \"vendor/\"").unwrap_err().contains("array of strings"));
+        assert!(Config::parse("a = 1\na = 2").unwrap_err().contains("duplicate key"));
+        assert!(Config::parse("a = [1, 2").unwrap_err().starts_with("line 1"));
+        assert!(Config::parse("[health]\nstale_after_days = 0").unwrap_err().contains("positive integer"));
+        assert!(Config::parse("[health]\nstale = -1").unwrap_err().contains("negative"));
+        let doc = parse_toml("x = { y = 1.5, z = [true] }\n[[t]]\nk = 'v'\n[[t]]\nk = 'w'").unwrap();
+        assert_eq!(get(&doc, "x.y"), Some(&TomlValue::Float(1.5)));
+        assert!(matches!(get(&doc, "t"), Some(TomlValue::Array(items)) if items.len() == 2));
+    }
+}
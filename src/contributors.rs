@@ -0,0 +1,271 @@
+use hashbrown::{HashMap, HashSet};
+use std::path::Path;
+
+use crate::backend::{Backend, CommitInfo};
+use crate::error::{Error, Result};
+use crate::filter::FileFilter;
+use crate::identity::{AuthorFilter, IdentityResolver, OptOut, Special};
+use crate::{AuthorCount, Count};
+
+//----
+// Release contributors
+//
+// `contributors --since <ref>` lists who committed between two refs, usually
+// the previous release's tag and HEAD, as a Markdown section to paste into the
+// release notes.  Authors are resolved like in the reports (mailmap, aliases,
+// --author-format, --author, opt-outs) and lines only count in files the
+// reports would analyze; commits touching nothing else are left out.
+//
+// Authors with no commit before the range get a "First-time contributors"
+// entry: the file of their oldest lines that survive at the end of the range,
+// the commit of those lines and the merge that brought it in, and whoever else
+// owns the most lines of that file's directory, a likely onboarding buddy.
+
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct Contributor {
+    pub name: String,
+    pub commits: usize,
+    pub added: Count,
+    pub deleted: Count,
+}
+
+/// Commits reachable from `until` but not from `since`, under the `scope` paths
+pub fn range_log(backend: &dyn Backend, since: &str, until: &str, scope: &[String]) -> Result<Vec<CommitInfo>> {
+    for rev in [since, until] {
+        if backend.revision(&Some(rev.to_string()), &None)?.is_none() {
+            return Err(Error::Config(format!("contributors: unknown revision {rev}")));
+        }
+    }
+    return backend.commit_log(&Some(format!("{since}..{until}")), scope);
+}
+
+/// Commits and lines per resolved author, most commits first
+pub fn tally(commits: &[CommitInfo], filter: &FileFilter, resolver: &IdentityResolver, authors: &AuthorFilter, opt_out: &OptOut) -> Vec<Contributor> {
+    let mut totals: HashMap<String, Contributor> = HashMap::new();
+    let mut raw = Vec::new();
+    for commit in commits.iter().filter(|commit| authors.keeps(&commit.author, resolver)) {
+        let files: Vec<_> = commit.files.iter().filter(|f| filter.reason_to_skip(&f.path).is_none()).collect();
+        if files.is_empty() {
+            continue;
+        }
+        let author = match opt_out.opted_out(&commit.author, resolver) {
+            true => OptOut::aggregate(),
+            false => commit.author.clone(),
+        };
+        let total = totals.entry(resolver.resolve(&author).author.key()).or_insert(Contributor {
+            name: String::new(),
+            commits: 0,
+            added: 0,
+            deleted: 0,
+        });
+        total.commits += 1;
+        total.added += files.iter().map(|f| f.added).sum::<Count>();
+        total.deleted += files.iter().map(|f| f.deleted).sum::<Count>();
+        raw.push(author);
+    }
+
+    let names = resolver.display_names(raw.iter().map(|author| (author, 1)));
+    let mut contributors: Vec<Contributor> = totals.into_iter()
+        .map(|(key, mut total)| {
+            total.name = names.get(&key).cloned().unwrap_or(key);
+            total
+        })
+        .collect();
+    contributors.sort_by(|a, b| b.commits.cmp(&a.commits).then(b.added.cmp(&a.added)).then(a.name.cmp(&b.name)));
+    return contributors;
+}
+
+/// An author whose first commits are in the range
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct FirstTimer {
+    pub name: String,
+    /// None if none of their lines survive
+    pub first: Option<FirstLines>,
+}
+
+/// Where a first-time contributor's oldest surviving lines are
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct FirstLines {
+    pub file: String,
+    /// Full commit ids
+    pub commit: String,
+    pub merge: Option<String>,
+    /// The file's directory; empty for the top level
+    pub dir: String,
+    /// Who else owns the most lines of `dir`
+    pub buddy: Option<String>,
+}
+
+/// The authors of `commits` with no commit before `since`, by name, with
+/// their first surviving lines at `until`
+#[allow(clippy::too_many_arguments)]
+pub fn first_timers(backend: &dyn Backend, since: &str, until: &str, scope: &[String], commits: &[CommitInfo],
+    filter: &FileFilter, resolver: &IdentityResolver, authors: &AuthorFilter, opt_out: &OptOut) -> Result<Vec<FirstTimer>> {
+    let earlier: HashSet<String> = backend.commit_log(&Some(since.to_string()), scope)?.iter()
+        .map(|commit| resolver.resolve(&commit.author).author.key())
+        .collect();
+    let mut touched: HashMap<String, Vec<String>> = HashMap::new();
+    let mut raw = Vec::new();
+    for commit in commits.iter().filter(|commit| authors.keeps(&commit.author, resolver) && !opt_out.opted_out(&commit.author, resolver)) {
+        let key = resolver.resolve(&commit.author).author.key();
+        if earlier.contains(&key) {
+            continue;
+        }
+        let files = touched.entry(key).or_default();
+        for file in commit.files.iter().filter(|f| filter.reason_to_skip(&f.path).is_none()) {
+            if !files.contains(&file.path) {
+                files.push(file.path.clone());
+            }
+        }
+        raw.push(commit.author.clone());
+    }
+    let names = resolver.display_names(raw.iter().map(|author| (author, 1)));
+
+    let mut first_timers = Vec::new();
+    for (key, files) in touched.iter().filter(|(_, files)| !files.is_empty()) {
+        let name = names.get(key).cloned().unwrap_or(key.clone());
+        first_timers.push(FirstTimer { name, first: first_lines(backend, until, key, files, filter, resolver)? });
+    }
+    first_timers.sort_by(|a, b| a.name.cmp(&b.name));
+    return Ok(first_timers);
+}
+
+/// The oldest surviving lines at `until` by the author `key` in `files`
+fn first_lines(backend: &dyn Backend, until: &str, key: &str, files: &[String], filter: &FileFilter, resolver: &IdentityResolver) -> Result<Option<FirstLines>> {
+    let present: HashSet<String> = backend.files(until, files)?.into_iter().map(|entry| entry.path).collect();
+    // (time, commit, file) of each line, oldest first, then most lines
+    let mut lines: HashMap<(i64, String, String), Count> = HashMap::new();
+    for file in files.iter().filter(|file| present.contains(*file)) {
+        for line in backend.blame_lines(until, file)? {
+            if resolver.resolve(&line.author).author.key() == key {
+                *lines.entry((line.time, line.commit, file.clone())).or_insert(0) += 1;
+            }
+        }
+    }
+    let Some(((_, commit, file), _)) = lines.into_iter().min_by(|(a, a_lines), (b, b_lines)| {
+        a.0.cmp(&b.0).then(b_lines.cmp(a_lines)).then(a.2.cmp(&b.2))
+    }) else {
+        return Ok(None);
+    };
+
+    let dir = Path::new(&file).parent().map(|dir| dir.to_string_lossy().to_string()).unwrap_or_default();
+    let scope = match dir.is_empty() {
+        true => Vec::new(),
+        false => vec![dir.clone()],
+    };
+    let mut owners = AuthorCount::new();
+    for entry in backend.files(until, &scope)?.into_iter().filter(|entry| filter.reason_to_skip(&entry.path).is_none()) {
+        for (author, count) in backend.blame(until, &entry.path)?.authors {
+            *owners.entry(author).or_insert(0) += count;
+        }
+    }
+    owners.retain(|author, _| Special::of(author).is_none() && resolver.resolve(author).author.key() != key);
+    let names = resolver.display_names(owners.iter().map(|(author, count)| (author, *count)));
+    let mut by_key: HashMap<String, Count> = HashMap::new();
+    for (author, count) in &owners {
+        *by_key.entry(resolver.resolve(author).author.key()).or_insert(0) += count;
+    }
+    let buddy = by_key.into_iter()
+        .max_by(|(a, a_lines), (b, b_lines)| a_lines.cmp(b_lines).then(b.cmp(a)))
+        .map(|(key, _)| names.get(&key).cloned().unwrap_or(key));
+    let merge = backend.merge_of(&commit, until)?;
+    return Ok(Some(FirstLines { file, commit, merge, dir, buddy }));
+}
+
+fn commits(n: usize) -> String {
+    match n {
+        1 => return "1 commit".to_string(),
+        _ => return format!("{n} commits"),
+    }
+}
+
+/// The "Contributors" section for the release notes of `since`..`until`
+pub fn render_markdown(contributors: &[Contributor], since: &str, until: &str) -> String {
+    let mut out = String::from("## Contributors\n\n");
+    if contributors.is_empty() {
+        out.push_str(&format!("No commits between {since} and {until}.\n"));
+        return out;
+    }
+    let people = match contributors.len() {
+        1 => "1 person".to_string(),
+        n => format!("{n} people"),
+    };
+    out.push_str(&format!("Thanks to the {people} who contributed between {since} and {until}:\n\n"));
+    for contributor in contributors {
+        out.push_str(&format!("- **{}**: {}, +{} / -{} lines\n",
+            contributor.name, commits(contributor.commits), contributor.added, contributor.deleted));
+    }
+    return out;
+}
+
+fn short(commit: &str) -> &str {
+    return &commit[..commit.len().min(7)];
+}
+
+/// The "First-time contributors" subsection; empty if there are none
+pub fn render_first_timers(first_timers: &[FirstTimer]) -> String {
+    if first_timers.is_empty() {
+        return String::new();
+    }
+    let mut out = String::from("\n### First-time contributors\n\n");
+    for first_timer in first_timers {
+        let Some(first) = &first_timer.first else {
+            out.push_str(&format!("- **{}**: no lines left yet\n", first_timer.name));
+            continue;
+        };
+        let landed = match &first.merge {
+            Some(merge) => format!("{}, merged in {}", short(&first.commit), short(merge)),
+            None => short(&first.commit).to_string(),
+        };
+        let dir = match first.dir.is_empty() {
+            true => "the top level".to_string(),
+            false => format!("`{}/`", first.dir),
+        };
+        let buddy = match &first.buddy {
+            Some(buddy) => format!("; {buddy} owns the most of {dir}"),
+            None => format!("; nobody else owns lines in {dir}"),
+        };
+        out.push_str(&format!("- **{}**: first lines in `{}` ({landed}){buddy}\n", first_timer.name, first.file));
+    }
+    return out;
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::fixture::Fixture;
+
+    #[test]
+    fn lists_contributors_between_refs() {
+        let fixture = Fixture::new();
+        let backend = fixture.backend();
+        let resolver = IdentityResolver::default();
+        let everyone = AuthorFilter::default();
+        let commits = range_log(backend.as_ref(), "HEAD~2", "HEAD", &[]).unwrap();
+        assert_eq!(commits.len(), 2);
+
+        let filter = FileFilter::default();
+        let contributors = tally(&commits, &filter, &resolver, &everyone, &OptOut::default());
+        assert_eq!(contributors, [Contributor { name: "Bob".to_string(), commits: 2, added: 3, deleted: 1 }]);
+        let markdown = render_markdown(&contributors, "HEAD~2", "HEAD");
+        assert!(markdown.starts_with("## Contributors\n"));
+        assert!(markdown.contains("Thanks to the 1 person who"));
+        assert!(markdown.contains("- **Bob**: 2 commits, +3 / -1 lines\n"));
+
+
+        // Bob's first commit is in the range; his oldest lines are most in src/main.rs
+        let newcomers = first_timers(backend.as_ref(), "HEAD~2", "HEAD", &[], &commits, &filter, &resolver, &everyone, &OptOut::default()).unwrap();
+        let first = newcomers[0].first.clone().unwrap();
+        assert_eq!((newcomers.len(), newcomers[0].name.as_str()), (1, "Bob"));
+        assert_eq!((first.file.as_str(), first.dir.as_str(), first.merge, first.buddy.as_deref()), ("src/main.rs", "src", None, Some("Alice")));
+        let section = render_first_timers(&newcomers);
+        assert_eq!(section, format!("\n### First-time contributors\n\n- **Bob**: first lines in `src/main.rs` ({}); Alice owns the most of `src/`\n", short(&first.commit)));
+        let commits = range_log(backend.as_ref(), "HEAD~1", "HEAD", &[]).unwrap();
+        assert!(first_timers(backend.as_ref(), "HEAD~1", "HEAD", &[], &commits, &filter, &resolver, &everyone, &OptOut::default()).unwrap().is_empty());
+
+        let commits = range_log(backend.as_ref(), "HEAD~3", "HEAD", &["src".to_string()]);
+        assert!(commits.is_err());
+        let commits = range_log(backend.as_ref(), "HEAD~2", "HEAD~1", &["README.md".to_string()]).unwrap();
+        assert!(tally(&commits, &filter, &resolver, &everyone, &OptOut::default()).is_empty());
+    }
+}
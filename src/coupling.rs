@@ -0,0 +1,106 @@
+use hashbrown::HashMap;
+
+use crate::backend::CommitInfo;
+use crate::filter::FileFilter;
+
+//----
+// Change coupling
+//
+// --coupling finds files that keep changing in the same commits, like the
+// coupling analysis of code-maat: the degree of a pair is the number of
+// commits changing both over the mean number of commits changing each.  The
+// report then tells whether the two files have the same owner; tightly coupled
+// files owned by different people (or teams) need them to coordinate.
+
+/// Commits changing more files than this are left out: mass renames,
+/// reformats and vendoring say nothing about how files depend on each other
+pub const MAX_CHANGESET: usize = 30;
+
+/// Pairs changed together in fewer commits than this aren't reported
+pub const MIN_SHARED: usize = 3;
+
+/// Lowest degree reported
+pub const MIN_DEGREE: f64 = 0.5;
+
+#[derive(Debug, Clone, PartialEq)]
+pub struct Coupling {
+    /// In name order
+    pub files: (String, String),
+    /// Commits changing both
+    pub shared: usize,
+    /// Commits changing each
+    pub revisions: (usize, usize),
+}
+
+impl Coupling {
+    /// Shared commits over the mean commits per file, 0..1
+    pub fn degree(&self) -> f64 {
+        let mean = (self.revisions.0 + self.revisions.1) as f64 / 2.0;
+        return self.shared as f64 / mean;
+    }
+}
+
+/// Pairs of files coupled by at least MIN_SHARED commits and MIN_DEGREE, most
+/// tightly coupled first.  Files the filter skips are left out.
+pub fn coupled_files(commits: &[CommitInfo], filter: &FileFilter) -> Vec<Coupling> {
+    let mut revisions: HashMap<&str, usize> = HashMap::new();
+    let mut shared: HashMap<(&str, &str), usize> = HashMap::new();
+    for commit in commits {
+        let mut files: Vec<&str> = commit.files.iter()
+            .map(|f| f.path.as_str())
+            .filter(|path| filter.reason_to_skip(path).is_none())
+            .collect();
+        files.sort();
+        files.dedup();
+        if files.len() > MAX_CHANGESET {
+            continue;
+        }
+        for (i, a) in files.iter().enumerate() {
+            *revisions.entry(a).or_insert(0) += 1;
+            for b in &files[i + 1..] {
+                *shared.entry((a, b)).or_insert(0) += 1;
+            }
+        }
+    }
+
+    let mut pairs: Vec<Coupling> = shared.into_iter()
+        .filter(|&(_, count)| count >= MIN_SHARED)
+        .map(|((a, b), count)| Coupling {
+            files: (a.to_string(), b.to_string()),
+            shared: count,
+            revisions: (revisions[a], revisions[b]),
+        })
+        .filter(|pair| pair.degree() >= MIN_DEGREE)
+        .collect();
+    pairs.sort_by(|a, b| b.degree().total_cmp(&a.degree()).then(b.shared.cmp(&a.shared)).then(a.files.cmp(&b.files)));
+    return pairs;
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::backend::FileChange;
+    use crate::identity::Author;
+
+    fn commit(paths: &[&str]) -> CommitInfo {
+        let files = paths.iter().map(|path| FileChange { path: path.to_string(), added: 1, deleted: 0 }).collect();
+        return CommitInfo { author: Author::new("Alice", "alice@example.com"), time: 0, files };
+    }
+
+    #[test]
+    fn finds_coupled_pairs() {
+        let mut commits = vec![commit(&["a.rs", "b.rs"]); 3];
+        commits.push(commit(&["a.rs"]));
+        commits.push(commit(&["b.rs", "c.rs"]));
+        // Too large to say anything
+        let mut many: Vec<String> = (0..=MAX_CHANGESET).map(|i| format!("gen/{i}.rs")).collect();
+        many.push("c.rs".to_string());
+        commits.extend(std::iter::repeat_n(commit(&many.iter().map(|s| s.as_str()).collect::<Vec<_>>()), 3));
+
+        let pairs = coupled_files(&commits, &FileFilter::default());
+        assert_eq!(pairs.len(), 1);
+        assert_eq!(pairs[0].files, ("a.rs".to_string(), "b.rs".to_string()));
+        assert_eq!((pairs[0].shared, pairs[0].revisions), (3, (4, 4)));
+        assert_eq!(pairs[0].degree(), 0.75);
+    }
+}
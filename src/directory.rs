@@ -0,0 +1,172 @@
+use std::fs;
+#[cfg(feature = "ldap")]
+use std::process::Command;
+
+use hashbrown::HashMap;
+
+//----
+// People directory
+//
+// --directory FILE maps author emails to attributes such as title, location
+// and cost center, so --group-by title, location or cost-center rolls the
+// author rows up under those headings, like the [teams] table does.  The CSV's
+// header names its columns, one of which must be `email`:
+//
+//   email,title,location,cost_center
+//   jdoe@example.com,Staff Engineer,Berlin,R&D 42
+//
+// Built with the `ldap` feature, an ldap:// or ldaps:// URL (with the search
+// base as its path) reads the same attributes from the mail, title, l and
+// departmentNumber attributes of the directory's entries, with the
+// `ldapsearch` command line tool.
+
+#[derive(Debug, Clone, Default)]
+pub struct Directory {
+    /// Column names, normalized; `email` isn't one of them
+    columns: Vec<String>,
+    /// Lowercased email -> one value per column
+    people: HashMap<String, Vec<String>>,
+}
+
+/// Lowercase a column name, with spaces and dashes as underscores
+pub fn column_name(name: &str) -> String {
+    return name.trim().to_lowercase().replace([' ', '-'], "_");
+}
+
+/// Split a CSV line into fields, unquoting quoted ones
+pub fn split_csv(line: &str) -> Vec<String> {
+    let mut fields = vec![String::new()];
+    let mut quoted = false;
+    let mut chars = line.chars().peekable();
+    while let Some(c) = chars.next() {
+        match (c, quoted) {
+            ('"', true) if chars.peek() == Some(&'"') => {
+                chars.next();
+                fields.last_mut().unwrap().push('"');
+            },
+            ('"', _) => quoted = !quoted,
+            (',', false) => fields.push(String::new()),
+            (c, _) => fields.last_mut().unwrap().push(c),
+        }
+    }
+    return fields.into_iter().map(|field| field.trim().to_string()).collect();
+}
+
+impl Directory {
+    /// Read a CSV file, or with the `ldap` feature an LDAP URL
+    pub fn load(source: &str) -> Result<Directory, String> {
+        if source.starts_with("ldap://") || source.starts_with("ldaps://") {
+            #[cfg(feature = "ldap")]
+            return Directory::from_ldap(source);
+            #[cfg(not(feature = "ldap"))]
+            return Err(format!("--directory {source}: LDAP needs a build with the ldap feature"));
+        }
+        let text = fs::read_to_string(source).map_err(|e| format!("--directory {source}: {e}"))?;
+        return Directory::parse(&text).map_err(|e| format!("--directory {source}: {e}"));
+    }
+
+    /// Parse CSV text with a header line
+    pub fn parse(text: &str) -> Result<Directory, String> {
+        let mut lines = text.lines().filter(|line| !line.trim().is_empty());
+        let header: Vec<String> = split_csv(lines.next().unwrap_or("")).iter().map(|name| column_name(name)).collect();
+        let email = header.iter().position(|name| name == "email").ok_or("no email column")?;
+        let mut directory = Directory { columns: header.clone(), people: HashMap::new() };
+        directory.columns.remove(email);
+        for line in lines {
+            let mut fields = split_csv(line);
+            fields.resize(header.len(), String::new());
+            let address = fields.remove(email).to_lowercase();
+            if !address.is_empty() {
+                directory.people.insert(address, fields);
+            }
+        }
+        return Ok(directory);
+    }
+
+    /// Run ldapsearch on `url`, anonymously unless ldap.conf says otherwise
+    #[cfg(feature = "ldap")]
+    fn from_ldap(url: &str) -> Result<Directory, String> {
+        let (scheme, rest) = url.split_once("://").unwrap();
+        let (host, base) = rest.split_once('/').unwrap_or((rest, ""));
+        let output = Command::new("ldapsearch")
+            .args(["-LLL", "-x", "-H", &format!("{scheme}://{host}")])
+            .args(["-b", base, "(mail=*)", "mail", "title", "l", "departmentNumber"])
+            .output()
+            .map_err(|e| format!("--directory: failed to run ldapsearch: {e}"))?;
+        if !output.status.success() {
+            let stderr = String::from_utf8_lossy(&output.stderr);
+            return Err(format!("--directory {url}: {}", stderr.lines().next().unwrap_or("ldapsearch failed")));
+        }
+        return Ok(Directory::parse_ldif(&String::from_utf8_lossy(&output.stdout)));
+    }
+
+    /// Entries of ldapsearch -LLL output.  Base64 values (`attr:: ...`) are
+    /// left out.
+    #[cfg(feature = "ldap")]
+    fn parse_ldif(text: &str) -> Directory {
+        const ATTRIBUTES: [(&str, &str); 3] = [("title", "title"), ("l", "location"), ("departmentnumber", "cost_center")];
+        let mut directory = Directory {
+            columns: ATTRIBUTES.iter().map(|(_, column)| column.to_string()).collect(),
+            people: HashMap::new(),
+        };
+        // Lines starting with a space continue the previous one
+        let unfolded = text.replace("\n ", "");
+        for entry in unfolded.split("\n\n") {
+            let mut mail = None;
+            let mut fields = vec![String::new(); ATTRIBUTES.len()];
+            for (name, value) in entry.lines().filter_map(|line| line.split_once(": ")) {
+                let name = name.to_lowercase();
+                match ATTRIBUTES.iter().position(|(attribute, _)| *attribute == name) {
+                    Some(i) => fields[i] = value.trim().to_string(),
+                    None if name == "mail" => mail = Some(value.trim().to_lowercase()),
+                    None => {},
+                }
+            }
+            if let Some(mail) = mail {
+                directory.people.insert(mail, fields);
+            }
+        }
+        return directory;
+    }
+
+    pub fn has_column(&self, column: &str) -> bool {
+        return self.columns.iter().any(|name| name == column);
+    }
+
+    /// The value of a person's attribute, if known and not empty
+    pub fn attribute(&self, email: &str, column: &str) -> Option<&str> {
+        let i = self.columns.iter().position(|name| name == column)?;
+        return self.people.get(&email.to_lowercase())
+            .map(|fields| fields[i].as_str())
+            .filter(|value| !value.is_empty());
+    }
+
+    /// (lowercased email, value) of everyone with a value for `column`
+    pub fn values(&self, column: &str) -> Vec<(&str, &str)> {
+        let mut values: Vec<(&str, &str)> = self.people.keys()
+            .filter_map(|email| self.attribute(email, column).map(|value| (email.as_str(), value)))
+            .collect();
+        values.sort();
+        return values;
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn reads_csv() {
+        let directory = Directory::parse("Email,Title,Location,Cost Center\n\
+            JDoe@example.com,Staff Engineer,Berlin,\"R&D, 42\"\n\
+            bob@example.com,,\"New \"\"York\"\"\",\n").unwrap();
+
+        assert!(directory.has_column("cost_center"));
+        assert_eq!(directory.attribute("jdoe@example.com", "title"), Some("Staff Engineer"));
+        assert_eq!(directory.attribute("jdoe@example.com", "cost_center"), Some("R&D, 42"));
+        assert_eq!(directory.attribute("bob@example.com", "location"), Some("New \"York\""));
+        assert_eq!(directory.attribute("bob@example.com", "title"), None);
+        assert_eq!(directory.values("title"), [("jdoe@example.com", "Staff Engineer")]);
+        assert!(Directory::parse("name,title\nJane,CTO\n").is_err());
+    }
+}
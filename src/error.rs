@@ -0,0 +1,153 @@
+use std::fmt;
+use std::io;
+
+use crate::report::json_string;
+use crate::Date;
+
+//----
+// Errors
+//
+// Library functions return these instead of panicking, so an embedding tool
+// decides what a failure means.  Parsers of user supplied files (config,
+// aliases, patterns) keep reporting plain messages, wrapped in Error::Config.
+
+#[derive(Debug)]
+pub enum Error {
+    /// The path isn't inside a git repository
+    NotARepo(String),
+    /// The path is inside a different repository, or none
+    OutsideRepo { path: String, root: String },
+    /// A git command couldn't be started, or failed
+    Git { command: String, message: String },
+    /// No revision of the branch before a sampled date
+    NoRevision { branch: String, date: Date },
+    /// The excludes skip more of a snapshot's lines than allowed
+    LowCoverage { date: Date, percent: f64, min: f64 },
+    /// Exporting the counts to a database failed
+    Export { target: String, message: String },
+    /// Invalid config file, alias file or file pattern
+    Config(String),
+    Io(io::Error),
+}
+
+pub type Result<T> = std::result::Result<T, Error>;
+
+impl Error {
+    /// Process exit status for the error: 2 for bad input (as for bad
+    /// arguments), 3 when the repo can't be found, 4 when git fails, 5 when a
+    /// snapshot has no revision, 6 when too few lines are counted, 7 when an
+    /// export fails, 1 for I/O errors
+    pub fn exit_code(&self) -> i32 {
+        match self {
+            Error::Config(_) => return 2,
+            Error::NotARepo(_) | Error::OutsideRepo { .. } => return 3,
+            Error::Git { .. } => return 4,
+            Error::NoRevision { .. } => return 5,
+            Error::LowCoverage { .. } => return 6,
+            Error::Export { .. } => return 7,
+            Error::Io(_) => return 1,
+        }
+    }
+
+    /// Stable name of the kind of error, for --error-format json
+    pub fn code(&self) -> &'static str {
+        match self {
+            Error::NotARepo(_) => return "not_a_repo",
+            Error::OutsideRepo { .. } => return "outside_repo",
+            Error::Git { .. } => return "git",
+            Error::NoRevision { .. } => return "no_revision",
+            Error::LowCoverage { .. } => return "low_coverage",
+            Error::Export { .. } => return "export",
+            Error::Config(_) => return "config",
+            Error::Io(_) => return "io",
+        }
+    }
+
+    /// What the run was doing: "configuration", "repository", "analysis",
+    /// "export" or "io"
+    pub fn phase(&self) -> &'static str {
+        match self {
+            Error::Config(_) => return "configuration",
+            Error::NotARepo(_) | Error::OutsideRepo { .. } => return "repository",
+            Error::Git { .. } | Error::NoRevision { .. } | Error::LowCoverage { .. } => return "analysis",
+            Error::Export { .. } => return "export",
+            Error::Io(_) => return "io",
+        }
+    }
+
+    /// A suggestion for what to do about it, for the ones with an obvious fix
+    pub fn hint(&self) -> Option<&'static str> {
+        match self {
+            Error::NotARepo(_) => return Some("run it inside a git repository, or pass a path in one"),
+            Error::OutsideRepo { .. } => return Some("pass paths of one repository, or several with --repos-file"),
+            Error::Git { message, .. } if message.starts_with("failed to start") => return Some("check that git is installed and on PATH"),
+            Error::NoRevision { .. } => return Some("skip such dates with --missing-snapshot skip, or start later with --since"),
+            Error::LowCoverage { .. } => return Some("exclude fewer files, or lower --min-coverage"),
+            _ => return None,
+        }
+    }
+
+    /// The error as one line of JSON: code, exit status, phase, message, the
+    /// git command that failed and a hint, the last two null if there's none
+    pub fn to_json(&self) -> String {
+        let git_command = match self {
+            Error::Git { command, .. } => Some(command.as_str()),
+            _ => None,
+        };
+        return render_json(self.code(), self.exit_code(), self.phase(), &self.to_string(), git_command, self.hint());
+    }
+}
+
+/// An error object as --error-format json prints it, also for the errors that
+/// aren't an Error, such as bad arguments
+pub fn render_json(code: &str, exit_status: i32, phase: &str, message: &str, git_command: Option<&str>, hint: Option<&str>) -> String {
+    let optional = |text: Option<&str>| text.map(json_string).unwrap_or("null".to_string());
+    return format!("{{\"error\": {{\"code\": {}, \"exit_status\": {exit_status}, \"phase\": {}, \"message\": {}, \"git_command\": {}, \"hint\": {}}}}}",
+        json_string(code), json_string(phase), json_string(message), optional(git_command), optional(hint));
+}
+
+impl fmt::Display for Error {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match self {
+            Error::NotARepo(path) => return write!(f, "{path} is not in a git repository"),
+            Error::OutsideRepo { path, root } => return write!(f, "{path} is outside the repository at {root}"),
+            Error::Git { command, message } => return write!(f, "git {command}: {message}"),
+            Error::NoRevision { branch, date } => return write!(f, "no revision of {branch} before {date}"),
+            Error::LowCoverage { date, percent, min } => {
+                return write!(f, "only {percent:.1}% of the lines at {date} are counted, less than {min}%: the excludes skip the rest")
+            },
+            Error::Export { target, message } => return write!(f, "export to {target}: {message}"),
+            Error::Config(message) => return write!(f, "{message}"),
+            Error::Io(e) => return write!(f, "{e}"),
+        }
+    }
+}
+
+impl std::error::Error for Error {
+    fn source(&self) -> Option<&(dyn std::error::Error + 'static)> {
+        match self {
+            Error::Io(e) => return Some(e),
+            _ => return None,
+        }
+    }
+}
+
+impl From<io::Error> for Error {
+    fn from(e: io::Error) -> Error {
+        return Error::Io(e);
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn renders_json() {
+        let e = Error::Git { command: "blame".to_string(), message: "failed to start: No such file \"git\"".to_string() };
+        assert_eq!(e.to_json(), "{\"error\": {\"code\": \"git\", \"exit_status\": 4, \"phase\": \"analysis\", \
+            \"message\": \"git blame: failed to start: No such file \\\"git\\\"\", \"git_command\": \"blame\", \
+            \"hint\": \"check that git is installed and on PATH\"}}");
+        assert!(Error::Config("bad".to_string()).to_json().ends_with("\"git_command\": null, \"hint\": null}}"));
+    }
+}
@@ -0,0 +1,501 @@
+use hashbrown::HashMap;
+use regex::Regex;
+use std::fmt;
+
+use crate::breakdown::Languages;
+use crate::config::Config;
+use crate::identity::{author_pattern, matches_identity, Author};
+
+//----
+// File selection
+//
+// Files are matched against gitignore-style glob patterns.  Exclude rules are
+// applied in order (built-in, then config file, then command line) and the
+// last matching rule wins, so a later `!pattern` re-includes files excluded
+// by an earlier one.  When any include patterns are given, only files
+// matching one of them are analyzed.  A `lang:NAME` pattern matches the files
+// of a language, as --group-by language tells them apart.  Files generated
+// from others (protobuf stubs from their .proto, say) can be left out when
+// their source is tracked too, keeping the credit with the source's authors.
+// The `[automated]` table leaves out only some authors' lines in some files,
+// a formatter bot's under `generated/` say, keeping their hand-written work.
+// With --split-docs, the same kind of patterns tell documentation files apart
+// from code, to count their lines in columns of their own.
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum RuleSource {
+    Builtin,
+    Config,
+    Cli,
+    Gitattributes,
+}
+
+impl fmt::Display for RuleSource {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        let name = match self {
+            RuleSource::Builtin => "builtin",
+            RuleSource::Config => "config",
+            RuleSource::Cli => "cli",
+            RuleSource::Gitattributes => "gitattributes",
+        };
+        return write!(f, "{name}");
+    }
+}
+
+#[derive(Debug, Clone)]
+pub struct Rule {
+    pub pattern: String,
+    pub reason: String,
+    pub source: RuleSource,
+    negated: bool,
+    test: Test,
+}
+
+/// What a rule matches paths by
+#[derive(Debug, Clone)]
+enum Test {
+    Glob(Regex),
+    /// `lang:NAME` patterns
+    Language(String, Languages),
+}
+
+/// Why a file was left out of the analysis
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct SkipReason {
+    pub reason: String,
+    pub pattern: String,
+    pub source: RuleSource,
+}
+
+impl fmt::Display for SkipReason {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match self.pattern.is_empty() {
+            true => return write!(f, "{}", self.reason),
+            false => return write!(f, "{} ({} {})", self.reason, self.source, self.pattern),
+        }
+    }
+}
+
+/// Translate a gitignore-style glob into an anchored regex over repo-relative paths
+pub fn glob_to_regex(glob: &str) -> String {
+    let dir_only = glob.ends_with('/');
+    let glob = glob.trim_end_matches('/');
+    // A slash anywhere but the end anchors the pattern at the repo root
+    let anchored = glob.contains('/');
+    let glob = glob.trim_start_matches('/');
+
+    let mut re = String::from("^");
+    if !anchored {
+        re.push_str("(?:.*/)?");
+    }
+    let chars: Vec<char> = glob.chars().collect();
+    let mut i = 0;
+    while i < chars.len() {
+        match chars[i] {
+            '*' if chars.get(i + 1) == Some(&'*') => {
+                if chars.get(i + 2) == Some(&'/') {
+                    re.push_str("(?:.*/)?");
+                    i += 3;
+                } else {
+                    re.push_str(".*");
+                    i += 2;
+                }
+                continue;
+            },
+            '*' => re.push_str("[^/]*"),
+            '?' => re.push_str("[^/]"),
+            '[' => {
+                match chars[i..].iter().position(|&c| c == ']') {
+                    Some(end) if end > 1 => {
+                        re.push('[');
+                        let class: String = chars[i + 1..i + end].iter().collect();
+                        match class.strip_prefix('!') {
+                            Some(rest) => { re.push('^'); re.push_str(&rest.replace('\\', "\\\\")); },
+                            None => re.push_str(&class.replace('\\', "\\\\")),
+                        }
+                        re.push(']');
+                        i += end + 1;
+                        continue;
+                    },
+                    _ => re.push_str(r"\["),
+                }
+            },
+            c => re.push_str(&regex::escape(&c.to_string())),
+        }
+        i += 1;
+    }
+    // Matching a directory matches everything beneath it
+    match dir_only {
+        true => re.push_str("/.*$"),
+        false => re.push_str("(?:/.*)?$"),
+    }
+    return re;
+}
+
+impl Rule {
+    pub fn new(pattern: &str, reason: &str, source: RuleSource) -> Result<Rule, String> {
+        let (negated, glob) = match pattern.strip_prefix('!') {
+            Some(glob) => (true, glob),
+            None => (false, pattern),
+        };
+        let regex = Regex::new(&glob_to_regex(glob)).map_err(|e| format!("bad pattern '{pattern}': {e}"))?;
+        return Ok(Rule {
+            pattern: pattern.to_string(),
+            reason: reason.to_string(),
+            source,
+            negated,
+            test: Test::Glob(regex),
+        });
+    }
+
+    /// Like new, also taking `lang:NAME` patterns, which match the files of
+    /// that language
+    pub fn with_languages(pattern: &str, reason: &str, source: RuleSource, languages: &Languages) -> Result<Rule, String> {
+        let (negated, name) = match pattern.strip_prefix('!').unwrap_or(pattern).strip_prefix("lang:") {
+            Some(name) => (pattern.starts_with('!'), name.trim()),
+            None => return Rule::new(pattern, reason, source),
+        };
+        if !languages.knows(name) {
+            return Err(format!("bad pattern '{pattern}': unknown language {name}"));
+        }
+        return Ok(Rule {
+            pattern: pattern.to_string(),
+            reason: reason.to_string(),
+            source,
+            negated,
+            test: Test::Language(name.to_string(), languages.clone()),
+        });
+    }
+
+    pub fn matches(&self, path: &str) -> bool {
+        match &self.test {
+            Test::Glob(regex) => return regex.is_match(path),
+            Test::Language(name, languages) => return languages.of(path).eq_ignore_ascii_case(name),
+        }
+    }
+}
+
+/// The skip rules that used to be hardcoded, for the FPGA projects this tool
+/// was first written for.  Enabled with --builtin-excludes.
+fn builtin_rules() -> Vec<Rule> {
+    // List of file extensions to skip
+    let binary_ext_list = [
+        "bin",
+        "data",
+        "elf",
+        "gz",
+        "hex128",
+        "hex8",
+        "pdf",
+        "png",
+        "tar",
+        "wcfg",
+        "xlsx",
+    ];
+
+    let generated_ext_list = [
+        "v",
+        "xml",
+        "edif",
+        "edf",
+        "rpt",
+        "xci",
+    ];
+
+    let mut rules = vec![
+        Rule::new("/xip/", "mostly imported", RuleSource::Builtin).unwrap(),
+        Rule::new("/cache/", "generated", RuleSource::Builtin).unwrap(),
+    ];
+    for ext in binary_ext_list {
+        rules.push(Rule::new(&format!("*.{ext}"), "binary extension", RuleSource::Builtin).unwrap());
+    }
+    for ext in generated_ext_list {
+        rules.push(Rule::new(&format!("*.{ext}"), "autogenerated", RuleSource::Builtin).unwrap());
+    }
+    rules.push(Rule::new("*.bd.tcl", "mostly autogenerated", RuleSource::Builtin).unwrap());
+    return rules;
+}
+
+/// Files generated from others, from the config's `[generated]` table, e.g.
+/// `"*.proto" = ["*.pb.go", "*_pb2.py"]`.  `*` stands for the same part of
+/// both file names, wherever the files are.
+#[derive(Debug, Clone)]
+struct Generated {
+    source: Regex,
+    outputs: Vec<(String, Regex)>,
+}
+
+/// Authors whose lines in some files don't count, from the config's
+/// `[automated]` table, e.g. `"generated/" = ["build-bot@example.com"]`.
+/// Authors match by the name and email of their commits, like --author.
+#[derive(Debug, Clone)]
+struct Automated {
+    files: Rule,
+    authors: Vec<Regex>,
+}
+
+/// Regex of file names matching `pattern`, capturing what its `*` stands for
+fn stem_regex(pattern: &str) -> Result<Regex, String> {
+    match pattern.split_once('*') {
+        Some((before, after)) if !after.contains('*') && !pattern.contains('/') => {
+            let re = format!("^{}(.+){}$", regex::escape(before), regex::escape(after));
+            return Regex::new(&re).map_err(|e| format!("bad pattern '{pattern}': {e}"));
+        },
+        _ => return Err(format!("bad pattern '{pattern}': generated files need a file name with one *")),
+    }
+}
+
+/// What the `*` of a file name regex stands for in `path`
+fn stem<'a>(regex: &Regex, path: &'a str) -> Option<&'a str> {
+    let name = path.rsplit('/').next().unwrap_or(path);
+    return regex.captures(name).and_then(|c| c.get(1)).map(|m| m.as_str());
+}
+
+#[derive(Debug, Clone, Default)]
+pub struct FileFilter {
+    includes: Vec<Rule>,
+    excludes: Vec<Rule>,
+    generated: Vec<Generated>,
+    automated: Vec<Automated>,
+}
+
+impl FileFilter {
+    pub fn new(config: &Config, builtin: bool, cli_excludes: &[String], cli_includes: &[String]) -> Result<FileFilter, String> {
+        let mut filter = FileFilter::default();
+        if builtin || config.builtin_excludes {
+            filter.excludes.extend(builtin_rules());
+        }
+        let languages = Languages::new(&config.languages);
+        let rule = |pattern: &str, reason, source| Rule::with_languages(pattern, reason, source, &languages);
+        for pattern in &config.exclude {
+            filter.excludes.push(rule(pattern, "excluded by config", RuleSource::Config)?);
+        }
+        for pattern in cli_excludes {
+            filter.excludes.push(rule(pattern, "excluded by --exclude", RuleSource::Cli)?);
+        }
+        for pattern in &config.include {
+            filter.includes.push(rule(pattern, "included by config", RuleSource::Config)?);
+        }
+        for pattern in cli_includes {
+            filter.includes.push(rule(pattern, "included by --include", RuleSource::Cli)?);
+        }
+        for (source, outputs) in &config.generated {
+            filter.generated.push(Generated {
+                source: stem_regex(source)?,
+                outputs: outputs.iter().map(|output| Ok((output.clone(), stem_regex(output)?))).collect::<Result<_, String>>()?,
+            });
+        }
+        for (files, authors) in &config.automated {
+            filter.automated.push(Automated {
+                files: Rule::new(files, "automated", RuleSource::Config)?,
+                authors: authors.iter().map(|author| author_pattern(author).map_err(|e| format!("automated.{files}: {e}"))).collect::<Result<_, String>>()?,
+            });
+        }
+        return Ok(filter);
+    }
+
+    /// Whether any `[automated]` rule covers `path`
+    pub fn has_automated(&self, path: &str) -> bool {
+        return self.automated.iter().any(|rule| rule.files.matches(path));
+    }
+
+    /// Whether `author`'s lines and commits in `path` don't count
+    pub fn automated(&self, path: &str, author: &Author) -> bool {
+        return self.automated.iter()
+            .any(|rule| rule.files.matches(path) && rule.authors.iter().any(|regex| matches_identity(regex, &[author])));
+    }
+
+    /// Skip reasons of the files generated from others, when both are among
+    /// the tracked `paths`
+    pub fn generated_files(&self, paths: &[&str]) -> HashMap<String, SkipReason> {
+        let mut skipped = HashMap::new();
+        for generated in &self.generated {
+            let mut sources: HashMap<&str, &str> = HashMap::new();
+            for path in paths {
+                if let Some(stem) = stem(&generated.source, path) {
+                    sources.entry(stem).or_insert(path);
+                }
+            }
+            for path in paths {
+                for (pattern, output) in &generated.outputs {
+                    if let Some(source) = stem(output, path).and_then(|stem| sources.get(stem)) {
+                        skipped.entry(path.to_string()).or_insert(SkipReason {
+                            reason: format!("generated from {source}"),
+                            pattern: pattern.clone(),
+                            source: RuleSource::Config,
+                        });
+                    }
+                }
+            }
+        }
+        return skipped;
+    }
+
+    pub fn reason_to_skip(&self, path: &str) -> Option<SkipReason> {
+        if !self.includes.is_empty() && !self.includes.iter().any(|r| r.matches(path)) {
+            let source = match self.includes.iter().any(|r| r.source == RuleSource::Cli) {
+                true => RuleSource::Cli,
+                false => RuleSource::Config,
+            };
+            return Some(SkipReason {
+                reason: "not included".to_string(),
+                pattern: String::new(),
+                source,
+            });
+        }
+
+        let rule = self.excludes.iter().rev().find(|r| r.matches(path))?;
+        match rule.negated {
+            true => return None,
+            false => {
+                return Some(SkipReason {
+                    reason: rule.reason.clone(),
+                    pattern: rule.pattern.clone(),
+                    source: rule.source,
+                });
+            },
+        }
+    }
+}
+
+/// Documentation files, unless the config's `[files] docs` lists others
+pub const DOC_FILES: [&str; 3] = ["*.md", "*.rst", "docs/"];
+
+/// Which files --split-docs counts as documentation
+#[derive(Debug, Clone, Default)]
+pub struct DocFiles {
+    rules: Vec<Rule>,
+}
+
+impl DocFiles {
+    /// The config's doc patterns, or DOC_FILES, then `cli_patterns`; the last
+    /// matching one wins, so `!pattern` takes files back out
+    pub fn new(config: &Config, cli_patterns: &[String]) -> Result<DocFiles, String> {
+        let languages = Languages::new(&config.languages);
+        let rule = |pattern: &str, source| Rule::with_languages(pattern, "documentation", source, &languages);
+        let mut rules = match config.docs.is_empty() {
+            true => DOC_FILES.iter().map(|pattern| rule(pattern, RuleSource::Builtin)).collect::<Result<Vec<Rule>, String>>()?,
+            false => config.docs.iter().map(|pattern| rule(pattern, RuleSource::Config)).collect::<Result<Vec<Rule>, String>>()?,
+        };
+        for pattern in cli_patterns {
+            rules.push(rule(pattern, RuleSource::Cli)?);
+        }
+        return Ok(DocFiles { rules });
+    }
+
+    pub fn is_doc(&self, path: &str) -> bool {
+        return self.rules.iter().rev().find(|r| r.matches(path)).is_some_and(|rule| !rule.negated);
+    }
+}
+
+/// Git attributes that mark a file as not hand-written
+pub const SKIP_ATTRIBUTES: [&str; 4] = ["binary", "diff", "linguist-generated", "linguist-vendored"];
+
+/// Skip reason for a file given its values of SKIP_ATTRIBUTES, as reported by
+/// `git check-attr`
+pub fn attribute_skip_reason(attrs: &[(String, String)]) -> Option<SkipReason> {
+    for (attr, value) in attrs {
+        let reason = match (attr.as_str(), value.as_str()) {
+            ("binary", "set") | ("diff", "unset") => "binary attribute",
+            ("linguist-generated", "set") | ("linguist-generated", "true") => "generated attribute",
+            ("linguist-vendored", "set") | ("linguist-vendored", "true") => "vendored attribute",
+            _ => continue,
+        };
+        return Some(SkipReason {
+            reason: reason.to_string(),
+            pattern: format!("{attr}={value}"),
+            source: RuleSource::Gitattributes,
+        });
+    }
+    return None;
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn filter(excludes: &[&str], includes: &[&str]) -> FileFilter {
+        let excludes: Vec<String> = excludes.iter().map(|x| x.to_string()).collect();
+        let includes: Vec<String> = includes.iter().map(|x| x.to_string()).collect();
+        return FileFilter::new(&Config::default(), false, &excludes, &includes).unwrap();
+    }
+
+    #[test]
+    fn globs() {
+        let rule = |pattern| Rule::new(pattern, "", RuleSource::Cli).unwrap();
+        assert!(rule("*.min.js").matches("web/app.min.js"));
+        assert!(!rule("/vendor/").matches("src/vendor/x.c"));
+        assert!(rule("vendor/").matches("vendor/x.c"));
+        assert!(rule("src/**/*.rs").matches("src/a/b/c.rs"));
+        assert!(rule("src/**/*.rs").matches("src/c.rs"));
+        assert!(rule("file[0-9].txt").matches("file7.txt"));
+        assert!(!rule("file[!0-9].txt").matches("file7.txt"));
+    }
+
+    #[test]
+    fn last_matching_rule_wins() {
+        let f = filter(&["*.js", "!keep.js"], &[]);
+        assert!(f.reason_to_skip("a.js").is_some());
+        assert!(f.reason_to_skip("keep.js").is_none());
+        assert!(f.reason_to_skip("a.rs").is_none());
+    }
+
+    #[test]
+    fn language_patterns() {
+        let config = Config { languages: vec![("SystemVerilog".to_string(), vec!["sv".to_string(), ".SVH".to_string()])], ..Config::default() };
+        let f = FileFilter::new(&config, false, &["lang:verilog".to_string(), "!top.v".to_string()], &[]).unwrap();
+        assert_eq!(f.reason_to_skip("rtl/core.v").unwrap().pattern, "lang:verilog");
+        assert!(f.reason_to_skip("rtl/top.v").is_none());
+        assert!(f.reason_to_skip("rtl/pkg.sv").is_none());
+        let f = FileFilter::new(&config, false, &[], &["lang:SystemVerilog".to_string()]).unwrap();
+        assert!(f.reason_to_skip("rtl/defs.svh").is_none());
+        assert!(f.reason_to_skip("rtl/core.v").is_some());
+        assert!(FileFilter::new(&config, false, &["lang:Cobol".to_string()], &[]).is_err());
+    }
+
+    #[test]
+    fn generated_pairs() {
+        let generate = |outputs: &[&str]| {
+            let config = Config { generated: vec![("*.proto".to_string(), outputs.iter().map(|x| x.to_string()).collect())], ..Config::default() };
+            return FileFilter::new(&config, false, &[], &[]);
+        };
+        let f = generate(&["*.pb.go", "*_pb2.py"]).unwrap();
+        let skipped = f.generated_files(&["api/user.proto", "gen/user.pb.go", "py/user_pb2.py", "gen/order.pb.go"]);
+        assert_eq!(skipped.len(), 2);
+        assert_eq!(skipped["gen/user.pb.go"].to_string(), "generated from api/user.proto (config *.pb.go)");
+        assert!(skipped.contains_key("py/user_pb2.py"));
+        assert!(generate(&["gen/*.go"]).is_err());
+        assert!(generate(&["*.*.go"]).is_err());
+    }
+
+    #[test]
+    fn automated_authors_per_path() {
+        let config = Config { automated: vec![("generated/".to_string(), vec!["build-bot@*".to_string()])], ..Config::default() };
+        let f = FileFilter::new(&config, false, &[], &[]).unwrap();
+        let bot = Author::new("Build Bot", "build-bot@example.com");
+        assert!(f.has_automated("generated/api.rs") && !f.has_automated("src/api.rs"));
+        assert!(f.automated("generated/api.rs", &bot));
+        assert!(!f.automated("src/api.rs", &bot));
+        assert!(!f.automated("generated/api.rs", &Author::new("Alice", "alice@example.com")));
+        let config = Config { automated: vec![("generated/".to_string(), vec!["re:(".to_string()])], ..Config::default() };
+        assert!(FileFilter::new(&config, false, &[], &[]).unwrap_err().starts_with("automated.generated/: "));
+    }
+
+    #[test]
+    fn doc_files() {
+        let docs = DocFiles::new(&Config::default(), &["!CHANGELOG.md".to_string(), "*.adoc".to_string()]).unwrap();
+        assert!(docs.is_doc("README.md") && docs.is_doc("docs/api/index.html") && docs.is_doc("guide/intro.adoc"));
+        assert!(!docs.is_doc("src/main.rs") && !docs.is_doc("CHANGELOG.md"));
+        let config = Config { docs: vec!["manual/".to_string()], ..Config::default() };
+        let docs = DocFiles::new(&config, &[]).unwrap();
+        assert!(docs.is_doc("manual/ch1.tex") && !docs.is_doc("README.md"));
+    }
+
+    #[test]
+    fn includes_are_an_allowlist() {
+        let f = filter(&["src/gen/"], &["src/"]);
+        assert_eq!(f.reason_to_skip("README.md").unwrap().reason, "not included");
+        assert!(f.reason_to_skip("src/main.rs").is_none());
+        assert_eq!(f.reason_to_skip("src/gen/x.rs").unwrap().pattern, "src/gen/");
+    }
+}
@@ -0,0 +1,109 @@
+use std::fs;
+use std::path::{Path, PathBuf};
+use std::process::Command;
+use std::sync::atomic::{AtomicUsize, Ordering};
+use std::sync::Arc;
+
+use crate::backend::{Backend, CliBackend};
+use crate::repo::Repo;
+
+//----
+// Test fixture repo
+//
+// A small repo with a fixed history, built fresh for each test:
+//
+//   2020-01-15  Alice  src/main.rs (3 lines), README.md (2 lines)
+//   2020-02-15  Bob    src/main.rs +2 lines, vendor/lib.js (1 line)
+//   2020-03-15  Bob    README.md -1 line
+
+pub const ALICE: (&str, &str) = ("Alice", "alice@example.com");
+pub const BOB: (&str, &str) = ("Bob", "bob@example.com");
+
+/// The fixture repo's sample dates: one before the first commit, and one
+/// after each commit
+pub const DATES: [&str; 4] = ["2020-01-01", "2020-02-01", "2020-03-01", "2020-04-01"];
+
+pub struct Fixture {
+    pub dir: PathBuf,
+}
+
+impl Fixture {
+    pub fn new() -> Fixture {
+        static COUNTER: AtomicUsize = AtomicUsize::new(0);
+        let n = COUNTER.fetch_add(1, Ordering::Relaxed);
+        let dir = std::env::temp_dir().join(format!("git-author-stats-fixture-{}-{n}", std::process::id()));
+        let _ = fs::remove_dir_all(&dir);
+        fs::create_dir_all(&dir).unwrap();
+        let fixture = Fixture { dir };
+
+        fixture.git(&["init", "-q", "-b", "main"], None);
+        fixture.write("src/main.rs", "a\nb\nc\n");
+        fixture.write("README.md", "x\ny\n");
+        fixture.commit(ALICE, "2020-01-15T12:00:00", "Initial commit");
+        fixture.write("src/main.rs", "a\nb\nc\nd\ne\n");
+        fixture.write("vendor/lib.js", "v\n");
+        fixture.commit(BOB, "2020-02-15T12:00:00", "Add vendored lib");
+        fixture.write("README.md", "x\n");
+        fixture.commit(BOB, "2020-03-15T12:00:00", "Trim readme");
+        return fixture;
+    }
+
+    pub fn path(&self) -> &str {
+        return self.dir.to_str().unwrap();
+    }
+
+    pub fn repo(&self) -> Arc<Repo> {
+        return Arc::new(Repo::discover(self.path()).unwrap());
+    }
+
+    pub fn backend(&self) -> Arc<dyn Backend> {
+        return Arc::new(CliBackend::new(self.repo()));
+    }
+
+    /// Add a commit after the fixed history
+    pub fn commit_files(&self, files: &[(&str, &[u8])], author: (&str, &str), date: &str, message: &str) {
+        for (path, content) in files {
+            self.write(path, content);
+        }
+        self.commit(author, date, message);
+    }
+
+    /// Stage a file written with `write`
+    pub fn stage(&self, path: &str) {
+        self.git(&["add", "--", path], None);
+    }
+
+    /// Write a file without committing it
+    pub fn write<C: AsRef<[u8]>>(&self, path: &str, text: C) {
+        let path = self.dir.join(path);
+        fs::create_dir_all(path.parent().unwrap()).unwrap();
+        fs::write(path, text).unwrap();
+    }
+
+    fn commit(&self, author: (&str, &str), date: &str, message: &str) {
+        self.git(&["add", "-A"], None);
+        self.git(&["commit", "-q", "-m", message], Some((author, date)));
+    }
+
+    fn git(&self, args: &[&str], author: Option<((&str, &str), &str)>) {
+        let mut cmd = Command::new("git");
+        cmd.args(args).current_dir(&self.dir);
+        // Keep the user's config out of the fixture
+        cmd.env("GIT_CONFIG_GLOBAL", "/dev/null").env("GIT_CONFIG_NOSYSTEM", "1");
+        if let Some(((name, email), date)) = author {
+            for who in ["AUTHOR", "COMMITTER"] {
+                cmd.env(format!("GIT_{who}_NAME"), name);
+                cmd.env(format!("GIT_{who}_EMAIL"), email);
+                cmd.env(format!("GIT_{who}_DATE"), date);
+            }
+        }
+        let out = cmd.output().unwrap();
+        assert!(out.status.success(), "git {args:?}: {}", String::from_utf8_lossy(&out.stderr));
+    }
+}
+
+impl Drop for Fixture {
+    fn drop(&mut self) {
+        let _ = fs::remove_dir_all(Path::new(&self.dir));
+    }
+}
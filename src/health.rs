@@ -0,0 +1,135 @@
+use crate::Count;
+
+//----
+// Repository health score
+//
+// --health-score folds four signals per snapshot into one number from 0 to
+// 100, each first mapped onto 0..1 where 1 is healthy:
+//
+//   bus factor (fewest authors owning half the lines)    1 - 1/n
+//   ownership entropy (mean bits per file)               1 - 2^-bits
+//   stale code (share of lines unchanged for too long)   1 - share
+//   active authors, against the period before            now / max(now, before)
+//
+// 2^bits is the effective number of owners of a file, so the first two use
+// the same scale.  The score is the average of the four, weighted by the
+// config's [health] table.
+
+/// Lines last changed longer ago than this count as stale, by default
+pub const DEFAULT_STALE_AFTER_DAYS: u32 = 365;
+
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct HealthWeights {
+    pub bus_factor: f64,
+    pub entropy: f64,
+    pub stale: f64,
+    pub active_authors: f64,
+}
+
+impl Default for HealthWeights {
+    fn default() -> HealthWeights {
+        return HealthWeights { bus_factor: 1.0, entropy: 1.0, stale: 1.0, active_authors: 1.0 };
+    }
+}
+
+impl HealthWeights {
+    pub fn validate(&self) -> Result<(), String> {
+        let weights = [self.bus_factor, self.entropy, self.stale, self.active_authors];
+        if weights.iter().any(|&w| w < 0.0 || !w.is_finite()) {
+            return Err("health weights can't be negative".to_string());
+        }
+        if weights.iter().sum::<f64>() == 0.0 {
+            return Err("health weights can't all be 0".to_string());
+        }
+        return Ok(());
+    }
+}
+
+/// Lines of a snapshot, and how many of them are stale
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq)]
+pub struct Staleness {
+    pub lines: usize,
+    pub stale: usize,
+}
+
+impl Staleness {
+    /// Staleness of a file's lines, given per author date, as of `cutoff`
+    pub fn of(times: &[(i64, Count)], cutoff: i64) -> Staleness {
+        let lines = times.iter().map(|(_, n)| *n as usize).sum();
+        let stale = times.iter().filter(|(time, _)| *time < cutoff).map(|(_, n)| *n as usize).sum();
+        return Staleness { lines, stale };
+    }
+
+    pub fn add(&mut self, other: Staleness) {
+        self.lines += other.lines;
+        self.stale += other.stale;
+    }
+
+    /// Share of the lines that are stale, 0 without lines
+    pub fn share(&self) -> f64 {
+        match self.lines {
+            0 => return 0.0,
+            n => return self.stale as f64 / n as f64,
+        }
+    }
+}
+
+/// The score at one date, with the signals behind it
+#[derive(Debug, Clone, Copy, Default, PartialEq)]
+pub struct Health {
+    /// 0 to 100
+    pub score: f64,
+    pub bus_factor: usize,
+    /// Mean ownership entropy of the files, in bits
+    pub entropy: f64,
+    pub stale_share: f64,
+    pub active_authors: usize,
+}
+
+impl Health {
+    /// Score the signals; `before` is the number of active authors at the
+    /// previous date, if any
+    pub fn new(weights: &HealthWeights, bus_factor: usize, entropy: f64, stale_share: f64, active_authors: usize, before: Option<usize>) -> Health {
+        let bus_score = match bus_factor {
+            0 => 0.0,
+            n => 1.0 - 1.0 / n as f64,
+        };
+        let entropy_score = 1.0 - (-entropy).exp2();
+        let active_score = match active_authors.max(before.unwrap_or(0)) {
+            0 => 0.0,
+            most => active_authors as f64 / most as f64,
+        };
+        let weighted = weights.bus_factor * bus_score
+            + weights.entropy * entropy_score
+            + weights.stale * (1.0 - stale_share)
+            + weights.active_authors * active_score;
+        let total = weights.bus_factor + weights.entropy + weights.stale + weights.active_authors;
+        return Health { score: 100.0 * weighted / total, bus_factor, entropy, stale_share, active_authors };
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn scores_signals() {
+        let weights = HealthWeights::default();
+        // Two equal owners everywhere, nothing stale, as many active authors as before
+        let health = Health::new(&weights, 2, 1.0, 0.0, 3, Some(3));
+        assert!((health.score - 75.0).abs() < 1e-9);
+        // Losing half the active authors costs an eighth with equal weights
+        let health = Health::new(&weights, 2, 1.0, 0.0, 3, Some(6));
+        assert!((health.score - 62.5).abs() < 1e-9);
+        let only_stale = HealthWeights { bus_factor: 0.0, entropy: 0.0, stale: 1.0, active_authors: 0.0 };
+        assert!((Health::new(&only_stale, 1, 0.0, 0.25, 0, None).score - 75.0).abs() < 1e-9);
+        assert_eq!(Health::new(&weights, 0, 0.0, 1.0, 0, None).score, 0.0);
+
+        assert!(HealthWeights { stale: -1.0, ..weights }.validate().is_err());
+        assert!(HealthWeights { bus_factor: 0.0, entropy: 0.0, stale: 0.0, active_authors: 0.0 }.validate().is_err());
+
+        let staleness = Staleness::of(&[(100, 3), (200, 1)], 150);
+        assert_eq!(staleness, Staleness { lines: 4, stale: 3 });
+        assert_eq!(staleness.share(), 0.75);
+    }
+}
@@ -0,0 +1,276 @@
+use chrono::{TimeZone, Utc};
+use hashbrown::HashMap;
+use std::env;
+use std::fmt::Write;
+use std::fs;
+use std::io;
+use std::path::{Path, PathBuf};
+
+use crate::at_rest::{self, AtRest};
+use crate::chart::sparkline;
+use crate::json::{self, Value};
+use crate::ownership::{self, BUS_FACTOR_SHARE};
+use crate::report::{json_string, Table};
+use crate::{csv_field, Date};
+
+//----
+// Run history
+//
+// --record-history appends the headline numbers of each run (total, authors,
+// bus factor and top author at the latest date) to a JSON Lines file shared by
+// every repo, and `trends` reads them back month by month, so a team can follow
+// its repos across months of runs without keeping the reports.  The file
+// prunes itself: every run of the last KEEP_ALL_DAYS is kept, then the last
+// run of each month up to KEEP_MONTHLY_DAYS, and nothing older.
+
+const KEEP_ALL_DAYS: i64 = 90;
+const KEEP_MONTHLY_DAYS: i64 = 730;
+const HISTORY_FILE_NAME: &str = "history.jsonl";
+
+/// Default location of the history: $XDG_DATA_HOME or ~/.local/share
+pub fn default_history_file() -> PathBuf {
+    let dir = match env::var_os("XDG_DATA_HOME").filter(|d| !d.is_empty()) {
+        Some(dir) => PathBuf::from(dir),
+        None => PathBuf::from(env::var_os("HOME").unwrap_or_else(|| ".".into())).join(".local").join("share"),
+    };
+    return dir.join("git-author-stats").join(HISTORY_FILE_NAME);
+}
+
+/// The headline numbers of one run
+#[derive(Debug, Clone, PartialEq)]
+pub struct Entry {
+    /// When the run was, seconds since the epoch
+    pub time: i64,
+    pub repo: String,
+    pub metric: String,
+    /// The latest date of the run
+    pub date: Date,
+    pub total: i64,
+    /// Authors with a count at the date
+    pub authors: usize,
+    pub bus_factor: usize,
+    pub top_author: String,
+    /// Percent of the total
+    pub top_share: f64,
+}
+
+/// The headline numbers of `table` at its latest date, if it has one
+pub fn headline(time: i64, repo: &str, metric: &str, table: &Table) -> Option<Entry> {
+    let last = table.dates.len().checked_sub(1)?;
+    let counts = || table.rows.iter().map(|row| row.counts[last]);
+    let total = table.totals[last] as i64;
+    let top = table.rows.iter().filter(|row| row.counts[last] > 0).max_by_key(|row| row.counts[last]);
+    return Some(Entry {
+        time,
+        repo: repo.to_string(),
+        metric: metric.to_string(),
+        date: table.dates[last].clone(),
+        total,
+        authors: counts().filter(|&count| count > 0).count(),
+        bus_factor: ownership::bus_factor(counts(), BUS_FACTOR_SHARE),
+        top_author: top.map(|row| row.author.clone()).unwrap_or_default(),
+        top_share: match (top, total) {
+            (Some(row), 1..) => 100.0 * row.counts[last] as f64 / total as f64,
+            _ => 0.0,
+        },
+    });
+}
+
+fn to_json(entry: &Entry) -> String {
+    return format!("{{\"time\": {}, \"repo\": {}, \"metric\": {}, \"date\": {}, \"total\": {}, \"authors\": {}, \"bus_factor\": {}, \"top_author\": {}, \"top_share\": {:.1}}}",
+        entry.time, json_string(&entry.repo), json_string(&entry.metric), json_string(&entry.date), entry.total, entry.authors,
+        entry.bus_factor, json_string(&entry.top_author), entry.top_share);
+}
+
+fn parse_entry(line: &str) -> Result<Entry, String> {
+    let Value::Object(fields) = json::parse(line)? else {
+        return Err("expected a JSON object".to_string());
+    };
+    let field = |name: &str| fields.iter().find(|(key, _)| key == name).map(|(_, value)| value);
+    let text = |name: &str| match field(name) {
+        Some(Value::String(text)) => Ok(text.clone()),
+        _ => Err(format!("no {name}")),
+    };
+    let number = |name: &str| match field(name) {
+        Some(Value::Number(number)) => number.parse::<f64>().map_err(|e| format!("{name}: {e}")),
+        _ => Err(format!("no {name}")),
+    };
+    return Ok(Entry {
+        time: number("time")? as i64,
+        repo: text("repo")?,
+        metric: text("metric")?,
+        date: text("date")?,
+        total: number("total")? as i64,
+        authors: number("authors")? as usize,
+        bus_factor: number("bus_factor")? as usize,
+        top_author: text("top_author")?,
+        top_share: number("top_share")?,
+    });
+}
+
+/// The runs recorded in `path`, oldest first; none if it doesn't exist
+pub fn load(path: &Path, at_rest: Option<&AtRest>) -> io::Result<Vec<Entry>> {
+    let data = match at_rest::read(path, at_rest) {
+        Ok(data) => data,
+        Err(e) if e.kind() == io::ErrorKind::NotFound => return Ok(Vec::new()),
+        Err(e) => return Err(e),
+    };
+    let mut entries = Vec::new();
+    for (i, line) in String::from_utf8_lossy(&data).lines().enumerate().filter(|(_, line)| !line.trim().is_empty()) {
+        let entry = parse_entry(line).map_err(|e| io::Error::new(io::ErrorKind::InvalidData, format!("{} line {}: {e}", path.display(), i + 1)))?;
+        entries.push(entry);
+    }
+    entries.sort_by_key(|entry| entry.time);
+    return Ok(entries);
+}
+
+fn month_of(time: i64) -> String {
+    match Utc.timestamp_opt(time, 0).single() {
+        Some(t) => return t.format("%Y-%m").to_string(),
+        None => return String::new(),
+    }
+}
+
+/// The sorted `entries` the retention policy keeps as of `now`
+pub fn retain(entries: Vec<Entry>, now: i64) -> Vec<Entry> {
+    let age = |entry: &Entry| (now - entry.time) / 86400;
+    // The latest entry of each repo, metric and month
+    let mut last: HashMap<(String, String, String), i64> = HashMap::new();
+    for entry in &entries {
+        last.insert((entry.repo.clone(), entry.metric.clone(), month_of(entry.time)), entry.time);
+    }
+    return entries.into_iter().filter(|entry| match age(entry) {
+        age if age <= KEEP_ALL_DAYS => true,
+        age if age <= KEEP_MONTHLY_DAYS => last[&(entry.repo.clone(), entry.metric.clone(), month_of(entry.time))] == entry.time,
+        _ => false,
+    }).collect();
+}
+
+/// Add `entry` to the history in `path`, pruning it as of `now`
+pub fn record(path: &Path, entry: Entry, now: i64, at_rest: Option<&AtRest>) -> io::Result<()> {
+    let mut entries = load(path, at_rest)?;
+    entries.push(entry);
+    let entries = retain(entries, now);
+    let mut text = String::new();
+    for entry in &entries {
+        writeln!(text, "{}", to_json(entry)).unwrap();
+    }
+    if let Some(dir) = path.parent().filter(|dir| !dir.as_os_str().is_empty()) {
+        fs::create_dir_all(dir)?;
+    }
+    // Whole, so a run that dies half way doesn't leave half a line
+    let temp = path.with_extension(format!("tmp-{}", std::process::id()));
+    at_rest::write(&temp, text, at_rest)?;
+    return fs::rename(&temp, path);
+}
+
+/// The last run of each month, by month, of one repo and metric
+type Series<'a> = ((String, String), Vec<(String, &'a Entry)>);
+
+/// The series of each repo and metric, in order
+fn monthly(entries: &[Entry]) -> Vec<Series<'_>> {
+    let mut series: Vec<Series> = Vec::new();
+    for entry in entries {
+        let key = (entry.repo.clone(), entry.metric.clone());
+        let month = month_of(entry.time);
+        let index = match series.iter().position(|(k, _)| *k == key) {
+            Some(index) => index,
+            None => {
+                series.push((key, Vec::new()));
+                series.len() - 1
+            },
+        };
+        let points = &mut series[index].1;
+        match points.last_mut() {
+            Some((last, latest)) if *last == month => *latest = entry,
+            _ => points.push((month, entry)),
+        }
+    }
+    series.sort_by(|a, b| a.0.cmp(&b.0));
+    return series;
+}
+
+/// A table per repo and metric of the last run of each month, with a
+/// sparkline of the totals
+pub fn render_text(entries: &[Entry]) -> String {
+    let mut out = String::new();
+    for ((repo, metric), points) in monthly(entries) {
+        writeln!(out, "{repo} ({metric})").unwrap();
+        writeln!(out, "  month    {:>10}  {:>7}  {:>10}  top author", "total", "authors", "bus factor").unwrap();
+        for (month, entry) in &points {
+            let top = match entry.top_author.is_empty() {
+                true => "-".to_string(),
+                false => format!("{} ({:.1}%)", entry.top_author, entry.top_share),
+            };
+            writeln!(out, "  {month}  {:>10}  {:>7}  {:>10}  {top}", entry.total, entry.authors, entry.bus_factor).unwrap();
+        }
+        let totals: Vec<f64> = points.iter().map(|(_, entry)| entry.total as f64).collect();
+        writeln!(out, "  trend    {}", sparkline(&totals, totals.iter().copied().fold(0.0, f64::max))).unwrap();
+        writeln!(out).unwrap();
+    }
+    return out;
+}
+
+/// Every run, one row each
+pub fn render_csv(entries: &[Entry]) -> String {
+    let mut out = String::from("time,repo,metric,date,total,authors,bus_factor,top_author,top_share\n");
+    for entry in entries {
+        let time = Utc.timestamp_opt(entry.time, 0).single().map(|t| t.to_rfc3339()).unwrap_or_default();
+        writeln!(out, "{time},{},{},{},{},{},{},{},{:.1}", csv_field(&entry.repo), entry.metric, entry.date, entry.total,
+            entry.authors, entry.bus_factor, csv_field(&entry.top_author), entry.top_share).unwrap();
+    }
+    return out;
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::fixture::Fixture;
+
+    fn entry(time: i64, repo: &str, total: i64) -> Entry {
+        return Entry {
+            time,
+            repo: repo.to_string(),
+            metric: "lines".to_string(),
+            date: "2026-01-01".to_string(),
+            total,
+            authors: 2,
+            bus_factor: 1,
+            top_author: "Alice".to_string(),
+            top_share: 60.0,
+        };
+    }
+
+    #[test]
+    fn keeps_recent_runs_then_monthly_ones() {
+        let day = 86400;
+        let now = Utc.with_ymd_and_hms(2026, 10, 14, 12, 0, 0).unwrap().timestamp();
+        let entries = vec![
+            // Too old
+            entry(now - 800 * day, "app", 10),
+            // Two in one month long ago: the later stays
+            entry(Utc.with_ymd_and_hms(2025, 3, 2, 0, 0, 0).unwrap().timestamp(), "app", 20),
+            entry(Utc.with_ymd_and_hms(2025, 3, 20, 0, 0, 0).unwrap().timestamp(), "app", 30),
+            entry(Utc.with_ymd_and_hms(2025, 3, 21, 0, 0, 0).unwrap().timestamp(), "lib", 5),
+            // Recent ones all stay
+            entry(now - 10 * day, "app", 40),
+            entry(now - 9 * day, "app", 50),
+        ];
+        let kept: Vec<(String, i64)> = retain(entries, now).into_iter().map(|entry| (entry.repo, entry.total)).collect();
+        assert_eq!(kept, [("app".to_string(), 30), ("lib".to_string(), 5), ("app".to_string(), 40), ("app".to_string(), 50)]);
+
+        let fixture = Fixture::new();
+        let path = fixture.dir.join("data").join(HISTORY_FILE_NAME);
+        record(&path, entry(now - 9 * day, "app", 50), now, None).unwrap();
+        record(&path, entry(now - 800 * day, "app", 10), now, None).unwrap();
+        record(&path, entry(now - 10 * day, "my, app", 40), now, None).unwrap();
+        let loaded = load(&path, None).unwrap();
+        assert_eq!(loaded, [entry(now - 10 * day, "my, app", 40), entry(now - 9 * day, "app", 50)]);
+
+        let text = render_text(&loaded);
+        assert!(text.starts_with("app (lines)\n  month         total  authors  bus factor  top author\n  2026-10          50        2           1  Alice (60.0%)\n"));
+        assert!(render_csv(&loaded).contains("\n2026-10-04T12:00:00+00:00,\"my, app\",lines,2026-01-01,40,2,1,Alice,60.0\n"));
+        fs::write(&path, "{\"time\": 1}\n").unwrap();
+        assert!(load(&path, None).unwrap_err().to_string().ends_with("line 1: no repo"));
+    }
+}
@@ -0,0 +1,275 @@
+use hashbrown::HashMap;
+use std::fmt::Write;
+
+use crate::branches::{self, Comparison};
+use crate::chart;
+use crate::cli_args::Args;
+use crate::filter::SkipReason;
+use crate::i18n::{self, t, tf};
+use crate::report::{group_digits, summary_label, Report, Row, Table, ENTROPY_LABEL};
+
+//----
+// HTML report
+//
+// A single self-contained page: the author table, then the owners of every
+// file at the latest date.  With --repo-url, authors link to their commits
+// and files to their blame page on the repo host.  --branches puts the
+// comparison of the branches first, then each branch's table.
+
+/// Number of owners listed per file
+const FILE_OWNERS: usize = 3;
+
+pub const STYLE: &str = "\
+body { font-family: sans-serif; margin: 2em; }
+table { border-collapse: collapse; margin-bottom: 2em; }
+th, td { padding: 2px 8px; border-bottom: 1px solid #ddd; }
+td.n { text-align: right; font-variant-numeric: tabular-nums; }
+tr.group th { text-align: left; background: #eee; }
+tr.subtotal td { font-style: italic; border-bottom: 2px solid #999; }
+";
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+enum Host {
+    GitHub,
+    GitLab,
+}
+
+/// URLs of pages on the repo host
+#[derive(Debug, Clone)]
+pub struct RepoLinks {
+    base: String,
+    host: Host,
+}
+
+impl RepoLinks {
+    /// From the repo's web URL; GitLab hosts are recognized by name, anything
+    /// else is assumed to use GitHub's URL layout
+    pub fn new(url: &str) -> RepoLinks {
+        let base = url.trim().trim_end_matches('/').trim_end_matches(".git").to_string();
+        let hostname = base.split("://").last().unwrap_or("").split('/').next().unwrap_or("");
+        let host = match hostname.contains("gitlab") {
+            true => Host::GitLab,
+            false => Host::GitHub,
+        };
+        return RepoLinks { base, host };
+    }
+
+    pub fn blame(&self, revision: &str, path: &str) -> String {
+        match self.host {
+            Host::GitHub => return format!("{}/blame/{revision}/{}", self.base, url_encode(path)),
+            Host::GitLab => return format!("{}/-/blame/{revision}/{}", self.base, url_encode(path)),
+        }
+    }
+
+    /// Commits by an author: GitHub filters on email, GitLab on name
+    pub fn author(&self, revision: &str, row: &Row) -> Option<String> {
+        // No single author behind a --top rollup row
+        if row.keys.is_empty() {
+            return None;
+        }
+        match self.host {
+            Host::GitHub => {
+                let email = row.keys.iter().find(|key| key.contains('@'))?;
+                return Some(format!("{}/commits/{revision}?author={}", self.base, url_encode(email)));
+            },
+            Host::GitLab => return Some(format!("{}/-/commits/{revision}?author={}", self.base, url_encode(&row.author))),
+        }
+    }
+}
+
+/// Percent-encode everything but unreserved characters and '/'
+fn url_encode(text: &str) -> String {
+    let mut out = String::new();
+    for b in text.bytes() {
+        match b {
+            b'A'..=b'Z' | b'a'..=b'z' | b'0'..=b'9' | b'-' | b'.' | b'_' | b'~' | b'/' => out.push(b as char),
+            _ => write!(out, "%{b:02X}").unwrap(),
+        }
+    }
+    return out;
+}
+
+pub fn escape(text: &str) -> String {
+    return text.replace('&', "&amp;").replace('<', "&lt;").replace('>', "&gt;").replace('"', "&quot;");
+}
+
+fn link(url: Option<String>, text: &str) -> String {
+    match url {
+        Some(url) => return format!("<a href=\"{}\">{}</a>", escape(&url), escape(text)),
+        None => return escape(text),
+    }
+}
+
+/// The author table, with team headings and subtotals if grouped
+fn author_table(out: &mut String, opt: &Args, table: &Table, links: Option<&RepoLinks>, revision: &str, separator: &str) {
+    writeln!(out, "<table>").unwrap();
+    write!(out, "<tr><th>{}</th>", escape(&t("author"))).unwrap();
+    let labels = table.column_labels();
+    for label in &labels {
+        write!(out, "<th>{}</th>", escape(label)).unwrap();
+    }
+    writeln!(out, "</tr>").unwrap();
+    let grouped = !table.groups.is_empty();
+    for group in &table.sections() {
+        if grouped {
+            writeln!(out, "<tr class=\"group\"><th colspan=\"{}\">{}</th></tr>", labels.len() + 1, escape(&group.name)).unwrap();
+        }
+        for row in &table.rows[group.rows.clone()] {
+            let url = links.and_then(|links| links.author(revision, row));
+            write!(out, "<tr><td>{}</td>", link(url, &row.author)).unwrap();
+            for cell in table.cells(opt, &row.counts, &row.docs, separator) {
+                write!(out, "<td class=\"n\">{}</td>", escape(&cell)).unwrap();
+            }
+            writeln!(out, "</tr>").unwrap();
+        }
+        if grouped {
+            write!(out, "<tr class=\"subtotal\"><td>{}</td>", escape(&tf("group-total", &[("group", &group.name)]))).unwrap();
+            for cell in table.cells(opt, &group.totals, &group.doc_totals, separator) {
+                write!(out, "<td class=\"n\">{}</td>", escape(&cell)).unwrap();
+            }
+            writeln!(out, "</tr>").unwrap();
+        }
+    }
+    if !table.entropy.is_empty() {
+        write!(out, "<tr class=\"subtotal\"><td>{}</td>", escape(&summary_label(ENTROPY_LABEL))).unwrap();
+        for value in table.spread(table.entropy.iter().map(|value| format!("{value:.2}")).collect()) {
+            write!(out, "<td class=\"n\">{value}</td>").unwrap();
+        }
+        writeln!(out, "</tr>").unwrap();
+    }
+    for (label, cells) in table.summary_rows() {
+        write!(out, "<tr class=\"subtotal\"><td>{}</td>", escape(&summary_label(label))).unwrap();
+        for cell in table.spread(cells) {
+            write!(out, "<td class=\"n\">{cell}</td>").unwrap();
+        }
+        writeln!(out, "</tr>").unwrap();
+    }
+    writeln!(out, "</table>").unwrap();
+}
+
+/// Where a page gets its stylesheet and data from
+#[derive(Debug, Clone, Copy, Default)]
+pub struct PageAssets<'a> {
+    /// Link to this stylesheet instead of embedding the style
+    pub stylesheet: Option<&'a str>,
+    /// JSON version of the same data, linked from the page
+    pub data: Option<&'a str>,
+}
+
+pub fn render(opt: &Args, report: &Report, excluded: &HashMap<String, SkipReason>, separator: &str, assets: PageAssets) -> String {
+    let table = &report.table;
+    let links = opt.repo_url.as_deref().map(RepoLinks::new);
+    let revision = opt.branch.clone()
+        .or(table.files_at.as_ref().map(|(_, revision)| revision.clone()))
+        .unwrap_or("HEAD".to_string());
+    let mut out = String::new();
+
+    writeln!(out, "<!DOCTYPE html>\n<html>\n<head>\n<meta charset=\"utf-8\">").unwrap();
+    writeln!(out, "<title>git-author-stats</title>").unwrap();
+    match assets.stylesheet {
+        Some(path) => writeln!(out, "<link rel=\"stylesheet\" href=\"{}\">", escape(path)).unwrap(),
+        None => writeln!(out, "<style>\n{STYLE}</style>").unwrap(),
+    }
+    writeln!(out, "</head>\n<body>").unwrap();
+
+    writeln!(out, "<h2>{}</h2>", escape(&t("authors-heading"))).unwrap();
+    if let Some(path) = assets.data {
+        writeln!(out, "<p>{0}: <a href=\"{1}\">{1}</a></p>", escape(&t("data")), escape(path)).unwrap();
+    }
+    if opt.chart {
+        out.push_str(&chart::svg(opt, table));
+    }
+    author_table(&mut out, opt, table, links.as_ref(), &revision, separator);
+    for (name, group_table) in &report.groups {
+        writeln!(out, "<h3>{}</h3>", escape(name)).unwrap();
+        author_table(&mut out, opt, group_table, links.as_ref(), &revision, separator);
+    }
+
+    if let Some((date, files_revision)) = &table.files_at {
+        writeln!(out, "<h2>{}</h2>\n<table>", escape(&tf("files-at-heading", &[("date", &i18n::date_label(date))]))).unwrap();
+        writeln!(out, "<tr><th>{}</th><th>{}</th><th>{}</th><th>{}</th></tr>", escape(&t("file")), escape(&t("lines")), escape(&t("entropy")), escape(&t("owners"))).unwrap();
+        for file in &table.files {
+            let url = links.as_ref().map(|links| links.blame(files_revision, &file.path));
+            let owners: Vec<String> = file.owners.iter().take(FILE_OWNERS).map(|(name, count)| {
+                let percent = if file.total == 0 { 0.0 } else { 100.0 * *count as f64 / file.total as f64 };
+                format!("{} {percent:.0}%", escape(name))
+            }).collect();
+            writeln!(out, "<tr><td>{}</td><td class=\"n\">{}</td><td class=\"n\">{:.2}</td><td>{}</td></tr>",
+                link(url, &file.path), escape(&group_digits(file.total as i64, separator)), file.entropy, owners.join(", ")).unwrap();
+        }
+        writeln!(out, "</table>").unwrap();
+    }
+
+    if !report.fingerprint.is_empty() {
+        let date = table.dates.last().map(|d| d.as_str()).unwrap_or_default();
+        writeln!(out, "<h2>{}</h2>\n<table>", escape(&tf("fingerprint-heading", &[("date", &i18n::date_label(date))]))).unwrap();
+        writeln!(out, "<tr><th>{}</th><th>{}</th><th>{}</th></tr>", escape(&t("author")), escape(&t("lines")), escape(&t("groups"))).unwrap();
+        for row in &report.fingerprint {
+            writeln!(out, "<tr><td>{}</td><td class=\"n\">{}</td><td>{}</td></tr>",
+                escape(&row.author), escape(&group_digits(row.total as i64, separator)), escape(&row.summary())).unwrap();
+        }
+        writeln!(out, "</table>").unwrap();
+    }
+
+    if opt.show_excluded {
+        let mut paths: Vec<&String> = excluded.keys().collect();
+        paths.sort();
+        writeln!(out, "<h2>{}</h2>\n<table>", escape(&tf("excluded-files-heading", &[("count", &paths.len().to_string())]))).unwrap();
+        writeln!(out, "<tr><th>{}</th><th>{}</th><th>{}</th></tr>", escape(&t("file")), escape(&t("reason")), escape(&t("rule"))).unwrap();
+        for path in paths {
+            let skip = &excluded[path];
+            let rule = match skip.pattern.is_empty() {
+                true => String::new(),
+                false => format!("{} {}", skip.source, skip.pattern),
+            };
+            writeln!(out, "<tr><td>{}</td><td>{}</td><td>{}</td></tr>", escape(path), escape(&skip.reason), escape(&rule)).unwrap();
+        }
+        writeln!(out, "</table>").unwrap();
+    }
+
+    writeln!(out, "</body>\n</html>").unwrap();
+    return out;
+}
+
+/// The comparison of --branches, then the author table of each branch
+pub fn render_branches(opt: &Args, branches: &[(String, Report)], comparison: Option<&Comparison>, separator: &str) -> String {
+    let links = opt.repo_url.as_deref().map(RepoLinks::new);
+    let mut out = String::new();
+    writeln!(out, "<!DOCTYPE html>\n<html>\n<head>\n<meta charset=\"utf-8\">").unwrap();
+    writeln!(out, "<title>git-author-stats</title>").unwrap();
+    writeln!(out, "<style>\n{STYLE}</style>").unwrap();
+    writeln!(out, "</head>\n<body>").unwrap();
+
+    if let Some(comparison) = comparison {
+        let heading = tf("branches-heading", &[("date", &i18n::date_label(&comparison.date)), ("base", &comparison.branches[0])]);
+        writeln!(out, "<h2>{}</h2>\n<table>", escape(&heading)).unwrap();
+        write!(out, "<tr><th>{}</th>", escape(&t("author"))).unwrap();
+        for name in &comparison.branches {
+            write!(out, "<th>{}</th>", escape(name)).unwrap();
+        }
+        writeln!(out, "</tr>").unwrap();
+        for row in &comparison.rows {
+            write!(out, "<tr><td>{}</td>", escape(&row.author)).unwrap();
+            for cell in branches::cells(row, separator) {
+                write!(out, "<td class=\"n\">{}</td>", escape(&cell)).unwrap();
+            }
+            writeln!(out, "</tr>").unwrap();
+        }
+        writeln!(out, "</table>").unwrap();
+    }
+
+    for (name, report) in branches {
+        writeln!(out, "<h2>{}</h2>", escape(name)).unwrap();
+        if opt.chart {
+            out.push_str(&chart::svg(opt, &report.table));
+        }
+        author_table(&mut out, opt, &report.table, links.as_ref(), name, separator);
+        for (group, group_table) in &report.groups {
+            writeln!(out, "<h3>{}</h3>", escape(group)).unwrap();
+            author_table(&mut out, opt, group_table, links.as_ref(), name, separator);
+        }
+    }
+
+    writeln!(out, "</body>\n</html>").unwrap();
+    return out;
+}
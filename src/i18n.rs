@@ -0,0 +1,441 @@
+use hashbrown::HashMap;
+use std::sync::OnceLock;
+
+use crate::cli_args::Lang;
+
+//----
+// Report languages
+//
+// --lang de prints the human-facing report text (table and markdown headers,
+// section titles, summary row labels, the HTML page) and the dates labeling
+// the columns in another language; CSV, JSON and the other machine formats
+// never change.  The messages are Fluent (.ftl) catalogs, the simple part of
+// the syntax: `id = text` with `{ $name }` placeholders, `#` comments and
+// indented continuation lines.  --lang-file adds or overrides messages from
+// another catalog.  A message missing from a catalog falls back to English,
+// and without --lang dates are printed as they are.
+
+const EN: &str = r#"
+# Tables
+date = date
+author = author
+group-total = { $group } total
+docs-column = { $date } docs
+entropy-row = (entropy)
+bus-factor-row = (bus factor)
+dominated-row = (dominated files)
+health-row = (health)
+ineligible-row = (no eligible files)
+margin-row = (± 95% margin)
+# Sections
+movers-since = movers since { $date }
+gained = gained
+lost = lost
+grew = grew
+shrank = shrank
+lines-at = Lines at { $date }
+lines-at-target = Lines at { $target } ({ $date })
+coupled-files-at = coupled files at { $date }
+coupled-files-heading = Coupled files at { $date }
+coupled-together = { $degree }% of { $commits } commits together
+different-teams = different teams
+different-owners = different owners
+fingerprint-at = fingerprint at { $date }
+fingerprint-heading = Fingerprint at { $date }
+branches-at = branches at { $date }, compared to { $base }
+branches-heading = Branches at { $date }, compared to { $base }
+excluded-files = excluded files: { $count }
+excluded-files-heading = Excluded files: { $count }
+authors-heading = Authors
+files-at-heading = Files at { $date }
+data = Data
+repositories-heading = Repositories
+repository = repository
+authors = authors
+bus-factor = bus factor
+top-author = top author
+failed = failed: { $error }
+# Columns
+file = file
+files = files
+lines = lines
+entropy = entropy
+owners = owners
+groups = groups
+coupling = coupling
+commits = commits
+risk = risk
+reason = reason
+rule = rule
+# Dates
+month-1 = Jan
+month-2 = Feb
+month-3 = Mar
+month-4 = Apr
+month-5 = May
+month-6 = Jun
+month-7 = Jul
+month-8 = Aug
+month-9 = Sep
+month-10 = Oct
+month-11 = Nov
+month-12 = Dec
+month-label = { $month } { $year }
+day-label = { $month } { $day }, { $year }
+"#;
+
+const DE: &str = r#"
+date = Datum
+author = Autor
+group-total = { $group } gesamt
+docs-column = { $date } Doku
+entropy-row = (Entropie)
+bus-factor-row = (Busfaktor)
+dominated-row = (dominierte Dateien)
+health-row = (Gesundheit)
+ineligible-row = (keine auswertbaren Dateien)
+margin-row = (± 95 % Fehlerspanne)
+movers-since = Veränderungen seit { $date }
+gained = neu
+lost = weg
+grew = mehr
+shrank = weniger
+lines-at = Zeilen am { $date }
+lines-at-target = Zeilen bei { $target } ({ $date })
+coupled-files-at = gekoppelte Dateien am { $date }
+coupled-files-heading = Gekoppelte Dateien am { $date }
+coupled-together = in { $degree } % von { $commits } Commits gemeinsam
+different-teams = verschiedene Teams
+different-owners = verschiedene Eigentümer
+fingerprint-at = Fingerabdruck am { $date }
+fingerprint-heading = Fingerabdruck am { $date }
+branches-at = Branches am { $date }, verglichen mit { $base }
+branches-heading = Branches am { $date }, verglichen mit { $base }
+excluded-files = ausgeschlossene Dateien: { $count }
+excluded-files-heading = Ausgeschlossene Dateien: { $count }
+authors-heading = Autoren
+files-at-heading = Dateien am { $date }
+data = Daten
+repositories-heading = Repositories
+repository = Repository
+authors = Autoren
+bus-factor = Busfaktor
+top-author = Hauptautor
+failed = fehlgeschlagen: { $error }
+file = Datei
+files = Dateien
+lines = Zeilen
+entropy = Entropie
+owners = Eigentümer
+groups = Gruppen
+coupling = Kopplung
+commits = Commits
+risk = Risiko
+reason = Grund
+rule = Regel
+month-1 = Jan.
+month-2 = Feb.
+month-3 = März
+month-4 = Apr.
+month-5 = Mai
+month-6 = Juni
+month-7 = Juli
+month-8 = Aug.
+month-9 = Sept.
+month-10 = Okt.
+month-11 = Nov.
+month-12 = Dez.
+month-label = { $month } { $year }
+day-label = { $day }. { $month } { $year }
+"#;
+
+const FR: &str = r#"
+date = date
+author = auteur
+group-total = total { $group }
+docs-column = { $date } doc
+entropy-row = (entropie)
+bus-factor-row = (facteur bus)
+dominated-row = (fichiers dominés)
+health-row = (santé)
+ineligible-row = (aucun fichier retenu)
+margin-row = (marge ± 95 %)
+movers-since = évolutions depuis { $date }
+gained = arrivés
+lost = partis
+grew = en hausse
+shrank = en baisse
+lines-at = Lignes au { $date }
+lines-at-target = Lignes à { $target } ({ $date })
+coupled-files-at = fichiers couplés au { $date }
+coupled-files-heading = Fichiers couplés au { $date }
+coupled-together = ensemble dans { $degree } % de { $commits } commits
+different-teams = équipes différentes
+different-owners = propriétaires différents
+fingerprint-at = empreinte au { $date }
+fingerprint-heading = Empreinte au { $date }
+branches-at = branches au { $date }, comparées à { $base }
+branches-heading = Branches au { $date }, comparées à { $base }
+excluded-files = fichiers exclus : { $count }
+excluded-files-heading = Fichiers exclus : { $count }
+authors-heading = Auteurs
+files-at-heading = Fichiers au { $date }
+data = Données
+repositories-heading = Dépôts
+repository = dépôt
+authors = auteurs
+bus-factor = facteur bus
+top-author = auteur principal
+failed = échec : { $error }
+file = fichier
+files = fichiers
+lines = lignes
+entropy = entropie
+owners = propriétaires
+groups = groupes
+coupling = couplage
+commits = commits
+risk = risque
+reason = raison
+rule = règle
+month-1 = janv.
+month-2 = févr.
+month-3 = mars
+month-4 = avr.
+month-5 = mai
+month-6 = juin
+month-7 = juil.
+month-8 = août
+month-9 = sept.
+month-10 = oct.
+month-11 = nov.
+month-12 = déc.
+month-label = { $month } { $year }
+day-label = { $day } { $month } { $year }
+"#;
+
+const ES: &str = r#"
+date = fecha
+author = autor
+group-total = total { $group }
+docs-column = { $date } docs
+entropy-row = (entropía)
+bus-factor-row = (factor bus)
+dominated-row = (archivos dominados)
+health-row = (salud)
+ineligible-row = (ningún archivo válido)
+margin-row = (margen ± 95%)
+movers-since = cambios desde { $date }
+gained = llegan
+lost = se van
+grew = suben
+shrank = bajan
+lines-at = Líneas a { $date }
+lines-at-target = Líneas en { $target } ({ $date })
+coupled-files-at = archivos acoplados a { $date }
+coupled-files-heading = Archivos acoplados a { $date }
+coupled-together = juntos en { $degree } % de { $commits } commits
+different-teams = equipos distintos
+different-owners = propietarios distintos
+fingerprint-at = huella a { $date }
+fingerprint-heading = Huella a { $date }
+branches-at = ramas a { $date }, comparadas con { $base }
+branches-heading = Ramas a { $date }, comparadas con { $base }
+excluded-files = archivos excluidos: { $count }
+excluded-files-heading = Archivos excluidos: { $count }
+authors-heading = Autores
+files-at-heading = Archivos a { $date }
+data = Datos
+repositories-heading = Repositorios
+repository = repositorio
+authors = autores
+bus-factor = factor bus
+top-author = autor principal
+failed = falló: { $error }
+file = archivo
+files = archivos
+lines = líneas
+entropy = entropía
+owners = propietarios
+groups = grupos
+coupling = acoplamiento
+commits = commits
+risk = riesgo
+reason = motivo
+rule = regla
+month-1 = ene.
+month-2 = feb.
+month-3 = mar.
+month-4 = abr.
+month-5 = may.
+month-6 = jun.
+month-7 = jul.
+month-8 = ago.
+month-9 = sept.
+month-10 = oct.
+month-11 = nov.
+month-12 = dic.
+month-label = { $month } { $year }
+day-label = { $day } { $month } { $year }
+"#;
+
+/// Messages by id
+#[derive(Debug, Clone, Default, PartialEq)]
+pub struct Catalog {
+    messages: HashMap<String, String>,
+    /// Whether dates are labeled, as they are with --lang
+    dates: bool,
+}
+
+impl Catalog {
+    /// Messages of a Fluent catalog
+    pub fn parse(ftl: &str) -> Result<Catalog, String> {
+        let mut catalog = Catalog::default();
+        let mut last: Option<String> = None;
+        for (i, line) in ftl.lines().enumerate() {
+            if line.trim().is_empty() || line.trim_start().starts_with('#') {
+                last = None;
+                continue;
+            }
+            if line.starts_with([' ', '\t']) {
+                let id = last.as_ref().ok_or_else(|| format!("line {}: continuation without a message", i + 1))?;
+                let text = catalog.messages.get_mut(id).expect("the message was added");
+                if !text.is_empty() {
+                    text.push(' ');
+                }
+                text.push_str(line.trim());
+                continue;
+            }
+            let (id, text) = line.split_once('=').ok_or_else(|| format!("line {}: expected ID = TEXT, got {line:?}", i + 1))?;
+            let id = id.trim();
+            if id.is_empty() || !id.chars().all(|c| c.is_ascii_alphanumeric() || c == '-' || c == '_') {
+                return Err(format!("line {}: bad message id {id:?}", i + 1));
+            }
+            catalog.messages.insert(id.to_string(), text.trim().to_string());
+            last = Some(id.to_string());
+        }
+        return Ok(catalog);
+    }
+
+    /// The built-in catalog of `lang`, over the English one
+    pub fn builtin(lang: Lang) -> Catalog {
+        let mut catalog = Catalog::parse(EN).expect("the English catalog parses");
+        let ftl = match lang {
+            Lang::En => "",
+            Lang::De => DE,
+            Lang::Fr => FR,
+            Lang::Es => ES,
+        };
+        catalog.merge(Catalog::parse(ftl).expect("built-in catalogs parse"));
+        catalog.dates = true;
+        return catalog;
+    }
+
+    /// Add `other`'s messages, replacing ours
+    pub fn merge(&mut self, other: Catalog) {
+        self.messages.extend(other.messages);
+    }
+
+    /// The message `id` with its `{ $name }` placeholders filled in, or the id
+    /// if there's no such message
+    pub fn format(&self, id: &str, args: &[(&str, &str)]) -> String {
+        let Some(text) = self.messages.get(id).or_else(|| english().messages.get(id)) else {
+            return id.to_string();
+        };
+        let mut out = String::new();
+        let mut rest = text.as_str();
+        while let Some(start) = rest.find('{') {
+            out.push_str(&rest[..start]);
+            let Some(end) = rest[start..].find('}') else {
+                break;
+            };
+            let name = rest[start + 1..start + end].trim().trim_start_matches('$');
+            match args.iter().find(|(arg, _)| *arg == name) {
+                Some((_, value)) => out.push_str(value),
+                None => out.push_str(&rest[start..start + end + 1]),
+            }
+            rest = &rest[start + end + 1..];
+        }
+        out.push_str(rest);
+        return out;
+    }
+
+    /// A column's date, `2020-04-01`, as a label such as "Apr 2020"; a date
+    /// other than the first of the month keeps its day
+    pub fn date_label(&self, date: &str) -> String {
+        let parts: Vec<&str> = date.splitn(3, '-').collect();
+        let (year, month, day) = match (self.dates, parts.as_slice()) {
+            (true, [year, month, day]) => (*year, *month, *day),
+            _ => return date.to_string(),
+        };
+        let (Ok(month), Ok(day)) = (month.parse::<u32>(), day.parse::<u32>()) else {
+            return date.to_string();
+        };
+        let name = self.format(&format!("month-{month}"), &[]);
+        let day = day.to_string();
+        match day.as_str() {
+            "1" => return self.format("month-label", &[("month", &name), ("year", year)]),
+            _ => return self.format("day-label", &[("month", &name), ("day", &day), ("year", year)]),
+        }
+    }
+}
+
+fn english() -> &'static Catalog {
+    static ENGLISH: OnceLock<Catalog> = OnceLock::new();
+    return ENGLISH.get_or_init(|| Catalog::parse(EN).expect("the English catalog parses"));
+}
+
+static CATALOG: OnceLock<Catalog> = OnceLock::new();
+
+/// Print the run's reports with `catalog`
+pub fn init(catalog: Catalog) {
+    let _ = CATALOG.set(catalog);
+}
+
+fn catalog() -> &'static Catalog {
+    return CATALOG.get().unwrap_or_else(|| english());
+}
+
+/// The message `id` in the run's language
+pub fn t(id: &str) -> String {
+    return catalog().format(id, &[]);
+}
+
+/// The message `id` in the run's language, with its placeholders filled in
+pub fn tf(id: &str, args: &[(&str, &str)]) -> String {
+    return catalog().format(id, args);
+}
+
+/// A column's date in the run's language
+pub fn date_label(date: &str) -> String {
+    return catalog().date_label(date);
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn formats_messages_with_an_english_fallback() {
+        let english = Catalog::parse(EN).unwrap();
+        assert_eq!(english.format("group-total", &[("group", "core")]), "core total");
+        assert_eq!(english.date_label("2020-04-01"), "2020-04-01");
+
+        let german = Catalog::builtin(Lang::De);
+        assert_eq!(german.format("group-total", &[("group", "core")]), "core gesamt");
+        assert_eq!(german.date_label("2020-03-01"), "März 2020");
+        assert_eq!(german.date_label("2020-03-15"), "15. März 2020");
+        assert_eq!(Catalog::builtin(Lang::En).date_label("2020-04-15"), "Apr 15, 2020");
+
+        let mut custom = Catalog::builtin(Lang::Fr);
+        custom.merge(Catalog::parse("# ours\nauthor = contributeur\nlines-at =\n    Lignes\n    au { $date }\n").unwrap());
+        assert_eq!(custom.format("author", &[]), "contributeur");
+        assert_eq!(custom.format("lines-at", &[("date", "mai 2020")]), "Lignes au mai 2020");
+        assert_eq!(custom.format("entropy-row", &[]), "(entropie)");
+        custom.messages.remove("data");
+        assert_eq!(custom.format("data", &[]), "Data");
+        assert_eq!(custom.format("no-such-message", &[]), "no-such-message");
+        assert!(Catalog::parse("author contributeur").unwrap_err().starts_with("line 1: "));
+        assert!(Catalog::parse("\n  Lignes").unwrap_err().starts_with("line 2: "));
+    }
+}
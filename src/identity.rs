@@ -0,0 +1,831 @@
+use hashbrown::HashMap;
+use lazy_static::lazy_static;
+use regex::Regex;
+use std::fmt::Debug;
+use std::fs;
+use std::path::Path;
+use std::sync::Arc;
+
+use crate::recording;
+use crate::repo::Repo;
+use crate::AuthorCount;
+
+//----
+// Author identities
+//
+// Blame output is counted per raw (name, email) pair.  Before display, every
+// raw identity is resolved to a canonical one so that the same person is
+// aggregated into one row, by a chain of steps each applying to what the
+// earlier ones made of it:
+//   1. the repo's .mailmap
+//   2. a user supplied alias file (--aliases)
+//   3. the config's domain rules, for a company that changed its email domain
+//   4. heuristics, such as GitHub's two forms of noreply addresses
+//   5. any steps a library user adds
+// After that, the email address alone identifies the author.
+// --explain-identity prints what each step did to an identity.
+
+#[derive(Debug, Clone, PartialEq, Eq, Hash, PartialOrd, Ord)]
+pub struct Author {
+    pub name: String,
+    pub email: String,
+}
+
+impl Author {
+    pub fn new(name: &str, email: &str) -> Author {
+        return Author { name: name.trim().to_string(), email: email.trim().to_string() };
+    }
+
+    /// Key used to aggregate counts: the lowercased email, or the name when
+    /// there is no email at all
+    pub fn key(&self) -> String {
+        if self.email.is_empty() {
+            return normalize_name(&self.name);
+        }
+        return self.email.to_lowercase();
+    }
+}
+
+/// Lowercase a name and fold punctuation, so "john.doe" and "John Doe" compare equal
+pub fn normalize_name(name: &str) -> String {
+    lazy_static! {
+        static ref RE_SPECIAL: Regex = Regex::new(r"[-_\.\s]+").unwrap();
+    };
+    return RE_SPECIAL.replace_all(name.trim(), " ").trim().to_lowercase();
+}
+
+/// Prettify a raw author name for display: "john_doe" -> "John Doe"
+pub fn display_name(name: &str) -> String {
+    lazy_static! {
+        static ref RE_CAPITAL: Regex = Regex::new(r"\b[a-z]").unwrap();
+    };
+
+    let mut author = normalize_name(name);
+    for mat in RE_CAPITAL.find_iter(&author.clone()) {
+        let c = author[mat.start()..mat.end()].to_uppercase();
+        author.replace_range(mat.start()..mat.end(), &c);
+    }
+    return author;
+}
+
+/// Split "Some Name <some@email>" into its name and email.  Either may be empty.
+pub fn parse_identity(text: &str) -> (String, String) {
+    let text = text.trim();
+    if let (Some(lt), Some(gt)) = (text.find('<'), text.rfind('>')) {
+        if lt < gt {
+            return (text[..lt].trim().to_string(), text[lt + 1..gt].trim().to_string());
+        }
+    }
+    if text.contains('@') && !text.contains(char::is_whitespace) {
+        return (String::new(), text.to_string());
+    }
+    return (text.to_string(), String::new());
+}
+
+//----
+// .mailmap
+
+#[derive(Debug, Clone)]
+struct MailmapEntry {
+    proper_name: Option<String>,
+    proper_email: Option<String>,
+    commit_name: Option<String>,
+    commit_email: String,
+}
+
+#[derive(Debug, Clone, Default)]
+pub struct Mailmap {
+    entries: Vec<MailmapEntry>,
+}
+
+impl Mailmap {
+    /// Parse the text of a .mailmap file.  Supports all four forms described
+    /// in gitmailmap(5).
+    pub fn parse(text: &str) -> Mailmap {
+        lazy_static! {
+            static ref RE_LINE: Regex =
+                Regex::new(r"^\s*([^<]*?)\s*<([^>]*)>\s*(?:([^<]*?)\s*<([^>]*)>)?\s*$").unwrap();
+        };
+
+        let mut entries = Vec::new();
+        for line in text.lines() {
+            let line = match line.find('#') {
+                Some(pos) => &line[..pos],
+                None => line,
+            };
+            if line.trim().is_empty() {
+                continue;
+            }
+            let Some(caps) = RE_LINE.captures(line) else {
+                continue;
+            };
+            let non_empty = |i: usize| caps.get(i).map(|m| m.as_str().trim().to_string()).filter(|s| !s.is_empty());
+            let entry = match caps.get(4) {
+                // Proper Name <commit@email>
+                None => MailmapEntry {
+                    proper_name: non_empty(1),
+                    proper_email: None,
+                    commit_name: None,
+                    commit_email: non_empty(2).unwrap_or_default(),
+                },
+                // [Proper Name] <proper@email> [Commit Name] <commit@email>
+                Some(_) => MailmapEntry {
+                    proper_name: non_empty(1),
+                    proper_email: non_empty(2),
+                    commit_name: non_empty(3),
+                    commit_email: non_empty(4).unwrap_or_default(),
+                },
+            };
+            entries.push(entry);
+        }
+        return Mailmap { entries };
+    }
+
+    pub fn map(&self, author: &Author) -> Author {
+        // Entries that also match on name take priority over email-only ones
+        let email = author.email.to_lowercase();
+        let found = self.entries.iter()
+            .filter(|e| e.commit_email.to_lowercase() == email)
+            .filter(|e| match &e.commit_name {
+                Some(name) => name.to_lowercase() == author.name.to_lowercase(),
+                None => true,
+            })
+            .max_by_key(|e| e.commit_name.is_some());
+        match found {
+            None => return author.clone(),
+            Some(e) => {
+                return Author {
+                    name: e.proper_name.clone().unwrap_or_else(|| author.name.clone()),
+                    email: e.proper_email.clone().unwrap_or_else(|| author.email.clone()),
+                };
+            },
+        }
+    }
+}
+
+/// The distinct author identities of the commits reachable from `branch`
+/// (default: HEAD), as committed, before the .mailmap
+pub fn commit_identities(repo: &Repo, branch: &Option<String>) -> Vec<Author> {
+    let mut cmd = repo.git();
+    cmd.arg("log").arg("--format=%an%x00%ae");
+    if let Some(branch) = branch {
+        cmd.arg(branch);
+    }
+    let Ok(log_out) = recording::output(&mut cmd, None) else {
+        return Vec::new();
+    };
+    let mut authors: Vec<Author> = Vec::new();
+    for line in String::from_utf8_lossy(&log_out.stdout).lines() {
+        let (name, email) = line.split_once('\0').unwrap_or((line, ""));
+        let author = Author::new(name, email);
+        if !authors.contains(&author) {
+            authors.push(author);
+        }
+    }
+    return authors;
+}
+
+/// Text of the repo's .mailmap from the work tree, falling back to the one
+/// committed at HEAD (bare repos).  Empty if there is none.
+pub fn read_mailmap(repo: &Repo) -> String {
+    if let Ok(text) = recording::read_to_string(&Path::new(&repo.root).join(".mailmap")) {
+        return text;
+    }
+    let show_out = recording::output(repo.git().arg("show").arg("HEAD:.mailmap"), None);
+    match show_out {
+        Ok(out) if out.status.success() => return String::from_utf8_lossy(&out.stdout).to_string(),
+        _ => return String::new(),
+    }
+}
+
+//----
+// Alias file
+//
+// One canonical identity per line, followed by the names and/or emails that
+// should be merged into it:
+//
+//   John Doe <jdoe@example.com> = jdoe, john.doe, john@old-company.com
+
+#[derive(Debug, Clone, Default)]
+pub struct Aliases {
+    by_email: HashMap<String, Author>,
+    by_name: HashMap<String, Author>,
+}
+
+impl Aliases {
+    pub fn parse(text: &str) -> Result<Aliases, String> {
+        let mut aliases = Aliases::default();
+        for (lineno, line) in text.lines().enumerate() {
+            let line = line.trim();
+            if line.is_empty() || line.starts_with('#') {
+                continue;
+            }
+            let Some((canonical, alias_list)) = line.split_once('=') else {
+                return Err(format!("line {}: expected 'Name <email> = alias, ...'", lineno + 1));
+            };
+            let (name, email) = parse_identity(canonical);
+            let canonical = Author::new(&name, &email);
+            // The canonical identity is an alias of itself
+            for alias in std::iter::once(canonical_text(&canonical)).chain(alias_list.split(',').map(|x| x.to_string())) {
+                let (name, email) = parse_identity(&alias);
+                if !email.is_empty() {
+                    aliases.by_email.insert(email.to_lowercase(), canonical.clone());
+                }
+                if !name.is_empty() {
+                    aliases.by_name.insert(normalize_name(&name), canonical.clone());
+                }
+            }
+        }
+        return Ok(aliases);
+    }
+
+    pub fn load(path: &str) -> Result<Aliases, String> {
+        let text = fs::read_to_string(path).map_err(|e| format!("{path}: {e}"))?;
+        return Aliases::parse(&text).map_err(|e| format!("{path}: {e}"));
+    }
+
+    pub fn map(&self, author: &Author) -> Option<Author> {
+        if let Some(canonical) = self.by_email.get(&author.email.to_lowercase()) {
+            return Some(canonical.clone());
+        }
+        return self.by_name.get(&normalize_name(&author.name)).cloned();
+    }
+}
+
+fn canonical_text(author: &Author) -> String {
+    return format!("{} <{}>", author.name, author.email);
+}
+
+//----
+// Domain rules and heuristics
+
+/// Email domains that stand for others, from the config's
+/// `[identity.domains]` table, e.g. `"old-company.com" = "example.com"`
+#[derive(Debug, Clone, Default)]
+pub struct DomainRules {
+    domains: HashMap<String, String>,
+}
+
+impl DomainRules {
+    pub fn new(rules: &[(String, String)]) -> DomainRules {
+        return DomainRules { domains: rules.iter().map(|(from, to)| (from.to_lowercase(), to.trim().to_string())).collect() };
+    }
+
+    pub fn map(&self, author: &Author) -> Option<Author> {
+        let (user, domain) = author.email.rsplit_once('@')?;
+        let to = self.domains.get(&domain.to_lowercase())?;
+        return Some(Author { name: author.name.clone(), email: format!("{user}@{to}") });
+    }
+}
+
+/// Guesses that hold for about every repo
+#[derive(Debug, Clone, Copy, Default)]
+pub struct Heuristics;
+
+impl Heuristics {
+    pub fn map(&self, author: &Author) -> Option<Author> {
+        lazy_static! {
+            // GitHub's noreply address with the account id, as used since 2017
+            static ref RE_NOREPLY: Regex = Regex::new(r"(?i)^\d+\+(.+@users\.noreply\.github\.com)$").unwrap();
+        };
+        let email = RE_NOREPLY.captures(&author.email)?.get(1)?.as_str();
+        return Some(Author { name: author.name.clone(), email: email.to_string() });
+    }
+}
+
+//----
+// Resolution chain
+
+/// One step of identity resolution
+pub trait Resolve: Debug + Send + Sync {
+    /// What --explain-identity calls the step
+    fn name(&self) -> &str;
+
+    /// Whether the names this step gives are the author's real name, rather
+    /// than kept from the commit
+    fn authoritative(&self) -> bool;
+
+    /// What `author`, which is `raw` after the earlier steps, resolves to, if
+    /// the step has a rule for it
+    fn resolve(&self, raw: &Author, author: &Author) -> Option<Author>;
+}
+
+impl Resolve for Mailmap {
+    fn name(&self) -> &str {
+        return "mailmap";
+    }
+
+    fn authoritative(&self) -> bool {
+        return true;
+    }
+
+    fn resolve(&self, _raw: &Author, author: &Author) -> Option<Author> {
+        let mapped = self.map(author);
+        return (mapped != *author).then_some(mapped);
+    }
+}
+
+impl Resolve for Aliases {
+    fn name(&self) -> &str {
+        return "aliases";
+    }
+
+    fn authoritative(&self) -> bool {
+        return true;
+    }
+
+    fn resolve(&self, raw: &Author, author: &Author) -> Option<Author> {
+        return self.map(author).or_else(|| self.map(raw));
+    }
+}
+
+impl Resolve for DomainRules {
+    fn name(&self) -> &str {
+        return "domains";
+    }
+
+    fn authoritative(&self) -> bool {
+        return false;
+    }
+
+    fn resolve(&self, _raw: &Author, author: &Author) -> Option<Author> {
+        return self.map(author);
+    }
+}
+
+impl Resolve for Heuristics {
+    fn name(&self) -> &str {
+        return "heuristics";
+    }
+
+    fn authoritative(&self) -> bool {
+        return false;
+    }
+
+    fn resolve(&self, _raw: &Author, author: &Author) -> Option<Author> {
+        return self.map(author);
+    }
+}
+
+//----
+// Resolution
+
+#[derive(Debug, Clone, Default)]
+pub struct IdentityResolver {
+    pub mailmap: Mailmap,
+    pub aliases: Aliases,
+    pub domains: DomainRules,
+    /// Apply the Heuristics step
+    pub heuristics: bool,
+    /// Steps after the built-in ones, in order
+    pub custom: Vec<Arc<dyn Resolve>>,
+    /// Row label template, by default the display name alone
+    pub format: Option<AuthorFormat>,
+    pub labels: SpecialLabels,
+}
+
+/// Canonical identity of a raw author, plus whether its name is authoritative
+/// (came from the mailmap or alias file) rather than just seen in a commit
+pub struct Resolved {
+    pub author: Author,
+    pub authoritative: bool,
+}
+
+/// What one step of the chain did to an identity, for --explain-identity
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct Step {
+    pub name: String,
+    /// The identity after the step, if it had a rule for it
+    pub author: Option<Author>,
+}
+
+impl IdentityResolver {
+    /// The steps of the chain, in order
+    pub fn chain(&self) -> Vec<&dyn Resolve> {
+        let mut chain: Vec<&dyn Resolve> = vec![&self.mailmap, &self.aliases, &self.domains];
+        if self.heuristics {
+            chain.push(&Heuristics);
+        }
+        chain.extend(self.custom.iter().map(|step| step.as_ref()));
+        return chain;
+    }
+
+    pub fn resolve(&self, raw: &Author) -> Resolved {
+        return self.explain(raw).0;
+    }
+
+    /// The canonical identity of `raw`, with what each step did to it
+    pub fn explain(&self, raw: &Author) -> (Resolved, Vec<Step>) {
+        if *raw == OptOut::aggregate() {
+            return (Resolved { author: raw.clone(), authoritative: true }, Vec::new());
+        }
+        if let Some(special) = Special::of(raw) {
+            return (Resolved { author: Author::new(self.labels.label(special), ""), authoritative: true }, Vec::new());
+        }
+        let mut resolved = Resolved { author: raw.clone(), authoritative: false };
+        let mut steps = Vec::new();
+        for step in self.chain() {
+            let author = step.resolve(raw, &resolved.author);
+            if let Some(author) = &author {
+                resolved.authoritative |= step.authoritative();
+                resolved.author = author.clone();
+            }
+            steps.push(Step { name: step.name().to_string(), author });
+        }
+        return (resolved, steps);
+    }
+
+    /// Build the display name for each canonical author key.  Names from the
+    /// mailmap or alias file win; otherwise the name seen on the most lines.
+    pub fn display_names<'a, I>(&self, counts: I) -> HashMap<String, String>
+    where
+        I: Iterator<Item = (&'a Author, i32)>,
+    {
+        let mut seen: HashMap<String, HashMap<String, i32>> = HashMap::new();
+        let mut fixed: HashMap<String, String> = HashMap::new();
+        let mut emails: HashMap<String, String> = HashMap::new();
+        for (raw, count) in counts {
+            let resolved = self.resolve(raw);
+            let key = resolved.author.key();
+            if !resolved.author.email.is_empty() {
+                emails.entry_ref(&key).or_insert(resolved.author.email.clone());
+            }
+            if resolved.authoritative && !resolved.author.name.is_empty() {
+                fixed.insert(key.clone(), resolved.author.name.clone());
+            }
+            *seen.entry(key).or_default().entry(resolved.author.name).or_insert(0) += count;
+        }
+
+        // Labels, not people: the template doesn't apply to them
+        let mut labels: Vec<String> = Special::ALL.iter().map(|special| self.resolve(&special.author()).author.key()).collect();
+        labels.push(OptOut::aggregate().key());
+        let mut names = HashMap::new();
+        for (key, name_counts) in seen {
+            let name = match fixed.get(&key) {
+                Some(name) => name.clone(),
+                None => {
+                    // Most common name, ties broken alphabetically for stable output
+                    let mut candidates: Vec<(&String, &i32)> = name_counts.iter().collect();
+                    candidates.sort_by(|a, b| b.1.cmp(a.1).then(a.0.cmp(b.0)));
+                    display_name(candidates[0].0)
+                },
+            };
+            let name = match &self.format {
+                Some(format) if !labels.contains(&key) => format.render(&name, emails.get(&key).map(|x| x.as_str()).unwrap_or("")),
+                _ => name,
+            };
+            names.insert(key, name);
+        }
+        return names;
+    }
+
+    /// `acnt` summed per display name, from display_names
+    pub fn count_by_name(&self, acnt: &AuthorCount, names: &HashMap<String, String>) -> HashMap<String, i64> {
+        let mut counts = HashMap::new();
+        for (author, count) in acnt {
+            let key = self.resolve(author).author.key();
+            let name = names.get(&key).cloned().unwrap_or(key);
+            *counts.entry(name).or_insert(0) += *count as i64;
+        }
+        return counts;
+    }
+}
+
+//----
+// Row labels
+//
+// --author-format builds each row's label from the canonical identity, e.g.
+// "{name} ({email_domain})".  Authors with the same label share a row.
+
+pub const FORMAT_FIELDS: [&str; 4] = ["name", "email", "email_user", "email_domain"];
+
+#[derive(Debug, Clone)]
+pub struct AuthorFormat {
+    template: String,
+}
+
+impl AuthorFormat {
+    pub fn parse(template: &str) -> Result<AuthorFormat, String> {
+        lazy_static! {
+            static ref RE_FIELD: Regex = Regex::new(r"\{([^{}]*)\}").unwrap();
+        };
+        for field in RE_FIELD.captures_iter(template) {
+            if !FORMAT_FIELDS.contains(&&field[1]) {
+                return Err(format!("unknown field {{{}}} in author format, expected one of: {}", &field[1], FORMAT_FIELDS.join(", ")));
+            }
+        }
+        return Ok(AuthorFormat { template: template.to_string() });
+    }
+
+    /// The fields the template uses
+    pub fn fields(&self) -> Vec<&'static str> {
+        return FORMAT_FIELDS.iter().copied().filter(|field| self.template.contains(&format!("{{{field}}}"))).collect();
+    }
+
+    pub fn render(&self, name: &str, email: &str) -> String {
+        let (user, domain) = email.split_once('@').unwrap_or((email, ""));
+        return self.template
+            .replace("{name}", name)
+            .replace("{email_user}", user)
+            .replace("{email_domain}", domain)
+            .replace("{email}", email);
+    }
+}
+
+//----
+// Special authors
+//
+// Some blamed lines have no regular author: lines of a shallow clone's
+// boundary commit (which stands in for all the history cut off before it),
+// lines not committed yet, and commits without author fields.  The backend
+// gives them reserved identities, which resolve to the labels of the config's
+// [authors] table rather than to odd names like "Not Committed Yet".
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Special {
+    Boundary,
+    Uncommitted,
+    Unknown,
+}
+
+impl Special {
+    const ALL: [Special; 3] = [Special::Boundary, Special::Uncommitted, Special::Unknown];
+
+    /// No commit has these emails: git strips angle brackets from identities
+    fn email(self) -> &'static str {
+        match self {
+            Special::Boundary => return "<boundary>",
+            Special::Uncommitted => return "<uncommitted>",
+            Special::Unknown => return "<unknown>",
+        }
+    }
+
+    pub fn author(self) -> Author {
+        return Author::new("", self.email());
+    }
+
+    pub fn of(author: &Author) -> Option<Special> {
+        return Special::ALL.into_iter().find(|special| author.name.is_empty() && author.email == special.email());
+    }
+}
+
+/// Row labels of the special authors, from `authors.boundary`,
+/// `authors.uncommitted` and `authors.unknown`
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct SpecialLabels {
+    pub boundary: String,
+    pub uncommitted: String,
+    pub unknown: String,
+}
+
+impl Default for SpecialLabels {
+    fn default() -> SpecialLabels {
+        return SpecialLabels {
+            boundary: "(before shallow clone)".to_string(),
+            uncommitted: "(uncommitted)".to_string(),
+            unknown: "(unknown author)".to_string(),
+        };
+    }
+}
+
+impl SpecialLabels {
+    pub fn label(&self, special: Special) -> &str {
+        match special {
+            Special::Boundary => return &self.boundary,
+            Special::Uncommitted => return &self.uncommitted,
+            Special::Unknown => return &self.unknown,
+        }
+    }
+}
+
+//----
+// Author filter
+//
+// --author and --exclude-author patterns are globs matched against an
+// author's name, email or "Name <email>" (case-insensitive); a `re:` prefix
+// makes the rest a regex instead.  Both the raw and canonical identity are
+// tried.
+
+lazy_static! {
+    /// Names and emails of common bots
+    static ref BOT: Regex = Regex::new(r"(?i)\[bot\]|(^|[-_. ])bot($|[-_.@ ])|^(dependabot|renovate|github-actions|greenkeeper|snyk|codecov|mergify|semantic-release|pre-commit-ci)\b").unwrap();
+}
+
+/// Whether an author looks like a bot account, by name or email
+pub fn is_bot(author: &Author) -> bool {
+    return BOT.is_match(&author.name) || BOT.is_match(&author.email);
+}
+
+/// Regex of an --author style pattern: a glob, or a `re:` regex
+pub fn author_pattern(pattern: &str) -> Result<Regex, String> {
+    let re = match pattern.strip_prefix("re:") {
+        Some(re) => re.to_string(),
+        None => {
+            let mut re = String::from("^");
+            for c in pattern.chars() {
+                match c {
+                    '*' => re.push_str(".*"),
+                    '?' => re.push('.'),
+                    c => re.push_str(&regex::escape(&c.to_string())),
+                }
+            }
+            re.push('$');
+            re
+        },
+    };
+    return Regex::new(&format!("(?i){re}")).map_err(|e| format!("bad author pattern '{pattern}': {e}"));
+}
+
+/// Whether the pattern matches the name, email or "name <email>" of any identity
+pub fn matches_identity(regex: &Regex, identities: &[&Author]) -> bool {
+    return identities.iter().any(|a| {
+        regex.is_match(&a.name) || regex.is_match(&a.email) || regex.is_match(&format!("{} <{}>", a.name, a.email))
+    });
+}
+
+#[derive(Debug, Default)]
+pub struct AuthorFilter {
+    include: Vec<Regex>,
+    exclude: Vec<Regex>,
+    exclude_bots: bool,
+}
+
+impl AuthorFilter {
+    pub fn new(include: &[String], exclude: &[String], exclude_bots: bool) -> Result<AuthorFilter, String> {
+        return Ok(AuthorFilter {
+            include: include.iter().map(|p| author_pattern(p)).collect::<Result<_, _>>()?,
+            exclude: exclude.iter().map(|p| author_pattern(p)).collect::<Result<_, _>>()?,
+            exclude_bots,
+        });
+    }
+
+    pub fn is_empty(&self) -> bool {
+        return self.include.is_empty() && self.exclude.is_empty() && !self.exclude_bots;
+    }
+
+    /// Whether to count this raw author.  With include patterns, only
+    /// matching authors are kept; excludes and bots are dropped either way.
+    pub fn keeps(&self, author: &Author, resolver: &IdentityResolver) -> bool {
+        let canonical = resolver.resolve(author).author;
+        let identities = [author, &canonical];
+        let matches = |regex: &Regex| matches_identity(regex, &identities);
+        if self.exclude_bots && identities.iter().any(|a| is_bot(a)) {
+            return false;
+        }
+        if self.exclude.iter().any(matches) {
+            return false;
+        }
+        return self.include.is_empty() || self.include.iter().any(matches);
+    }
+}
+
+/// Row the counts of opted-out authors are summed into
+pub const OPTED_OUT: &str = "Opted-out";
+
+/// Authors who opted out of individual tracking, from the config's
+/// `authors.opt_out` patterns (the same globs or `re:` regexes as --author)
+#[derive(Debug, Default)]
+pub struct OptOut {
+    patterns: Vec<Regex>,
+}
+
+impl OptOut {
+    pub fn new(patterns: &[String]) -> Result<OptOut, String> {
+        return Ok(OptOut { patterns: patterns.iter().map(|p| author_pattern(p)).collect::<Result<_, _>>()? });
+    }
+
+    pub fn is_empty(&self) -> bool {
+        return self.patterns.is_empty();
+    }
+
+    pub fn opted_out(&self, author: &Author, resolver: &IdentityResolver) -> bool {
+        let canonical = resolver.resolve(author).author;
+        return self.patterns.iter().any(|regex| matches_identity(regex, &[author, &canonical]));
+    }
+
+    /// The author every opted-out author is counted as
+    pub fn aggregate() -> Author {
+        return Author::new(OPTED_OUT, "");
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn mailmap_forms() {
+        let mailmap = Mailmap::parse("\
+Proper Name <commit@example.com>
+<proper@example.com> <old@example.com>
+Other Name <other@example.com> Commit Name <shared@example.com>
+# comment
+");
+        assert_eq!(mailmap.map(&Author::new("x", "Commit@Example.com")), Author::new("Proper Name", "Commit@Example.com"));
+        assert_eq!(mailmap.map(&Author::new("x", "old@example.com")), Author::new("x", "proper@example.com"));
+        assert_eq!(mailmap.map(&Author::new("Commit Name", "shared@example.com")), Author::new("Other Name", "other@example.com"));
+        assert_eq!(mailmap.map(&Author::new("Someone Else", "shared@example.com")), Author::new("Someone Else", "shared@example.com"));
+    }
+
+    #[test]
+    fn aliases_merge_identities() {
+        let resolver = IdentityResolver {
+            aliases: Aliases::parse("John Doe <jdoe@example.com> = john.doe, john@old.com").unwrap(),
+            ..Default::default()
+        };
+        assert_eq!(resolver.resolve(&Author::new("John_Doe", "")).author.key(), "jdoe@example.com");
+        assert_eq!(resolver.resolve(&Author::new("J", "JOHN@old.com")).author.key(), "jdoe@example.com");
+        assert!(Aliases::parse("no equals sign").is_err());
+    }
+
+    #[test]
+    fn resolves_through_the_chain() {
+        let resolver = IdentityResolver {
+            mailmap: Mailmap::parse("Jane Roe <jane@old-company.com> <jroe@old-company.com>"),
+            domains: DomainRules::new(&[("Old-Company.com".to_string(), "example.com".to_string())]),
+            heuristics: true,
+            ..Default::default()
+        };
+        let (resolved, steps) = resolver.explain(&Author::new("jroe", "jroe@old-company.com"));
+        assert_eq!(resolved.author, Author::new("Jane Roe", "jane@example.com"));
+        assert!(resolved.authoritative);
+        let applied: Vec<(&str, bool)> = steps.iter().map(|step| (step.name.as_str(), step.author.is_some())).collect();
+        assert_eq!(applied, [("mailmap", true), ("aliases", false), ("domains", true), ("heuristics", false)]);
+
+        let noreply = Author::new("octocat", "583231+octocat@users.noreply.github.com");
+        assert_eq!(resolver.resolve(&noreply).author.key(), "octocat@users.noreply.github.com");
+        assert!(!resolver.resolve(&noreply).authoritative);
+        assert_eq!(IdentityResolver::default().resolve(&noreply).author, noreply);
+
+        #[derive(Debug)]
+        struct Anonymize;
+        impl Resolve for Anonymize {
+            fn name(&self) -> &str {
+                return "anonymize";
+            }
+            fn authoritative(&self) -> bool {
+                return true;
+            }
+            fn resolve(&self, _raw: &Author, author: &Author) -> Option<Author> {
+                return Some(Author::new("Someone", &author.email));
+            }
+        }
+        let resolver = IdentityResolver { custom: vec![Arc::new(Anonymize)], ..resolver };
+        assert_eq!(resolver.resolve(&noreply).author, Author::new("Someone", "octocat@users.noreply.github.com"));
+    }
+
+    #[test]
+    fn author_formats() {
+        let format = AuthorFormat::parse("{name} ({email_domain})").unwrap();
+        assert_eq!(format.render("Jane Roe", "jane@example.com"), "Jane Roe (example.com)");
+        assert_eq!(AuthorFormat::parse("{email_user}").unwrap().render("Jane Roe", "jane@example.com"), "jane");
+        assert!(AuthorFormat::parse("{login}").is_err());
+
+        let resolver = IdentityResolver { format: Some(format), ..Default::default() };
+        let names = resolver.display_names([(&Author::new("jroe", "Jane@Example.com"), 1)].into_iter());
+        assert_eq!(names["jane@example.com"], "Jroe (Example.com)");
+
+        // Special authors keep their label
+        let resolver = IdentityResolver {
+            labels: SpecialLabels { uncommitted: "WIP".to_string(), ..SpecialLabels::default() },
+            ..resolver
+        };
+        let wip = resolver.resolve(&Special::Uncommitted.author()).author;
+        assert_eq!(resolver.display_names([(&Special::Uncommitted.author(), 1)].into_iter())[&wip.key()], "WIP");
+        assert_eq!(Special::of(&Author::new("Boundary", "<boundary>")), None);
+    }
+
+    #[test]
+    fn author_filters() {
+        let resolver = IdentityResolver::default();
+        let alice = Author::new("Alice Smith", "alice@example.com");
+        let bob = Author::new("bob", "bob@corp.example.com");
+        let filter = AuthorFilter::new(&["*@example.com".to_string()], &[], false).unwrap();
+        assert!(filter.keeps(&alice, &resolver));
+        assert!(!filter.keeps(&bob, &resolver));
+
+        let filter = AuthorFilter::new(&[], &["re:^ali".to_string()], true).unwrap();
+        assert!(!filter.keeps(&alice, &resolver));
+        assert!(filter.keeps(&bob, &resolver));
+        assert!(!filter.keeps(&Author::new("dependabot[bot]", "49699333+dependabot[bot]@users.noreply.github.com"), &resolver));
+        assert!(!filter.keeps(&Author::new("Renovate Bot", "bot@renovateapp.com"), &resolver));
+        assert!(AuthorFilter::new(&["re:(".to_string()], &[], false).is_err());
+
+        let opt_out = OptOut::new(&["Bob".to_string()]).unwrap();
+        assert!(opt_out.opted_out(&bob, &resolver));
+        assert!(!opt_out.opted_out(&alice, &resolver));
+    }
+
+    #[test]
+    fn display_names_prefer_authoritative() {
+        let resolver = IdentityResolver {
+            mailmap: Mailmap::parse("Jane Roe <jane@example.com>"),
+            ..Default::default()
+        };
+        let a = Author::new("jroe", "jane@example.com");
+        let b = Author::new("bob_smith", "bob@example.com");
+        let names = resolver.display_names([(&a, 10), (&b, 1)].into_iter());
+        assert_eq!(names["jane@example.com"], "Jane Roe");
+        assert_eq!(names["bob@example.com"], "Bob Smith");
+    }
+}
@@ -0,0 +1,159 @@
+//----
+// Reading JSON back
+//
+// The machine readable formats other than JSON are converted from the JSON
+// document, so they all carry the same data.  This reads the JSON the report
+// writes, keeping the keys in order and numbers as written.
+
+#[derive(Debug, Clone, PartialEq)]
+pub enum Value {
+    Null,
+    Bool(bool),
+    /// As written in the JSON
+    Number(String),
+    String(String),
+    Array(Vec<Value>),
+    /// Keys in document order
+    Object(Vec<(String, Value)>),
+}
+
+struct Parser<'a> {
+    text: &'a str,
+    /// Byte offset of the next character
+    pos: usize,
+}
+
+impl Parser<'_> {
+    fn peek(&self) -> Option<u8> {
+        return self.text.as_bytes().get(self.pos).copied();
+    }
+
+    fn skip_space(&mut self) {
+        while self.peek().is_some_and(|b| b.is_ascii_whitespace()) {
+            self.pos += 1;
+        }
+    }
+
+    fn expect(&mut self, byte: u8) -> Result<(), String> {
+        self.skip_space();
+        match self.peek() == Some(byte) {
+            true => {
+                self.pos += 1;
+                return Ok(());
+            },
+            false => return Err(format!("expected '{}' at byte {}", byte as char, self.pos)),
+        }
+    }
+
+    /// The comma separated items up to `close`, just after the opening bracket
+    fn list<T>(&mut self, close: u8, mut item: impl FnMut(&mut Self) -> Result<T, String>) -> Result<Vec<T>, String> {
+        let mut items = Vec::new();
+        self.skip_space();
+        if self.peek() == Some(close) {
+            self.pos += 1;
+            return Ok(items);
+        }
+        loop {
+            items.push(item(self)?);
+            self.skip_space();
+            match self.peek() {
+                Some(b',') => self.pos += 1,
+                Some(b) if b == close => {
+                    self.pos += 1;
+                    return Ok(items);
+                },
+                _ => return Err(format!("expected ',' or '{}' at byte {}", close as char, self.pos)),
+            }
+        }
+    }
+
+    fn string(&mut self) -> Result<String, String> {
+        self.expect(b'"')?;
+        let mut out = String::new();
+        let mut chars = self.text[self.pos..].char_indices();
+        while let Some((i, c)) = chars.next() {
+            match c {
+                '"' => {
+                    self.pos += i + 1;
+                    return Ok(out);
+                },
+                '\\' => match chars.next().map(|(_, c)| c) {
+                    Some('n') => out.push('\n'),
+                    Some('t') => out.push('\t'),
+                    Some('r') => out.push('\r'),
+                    Some('u') => {
+                        let hex: String = (0..4).filter_map(|_| chars.next().map(|(_, c)| c)).collect();
+                        let code = u32::from_str_radix(&hex, 16).map_err(|_| format!("bad escape \\u{hex}"))?;
+                        out.push(char::from_u32(code).unwrap_or(char::REPLACEMENT_CHARACTER));
+                    },
+                    Some(c) => out.push(c),
+                    None => break,
+                },
+                c => out.push(c),
+            }
+        }
+        return Err("unterminated string".to_string());
+    }
+
+    fn value(&mut self) -> Result<Value, String> {
+        self.skip_space();
+        let rest = &self.text[self.pos..];
+        match self.peek() {
+            Some(b'{') => {
+                self.pos += 1;
+                return Ok(Value::Object(self.list(b'}', |p| {
+                    let key = p.string()?;
+                    p.expect(b':')?;
+                    return Ok((key, p.value()?));
+                })?));
+            },
+            Some(b'[') => {
+                self.pos += 1;
+                return Ok(Value::Array(self.list(b']', |p| p.value())?));
+            },
+            Some(b'"') => return Ok(Value::String(self.string()?)),
+            _ => {},
+        }
+        for (word, value) in [("null", Value::Null), ("true", Value::Bool(true)), ("false", Value::Bool(false))] {
+            if rest.starts_with(word) {
+                self.pos += word.len();
+                return Ok(value);
+            }
+        }
+        let len = rest.find(|c: char| !matches!(c, '0'..='9' | '-' | '+' | '.' | 'e' | 'E')).unwrap_or(rest.len());
+        if len == 0 {
+            return Err(format!("expected a value at byte {}", self.pos));
+        }
+        self.pos += len;
+        return Ok(Value::Number(rest[..len].to_string()));
+    }
+}
+
+/// The JSON document `text`
+pub fn parse(text: &str) -> Result<Value, String> {
+    let mut parser = Parser { text, pos: 0 };
+    let value = parser.value()?;
+    parser.skip_space();
+    match parser.pos == text.len() {
+        true => return Ok(value),
+        false => return Err(format!("unexpected text at byte {}", parser.pos)),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn parses_documents() {
+        let value = parse(r#" {"a": [1, -2.5e3, "x\"é"], "b": {}, "c": [true, false, null]} "#).unwrap();
+        assert_eq!(value, Value::Object(vec![
+            ("a".to_string(), Value::Array(vec![Value::Number("1".to_string()), Value::Number("-2.5e3".to_string()), Value::String("x\"é".to_string())])),
+            ("b".to_string(), Value::Object(Vec::new())),
+            ("c".to_string(), Value::Array(vec![Value::Bool(true), Value::Bool(false), Value::Null])),
+        ]));
+        for bad in ["", "{", "[1 2]", "{\"a\" 1}", "\"open", "[] x"] {
+            assert!(parse(bad).is_err(), "{bad}");
+        }
+    }
+}
@@ -0,0 +1,152 @@
+use chrono::{Local, TimeZone};
+use hashbrown::HashMap;
+use std::fmt::Write;
+
+use crate::analysis::FileOwnership;
+use crate::backend::CommitInfo;
+use crate::breakdown::{self, Languages};
+use crate::cli_args::GroupBy;
+use crate::filter::FileFilter;
+use crate::identity::{display_name, Author, IdentityResolver};
+use crate::Count;
+
+//----
+// Knowledge map
+//
+// --knowledge-map writes a markdown page for onboarding docs: for every
+// top-level directory, the people owning most of its lines at the latest date
+// and when each last committed there.
+
+/// Number of people listed per directory
+const CONTACTS: usize = 3;
+
+/// Latest commit time (seconds since the epoch) per author, per directory
+pub type LastActivity = HashMap<String, HashMap<Author, i64>>;
+
+/// Someone to ask about a directory
+#[derive(Debug, Clone, PartialEq)]
+pub struct Contact {
+    pub name: String,
+    pub lines: Count,
+    /// Latest commit touching the directory
+    pub last_active: Option<i64>,
+}
+
+#[derive(Debug, Clone, PartialEq)]
+pub struct DirOwners {
+    pub dir: String,
+    pub lines: Count,
+    /// Largest owners first
+    pub contacts: Vec<Contact>,
+}
+
+fn dir_of(path: &str) -> String {
+    return breakdown::group_of(GroupBy::Dir, &Languages::default(), path).unwrap_or_default();
+}
+
+pub fn last_activity(commits: &[CommitInfo], filter: &FileFilter) -> LastActivity {
+    let mut activity = LastActivity::new();
+    for commit in commits {
+        for f in commit.files.iter().filter(|f| filter.reason_to_skip(&f.path).is_none()) {
+            let time = activity.entry(dir_of(&f.path)).or_default().entry(commit.author.clone()).or_insert(commit.time);
+            *time = (*time).max(commit.time);
+        }
+    }
+    return activity;
+}
+
+/// Top owners of each directory, by canonical author, directories by name
+pub fn knowledge_map(latest: &FileOwnership, activity: &LastActivity, resolver: &IdentityResolver) -> Vec<DirOwners> {
+    let names = resolver.display_names(latest.files.iter().flat_map(|(_, acnt)| acnt.iter().map(|(a, c)| (a, *c))));
+    let name_of = |author: &Author| {
+        let key = resolver.resolve(author).author.key();
+        return names.get(&key).cloned().unwrap_or_else(|| display_name(&author.name));
+    };
+
+    let mut lines: HashMap<String, HashMap<String, Count>> = HashMap::new();
+    for (path, acnt) in &latest.files {
+        let dir = lines.entry(dir_of(path)).or_default();
+        for (author, count) in acnt {
+            *dir.entry(name_of(author)).or_insert(0) += count;
+        }
+    }
+
+    let mut map: Vec<DirOwners> = lines.into_iter().map(|(dir, owners)| {
+        let mut last: HashMap<String, i64> = HashMap::new();
+        for (author, &time) in activity.get(&dir).into_iter().flatten() {
+            let latest = last.entry(name_of(author)).or_insert(time);
+            *latest = (*latest).max(time);
+        }
+        let mut contacts: Vec<Contact> = owners.into_iter()
+            .filter(|(_, lines)| *lines > 0)
+            .map(|(name, lines)| Contact { last_active: last.get(&name).copied(), name, lines })
+            .collect();
+        contacts.sort_by(|a, b| b.lines.cmp(&a.lines).then(a.name.cmp(&b.name)));
+        let lines = contacts.iter().map(|c| c.lines).sum();
+        contacts.truncate(CONTACTS);
+        DirOwners { dir, lines, contacts }
+    }).filter(|dir| dir.lines > 0).collect();
+    map.sort_by(|a, b| a.dir.cmp(&b.dir));
+    return map;
+}
+
+fn format_day(time: i64) -> String {
+    match Local.timestamp_opt(time, 0).single() {
+        Some(t) => return t.format("%Y-%m-%d").to_string(),
+        None => return String::new(),
+    }
+}
+
+pub fn render_markdown(map: &[DirOwners], date: &str) -> String {
+    let mut out = String::new();
+    writeln!(out, "# People to ask\n").unwrap();
+    writeln!(out, "Top owners of each directory's lines at {date} (git blame), with their latest commit there.\n").unwrap();
+    for dir in map {
+        writeln!(out, "## {}\n", dir.dir).unwrap();
+        writeln!(out, "| person | share of lines | last active |").unwrap();
+        writeln!(out, "|---|---:|---|").unwrap();
+        for contact in &dir.contacts {
+            let percent = if dir.lines == 0 { 0.0 } else { 100.0 * contact.lines as f64 / dir.lines as f64 };
+            let last = contact.last_active.map(format_day).unwrap_or("-".to_string());
+            writeln!(out, "| {} | {percent:.0}% | {last} |", contact.name.replace('|', "\\|")).unwrap();
+        }
+        writeln!(out).unwrap();
+    }
+    return out;
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::backend::FileChange;
+    use crate::AuthorCount;
+
+    #[test]
+    fn top_owners_per_directory() {
+        let (alice, bob, carol) = (Author::new("alice", "a@x.com"), Author::new("bob", "b@x.com"), Author::new("carol", "c@x.com"));
+        let latest = FileOwnership {
+            date: "2024-01-01".to_string(),
+            revision: "HEAD".to_string(),
+            files: vec![
+                ("src/a.rs".to_string(), AuthorCount::from([(alice.clone(), 6), (bob.clone(), 2)])),
+                ("src/b.rs".to_string(), AuthorCount::from([(bob.clone(), 2)])),
+                ("README.md".to_string(), AuthorCount::from([(carol.clone(), 1)])),
+            ],
+        };
+        let change = |path: &str| FileChange { path: path.to_string(), added: 1, deleted: 0 };
+        let commits = vec![
+            CommitInfo { author: bob.clone(), time: 100, files: vec![change("src/b.rs")] },
+            CommitInfo { author: bob.clone(), time: 300, files: vec![change("src/a.rs"), change("README.md")] },
+            CommitInfo { author: alice.clone(), time: 200, files: vec![change("src/a.rs")] },
+        ];
+        let map = knowledge_map(&latest, &last_activity(&commits, &FileFilter::default()), &IdentityResolver::default());
+
+        assert_eq!(map.iter().map(|d| d.dir.as_str()).collect::<Vec<_>>(), ["(top level)", "src/"]);
+        assert_eq!(map[0].contacts, vec![Contact { name: "Carol".to_string(), lines: 1, last_active: None }]);
+        assert_eq!(map[1].lines, 10);
+        assert_eq!(map[1].contacts, vec![
+            Contact { name: "Alice".to_string(), lines: 6, last_active: Some(200) },
+            Contact { name: "Bob".to_string(), lines: 4, last_active: Some(300) },
+        ]);
+    }
+}
@@ -0,0 +1,101 @@
+//! Track how much code each developer has authored in a git repo over time.
+//!
+//! The `git-author-stats` binary is a thin wrapper over this library:
+//!
+//! ```no_run
+//! use std::sync::Arc;
+//! use git_author_stats::analysis::{blame_snapshots, monthly_dates, SnapshotSpec};
+//! use git_author_stats::backend::{Backend, CliBackend};
+//! use git_author_stats::cache::BlameCache;
+//! use git_author_stats::filter::FileFilter;
+//! use git_author_stats::repo::Repo;
+//!
+//! let repo = Arc::new(Repo::discover(".")?);
+//! let backend: Arc<dyn Backend> = Arc::new(CliBackend::new(repo));
+//! let spec = SnapshotSpec::new(monthly_dates(2020, 2023));
+//! let stats = blame_snapshots(&backend, &spec, &FileFilter::default(), &mut BlameCache::in_memory())?;
+//! for (date, authors) in &stats.authors {
+//!     println!("{date}: {} authors", authors.len());
+//! }
+//! # Ok::<(), git_author_stats::error::Error>(())
+//! ```
+
+#![allow(clippy::needless_return)]
+
+use hashbrown::HashMap;
+
+pub mod activity;
+pub mod analysis;
+pub mod annotate;
+pub mod at_rest;
+pub mod attribution;
+pub mod backend;
+pub mod badge;
+pub mod batch;
+pub mod branches;
+pub mod breakdown;
+pub mod cache;
+pub mod category;
+pub mod chart;
+pub mod cli_args;
+pub mod code_maat;
+pub mod config;
+pub mod contributors;
+pub mod coupling;
+pub mod directory;
+pub mod error;
+pub mod filter;
+pub mod health;
+pub mod history;
+pub mod html;
+pub mod i18n;
+pub mod identity;
+pub mod json;
+pub mod knowledge;
+pub mod lineage;
+pub mod log;
+pub mod model;
+pub mod msgpack;
+pub mod notebook;
+pub mod ownership;
+pub mod patch;
+pub mod policy;
+pub mod porcelain;
+pub mod postgres;
+pub mod pushgateway;
+pub mod recording;
+pub mod regions;
+pub mod release;
+pub mod report;
+pub mod repo;
+pub mod reviewers;
+pub mod sampling;
+pub mod seniority;
+pub mod server;
+pub mod site;
+pub mod stream;
+pub mod teams;
+pub mod workload;
+pub mod yaml;
+
+#[cfg(test)]
+mod fixture;
+
+pub use analysis::{AuthorStats, SnapshotSpec};
+pub use error::{Error, Result};
+pub use repo::Repo;
+
+use identity::Author;
+
+pub type Date = String;
+pub type Count = i32;
+pub type AuthorCount = HashMap<Author, Count>;
+pub type AuthorPerformance = HashMap<Date, AuthorCount>;
+
+/// Quote a CSV field if it contains a delimiter, quote or newline
+pub fn csv_field(field: &str) -> String {
+    match field.contains([',', '"', '\n']) {
+        false => return field.to_string(),
+        true => return format!("\"{}\"", field.replace('"', "\"\"")),
+    }
+}
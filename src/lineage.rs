@@ -0,0 +1,105 @@
+use hashbrown::HashMap;
+
+use crate::activity::date_timestamp;
+use crate::backend::Rename;
+use crate::filter::FileFilter;
+use crate::report::json_string;
+use crate::Date;
+
+//----
+// Rename graph
+//
+// --rename-graph FILE writes the renames and copies of the analyzed files as
+// JSON: each one with its commit and the snapshot it first shows in (the first
+// sampled date after it, as for the activity metrics), plus the earlier names
+// of each file.  A file's ownership series jumps where it was renamed or
+// copied, which this lets users trace, and it's the lineage a rename-following
+// analysis would need.
+
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct RenameEdge {
+    pub rename: Rename,
+    /// The first sampled date after the rename; None after the last one
+    pub snapshot: Option<Date>,
+}
+
+#[derive(Debug, Clone, Default, PartialEq, Eq)]
+pub struct RenameGraph {
+    /// Oldest first
+    pub edges: Vec<RenameEdge>,
+    /// Each renamed or copied file's earlier names, oldest first, by path
+    pub lineage: Vec<(String, Vec<String>)>,
+}
+
+/// The graph of `renames` (newest first, as the log lists them) between files
+/// `filter` keeps at either end, placed at the sorted `dates`
+pub fn rename_graph(renames: &[Rename], dates: &[Date], filter: &FileFilter) -> RenameGraph {
+    let times: Vec<i64> = dates.iter().map(|date| date_timestamp(date).unwrap_or(i64::MAX)).collect();
+    let kept = |path: &str| filter.reason_to_skip(path).is_none();
+    let mut graph = RenameGraph::default();
+    let mut names: HashMap<String, Vec<String>> = HashMap::new();
+    for rename in renames.iter().rev().filter(|r| kept(&r.from) || kept(&r.to)) {
+        let snapshot = dates.get(times.partition_point(|&time| time <= rename.time)).cloned();
+        let mut earlier = match rename.copy {
+            true => names.get(&rename.from).cloned().unwrap_or_default(),
+            false => names.remove(&rename.from).unwrap_or_default(),
+        };
+        earlier.push(rename.from.clone());
+        names.insert(rename.to.clone(), earlier);
+        graph.edges.push(RenameEdge { rename: rename.clone(), snapshot });
+    }
+    graph.lineage = names.into_iter().collect();
+    graph.lineage.sort();
+    return graph;
+}
+
+pub fn render_json(graph: &RenameGraph) -> String {
+    let edges: Vec<String> = graph.edges.iter().map(|edge| {
+        let r = &edge.rename;
+        format!("    {{\"from\": {}, \"to\": {}, \"kind\": \"{}\", \"similarity\": {}, \"commit\": \"{}\", \"time\": {}, \"snapshot\": {}}}",
+            json_string(&r.from), json_string(&r.to), if r.copy { "copy" } else { "rename" }, r.similarity, r.commit, r.time,
+            edge.snapshot.as_deref().map(json_string).unwrap_or("null".to_string()))
+    }).collect();
+    let lineage: Vec<String> = graph.lineage.iter().map(|(path, earlier)| {
+        let earlier: Vec<String> = earlier.iter().map(|name| json_string(name)).collect();
+        format!("    {{\"path\": {}, \"earlier\": [{}]}}", json_string(path), earlier.join(", "))
+    }).collect();
+    let list = |items: Vec<String>| match items.is_empty() {
+        true => "[]".to_string(),
+        false => format!("[\n{}\n  ]", items.join(",\n")),
+    };
+    return format!("{{\n  \"edges\": {},\n  \"lineage\": {}\n}}\n", list(edges), list(lineage));
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::config::Config;
+    use crate::fixture::{Fixture, ALICE, BOB, DATES};
+    use std::fs;
+
+    #[test]
+    fn traces_renames_and_copies() {
+        let fixture = Fixture::new();
+        fs::remove_file(fixture.dir.join("src/main.rs")).unwrap();
+        fixture.commit_files(&[("src/app.rs", b"a\nb\nc\nd\ne\n")], ALICE, "2020-03-20T12:00:00", "Rename main");
+        fs::remove_file(fixture.dir.join("src/app.rs")).unwrap();
+        fixture.commit_files(&[("src/lib.rs", b"a\nb\nc\nd\ne\n")], BOB, "2020-04-20T12:00:00", "Rename app");
+
+        let renames = fixture.backend().renames(&None, &[]).unwrap();
+        let dates: Vec<Date> = DATES.iter().map(|date| date.to_string()).collect();
+        let filter = FileFilter::new(&Config::default(), true, &[], &[]).unwrap();
+        let graph = rename_graph(&renames, &dates, &filter);
+
+        let edges: Vec<(&str, &str, Option<&str>)> = graph.edges.iter()
+            .map(|edge| (edge.rename.from.as_str(), edge.rename.to.as_str(), edge.snapshot.as_deref()))
+            .collect();
+        assert_eq!(edges, [("src/main.rs", "src/app.rs", Some("2020-04-01")), ("src/app.rs", "src/lib.rs", None)]);
+        assert_eq!(graph.lineage, [("src/lib.rs".to_string(), vec!["src/main.rs".to_string(), "src/app.rs".to_string()])]);
+
+        let json = render_json(&graph);
+        assert!(json.contains("{\"from\": \"src/main.rs\", \"to\": \"src/app.rs\", \"kind\": \"rename\", \"similarity\": 100, \"commit\": \""));
+        assert!(json.contains("\"snapshot\": null}"));
+        assert!(json.ends_with("  \"lineage\": [\n    {\"path\": \"src/lib.rs\", \"earlier\": [\"src/main.rs\", \"src/app.rs\"]}\n  ]\n}\n"));
+    }
+}
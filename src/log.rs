@@ -0,0 +1,127 @@
+use std::io::{IsTerminal, Write};
+use std::sync::atomic::{AtomicBool, AtomicU8, Ordering};
+use std::time::{Duration, Instant};
+
+//----
+// Logging and progress
+//
+// Messages go to stderr, filtered by one global verbosity: --quiet keeps
+// errors only, the default adds warnings, -v what the analysis resolved and
+// skipped, -vv every file blamed.  With progress enabled (the binary, on a
+// terminal), long phases redraw a single status line, which log messages
+// clear before printing.
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, PartialOrd, Ord)]
+pub enum Level {
+    Error = 0,
+    Warn = 1,
+    Info = 2,
+    Debug = 3,
+}
+
+static VERBOSITY: AtomicU8 = AtomicU8::new(Level::Warn as u8);
+static PROGRESS: AtomicBool = AtomicBool::new(false);
+
+/// Whether a progress line is currently drawn
+static DRAWN: AtomicBool = AtomicBool::new(false);
+
+pub fn set_verbosity(level: Level) {
+    VERBOSITY.store(level as u8, Ordering::Relaxed);
+}
+
+pub fn enabled(level: Level) -> bool {
+    return level as u8 <= VERBOSITY.load(Ordering::Relaxed);
+}
+
+/// Draw progress lines, if stderr is a terminal and warnings are shown
+pub fn enable_progress() {
+    PROGRESS.store(std::io::stderr().is_terminal() && enabled(Level::Warn), Ordering::Relaxed);
+}
+
+/// Print a message at `level`, clearing any progress line first
+pub fn message(level: Level, args: std::fmt::Arguments) {
+    if !enabled(level) {
+        return;
+    }
+    let prefix = match level {
+        Level::Error => "error: ",
+        Level::Warn => "warning: ",
+        Level::Info | Level::Debug => "",
+    };
+    let mut err = std::io::stderr().lock();
+    if DRAWN.swap(false, Ordering::Relaxed) {
+        let _ = write!(err, "\r\x1b[K");
+    }
+    let _ = writeln!(err, "{prefix}{args}");
+}
+
+#[macro_export]
+macro_rules! warn {
+    ($($arg:tt)*) => { $crate::log::message($crate::log::Level::Warn, format_args!($($arg)*)) };
+}
+
+#[macro_export]
+macro_rules! info {
+    ($($arg:tt)*) => { $crate::log::message($crate::log::Level::Info, format_args!($($arg)*)) };
+}
+
+#[macro_export]
+macro_rules! debug {
+    ($($arg:tt)*) => { $crate::log::message($crate::log::Level::Debug, format_args!($($arg)*)) };
+}
+
+/// Minimum time between redraws of the progress line
+const REDRAW: Duration = Duration::from_millis(100);
+
+/// "label: done/total (percent) ETA" status line for one phase
+pub struct Progress {
+    label: String,
+    total: usize,
+    done: usize,
+    start: Instant,
+    drawn_at: Option<Instant>,
+}
+
+impl Progress {
+    pub fn new(label: &str, total: usize) -> Progress {
+        let mut progress = Progress { label: label.to_string(), total, done: 0, start: Instant::now(), drawn_at: None };
+        progress.draw(true);
+        return progress;
+    }
+
+    pub fn inc(&mut self) {
+        self.done += 1;
+        self.draw(self.done == self.total);
+    }
+
+    fn draw(&mut self, force: bool) {
+        if !PROGRESS.load(Ordering::Relaxed) {
+            return;
+        }
+        if !force && self.drawn_at.is_some_and(|t| t.elapsed() < REDRAW) {
+            return;
+        }
+        self.drawn_at = Some(Instant::now());
+
+        let percent = (100 * self.done).checked_div(self.total).unwrap_or(100);
+        let eta = match self.done {
+            0 => String::new(),
+            done => {
+                let left = self.start.elapsed().mul_f64((self.total - done) as f64 / done as f64).as_secs();
+                format!(" ETA {}m{:02}s", left / 60, left % 60)
+            },
+        };
+        let mut err = std::io::stderr().lock();
+        let _ = write!(err, "\r\x1b[K{}: {}/{} ({percent}%){eta}", self.label, self.done, self.total);
+        let _ = err.flush();
+        DRAWN.store(true, Ordering::Relaxed);
+    }
+}
+
+impl Drop for Progress {
+    fn drop(&mut self) {
+        if DRAWN.swap(false, Ordering::Relaxed) {
+            let _ = write!(std::io::stderr(), "\r\x1b[K");
+        }
+    }
+}
@@ -1,272 +1,1504 @@
-use chrono::{Datelike, Local};
-use hashbrown::HashMap;
-use lazy_static::lazy_static;
-use regex::Regex;
-use std::path::PathBuf;
-use std::process::Command;
-use std::sync::mpsc::channel;
-use threadpool::ThreadPool;
-
-mod cli_args;
-use cli_args::Args;
-use clap::Parser;
-
-type Author = String;
-type Date = String;
-type Count = i32;
-type AuthorCount = HashMap<Author, Count>;
-type AuthorPerformance = HashMap<Date, AuthorCount>;
-
-fn git_repo_root(path: &str) -> Option<String> {
-    let repo_root_out =
-        Command::new("git")
-                .arg("rev-parse")
-                .arg("--show-toplevel")
-                .current_dir(path)
-                .output()
-                .expect("git rev-parse failed to start");
-    match repo_root_out.status.success() {
-        false => return None,
-        true => {
-            let repo_root = String::from_utf8_lossy(&repo_root_out.stdout);
-            return Some(repo_root.trim().to_string());
-        },
+#![allow(clippy::needless_return)]
+
+use chrono::{Datelike, Local, NaiveDate, TimeZone, Utc};
+use clap::{CommandFactory, FromArgMatches, Parser};
+use std::env;
+use std::ffi::OsString;
+use std::fs;
+use std::io::{self, Read};
+use std::net::TcpListener;
+use std::path::{Path, PathBuf};
+use std::process::{self, Stdio};
+use std::sync::{Arc, Mutex};
+
+use git_author_stats::analysis::{activity_snapshots, blame_snapshots, default_jobs, interval_dates, monthly_dates, AuthorStats, FileOwnership, SnapshotSpec};
+use git_author_stats::annotate::{self, annotate};
+use git_author_stats::at_rest::{self, AtRest};
+use git_author_stats::attribution;
+use git_author_stats::badge::{self, BadgeKind};
+use git_author_stats::backend::{Backend, CliBackend, STAGED, WORKING_TREE};
+use git_author_stats::batch::{self, BatchRepo};
+use git_author_stats::branches;
+use git_author_stats::breakdown::{self, Languages};
+use git_author_stats::cache::{self, BlameCache, CacheStats};
+use git_author_stats::chart;
+use git_author_stats::cli_args::{all_options, given_options, used_options, AnnotateFormat, Args, CacheCommand, Command, ErrorFormat, FingerprintBy, GroupBy, Interval, Lang, Metric, OutputFormat, RegionsFormat, ReviewersFormat, ToolCommand, TrendsFormat, Weight};
+use git_author_stats::config::Config;
+use git_author_stats::contributors;
+use git_author_stats::coupling::{coupled_files, Coupling};
+use git_author_stats::directory::Directory;
+use git_author_stats::error;
+use git_author_stats::filter::{DocFiles, FileFilter};
+use git_author_stats::history::{self, default_history_file};
+use git_author_stats::i18n::{self, Catalog};
+use git_author_stats::identity::{commit_identities, parse_identity, read_mailmap, Aliases, Author, AuthorFilter, AuthorFormat, DomainRules, IdentityResolver, Mailmap, OptOut};
+use git_author_stats::knowledge::{self, knowledge_map, last_activity, LastActivity};
+use git_author_stats::lineage;
+use git_author_stats::log::{self, Level, Progress};
+use git_author_stats::model::Model;
+use git_author_stats::patch;
+use git_author_stats::policy::Policy;
+use git_author_stats::postgres;
+use git_author_stats::pushgateway;
+use git_author_stats::recording;
+use git_author_stats::regions::{self, parse_regions, region_owners};
+use git_author_stats::release;
+use git_author_stats::repo::{parse_repo_list, read_repo_list};
+use git_author_stats::report::{bus_factor_trend, category_rows, coupling_rows, digit_separator, display_ranking, display_results, entropy_trend, fingerprint_rows, health_trend, Report, Table};
+use git_author_stats::reviewers;
+use git_author_stats::seniority::{self, FirstCommits};
+use git_author_stats::server::{self, Access, RateLimiter, Request, Response, Tokens};
+use git_author_stats::site;
+use git_author_stats::teams::{AuthorOrder, Teams};
+use git_author_stats::workload;
+use git_author_stats::{info, warn, Count, Date, Error, Repo, Result};
+
+/// One repository's counts, and the settings read from it
+struct RepoStats {
+    name: String,
+    /// One per metric, in the order of --metric
+    stats: Vec<AuthorStats>,
+    config: Config,
+    mailmap: String,
+    /// For --knowledge-map
+    activity: LastActivity,
+    /// Commits per author per period, for --health-score
+    active: AuthorStats,
+    /// For --coupling
+    coupling: Vec<Coupling>,
+    /// For --seniority-csv and --seniority-svg
+    first_commits: FirstCommits,
+}
+
+/// The date or revision of `at`, or the working tree or index with
+/// --working-tree or --staged
+fn at_target(opt: &Args) -> Option<&str> {
+    match &opt.command {
+        Some(Command::At { target, .. }) => return Some(target),
+        _ if opt.working_tree => return Some(WORKING_TREE),
+        _ if opt.staged => return Some(STAGED),
+        _ => return None,
+    }
+}
+
+fn is_date(text: &str) -> bool {
+    return NaiveDate::parse_from_str(text, "%Y-%m-%d").is_ok();
+}
+
+/// The dates to sample, from --since and --interval
+fn sampled_dates(opt: &Args) -> Result<Vec<Date>> {
+    if let Some(date) = &opt.isolated_snapshot {
+        return Ok(vec![date.clone()]);
     }
+    if let Some(date) = at_target(opt).filter(|target| is_date(target)) {
+        return Ok(vec![date.to_string()]);
+    }
+    let until = match &opt.date {
+        Some(text) => NaiveDate::parse_from_str(text, "%Y-%m-%d")
+            .map_err(|_| Error::Config(format!("--date: expected a YYYY-MM-DD date, not {text}")))?,
+        None => Local::now().date_naive(),
+    };
+    if opt.since.is_none() && opt.interval.is_none() {
+        let mut dates = monthly_dates(2016, until.year());
+        if let Some(last) = &opt.date {
+            dates.retain(|date| date <= last);
+        }
+        return Ok(dates);
+    }
+    let since = match &opt.since {
+        Some(text) => NaiveDate::parse_from_str(text, "%Y-%m-%d")
+            .map_err(|_| Error::Config(format!("--since: expected a YYYY-MM-DD date, not {text}")))?,
+        None => NaiveDate::from_ymd_opt(2016, 1, 1).unwrap(),
+    };
+    return Ok(interval_dates(since, until, opt.interval.unwrap_or(Interval::Month)));
 }
 
-fn git_revision(repo_root: &str, branch: &Option<String>, date: &Option<String>) -> Option<String> {
-    //println!("repo_root: {repo_root}");
-    //println!("branch: {branch:?}");
-    //println!("date: {date:?}");
-    // git log --format=format:"%H" --before=2023-01-01
-    let mut cmd = Command::new("git");
-    cmd.arg("log");
-    cmd.arg("-n1").arg("--format=format:%H");
-    if let Some(date) = date {
-        cmd.arg(format!("--before={date}"));
-    }
-    if let Some(branch) = branch {
-       cmd.arg(branch);
-    }
-    cmd.current_dir(repo_root);
-    //println!("cmd: {:?}", cmd);
-    let cmd_out = cmd.output().expect("git log failed to start");
-    //println!("{:?}", cmd_out.status);
-    //println!("{:?}", cmd_out.stdout);
-    match cmd_out.status.success() {
-        false => return None,
+/// The blame cache: on disk with --cache, --cache-dir or `persistent`
+fn open_cache(opt: &Args, repo_root: &str, mailmap: &str, persistent: bool, at_rest: Option<&AtRest>) -> BlameCache {
+    // A recording needs every blame to run, and a replay has nothing to cache
+    let recording = opt.record_git.is_some() || opt.replay_git.is_some();
+    match (persistent || opt.cache || opt.cache_dir.is_some()) && !recording {
+        false => return BlameCache::in_memory(),
         true => {
-            let revision = String::from_utf8_lossy(&cmd_out.stdout);
-            return Some(revision.trim().to_string());
+            let cache_dir = opt.cache_dir.as_ref().map(PathBuf::from).unwrap_or_else(cache::default_cache_dir);
+            return BlameCache::open(&cache_dir, repo_root, &cache::fingerprint(mailmap.as_bytes()), at_rest);
         },
     }
 }
 
-fn git_files(repo_root: &str, revision: &str) -> Vec<String> {
-    let ls_tree_out =
-        Command::new("git")
-                .arg("ls-tree")
-                .arg("-r")
-                .arg(revision)
-                .arg("--name-only")
-                .current_dir(repo_root)
-                .output()
-                .expect("git ls-tree failed to start");
-    return String::from_utf8_lossy(&ls_tree_out.stdout)
-        .lines()
-        .map(|x| x.to_string())
-        .collect();
+/// The repo holding `path`, if --policy allows analyzing it
+fn discover(policy: &Policy, path: &str) -> Result<Repo> {
+    let repo = Repo::discover(path)?;
+    policy.check_repository(&repo.root).map_err(Error::Config)?;
+    return Ok(repo);
 }
 
-fn reason_to_skip(path_buf: &PathBuf) -> Option<String> {
-    // List of file extensions to skip
-    let binary_ext_list = [
-        "bin",
-        "data",
-        "elf",
-        "gz",
-        "hex128",
-        "hex8",
-        "pdf",
-        "png",
-        "tar",
-        "wcfg",
-        "xlsx",
-    ];
+/// Analyze the repo holding `paths`, limited to those paths.  Warnings are
+/// prefixed with `label` when several repos are analyzed.
+fn analyze_repo(opt: &Args, paths: &[String], label: Option<&str>, at_rest: Option<&AtRest>, policy: &Policy) -> Result<RepoStats> {
+    let repo = Arc::new(discover(policy, &paths[0])?);
+    let repo_root = repo.root.clone();
+    let prefix = label.map(|name| format!("{name}: ")).unwrap_or_default();
 
-    let generated_ext_list = [
-        "v",
-        "xml",
-        "edif",
-        "edf",
-        "rpt",
-        "xci",
-    ];
+    // The paths limit the analysis to those parts of the repo
+    let mut scope = paths.iter()
+        .map(|path| repo.relative_path(path))
+        .collect::<Result<Vec<String>>>()?;
+    if scope.iter().any(|rel| rel.is_empty()) {
+        if opt.per_path {
+            return Err(Error::Config("--per-path needs paths within the repo, not its top level".to_string()));
+        }
+        scope.clear();
+    }
+    if repo.read_only {
+        info!("{} is read-only, using scratch space for index operations", repo.git_dir.display());
+    }
+
+    if repo.shallow {
+        warn!("{prefix}{repo_root} is a shallow clone, so snapshots before its oldest commits are missing or incomplete; \
+            run `git fetch --unshallow` for the full history");
+    }
+
+    let mailmap = read_mailmap(&repo);
+    let config = Config::load(&repo_root, &opt.config).map_err(Error::Config)?;
+    let filter = FileFilter::new(&config, opt.builtin_excludes, &opt.exclude, &opt.include).map_err(Error::Config)?;
+    let docs = match opt.split_docs {
+        true => Some(DocFiles::new(&config, &opt.doc_files).map_err(Error::Config)?),
+        false => None,
+    };
+
+    let backend: Arc<dyn Backend> = Arc::new(CliBackend::new(repo.clone()));
+
+    // `at` a revision analyzes it as of its commit date
+    // and the working tree or index as of today
+    let (dates, revision) = match at_target(opt).filter(|target| !is_date(target)) {
+        None => (sampled_dates(opt)?, None),
+        Some(target @ (WORKING_TREE | STAGED)) => (vec![Local::now().format("%Y-%m-%d").to_string()], Some(target.to_string())),
+        Some(target) => {
+            let revision = backend.revision(&Some(target.to_string()), &None)?
+                .ok_or_else(|| Error::Config(format!("at: unknown revision {target}")))?;
+            (vec![backend.commit_date(&revision)?], Some(revision))
+        },
+    };
+
+    let group_by = match opt.per_path {
+        true => GroupBy::Path,
+        false => opt.group_by,
+    };
+    let dir_depth = match (opt.auto_depth, group_by) {
+        (Some(range), GroupBy::Dir) => {
+            let latest = match &revision {
+                Some(revision) => Some(revision.clone()),
+                None => backend.revision(&opt.branch, &dates.last().cloned())?,
+            };
+            let files = match latest {
+                Some(latest) => backend.files(&latest, &scope)?,
+                None => Vec::new(),
+            };
+            let paths: Vec<&str> = files.iter().map(|f| f.path.as_str()).filter(|path| filter.reason_to_skip(path).is_none()).collect();
+            let depth = breakdown::auto_depth(&paths, range);
+            info!("{prefix}grouping by directories {depth} level(s) down");
+            depth
+        },
+        _ => 1,
+    };
+
+    let spec = SnapshotSpec {
+        branch: opt.branch.clone(),
+        revision,
+        scope,
+        missing_snapshot: opt.missing_snapshot,
+        gitattributes: opt.gitattributes,
+        group_by,
+        dir_depth,
+        languages: Languages::new(&config.languages),
+        docs,
+        // Files of several repos can't be linked to one place, but a
+        // --batch repo has a page of its own
+        keep_files: opt.batch || (label.is_none() && (matches!(opt.format, OutputFormat::Html | OutputFormat::CodeMaat) || opt.emit_static_site.is_some() || opt.knowledge_map.is_some()
+            || opt.coupling || (opt.bus_factor && matches!(opt.format, OutputFormat::Json | OutputFormat::Yaml | OutputFormat::Msgpack)))),
+        categories: opt.categories,
+        profile_files: opt.profile_files.clone(),
+        exclusion_log: opt.exclusion_log.clone(),
+        at_rest: at_rest.cloned(),
+        jobs: opt.jobs.unwrap_or_else(default_jobs),
+        session_gap: (opt.session_gap * 3600.0) as i64,
+        weight: opt.weight,
+        fingerprint: opt.fingerprint.group_by(),
+        entropy: opt.entropy || opt.health_score,
+        dominance: opt.bus_factor.then_some(opt.dominance / 100.0),
+        stale_after: opt.health_score.then_some(config.stale_after_days),
+        coverage: opt.min_coverage.is_some(),
+        sample: opt.sample,
+        binary_metric: opt.binary_metric,
+        ..SnapshotSpec::new(dates)
+    };
+
+    // Lines come from one blame pass, every other metric from one log pass.
+    // A --isolate-snapshots child only blames.
+    let child = opt.isolated_snapshot.is_some();
+    let mut blamed = match opt.metrics.contains(&Metric::Lines) {
+        false => None,
+        true if opt.isolate_snapshots && !child => Some(isolated_blame(paths, &spec.dates, at_rest)?),
+        true => {
+            let mut cache = open_cache(opt, &repo_root, &mailmap, false, at_rest);
+            let stats = blame_snapshots(&backend, &spec, &filter, &mut cache)?;
+            if let Err(e) = cache.save() {
+                warn!("{prefix}failed to save blame cache: {e}");
+            }
+            if let (Some(min), Some(coverage)) = (opt.min_coverage, &stats.coverage) {
+                coverage.check(min)?;
+            }
+            report_estimate(&prefix, &stats);
+            Some(stats)
+        },
+    };
+    let mut activity_metrics: Vec<Metric> = opt.metrics.iter().copied().filter(|&metric| metric != Metric::Lines && !child).collect();
+    // --health-score and the active authors badge also need the authors with
+    // commits in each period
+    let health = (opt.health_score || opt.badges.iter().any(|(kind, _)| *kind == BadgeKind::ActiveAuthors)) && blamed.is_some() && !child;
+    if health && !activity_metrics.contains(&Metric::Commits) {
+        activity_metrics.push(Metric::Commits);
+    }
+    let logged = match activity_metrics.is_empty() {
+        true => Vec::new(),
+        false => activity_snapshots(backend.as_ref(), &spec, &activity_metrics, &filter)?,
+    };
+    let active = match health {
+        false => AuthorStats::default(),
+        true => logged[activity_metrics.iter().position(|&metric| metric == Metric::Commits).unwrap()].clone(),
+    };
+    let mut logged = logged.into_iter();
+    let stats: Vec<AuthorStats> = opt.metrics.iter().map(|&metric| match metric {
+        Metric::Lines => blamed.take().unwrap_or_default(),
+        _ => logged.next().unwrap_or_default(),
+    }).collect();
 
-    let path = path_buf.to_str().unwrap();
-    if path.starts_with("xip/") {
-        return Some("mostly imported     ".to_string());
+    // Every metric samples the same dates
+    let branch = opt.branch.as_deref().unwrap_or("HEAD");
+    let sampled = &stats[0];
+    if let (Some(first), Some(last)) = (sampled.missing.first(), sampled.missing.last()) {
+        warn!("{prefix}skipped {} snapshot(s) with no revision of {branch}: {first}..{last}", sampled.missing.len());
+    }
+    if let (Some(first), Some(last)) = (sampled.fallbacks.first(), sampled.fallbacks.last()) {
+        warn!("{prefix}{} snapshot(s) analyzed on {} instead of {branch}: {first}..{last}",
+            sampled.fallbacks.len(), backend.default_branch()?);
+    }
+    let blamed = opt.metrics.iter().position(|&metric| metric == Metric::Lines).map(|i| &stats[i]);
+    if let Some(ineligible) = blamed.map(|stats| &stats.ineligible).filter(|ineligible| !ineligible.is_empty()) {
+        let mut rules: Vec<&String> = Vec::new();
+        for rule in ineligible.iter().flat_map(|(_, rules)| rules) {
+            if !rules.contains(&rule) {
+                rules.push(rule);
+            }
+        }
+        let rules: Vec<&str> = rules.into_iter().map(|rule| rule.as_str()).collect();
+        warn!("{prefix}{} snapshot(s) had no eligible files and count no lines: {}..{}; every file was skipped by {}",
+            ineligible.len(), ineligible[0].0, ineligible[ineligible.len() - 1].0, rules.join(", "));
+    }
+
+    // The knowledge map, --coupling and the seniority curves share one more
+    // pass over the log
+    let has_files = stats.iter().any(|stats| stats.latest.is_some()) && !child;
+    let seniority = (opt.seniority_csv.is_some() || opt.seniority_svg.is_some()) && !child;
+    let commits = match (has_files && (opt.knowledge_map.is_some() || opt.coupling)) || seniority {
+        true => backend.commit_log(&opt.branch, &spec.scope)?,
+        false => Vec::new(),
+    };
+    let activity = match has_files && opt.knowledge_map.is_some() {
+        true => last_activity(&commits, &filter),
+        false => LastActivity::new(),
+    };
+    let coupling = match has_files && opt.coupling {
+        true => coupled_files(&commits, &filter),
+        false => Vec::new(),
+    };
+    let first_commits = match seniority {
+        true => seniority::first_commits(&commits, &filter),
+        false => FirstCommits::new(),
+    };
+    if let (Some(path), None, false) = (&opt.rename_graph, label, child) {
+        let graph = lineage::rename_graph(&backend.renames(&opt.branch, &spec.scope)?, &spec.dates, &filter);
+        at_rest::write(Path::new(path), lineage::render_json(&graph), at_rest)?;
     }
-    if path.starts_with("cache/") {
-        return Some("generated           ".to_string());
+
+    let name = Path::new(&repo_root).file_name().map(|n| n.to_string_lossy().to_string()).unwrap_or(repo_root.clone());
+    return Ok(RepoStats { name, stats, config, mailmap, activity, active, coupling, first_commits });
+}
+
+/// Say how close the --sample counts of the latest date are likely to be
+fn report_estimate(prefix: &str, stats: &AuthorStats) {
+    let Some((date, estimate)) = stats.estimates.iter().max_by(|a, b| a.0.cmp(b.0)) else {
+        return;
+    };
+    let mut authors: Vec<(&Author, &Count)> = stats.authors.get(date).map(|acnt| acnt.iter().collect()).unwrap_or_default();
+    authors.sort_by(|a, b| b.1.cmp(a.1).then_with(|| a.0.cmp(b.0)));
+    let total: Count = authors.iter().map(|(_, &count)| count).sum();
+    warn!("{prefix}{date}: blamed {} of {} files ({}%), so the counts are estimates: {total} \u{b1} {:.0} lines in all (95% confidence)",
+        estimate.sampled, estimate.files, estimate.fraction * 100.0, estimate.total_margin());
+    for (author, count) in authors {
+        info!("{prefix}{date}: {} <{}>: {count} \u{b1} {:.0} lines", author.name, author.email, estimate.margin(author));
     }
+}
 
-    if let Some(ext) = path_buf.extension() {
-        let ext = ext.to_str().unwrap();
-        if binary_ext_list.contains(&ext) {
-            return Some("binary extension    ".to_string());
+/// Blame each of `dates` in a child process running this binary with the same
+/// arguments, merging the models they save.  A date whose child fails, e.g.
+/// killed for running out of memory, is left out with a warning.
+fn isolated_blame(paths: &[String], dates: &[Date], at_rest: Option<&AtRest>) -> Result<AuthorStats> {
+    let exe = env::current_exe()?;
+    let mut stats = AuthorStats::default();
+    let mut progress = Progress::new("isolated snapshots", dates.len());
+    for (n, date) in dates.iter().enumerate() {
+        let output = env::temp_dir().join(format!("git-author-stats-{}-{n}.model", std::process::id()));
+        let mut child = process::Command::new(&exe);
+        // Ahead of the original arguments, in case those end with "--"
+        child.arg("--isolated-snapshot").arg(date).arg("--isolated-output").arg(&output);
+        for path in paths {
+            child.arg("--isolated-path").arg(path);
         }
-        if generated_ext_list.contains(&ext) {
-            return Some("autogenerated       ".to_string());
+        let status = child.args(env::args_os().skip(1)).stdin(Stdio::null()).stdout(Stdio::null()).status()?;
+        progress.inc();
+        let model = match status.success() {
+            true => Model::load(&output, at_rest).map_err(|e| e.to_string()),
+            false => Err(status.to_string()),
+        };
+        let _ = fs::remove_file(&output);
+        match model.map(|model| model.metrics.into_iter().next()) {
+            Ok(Some((_, snapshot))) => stats.extend(snapshot),
+            Ok(None) => warn!("{date}: the snapshot's process saved no counts, skipped"),
+            Err(e) => warn!("{date}: the snapshot's process failed ({e}), skipped"),
         }
     }
+    return Ok(stats);
+}
+
+/// Run as a --isolate-snapshots child: blame the one date and save the counts
+fn blame_isolated_snapshot(opt: &Args, output: &str, at_rest: Option<&AtRest>, policy: &Policy) -> Result<()> {
+    let single = analyze_repo(opt, &opt.isolated_paths, None, at_rest, policy)?;
+    let lines = opt.metrics.iter().position(|&metric| metric == Metric::Lines).expect("children are only run for --metric lines");
+    let stats = single.stats.into_iter().nth(lines).unwrap();
+    let model = Model { metrics: vec![(Metric::Lines, stats)], ..Default::default() };
+    return model.save(Path::new(output), at_rest);
+}
+
+/// One team per value of a --directory column, replacing the config's [teams]
+fn directory_teams(source: &str, column: &str) -> Result<Teams> {
+    let directory = Directory::load(source).map_err(Error::Config)?;
+    if !directory.has_column(column) {
+        return Err(Error::Config(format!("--directory {source} has no {column} column")));
+    }
+    return Ok(Teams::from_directory(&directory, column));
+}
+
+/// The authors --author-order pins to the top of the tables
+fn author_order(opt: &Args) -> Result<AuthorOrder> {
+    match &opt.author_order {
+        Some(path) => return AuthorOrder::load(path).map_err(|e| Error::Config(format!("--author-order {e}"))),
+        None => return Ok(AuthorOrder::default()),
+    }
+}
+
+/// Tables of one metric's counts
+fn build_report(opt: &Args, metric: Metric, stats: &AuthorStats, resolver: &IdentityResolver, teams: &Teams, order: &AuthorOrder) -> Report {
+    // Whether the analysis, maybe a saved one, split them
+    let docs = (!stats.docs.is_empty()).then_some(&stats.docs);
+    let mut table = Table::build(opt, &stats.authors, docs, resolver, teams, order, stats.latest.as_ref());
+    if opt.entropy && metric == Metric::Lines {
+        table.entropy = entropy_trend(&stats.entropy, &table.dates);
+    }
+    if opt.bus_factor && metric == Metric::Lines {
+        table.bus_factor = bus_factor_trend(&stats.authors, &stats.concentration, resolver, &table.dates);
+    }
+    if !stats.estimates.is_empty() {
+        table.add_margins(&stats.estimates, resolver);
+    }
+    if !stats.ineligible.is_empty() {
+        table.ineligible = table.dates.iter().map(|date| stats.ineligible.iter().any(|(none, _)| none == date)).collect();
+    }
+    let mut group_names: Vec<&String> = stats.groups.keys().collect();
+    group_names.sort();
+    let groups = group_names.into_iter()
+        .map(|name| (name.clone(), Table::build(opt, &stats.groups[name], None, resolver, teams, order, None)))
+        .collect();
+    let categories = match opt.categories && metric == Metric::Lines {
+        false => Vec::new(),
+        true => category_rows(&stats.authors, &stats.categories, resolver, &table.dates),
+    };
+    let fingerprint = fingerprint_rows(&stats.fingerprint, resolver);
+    return Report { table, groups, categories, fingerprint, coupling: Vec::new() };
+}
 
-    if let Some(name) = path_buf.file_name() {
-        let name = name.to_str().unwrap();
-        if name.ends_with(".bd.tcl") {
-            return Some("mostly autogenerated".to_string());
+/// Analyze each of --branches in turn and print them side by side.  They
+/// share the blame cache, so files the branches have in common are blamed
+/// once with --cache.
+fn compare_branches(opt: &Args, paths: &[String], directory_teams: Option<Teams>, order: &AuthorOrder, at_rest: Option<&AtRest>, policy: &Policy) -> Result<()> {
+    let repo = Arc::new(discover(policy, &paths[0])?);
+    let backend = CliBackend::new(repo);
+    let names = branches::expand(&opt.branches, &backend.branches()?).map_err(|e| Error::Config(format!("--branches: {e}")))?;
+    for name in &names {
+        if backend.revision(&Some(name.clone()), &None)?.is_none() {
+            return Err(Error::Config(format!("--branches: unknown branch {name}")));
         }
     }
+    let metric = opt.metrics[0];
+    if opt.metrics.len() > 1 {
+        warn!("--branches only compares the first --metric, {}", metric.name());
+    }
+
+    let mut reports: Vec<(String, Report)> = Vec::new();
+    for name in names {
+        let mut on_branch = opt.clone();
+        on_branch.branch = Some(name.clone());
+        on_branch.metrics = vec![metric];
+        let run = analyze_repo(&on_branch, paths, Some(&name), at_rest, policy)?;
+        reports.push((name, single_report(opt, metric, run, &directory_teams, order)?));
+    }
+    branches::display_branches(opt, &reports);
+    return Ok(());
+}
 
-    return None;
+/// The report of the first metric of one repo's counts, with the authors
+/// filtered and opted out like the main report's
+fn single_report(opt: &Args, metric: Metric, run: RepoStats, directory_teams: &Option<Teams>, order: &AuthorOrder) -> Result<Report> {
+    let resolver = identity_resolver(opt, &run.mailmap, &run.config)?;
+    let mut stats = run.stats.into_iter().next().unwrap_or_default();
+    let author_filter = AuthorFilter::new(&opt.authors, &opt.exclude_authors, opt.exclude_bots).map_err(Error::Config)?;
+    if !author_filter.is_empty() {
+        stats.retain_authors(|author| author_filter.keeps(author, &resolver));
+    }
+    let opt_out = opt_out(&run.config)?;
+    if !opt_out.is_empty() {
+        stats.fold_authors(|author| opt_out.opted_out(author, &resolver), &OptOut::aggregate());
+    }
+    let teams = directory_teams.clone().unwrap_or_else(|| Teams::from_config(&run.config));
+    return Ok(build_report(opt, metric, &stats, &resolver, &teams, order));
 }
 
-fn git_author_line_count(repo_root: &str, revision: &str, file_path: &str) -> AuthorCount {
-    let mut authors = AuthorCount::new();
+/// Analyze one --batch repo, mirrored first if it's a URL, into its site
+/// under `dir`, returning its headline numbers
+#[allow(clippy::too_many_arguments)]
+fn batch_repo(opt: &Args, entry: &str, name: &str, dir: &Path, clones: &Path, directory_teams: &Option<Teams>, order: &AuthorOrder,
+    at_rest: Option<&AtRest>, policy: &Policy) -> Result<Option<history::Entry>>
+{
+    let path = match batch::is_url(entry) {
+        true => batch::mirror(entry, clones)?.to_string_lossy().to_string(),
+        false => entry.to_string(),
+    };
+    let run = analyze_repo(opt, &[path], Some(name), at_rest, policy)?;
+    let excluded = run.stats.iter().flat_map(|stats| stats.excluded.clone()).collect();
+    let metric = opt.metrics[0];
+    let report = single_report(opt, metric, run, directory_teams, order)?;
+    site::emit_static_site(&dir.join(name), opt, &report, &excluded, at_rest)?;
+    return Ok(history::headline(Utc::now().timestamp(), name, metric.name(), &report.table));
+}
 
-    let blame_out =
-        Command::new("git")
-                .arg("blame")
-                .arg("--line-porcelain")
-                .arg(revision)
-                .arg(file_path)
-                .current_dir(repo_root)
-                .output()
-                .expect("git blame failed to start");
-    let auth_lines = String::from_utf8_lossy(&blame_out.stdout);
-    auth_lines.lines().filter(|x| x.starts_with("author ")).for_each(|x| {
-        let author = x[7..].to_string();
-        *authors.entry_ref(&author).or_insert(0) += 1;
-    });
+/// Analyze each repo listed on stdin into its own static site under
+/// --output-dir, then write the index of them all.  A repo that fails is
+/// skipped with a warning, and the first failure is returned at the end.
+fn run_batch(opt: &Args, directory_teams: Option<Teams>, order: &AuthorOrder, at_rest: Option<&AtRest>, policy: &Policy) -> Result<()> {
+    let dir = PathBuf::from(opt.output_dir.as_ref().expect("checked by run"));
+    let mut text = String::new();
+    io::stdin().read_to_string(&mut text)?;
+    let entries = parse_repo_list(&text, Path::new(""));
+    if entries.is_empty() {
+        return Err(Error::Config("--batch: no repositories on stdin".to_string()));
+    }
+    let clones = opt.cache_dir.as_ref().map(PathBuf::from).unwrap_or_else(cache::default_cache_dir).join("clones");
+
+    let mut repos = Vec::new();
+    let mut failure = None;
+    for (entry, name) in entries.iter().zip(batch::output_names(&entries)) {
+        let outcome = match batch_repo(opt, entry, &name, &dir, &clones, &directory_teams, order, at_rest, policy) {
+            Ok(headline) => Ok(headline),
+            Err(e) => {
+                warn!("{name}: {e}, skipped");
+                let message = e.to_string();
+                failure.get_or_insert(e);
+                Err(message)
+            },
+        };
+        repos.push(BatchRepo { name, source: entry.clone(), outcome });
+    }
+    fs::create_dir_all(&dir)?;
+    at_rest::write(&dir.join("index.html"), batch::render_index(&repos), at_rest)?;
+    info!("wrote the reports of {} repositories to {}", repos.len(), dir.display());
+    match failure {
+        Some(e) => return Err(e),
+        None => return Ok(()),
+    }
+}
 
-    return authors;
+/// Blame the files at every sampled date into the on-disk cache, so later
+/// runs over the same dates don't blame anything
+fn warm_cache(opt: &Args, paths: &[String], at_rest: Option<&AtRest>, policy: &Policy) -> Result<()> {
+    let repo = Arc::new(discover(policy, &paths[0])?);
+    let mut scope = paths.iter()
+        .map(|path| repo.relative_path(path))
+        .collect::<Result<Vec<String>>>()?;
+    if scope.iter().any(|rel| rel.is_empty()) {
+        scope.clear();
+    }
+    let mailmap = read_mailmap(&repo);
+    let config = Config::load(&repo.root, &opt.config).map_err(Error::Config)?;
+    let filter = FileFilter::new(&config, opt.builtin_excludes, &opt.exclude, &opt.include).map_err(Error::Config)?;
+    let backend: Arc<dyn Backend> = Arc::new(CliBackend::new(repo.clone()));
+    let spec = SnapshotSpec {
+        branch: opt.branch.clone(),
+        scope,
+        missing_snapshot: opt.missing_snapshot,
+        gitattributes: opt.gitattributes,
+        jobs: opt.jobs.unwrap_or_else(default_jobs),
+        ..SnapshotSpec::new(sampled_dates(opt)?)
+    };
+
+    let mut cache = open_cache(opt, &repo.root, &mailmap, true, at_rest);
+    blame_snapshots(&backend, &spec, &filter, &mut cache)?;
+    cache.save()?;
+    info!("{} files blamed, {} already cached", cache.misses, cache.hits);
+    return Ok(());
 }
 
-fn reformat(perf: &AuthorPerformance) -> AuthorPerformance {
-    lazy_static! {
-        // Regex for reformatting author names
-        static ref RE_SPECIAL: Regex = Regex::new(r"[-_\.]").unwrap();
-        static ref RE_CAPITAL: Regex = Regex::new(r"\b[a-z]").unwrap();
+/// Identities from the mailmap, --aliases, the config and --author-format
+fn identity_resolver(opt: &Args, mailmap: &str, config: &Config) -> Result<IdentityResolver> {
+    let mut resolver = IdentityResolver {
+        mailmap: Mailmap::parse(mailmap),
+        domains: DomainRules::new(&config.domains),
+        heuristics: config.heuristics,
+        labels: config.labels.clone(),
+        ..Default::default()
     };
+    if let Some(path) = &opt.aliases {
+        resolver.aliases = Aliases::load(path).map_err(|e| Error::Config(format!("aliases: {e}")))?;
+    }
+    if let Some(template) = &opt.author_format {
+        resolver.format = Some(AuthorFormat::parse(template).map_err(Error::Config)?);
+    }
+    return Ok(resolver);
+}
 
-    let mut formatted = AuthorPerformance::new();
+fn opt_out(config: &Config) -> Result<OptOut> {
+    return OptOut::new(&config.opt_out).map_err(|e| Error::Config(format!("authors.opt_out: {e}")));
+}
 
-    perf.iter().for_each(|(date, acnt_in)| {
-        let acnt_out = formatted.entry_ref(date).or_insert(AuthorCount::new());
-        for author in acnt_in.keys() {
-            let cnt_in = acnt_in[author];
-            // reformat author name
-            let mut author = RE_SPECIAL.replace_all(&author, " ").to_lowercase();
-            for mat in RE_CAPITAL.find_iter(&author.clone()) {
-                let mut c = author.chars().nth(mat.start()).unwrap();
-                c = c.to_uppercase().nth(0).unwrap();
-                author.replace_range(mat.start()..mat.start()+1, &c.to_string());
+/// Print what each step of identity resolution makes of the authors matching
+/// `text`, or of `text` itself if no commit has it
+fn explain_identity(opt: &Args, text: &str, policy: &Policy) -> Result<()> {
+    let repo = discover(policy, ".")?;
+    let config = Config::load(&repo.root, &opt.config).map_err(Error::Config)?;
+    let resolver = identity_resolver(opt, &read_mailmap(&repo), &config)?;
+    let (name, email) = parse_identity(text);
+    let wanted = |author: &Author| match email.is_empty() {
+        true => author.name.eq_ignore_ascii_case(&name) || author.email.eq_ignore_ascii_case(&name),
+        false => author.email.eq_ignore_ascii_case(&email),
+    };
+    let mut authors: Vec<Author> = commit_identities(&repo, &opt.branch).into_iter().filter(wanted).collect();
+    if authors.is_empty() {
+        warn!("no commit has an author matching {text:?}");
+        authors.push(Author::new(&name, &email));
+    }
+    let show = |author: &Author| match policy.allows_field("email") {
+        true => format!("{} <{}>", author.name, author.email),
+        false => author.name.clone(),
+    };
+    for raw in &authors {
+        let (resolved, steps) = resolver.explain(raw);
+        println!("{}", show(raw));
+        for step in &steps {
+            match &step.author {
+                Some(author) => println!("  {:<12}{}", step.name, show(author)),
+                None => println!("  {:<12}-", step.name),
             }
+        }
+        let names = resolver.display_names([(raw, 1)].into_iter());
+        let row = names.get(&resolved.author.key()).cloned().unwrap_or(resolved.author.name.clone());
+        println!("  = {} (row {row:?})", show(&resolved.author));
+    }
+    return Ok(());
+}
+
+/// Print the owner of each line of `file` at `rev`
+fn annotate_file(opt: &Args, rev: &str, file: &str, format: AnnotateFormat, policy: &Policy) -> Result<()> {
+    let repo = Arc::new(discover(policy, ".")?);
+    let config = Config::load(&repo.root, &opt.config).map_err(Error::Config)?;
+    let resolver = identity_resolver(opt, &read_mailmap(&repo), &config)?;
+    let backend: Arc<dyn Backend> = Arc::new(CliBackend::new(repo.clone()));
+    let mut annotation = annotate(backend.as_ref(), rev, file, &resolver, &opt_out(&config)?)?;
+    if !policy.allows_field("email") {
+        annotation.lines.iter_mut().for_each(|line| line.email.clear());
+    }
+    match format {
+        AnnotateFormat::Json => println!("{}", annotate::render_json(&annotation, Utc::now().timestamp())),
+    }
+    return Ok(());
+}
+
+/// Print the recorded runs of the repos `names` (all if empty)
+fn show_trends(opt: &Args, names: &[String], format: TrendsFormat, at_rest: Option<&AtRest>) -> Result<()> {
+    let path = opt.history_file.as_ref().map(PathBuf::from).unwrap_or_else(default_history_file);
+    let mut entries = history::load(&path, at_rest)?;
+    if !names.is_empty() {
+        entries.retain(|entry| names.contains(&entry.repo));
+    }
+    if entries.is_empty() {
+        warn!("no runs recorded in {}; see --record-history", path.display());
+    }
+    match format {
+        TrendsFormat::Text => print!("{}", history::render_text(&entries)),
+        TrendsFormat::Csv => print!("{}", history::render_csv(&entries)),
+    }
+    return Ok(());
+}
+
+/// Print who owns the line ranges listed on stdin at `rev`
+fn region_ownership(opt: &Args, rev: &str, format: RegionsFormat, policy: &Policy) -> Result<()> {
+    let mut input = String::new();
+    io::stdin().read_to_string(&mut input)?;
+    let regions = parse_regions(&input).map_err(|e| Error::Config(format!("regions: stdin {e}")))?;
+    let repo = Arc::new(discover(policy, ".")?);
+    let config = Config::load(&repo.root, &opt.config).map_err(Error::Config)?;
+    let resolver = identity_resolver(opt, &read_mailmap(&repo), &config)?;
+    let backend: Arc<dyn Backend> = Arc::new(CliBackend::new(repo.clone()));
+    let revision = backend.revision(&Some(rev.to_string()), &None)?
+        .ok_or_else(|| Error::Config(format!("regions: unknown revision {rev}")))?;
+    let mut ownership = region_owners(backend.as_ref(), &revision, &regions, &resolver, &opt_out(&config)?)?;
+    for path in &ownership.missing {
+        warn!("{path} has no lines at {rev}");
+    }
+    for (region, lines) in &ownership.overruns {
+        warn!("{}:{}-{} runs past the end of the file, which has {lines} line(s) at {rev}", region.path, region.start, region.end);
+    }
+    if ownership.past_end > 0 {
+        warn!("{} listed line(s) are past the end of their file", ownership.past_end);
+    }
+    if !policy.allows_field("email") {
+        ownership.owners.iter_mut().for_each(|owner| owner.email.clear());
+    }
+    match format {
+        RegionsFormat::Text => print!("{}", regions::render_text(&ownership)),
+        RegionsFormat::Json => println!("{}", regions::render_json(&ownership)),
+    }
+    return Ok(());
+}
 
-            *acnt_out.entry_ref(&author).or_insert(0) += cnt_in;
+/// Print the best reviewers of a change to `files`, and those of `patch`, at
+/// `rev`
+#[allow(clippy::too_many_arguments)]
+fn suggest_reviewers(opt: &Args, rev: &str, files: &[String], patch: &Option<String>, exclude: &[String], top: usize,
+    format: ReviewersFormat, at_rest: Option<&AtRest>, policy: &Policy) -> Result<()> {
+    let patches = match patch {
+        Some(path) => {
+            let text = match path.as_str() {
+                "-" => {
+                    let mut text = String::new();
+                    io::stdin().read_to_string(&mut text)?;
+                    text
+                },
+                _ => fs::read_to_string(path).map_err(|e| Error::Config(format!("--patch {path}: {e}")))?,
+            };
+            patch::parse_patch(&text).map_err(|e| Error::Config(format!("--patch {path}: {e}")))?
+        },
+        None => Vec::new(),
+    };
+    let mut files = files.to_vec();
+    for patch in &patches {
+        if !files.contains(&patch.path) {
+            files.push(patch.path.clone());
         }
-    });
+    }
+    let files = &files[..];
+    let repo = Arc::new(discover(policy, ".")?);
+    let mailmap = read_mailmap(&repo);
+    let config = Config::load(&repo.root, &opt.config).map_err(Error::Config)?;
+    let filter = FileFilter::new(&config, opt.builtin_excludes, &opt.exclude, &opt.include).map_err(Error::Config)?;
+    let resolver = identity_resolver(opt, &mailmap, &config)?;
+    let mut excluded = opt.exclude_authors.clone();
+    excluded.extend(exclude.iter().cloned());
+    // Bots don't review
+    let author_filter = AuthorFilter::new(&opt.authors, &excluded, true).map_err(Error::Config)?;
+    let opt_out = opt_out(&config)?;
+    let backend: Arc<dyn Backend> = Arc::new(CliBackend::new(repo.clone()));
 
-    return formatted;
+    let revision = backend.revision(&Some(rev.to_string()), &None)?
+        .ok_or_else(|| Error::Config(format!("suggest-reviewers: unknown revision {rev}")))?;
+    let mut scope: Vec<String> = files.iter().map(|file| reviewers::dir_of(file).to_string()).collect();
+    if scope.iter().any(|dir| dir.is_empty()) {
+        scope.clear();
+    }
+    scope.sort();
+    scope.dedup();
+    let spec = SnapshotSpec {
+        revision: Some(revision.clone()),
+        scope,
+        gitattributes: opt.gitattributes,
+        languages: Languages::new(&config.languages),
+        keep_files: true,
+        jobs: opt.jobs.unwrap_or_else(default_jobs),
+        ..SnapshotSpec::new(vec![backend.commit_date(&revision)?])
+    };
+    let mut cache = open_cache(opt, &repo.root, &mailmap, false, at_rest);
+    let mut stats = blame_snapshots(&backend, &spec, &filter, &mut cache)?;
+    if let Err(e) = cache.save() {
+        warn!("failed to save blame cache: {e}");
+    }
+    if !opt_out.is_empty() {
+        stats.fold_authors(|author| opt_out.opted_out(author, &resolver), &OptOut::aggregate());
+    }
+    let latest = stats.latest.unwrap_or_default();
+    // A patch's new files don't have any yet
+    let added = |file: &String| patches.iter().any(|patch| patch.old_path.is_none() && patch.path == *file);
+    for file in files.iter().filter(|file| !added(file) && !latest.files.iter().any(|(path, _)| path == *file)) {
+        warn!("{file} has no lines at {rev}, or is excluded");
+    }
+
+    let mut candidates = reviewers::rank(&latest, files, &resolver, &|author| author_filter.keeps(author, &resolver));
+    candidates.truncate(top);
+    if patch.is_some() {
+        let mut owners: Vec<Option<String>> = Vec::new();
+        for patch in &patches {
+            let blamed = match &patch.old_path {
+                Some(old_path) => backend.blame_lines(&revision, old_path)?,
+                None => Vec::new(),
+            };
+            owners.extend(reviewers::touched_owners(patch, &blamed, &resolver));
+        }
+        candidates.iter_mut().for_each(|candidate| candidate.burden = Some(reviewers::burden(candidate, &owners)));
+    }
+    if !policy.allows_field("email") {
+        candidates.iter_mut().for_each(|candidate| candidate.email.clear());
+    }
+    match format {
+        ReviewersFormat::Text => print!("{}", reviewers::render_text(&candidates)),
+        ReviewersFormat::Json => println!("{}", reviewers::render_json(&revision, files, &candidates)),
+    }
+    return Ok(());
 }
 
-fn display_results(_opt: &Args, perf: &AuthorPerformance) { //, skip_files: i32, use_files: i32) {
-    let perf = reformat(&perf);
+fn list_contributors(opt: &Args, since: &str, until: &str, paths: &[String], policy: &Policy) -> Result<()> {
+    if opt.since.is_some() {
+        return Err(Error::Config("contributors takes the previous release as --since-ref REF; --since is a date to sample from".to_string()));
+    }
+    let repo = Arc::new(discover(policy, &paths[0])?);
+    let mut scope = paths.iter()
+        .map(|path| repo.relative_path(path))
+        .collect::<Result<Vec<String>>>()?;
+    if scope.iter().any(|rel| rel.is_empty()) {
+        scope.clear();
+    }
+    let config = Config::load(&repo.root, &opt.config).map_err(Error::Config)?;
+    let filter = FileFilter::new(&config, opt.builtin_excludes, &opt.exclude, &opt.include).map_err(Error::Config)?;
+    let resolver = identity_resolver(opt, &read_mailmap(&repo), &config)?;
+    let author_filter = AuthorFilter::new(&opt.authors, &opt.exclude_authors, opt.exclude_bots).map_err(Error::Config)?;
+    let backend: Arc<dyn Backend> = Arc::new(CliBackend::new(repo.clone()));
+    let commits = contributors::range_log(backend.as_ref(), since, until, &scope)?;
+    let opt_out = opt_out(&config)?;
+    let contributors = contributors::tally(&commits, &filter, &resolver, &author_filter, &opt_out);
+    let first_timers = contributors::first_timers(backend.as_ref(), since, until, &scope, &commits, &filter, &resolver, &author_filter, &opt_out)?;
+    print!("{}", contributors::render_markdown(&contributors, since, until));
+    print!("{}", contributors::render_first_timers(&first_timers));
+    return Ok(());
+}
 
-    let mut dates = perf.keys().map(|x| x.to_string()).collect::<Vec<String>>();
-    dates.sort();
+/// Print the files behind each author's change in lines from `from` to `to`
+fn explain_change(opt: &Args, from: &str, to: &str, top: usize, paths: &[String], at_rest: Option<&AtRest>, policy: &Policy) -> Result<()> {
+    let repo = Arc::new(discover(policy, &paths[0])?);
+    let mut scope = paths.iter()
+        .map(|path| repo.relative_path(path))
+        .collect::<Result<Vec<String>>>()?;
+    if scope.iter().any(|rel| rel.is_empty()) {
+        scope.clear();
+    }
+    let mailmap = read_mailmap(&repo);
+    let config = Config::load(&repo.root, &opt.config).map_err(Error::Config)?;
+    let filter = FileFilter::new(&config, opt.builtin_excludes, &opt.exclude, &opt.include).map_err(Error::Config)?;
+    let resolver = identity_resolver(opt, &mailmap, &config)?;
+    let author_filter = AuthorFilter::new(&opt.authors, &opt.exclude_authors, opt.exclude_bots).map_err(Error::Config)?;
+    let opt_out = opt_out(&config)?;
+    let backend: Arc<dyn Backend> = Arc::new(CliBackend::new(repo.clone()));
 
-    let mut authors = Vec::new();
-    for date in &dates {
-        if let Some(acnt) = perf.get(date) {
-            authors.extend(acnt.keys().map(|x| x.to_string()));
+    let mut cache = open_cache(opt, &repo.root, &mailmap, false, at_rest);
+    let mut snapshot = |target: &str| -> Result<FileOwnership> {
+        // A revision is blamed as of its commit date, like `at`
+        let (date, revision) = match is_date(target) {
+            true => (target.to_string(), None),
+            false => {
+                let revision = backend.revision(&Some(target.to_string()), &None)?
+                    .ok_or_else(|| Error::Config(format!("explain: unknown revision {target}")))?;
+                (backend.commit_date(&revision)?, Some(revision))
+            },
+        };
+        let spec = SnapshotSpec {
+            branch: opt.branch.clone(),
+            revision,
+            scope: scope.clone(),
+            missing_snapshot: opt.missing_snapshot,
+            gitattributes: opt.gitattributes,
+            languages: Languages::new(&config.languages),
+            keep_files: true,
+            jobs: opt.jobs.unwrap_or_else(default_jobs),
+            binary_metric: opt.binary_metric,
+            ..SnapshotSpec::new(vec![date])
+        };
+        let mut stats = blame_snapshots(&backend, &spec, &filter, &mut cache)?;
+        if !author_filter.is_empty() {
+            stats.retain_authors(|author| author_filter.keeps(author, &resolver));
+        }
+        if !opt_out.is_empty() {
+            stats.fold_authors(|author| opt_out.opted_out(author, &resolver), &OptOut::aggregate());
         }
+        return stats.latest.ok_or_else(|| Error::Config(format!("explain: no commit at or before {target}")));
+    };
+    let before = snapshot(from)?;
+    let after = snapshot(to)?;
+    if let Err(e) = cache.save() {
+        warn!("failed to save blame cache: {e}");
     }
-    authors.sort();
-    authors.dedup();
-    let long_auth = authors.iter().map(|x| x.len()).max().unwrap_or(0);
 
-    print!("{:<long_auth$}, ", "date");
-    for date in &dates {
-       print!("{:>10}, ", date);
+    let deltas = attribution::attribute(&before, &after, &resolver);
+    let label = |target: &str, snapshot: &FileOwnership| match target == snapshot.date {
+        true => target.to_string(),
+        false => format!("{target} ({})", snapshot.date),
+    };
+    print!("{}", attribution::render_text(&deltas, &label(from, &before), &label(to, &after), top, &digit_separator(opt)));
+    return Ok(());
+}
+
+fn format_day(time: u64) -> String {
+    match Local.timestamp_opt(time as i64, 0).single() {
+        Some(t) => return t.format("%Y-%m-%d").to_string(),
+        None => return "-".to_string(),
+    }
+}
+
+/// The cache subcommands other than warm, on --cache-dir or the default dir
+fn manage_cache(opt: &Args, command: &CacheCommand, at_rest: Option<&AtRest>) -> Result<()> {
+    let cache_dir = opt.cache_dir.as_ref().map(PathBuf::from).unwrap_or_else(cache::default_cache_dir);
+    let caches = cache::list(&cache_dir, at_rest)?;
+    let long_name = caches.iter().map(|c| c.name.len()).max().unwrap_or(0).max("repo".len());
+    match command {
+        CacheCommand::Warm { .. } => unreachable!("cache warm analyzes a repo"),
+        CacheCommand::Ls => {
+            println!("{:<long_name$}  {:>8}  {:>9}  last used", "repo", "size", "entries");
+            for cache in &caches {
+                let entries = match (cache.entries, cache.locked) {
+                    (Some(n), _) => n.to_string(),
+                    (None, true) => "locked".to_string(),
+                    (None, false) => "outdated".to_string(),
+                };
+                let last_used = cache.last_used.map(format_day).unwrap_or("-".to_string());
+                println!("{:<long_name$}  {:>8}  {entries:>9}  {last_used}", cache.name, cache::format_size(cache.bytes));
+            }
+            let bytes = caches.iter().map(|c| c.bytes).sum();
+            println!("{} cache(s), {} in {}", caches.len(), cache::format_size(bytes), cache_dir.display());
+        },
+        CacheCommand::Stats => {
+            let ratio = |stats: &CacheStats| stats.hit_ratio().map(|r| format!("{:.1}%", 100.0 * r)).unwrap_or("-".to_string());
+            println!("{:<long_name$}  {:>6}  {:>10}  {:>10}  {:>8}", "repo", "runs", "hits", "misses", "hit rate");
+            let mut total = CacheStats::default();
+            for cache in &caches {
+                let stats = &cache.stats;
+                println!("{:<long_name$}  {:>6}  {:>10}  {:>10}  {:>8}", cache.name, stats.runs, stats.hits, stats.misses, ratio(stats));
+                total.add(cache.stats);
+            }
+            println!("{:<long_name$}  {:>6}  {:>10}  {:>10}  {:>8}", "total", total.runs, total.hits, total.misses, ratio(&total));
+        },
+        CacheCommand::Gc { max_size } => {
+            let collected = cache::gc(&cache_dir, *max_size, at_rest)?;
+            println!("removed {} entries, {}", collected.entries, cache::format_size(collected.bytes));
+        },
     }
-    println!();
+    return Ok(());
+}
 
-    for author in authors {
-        print!("{author:<long_auth$}, ");
-        for date in &dates {
-            if let Some(acnt) = perf.get(date) {
-                print!("{:>10}, ", acnt.get(&author).unwrap_or(&0));
+/// The self subcommands, on the latest release
+fn manage_tool(command: &ToolCommand) -> Result<()> {
+    #[cfg(not(feature = "self-update"))]
+    if let ToolCommand::Update { .. } = command {
+        return Err(Error::Config("self update needs a build with the self-update feature".to_string()));
+    }
+    let latest = release::latest()?;
+    let newer = release::is_newer(&latest.tag, release::VERSION);
+    match command {
+        ToolCommand::Check => {
+            println!("git-author-stats {}", release::VERSION);
+            let platform = match latest.asset(&release::asset_name()) {
+                Ok(_) => "",
+                Err(_) => " (no binary for this platform)",
+            };
+            match newer {
+                true => println!("{} is available{platform}", latest.tag),
+                false => println!("{} is the latest release", latest.tag),
             }
+        },
+        ToolCommand::Update { public_key, skip_signature: _, force } => {
+            if !newer && !force {
+                println!("git-author-stats {} is up to date ({} is the latest release)", release::VERSION, latest.tag);
+                return Ok(());
+            }
+            if public_key.is_none() {
+                warn!("--skip-signature: NOT checking who published {}; anyone who can change the release or {} can replace this binary", latest.tag, release::RELEASES_ENV);
+            }
+            #[cfg(feature = "self-update")]
+            release::update(&latest, public_key.as_deref())?;
+            #[cfg(not(feature = "self-update"))]
+            let _ = public_key;
+            println!("updated git-author-stats {} to {}", release::VERSION, latest.tag);
+        },
+    }
+    return Ok(());
+}
+
+/// The arguments of the analysis run for `request`, after the options given
+/// with `serve`
+fn api_arguments(options: &[OsString], request: &Request) -> std::result::Result<Vec<OsString>, Response> {
+    let path = request.param("path").unwrap_or(".");
+    // Relative to the served repo and staying inside it
+    let relative = Path::new(path).components().all(|component| matches!(component, std::path::Component::Normal(_) | std::path::Component::CurDir));
+    if !relative {
+        return Err(Response::error(400, &format!("path {path} must be relative to the served repo, without ..")));
+    }
+    // After "--", so nothing the client sends is read as an option
+    let request_args: Vec<&str> = match request.path.as_str() {
+        "/ownership" => vec!["--format", "json", "at", "--", request.param("at").unwrap_or("HEAD"), path],
+        "/history" => vec!["--format", "json", "--", path],
+        _ => return Err(Response::error(404, &format!("no such report: {}; try /ownership or /history", request.path))),
+    };
+    return Ok(options.iter().cloned().chain(request_args.into_iter().map(OsString::from)).collect());
+}
+
+/// Analyze the repo in a child process for each request on `listen`, with
+/// the top-level `options` given
+fn serve_api(options: &[OsString], listen: &str, tokens: &Option<String>, rate_limit: u32, workers: usize) -> Result<()> {
+    let tokens = tokens.as_ref().map(|path| Tokens::load(path)).transpose().map_err(|e| Error::Config(format!("--tokens {e}")))?;
+    let listener = TcpListener::bind(listen).map_err(|e| Error::Config(format!("--listen {listen}: {e}")))?;
+    let address = listener.local_addr()?;
+    if tokens.is_none() && !address.ip().is_loopback() {
+        return Err(Error::Config(format!("--listen {listen} is reachable beyond this host; give --tokens")));
+    }
+    if rate_limit == 0 {
+        return Err(Error::Config("--rate-limit must be at least 1 request per minute".to_string()));
+    }
+    let options = options.to_vec();
+    let probe = Request { method: "GET".to_string(), path: "/ownership".to_string(), query: Vec::new(), headers: Vec::new() };
+    let args = api_arguments(&options, &probe).expect("/ownership is a report");
+    Args::try_parse_from([OsString::from("git-author-stats")].into_iter().chain(args))
+        .map_err(|e| Error::Config(format!("the options ahead of serve don't fit a JSON report: {}", e.kind())))?;
+    let exe = env::current_exe()?;
+    let dir = env::current_dir()?;
+    let root = Repo::discover(".")?.root;
+    info!("serving {root} on http://{address}");
+    let access = Access { tokens, limiter: Mutex::new(RateLimiter::new(rate_limit)) };
+    server::serve(listener, access, workers, move |request| {
+        let args = match api_arguments(&options, request) {
+            Ok(args) => args,
+            Err(response) => return response,
+        };
+        // A symlink or nested repo could still lead somewhere else
+        let path = dir.join(request.param("path").unwrap_or("."));
+        match Repo::discover(&path.to_string_lossy()) {
+            Ok(repo) if repo.root == root => {},
+            _ => return Response::error(400, &format!("path {} is not in the served repo", request.param("path").unwrap_or("."))),
+        }
+        let out = process::Command::new(&exe).args(&args).current_dir(&dir).stdin(Stdio::null()).output();
+        let out = match out {
+            Ok(out) => out,
+            Err(e) => return Response::error(500, &format!("failed to run the analysis: {e}")),
+        };
+        let message = String::from_utf8_lossy(&out.stderr).lines().filter_map(|line| line.strip_prefix("error: ")).next_back().unwrap_or("the analysis failed").to_string();
+        match out.status.code() {
+            Some(0) => return Response::json(200, String::from_utf8_lossy(&out.stdout).to_string()),
+            // Bad arguments, paths outside the repo, dates before the history
+            Some(2 | 3 | 5) => return Response::error(400, &message),
+            _ => return Response::error(500, &message),
         }
-        println!();
+    })?;
+    return Ok(());
+}
+
+/// Refuse the analysis options whose data the model at `path` wasn't saved
+/// with, rather than leave their rows out
+fn check_model(opt: &Args, path: &str, model: &Model) -> Result<()> {
+    // A model with dates of blame holds each per-date breakdown it was saved with
+    let Some(lines) = model.stats(Metric::Lines).filter(|lines| !lines.authors.is_empty()) else {
+        return Ok(());
+    };
+    let file_groups = opt.group_by != GroupBy::None && opt.group_by.attribute().is_none();
+    let needs = [
+        (opt.entropy, lines.entropy.is_empty(), "--entropy"),
+        (opt.bus_factor, lines.concentration.is_empty(), "--bus-factor"),
+        (opt.health_score, lines.staleness.is_empty(), "--health-score"),
+        (opt.categories, lines.categories.is_empty(), "--categories"),
+        (opt.split_docs, lines.docs.is_empty(), "--split-docs"),
+        (file_groups, lines.groups.is_empty(), "--group-by"),
+        (opt.fingerprint != FingerprintBy::None, lines.fingerprint.is_empty(), "--fingerprint"),
+    ];
+    if let Some((_, _, option)) = needs.into_iter().find(|&(given, missing, _)| given && missing) {
+        return Err(Error::Config(format!("{option} takes effect when saving, and {path} was saved without it; save the model again with {option}")));
+    }
+    // Nothing coupled or no activity can be the true answer
+    if opt.coupling && model.coupling.is_empty() {
+        warn!("{path} has no coupled files; --coupling takes effect when saving");
+    }
+    if opt.knowledge_map.is_some() && model.activity.is_empty() {
+        warn!("{path} has no activity for --knowledge-map, which takes effect when saving");
+    }
+    return Ok(());
+}
+
+/// The --policy, after checking the command line against it
+fn load_policy(opt: &Args, used: &[String]) -> Result<Policy> {
+    let Some(path) = &opt.policy else {
+        return Ok(Policy::default());
     };
-    println!();
+    let policy = Policy::load(path).map_err(|e| Error::Config(format!("--policy {e}")))?;
+    policy.check_denied(&all_options(&Args::command())).map_err(|e| Error::Config(format!("--policy {path}: {e}")))?;
+    policy.check_options(used).map_err(Error::Config)?;
+    let fields = match &opt.author_format {
+        Some(template) => AuthorFormat::parse(template).map_err(Error::Config)?.fields(),
+        None => vec!["name"],
+    };
+    if let Some(field) = fields.iter().find(|field| !policy.allows_field(field)) {
+        return Err(Error::Config(format!("the policy doesn't allow showing authors' {field}; pick other fields with --author-format")));
+    }
+    return Ok(policy);
 }
 
-fn main() {
-    let opt = Args::parse();
-    let repo_root = git_repo_root(&opt.path).expect("Not a git repo");
+/// Run the command line, whose options and subcommands are `used` and whose
+/// top-level options are `given`
+fn run(opt: &Args, used: &[String], given: &[OsString]) -> Result<()> {
+    let at_rest = match opt.encrypt_at_rest {
+        false => None,
+        true => Some(AtRest::new(opt.key_file.as_deref()).map_err(Error::Config)?),
+    };
+    let at_rest = at_rest.as_ref();
+    if let Some(dir) = &opt.record_git {
+        recording::record(Path::new(dir), at_rest).map_err(Error::Config)?;
+    }
+    if let Some(dir) = &opt.replay_git {
+        recording::replay(Path::new(dir), at_rest).map_err(Error::Config)?;
+    }
+    if opt.lang.is_some() || opt.lang_file.is_some() {
+        let mut catalog = Catalog::builtin(opt.lang.unwrap_or(Lang::En));
+        if let Some(path) = &opt.lang_file {
+            let ftl = fs::read_to_string(path).map_err(|e| Error::Config(format!("--lang-file {path}: {e}")))?;
+            catalog.merge(Catalog::parse(&ftl).map_err(|e| Error::Config(format!("--lang-file {path}: {e}")))?);
+        }
+        i18n::init(catalog);
+    }
+    let policy = load_policy(opt, used)?;
+    let policy = &policy;
+    if let Some(Command::Tool(command)) = &opt.command {
+        return manage_tool(command);
+    }
+    if opt.key_file.is_some() && at_rest.is_none() {
+        warn!("--key-file only applies with --encrypt-at-rest");
+    }
+    if let Some(output) = &opt.isolated_output {
+        return blame_isolated_snapshot(opt, output, at_rest, policy);
+    }
+    if let Some(name) = &opt.explain_identity {
+        return explain_identity(opt, name, policy);
+    }
+    match &opt.command {
+        Some(Command::Cache(CacheCommand::Warm { paths })) => return warm_cache(opt, paths, at_rest, policy),
+        Some(Command::Cache(command)) => return manage_cache(opt, command, at_rest),
+        Some(Command::Annotate { rev, file, format }) => return annotate_file(opt, rev, file, *format, policy),
+        Some(Command::SuggestReviewers { rev, file, patch, exclude, top, format }) =>
+            return suggest_reviewers(opt, rev, file, patch, exclude, *top, *format, at_rest, policy),
+        Some(Command::Trends { name, format }) => return show_trends(opt, name, *format, at_rest),
+        Some(Command::Regions { rev, format }) => return region_ownership(opt, rev, *format, policy),
+        Some(Command::Contributors { since_ref, until, paths }) => return list_contributors(opt, since_ref, until, paths, policy),
+        Some(Command::Explain { from, to, top, paths }) => return explain_change(opt, from, to, *top, paths, at_rest, policy),
+        Some(Command::Serve { listen, tokens, rate_limit, workers }) => return serve_api(given, listen, tokens, *rate_limit, *workers),
+        Some(Command::Tool(_)) => unreachable!("self is handled before anything else"),
+        Some(Command::At { .. }) | None => {},
+    }
+    let paths = match &opt.command {
+        Some(Command::At { paths, .. }) => paths,
+        _ => &opt.paths,
+    };
+    let name = match (opt.working_tree, opt.staged) {
+        (true, _) => "--working-tree",
+        (_, true) => "--staged",
+        _ => "at",
+    };
+    if (opt.working_tree || opt.staged) && matches!(opt.command, Some(Command::At { .. })) {
+        return Err(Error::Config(format!("{name} can't be combined with at")));
+    }
+    if at_target(opt).is_some() && opt.metrics != [Metric::Lines] {
+        return Err(Error::Config(format!("{name} only counts --metric lines")));
+    }
+
+    let mut repo_paths = opt.repos.clone();
+    if let Some(path) = &opt.repos_file {
+        repo_paths.extend(read_repo_list(path)?);
+    }
+    if !repo_paths.is_empty() && paths != &["."] {
+        return Err(Error::Config("paths can't be combined with --repo or --repos-file".to_string()));
+    }
+    if opt.batch {
+        if opt.output_dir.is_none() {
+            return Err(Error::Config("--batch needs --output-dir".to_string()));
+        }
+        if !repo_paths.is_empty() || paths != &["."] || opt.load_model.is_some() || !opt.branches.is_empty() {
+            return Err(Error::Config("--batch can't be combined with paths, --repo, --repos-file, --load-model or --branches".to_string()));
+        }
+    } else if opt.output_dir.is_some() {
+        warn!("--output-dir only applies to --batch");
+    }
+    if !opt.branches.is_empty() {
+        if !repo_paths.is_empty() || opt.load_model.is_some() {
+            return Err(Error::Config("--branches can't be combined with --repo, --repos-file or --load-model".to_string()));
+        }
+        if opt.branch.is_some() || at_target(opt).is_some() || opt.isolate_snapshots {
+            return Err(Error::Config(format!("--branches can't be combined with --branch, {name} or --isolate-snapshots")));
+        }
+        if opt.format == OutputFormat::CodeMaat {
+            return Err(Error::Config("--branches can't be written as --format code-maat".to_string()));
+        }
+    }
+    if opt.batch || !opt.branches.is_empty() {
+        let outputs = [
+            ("--save-model", opt.save_model.is_some()), ("--knowledge-map", opt.knowledge_map.is_some()), ("--chart-svg", opt.chart_svg.is_some()),
+            ("--badge", !opt.badges.is_empty()), ("--seniority-csv", opt.seniority_csv.is_some()), ("--seniority-svg", opt.seniority_svg.is_some()),
+            ("--emit-static-site", opt.emit_static_site.is_some()), ("--workload-csv", opt.workload_csv.is_some()),
+            ("--export-postgres", opt.export_postgres.is_some()), ("--push-gateway", opt.push_gateway.is_some()), ("--record-history", opt.record_history),
+        ];
+        let ignored: Vec<&str> = outputs.iter().filter(|(_, given)| *given).map(|(option, _)| *option).collect();
+        if !ignored.is_empty() {
+            let mode = if opt.batch { "--batch" } else { "--branches" };
+            warn!("{} only apply without {mode}", ignored.join(", "));
+        }
+    }
+    if opt.per_repo && repo_paths.len() < 2 {
+        warn!("--per-repo only applies to several repositories");
+    }
+    if opt.per_path && paths.len() < 2 {
+        warn!("--per-path only applies to several paths");
+    }
+    if opt.auto_depth.is_some() && opt.group_by != GroupBy::Dir {
+        warn!("--auto-depth only applies to --group-by dir");
+    }
+    if opt.issues.is_some() && opt.workload_csv.is_none() {
+        warn!("--issues only applies with --workload-csv");
+    }
+    if opt.coupling && repo_paths.len() > 1 {
+        warn!("--coupling only applies to a single repository");
+    }
+    if opt.rename_graph.is_some() && (repo_paths.len() > 1 || opt.load_model.is_some()) {
+        warn!("--rename-graph only applies to a single repository, not --load-model");
+    }
 
-    let mut dates = Vec::new();
-    let dt = Local::now();
-    for year in 2016..=dt.year() {
-        for month in 1..=12 {
-            dates.push(format!("{year:4}-{month:02}-01"));
+    if opt.fingerprint != FingerprintBy::None && !opt.metrics.contains(&Metric::Lines) {
+        warn!("--fingerprint only applies to --metric lines");
+    }
+    if opt.weight != Weight::Lines && !opt.metrics.contains(&Metric::Lines) {
+        warn!("--weight only applies to --metric lines");
+    }
+    if opt.coupling && !opt.metrics.contains(&Metric::Lines) {
+        warn!("--coupling only applies to --metric lines");
+    }
+    if opt.entropy && !opt.metrics.contains(&Metric::Lines) {
+        warn!("--entropy only applies to --metric lines");
+    }
+    if opt.bus_factor && !opt.metrics.contains(&Metric::Lines) {
+        warn!("--bus-factor only applies to --metric lines");
+    }
+    if (opt.seniority_csv.is_some() || opt.seniority_svg.is_some()) && (!opt.metrics.contains(&Metric::Lines) || opt.load_model.is_some()) {
+        warn!("--seniority-csv and --seniority-svg only apply to --metric lines, not --load-model");
+    }
+    if opt.health_score && !opt.metrics.contains(&Metric::Lines) {
+        warn!("--health-score only applies to --metric lines");
+    }
+    if !opt.session_gap.is_finite() || opt.session_gap <= 0.0 {
+        return Err(Error::Config(format!("--session-gap must be more than 0 hours, not {}", opt.session_gap)));
+    }
+    if !(0.0..=100.0).contains(&opt.dominance) {
+        return Err(Error::Config(format!("--dominance must be from 0 to 100, not {}", opt.dominance)));
+    }
+    if let Some(min) = opt.min_coverage {
+        if !(0.0..=100.0).contains(&min) {
+            return Err(Error::Config(format!("--min-coverage must be from 0 to 100, not {min}")));
+        }
+        if !opt.metrics.contains(&Metric::Lines) {
+            warn!("--min-coverage only applies to --metric lines");
         }
     }
+    if !opt.badges.is_empty() && !opt.metrics.contains(&Metric::Lines) {
+        warn!("--badge only applies to --metric lines");
+    }
+    if opt.sample.is_some() && !opt.metrics.contains(&Metric::Lines) {
+        warn!("--sample only applies to --metric lines");
+    }
+    if opt.format == OutputFormat::CodeMaat && (!opt.metrics.contains(&Metric::Lines) || !repo_paths.is_empty()) {
+        warn!("--format code-maat only applies to --metric lines on a single repository");
+    }
+    if opt.split_docs && !opt.metrics.contains(&Metric::Lines) {
+        warn!("--split-docs only applies to --metric lines");
+    }
+    if !opt.doc_files.is_empty() && !opt.split_docs {
+        warn!("--doc-files only applies to --split-docs");
+    }
+    if opt.categories && !opt.metrics.contains(&Metric::Lines) {
+        warn!("--categories only applies to --metric lines");
+    } else if opt.categories && !matches!(opt.format, OutputFormat::Json | OutputFormat::Yaml | OutputFormat::Msgpack) && opt.emit_static_site.is_none() {
+        warn!("--categories is only written to --format json, yaml or msgpack and --emit-static-site");
+    }
 
-    // HashMap<date, HashMap<name, count>>
-    let mut authors = AuthorPerformance::new();
+    if opt.knowledge_map.is_some() && (!opt.metrics.contains(&Metric::Lines) || !repo_paths.is_empty()) {
+        warn!("--knowledge-map only applies to --metric lines on a single repository");
+    }
+    if opt.metrics.len() > 1 && (opt.format == OutputFormat::Html || opt.emit_static_site.is_some() || opt.chart_svg.is_some()) {
+        warn!("html, --emit-static-site and --chart-svg only show the first --metric, {}", opt.metrics[0].name());
+    }
 
-    for date in dates.iter() {
-        let revision = git_revision(&repo_root, &opt.branch, &Some(date.to_string())).expect("Failed to get revision from branch and date");
-        let files = git_files(&repo_root, &revision);
+    if (opt.export_postgres.is_some() || opt.push_gateway.is_some()) && opt.load_model.is_some() && opt.export_name.is_none() {
+        return Err(Error::Config("--export-postgres and --push-gateway with --load-model need --export-name".to_string()));
+    }
+
+    // Read ahead of the analysis, so a bad --directory or --author-order fails fast
+    let directory_teams = match (opt.group_by.attribute(), &opt.directory) {
+        (Some(column), Some(source)) => Some(directory_teams(source, column)?),
+        (Some(column), None) => return Err(Error::Config(format!("grouping by {} needs --directory", column.replace('_', " ")))),
+        (None, Some(_)) => {
+            warn!("--directory only applies to --group-by title, location or cost-center");
+            None
+        },
+        (None, None) => None,
+    };
+    let order = author_order(opt)?;
+
+    if opt.batch {
+        return run_batch(opt, directory_teams, &order, at_rest, policy);
+    }
+    if !opt.branches.is_empty() {
+        return compare_branches(opt, paths, directory_teams, &order, at_rest, policy);
+    }
 
-        let files: Vec<String> = files.iter().filter(|f| {
-            let pb = PathBuf::from(&f);
-            if let Some(_) = reason_to_skip(&pb) {
-                false
-            } else {
-                true
+    // What --export-postgres and --push-gateway file the counts under
+    let mut repo_name = opt.export_name.clone();
+    let (mut stats, mut active, config, mailmap, activity, coupling, first_commits) = match (&opt.load_model, repo_paths.len()) {
+        (Some(path), _) => {
+            let model = Model::load(Path::new(path), at_rest)?;
+            check_model(opt, path, &model)?;
+            let stats = opt.metrics.iter().map(|&metric| match model.stats(metric) {
+                Some(stats) => Ok(stats.clone()),
+                None => Err(Error::Config(format!("{path} has no {} counts; save it with --metric {}", metric.name(), metric.name()))),
+            }).collect::<Result<Vec<AuthorStats>>>()?;
+            (stats, model.active, model.config, model.mailmap, model.activity, model.coupling, FirstCommits::new())
+        },
+        (None, 0) => {
+            let single = analyze_repo(opt, paths, None, at_rest, policy)?;
+            repo_name.get_or_insert(single.name);
+            (single.stats, single.active, single.config, single.mailmap, single.activity, single.coupling, single.first_commits)
+        },
+        (None, _) => {
+            let runs = repo_paths.iter()
+                .map(|path| analyze_repo(opt, std::slice::from_ref(path), Some(path), at_rest, policy))
+                .collect::<Result<Vec<RepoStats>>>()?;
+            // Teams and identities are read from the first repo; every mailmap applies
+            let mailmap = runs.iter().map(|run| run.mailmap.as_str()).collect::<Vec<&str>>().join("\n");
+            let names: Vec<String> = runs.iter().map(|run| run.name.clone()).collect();
+            repo_name.get_or_insert(names.join(","));
+            let mut stats: Vec<AuthorStats> = opt.metrics.iter().map(|_| AuthorStats::default()).collect();
+            let mut active = AuthorStats::default();
+            let mut first_commits = FirstCommits::new();
+            let mut config: Option<Config> = None;
+            let mut opt_out = Vec::new();
+            for (run, path) in runs.into_iter().zip(&repo_paths) {
+                // Tell apart repos with the same directory name by their path
+                let name = match names.iter().filter(|&name| name == &run.name).count() {
+                    1 => run.name,
+                    _ => path.clone(),
+                };
+                for (merged, mut repo_stats) in stats.iter_mut().zip(run.stats) {
+                    if opt.per_repo {
+                        repo_stats.groups = repo_stats.groups.into_iter().map(|(group, perf)| (format!("{name}: {group}"), perf)).collect();
+                        repo_stats.groups.insert(name.clone(), repo_stats.authors.clone());
+                    }
+                    merged.merge(&name, repo_stats);
+                }
+                active.merge(&name, run.active);
+                seniority::merge(&mut first_commits, run.first_commits);
+                // Opting out in any of the repos counts for all of them
+                opt_out.extend(run.config.opt_out.iter().cloned());
+                config.get_or_insert(run.config);
             }
-        }).map(|x| x.to_string()).collect();
-        if files.len() == 0 { continue; }
-
-        let pool = ThreadPool::new(files.len().min(16)); // TODO: make this configurable, default to # of cores
-        let (tx, rx) = channel();
-        for f in files.iter() {
-            let trepo_root = repo_root.clone();
-            let trevision = revision.clone();
-            let tf = f.clone();
-            let ttx = tx.clone();
-            pool.execute(move || {
-                ttx.send(git_author_line_count(&trepo_root, &trevision, &tf)).unwrap();
-            });
+            let mut config = config.unwrap();
+            config.opt_out = opt_out;
+            (stats, active, config, mailmap, LastActivity::new(), Vec::new(), first_commits)
+        },
+    };
+
+    if let Some(path) = &opt.save_model {
+        let metrics = opt.metrics.iter().copied().zip(stats.iter().cloned()).collect();
+        let model = Model {
+            metrics,
+            active: active.clone(),
+            config: config.clone(),
+            mailmap: mailmap.clone(),
+            activity: activity.clone(),
+            coupling: coupling.clone(),
         };
+        model.save(Path::new(path), at_rest)?;
+    }
 
-        let mut dauth = AuthorCount::new();
-        rx.iter().take(files.len()).for_each(|fauth| {
-            fauth.iter().for_each(|(author, count)| {
-                *dauth.entry_ref(author).or_insert(0) += count;
-            });
-        });
+    // git blame already applies the .mailmap, but other git commands used
+    // later (log) only do so on request, so it is resolved here as well
+    let resolver = identity_resolver(opt, &mailmap, &config)?;
 
-        let date_str = date.to_string();
-        authors.insert(date_str, dauth);
+    // On the same dates as the metrics, and filtered and folded like them
+    let mut issues = match (&opt.workload_csv, &opt.issues) {
+        (Some(_), Some(path)) => {
+            let mut dates: Vec<Date> = stats.iter().flat_map(|stats| stats.authors.keys().cloned()).collect();
+            dates.sort();
+            dates.dedup();
+            workload::issues_by_date(&workload::load_issues(path).map_err(Error::Config)?, &dates)
+        },
+        _ => AuthorStats::default(),
     };
 
-    display_results(&opt, &authors);//, skip_files, use_files);
+    let author_filter = AuthorFilter::new(&opt.authors, &opt.exclude_authors, opt.exclude_bots).map_err(Error::Config)?;
+    if !author_filter.is_empty() {
+        for stats in stats.iter_mut().chain([&mut active, &mut issues]) {
+            stats.retain_authors(|author| author_filter.keeps(author, &resolver));
+        }
+    }
+    // Folded before any report is built, so every output format respects it
+    let opt_out = opt_out(&config)?;
+    if !opt_out.is_empty() {
+        for stats in stats.iter_mut().chain([&mut active, &mut issues]) {
+            stats.fold_authors(|author| opt_out.opted_out(author, &resolver), &OptOut::aggregate());
+        }
+    }
+
+    let teams = directory_teams.unwrap_or_else(|| Teams::from_config(&config));
+    let mut reports: Vec<(Metric, Report)> = opt.metrics.iter().zip(&stats)
+        .map(|(&metric, stats)| (metric, build_report(opt, metric, stats, &resolver, &teams, &order)))
+        .collect();
+    if opt.health_score {
+        for ((_, report), stats) in reports.iter_mut().zip(&stats).filter(|((metric, _), _)| *metric == Metric::Lines) {
+            report.table.health = health_trend(stats, &active.authors, &config.health_weights, &resolver, &report.table.dates);
+        }
+    }
+    if opt.coupling {
+        for (_, report) in reports.iter_mut().filter(|(metric, _)| *metric == Metric::Lines) {
+            report.coupling = coupling_rows(&coupling, &report.table, &teams);
+        }
+    }
+    // Only blaming skips files, and it does so the same way for every metric
+    let excluded = stats.iter().flat_map(|stats| stats.excluded.clone()).collect();
+    match at_target(opt) {
+        Some(target) if opt.format == OutputFormat::Table && !opt.chart => display_ranking(opt, &reports[0].1.table, target),
+        _ => display_results(opt, &reports, &excluded),
+    }
+    let latest = stats.iter().find_map(|stats| stats.latest.as_ref());
+    if let (Some(path), Some(latest)) = (&opt.knowledge_map, latest) {
+        let map = knowledge_map(latest, &activity, &resolver);
+        at_rest::write(Path::new(path), knowledge::render_markdown(&map, &latest.date), at_rest)?;
+    }
+    let (_, report) = &reports[0];
+    if let Some(path) = &opt.chart_svg {
+        at_rest::write(Path::new(path), chart::svg(opt, &report.table), at_rest)?;
+    }
+    let lines = reports.iter().find(|(metric, _)| *metric == Metric::Lines);
+    if let (Some((_, report)), true) = (lines, opt.seniority_csv.is_some() || opt.seniority_svg.is_some()) {
+        let curves = seniority::curves(&report.table, &first_commits, &resolver);
+        if let Some(path) = &opt.seniority_csv {
+            at_rest::write(Path::new(path), seniority::render_csv(&curves), at_rest)?;
+        }
+        if let Some(path) = &opt.seniority_svg {
+            at_rest::write(Path::new(path), seniority::render_svg(&curves), at_rest)?;
+        }
+    }
+    if let (Some((_, report)), false) = (lines, opt.badges.is_empty()) {
+        let latest = report.table.dates.last().and_then(|date| active.authors.get(date));
+        let active = latest.map(|acnt| badge::active_authors(acnt, &resolver)).unwrap_or(0);
+        for (kind, path) in &opt.badges {
+            at_rest::write(Path::new(path), badge::render_svg(&badge::badge(*kind, &report.table, active)), at_rest)?;
+        }
+    }
+    if let Some(dir) = &opt.emit_static_site {
+        site::emit_static_site(Path::new(dir), opt, report, &excluded, at_rest)?;
+    }
+    if let Some(path) = &opt.workload_csv {
+        let metrics: Vec<(Metric, &AuthorStats)> = opt.metrics.iter().copied().zip(&stats).collect();
+        at_rest::write(Path::new(path), workload::render_csv(&issues, &metrics, &resolver), at_rest)?;
+    }
+    if let (Some(url), Some(name)) = (&opt.export_postgres, &repo_name) {
+        let metrics: Vec<(Metric, &AuthorStats)> = opt.metrics.iter().copied().zip(&stats).collect();
+        postgres::export(url, &postgres::export_sql(name, &metrics, &resolver))?;
+        info!("exported the counts of {name} to PostgreSQL");
+    }
+    if let (Some(url), Some(name)) = (&opt.push_gateway, &repo_name) {
+        let metrics: Vec<(Metric, &AuthorStats)> = opt.metrics.iter().copied().zip(&stats).collect();
+        pushgateway::push(url, name, &pushgateway::render(&metrics, &resolver))?;
+        info!("pushed the counts of {name} to {url}");
+    }
+    if let (true, Some(name)) = (opt.record_history, &repo_name) {
+        let path = opt.history_file.as_ref().map(PathBuf::from).unwrap_or_else(default_history_file);
+        let now = Utc::now().timestamp();
+        for (metric, report) in &reports {
+            if let Some(entry) = history::headline(now, name, metric.name(), &report.table) {
+                history::record(&path, entry, now, at_rest)?;
+            }
+        }
+        info!("recorded the run of {name} in {}", path.display());
+    }
+    return Ok(());
+}
+
+/// Whether --error-format json was given, read from the raw arguments so
+/// that failing to parse the others is reported as JSON too
+fn json_errors() -> bool {
+    let args: Vec<String> = env::args_os().map(|arg| arg.to_string_lossy().to_string()).collect();
+    return args.iter().any(|arg| arg == "--error-format=json") || args.windows(2).any(|pair| pair[0] == "--error-format" && pair[1] == "json");
 }
 
+/// Exit for bad arguments; --help and --version still print as usual
+fn exit_usage(e: clap::Error, json: bool) -> ! {
+    if !json || !e.use_stderr() {
+        e.exit();
+    }
+    let text = e.to_string();
+    let message = text.lines().next().unwrap_or("").trim_start_matches("error: ");
+    eprintln!("{}", error::render_json("usage", 2, "arguments", message, None, Some("see --help")));
+    process::exit(2);
+}
 
+fn main() {
+    let json = json_errors();
+    if json {
+        std::panic::set_hook(Box::new(|info| {
+            eprintln!("{}", error::render_json("panic", 101, "internal", &info.to_string(), None, Some("this is a bug, please report it")));
+        }));
+    }
+    let command = Args::command();
+    let matches = command.clone().try_get_matches().unwrap_or_else(|e| exit_usage(e, json));
+    let mut opt = Args::from_arg_matches(&matches).unwrap_or_else(|e| exit_usage(e, json));
+    let used = used_options(&command, &matches);
+    let given = given_options(&command, &matches);
+    let mut metrics = Vec::new();
+    for metric in opt.metrics {
+        if !metrics.contains(&metric) {
+            metrics.push(metric);
+        }
+    }
+    opt.metrics = metrics;
+    log::set_verbosity(match (opt.quiet, opt.verbose) {
+        (true, _) => Level::Error,
+        (false, 0) => Level::Warn,
+        (false, 1) => Level::Info,
+        (false, _) => Level::Debug,
+    });
+    // A --isolate-snapshots child leaves the progress line to its parent
+    if opt.isolated_snapshot.is_none() {
+        log::enable_progress();
+    }
+    if let Err(e) = run(&opt, &used, &given) {
+        match opt.error_format {
+            ErrorFormat::Text => eprintln!("error: {e}"),
+            ErrorFormat::Json => eprintln!("{}", e.to_json()),
+        }
+        std::process::exit(e.exit_code());
+    }
+}
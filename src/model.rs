@@ -0,0 +1,470 @@
+use clap::ValueEnum;
+use hashbrown::HashMap;
+use std::path::Path;
+
+use crate::analysis::{AuthorStats, FileOwnership};
+use crate::at_rest::{self, AtRest};
+use crate::category::{AuthorCategories, CategoryCounts};
+use crate::cli_args::Metric;
+use crate::config::Config;
+use crate::coupling::Coupling;
+use crate::error::{Error, Result};
+use crate::filter::{RuleSource, SkipReason};
+use crate::health::{HealthWeights, Staleness};
+use crate::identity::{Author, SpecialLabels};
+use crate::knowledge::LastActivity;
+use crate::ownership::{Concentration, EntropyTotal};
+use crate::sampling::Estimate;
+use crate::{AuthorCount, AuthorPerformance, Date};
+
+//----
+// Saved analysis model
+//
+// --save-model writes everything a report is built from (the counts of each
+// metric, the settings read from the repo, the mailmap) to one binary file;
+// --load-model builds the reports from it without running git at all.  The
+// file starts with MAGIC and the schema VERSION; integers are little endian,
+// strings and collections are prefixed with their length.
+
+const MAGIC: &[u8] = b"git-author-stats model\n";
+
+/// Bumped whenever the layout changes; other versions are refused
+const VERSION: u32 = 10;
+
+/// The results of an analysis, as --save-model stores them
+#[derive(Debug, Clone, Default)]
+pub struct Model {
+    /// Counts of each metric analyzed, in the order of --metric
+    pub metrics: Vec<(Metric, AuthorStats)>,
+    /// Commits per author per period, for --health-score
+    pub active: AuthorStats,
+    pub config: Config,
+    pub mailmap: String,
+    /// For --knowledge-map
+    pub activity: LastActivity,
+    /// For --coupling
+    pub coupling: Vec<Coupling>,
+}
+
+struct Writer {
+    out: Vec<u8>,
+}
+
+impl Writer {
+    fn u64(&mut self, value: u64) {
+        self.out.extend_from_slice(&value.to_le_bytes());
+    }
+
+    fn i64(&mut self, value: i64) {
+        self.out.extend_from_slice(&value.to_le_bytes());
+    }
+
+    fn f64(&mut self, value: f64) {
+        self.out.extend_from_slice(&value.to_le_bytes());
+    }
+
+    fn len(&mut self, len: usize) {
+        self.u64(len as u64);
+    }
+
+    fn str(&mut self, text: &str) {
+        self.len(text.len());
+        self.out.extend_from_slice(text.as_bytes());
+    }
+
+    fn strs(&mut self, texts: &[String]) {
+        self.len(texts.len());
+        texts.iter().for_each(|text| self.str(text));
+    }
+
+    fn author(&mut self, author: &Author) {
+        self.str(&author.name);
+        self.str(&author.email);
+    }
+
+    /// A map with string keys, in key order so equal models give equal files
+    fn map<T>(&mut self, map: &HashMap<String, T>, mut value: impl FnMut(&mut Writer, &T)) {
+        let mut keys: Vec<&String> = map.keys().collect();
+        keys.sort();
+        self.len(keys.len());
+        for key in keys {
+            self.str(key);
+            value(self, &map[key]);
+        }
+    }
+
+    fn authors<T>(&mut self, map: &HashMap<Author, T>, mut value: impl FnMut(&mut Writer, &T)) {
+        let mut authors: Vec<&Author> = map.keys().collect();
+        authors.sort_by(|a, b| (&a.name, &a.email).cmp(&(&b.name, &b.email)));
+        self.len(authors.len());
+        for author in authors {
+            self.author(author);
+            value(self, &map[author]);
+        }
+    }
+
+    fn author_count(&mut self, acnt: &AuthorCount) {
+        self.authors(acnt, |w, &count| w.i64(count as i64));
+    }
+
+    fn perf(&mut self, perf: &AuthorPerformance) {
+        self.map(perf, |w, acnt| w.author_count(acnt));
+    }
+
+    fn stats(&mut self, stats: &AuthorStats) {
+        // Destructured so that a new field can't be left out by accident
+        // The coverage is checked before the model is saved
+        let AuthorStats { authors, docs, excluded, latest, groups, categories, fingerprint, entropy, concentration, staleness, coverage: _, missing, fallbacks, ineligible, estimates } = stats;
+        self.perf(authors);
+        self.perf(docs);
+        self.map(excluded, |w, skip| {
+            w.str(&skip.reason);
+            w.str(&skip.pattern);
+            w.u64(match skip.source {
+                RuleSource::Builtin => 0,
+                RuleSource::Config => 1,
+                RuleSource::Cli => 2,
+                RuleSource::Gitattributes => 3,
+            });
+        });
+        match latest {
+            None => self.u64(0),
+            Some(latest) => {
+                self.u64(1);
+                self.str(&latest.date);
+                self.str(&latest.revision);
+                self.len(latest.files.len());
+                for (path, acnt) in &latest.files {
+                    self.str(path);
+                    self.author_count(acnt);
+                }
+            },
+        }
+        self.map(groups, |w, perf| w.perf(perf));
+        self.map(categories, |w, acat| w.authors(acat, |w, counts| counts.iter().for_each(|&c| w.i64(c as i64))));
+        self.map(fingerprint, |w, acnt| w.author_count(acnt));
+        self.map(entropy, |w, total| {
+            w.f64(total.sum);
+            w.len(total.files);
+        });
+        self.map(concentration, |w, files| {
+            w.len(files.files);
+            w.len(files.dominated);
+        });
+        self.map(staleness, |w, lines| {
+            w.len(lines.lines);
+            w.len(lines.stale);
+        });
+        self.strs(missing);
+        self.strs(fallbacks);
+        self.len(ineligible.len());
+        for (date, rules) in ineligible {
+            self.str(date);
+            self.strs(rules);
+        }
+        // So that loaded --sample counts still show as estimates
+        self.map(estimates, |w, estimate| {
+            let Estimate { fraction, sampled, files, squares, total_squares } = estimate;
+            w.f64(*fraction);
+            w.len(*sampled);
+            w.len(*files);
+            w.authors(squares, |w, &square| w.f64(square));
+            w.f64(*total_squares);
+        });
+    }
+}
+
+struct Reader<'a> {
+    data: &'a [u8],
+    pos: usize,
+}
+
+type Decoded<T> = std::result::Result<T, String>;
+
+impl<'a> Reader<'a> {
+    fn bytes(&mut self, n: usize) -> Decoded<&'a [u8]> {
+        let end = self.pos.checked_add(n).filter(|&end| end <= self.data.len()).ok_or("truncated model")?;
+        let bytes = &self.data[self.pos..end];
+        self.pos = end;
+        return Ok(bytes);
+    }
+
+    fn u64(&mut self) -> Decoded<u64> {
+        return Ok(u64::from_le_bytes(self.bytes(8)?.try_into().unwrap()));
+    }
+
+    fn i64(&mut self) -> Decoded<i64> {
+        return Ok(i64::from_le_bytes(self.bytes(8)?.try_into().unwrap()));
+    }
+
+    fn f64(&mut self) -> Decoded<f64> {
+        return Ok(f64::from_le_bytes(self.bytes(8)?.try_into().unwrap()));
+    }
+
+    fn len(&mut self) -> Decoded<usize> {
+        let len = self.u64()? as usize;
+        // Every element takes at least a byte, so this bounds allocations
+        match len <= self.data.len() - self.pos {
+            true => return Ok(len),
+            false => return Err("truncated model".to_string()),
+        }
+    }
+
+    fn str(&mut self) -> Decoded<String> {
+        let len = self.len()?;
+        return String::from_utf8(self.bytes(len)?.to_vec()).map_err(|_| "bad string in model".to_string());
+    }
+
+    fn strs(&mut self) -> Decoded<Vec<String>> {
+        return (0..self.len()?).map(|_| self.str()).collect();
+    }
+
+    fn author(&mut self) -> Decoded<Author> {
+        let name = self.str()?;
+        let email = self.str()?;
+        return Ok(Author { name, email });
+    }
+
+    fn map<T>(&mut self, mut value: impl FnMut(&mut Reader<'a>) -> Decoded<T>) -> Decoded<HashMap<String, T>> {
+        let mut map = HashMap::new();
+        for _ in 0..self.len()? {
+            let key = self.str()?;
+            map.insert(key, value(self)?);
+        }
+        return Ok(map);
+    }
+
+    fn authors<T>(&mut self, mut value: impl FnMut(&mut Reader<'a>) -> Decoded<T>) -> Decoded<HashMap<Author, T>> {
+        let mut map = HashMap::new();
+        for _ in 0..self.len()? {
+            let author = self.author()?;
+            map.insert(author, value(self)?);
+        }
+        return Ok(map);
+    }
+
+    fn author_count(&mut self) -> Decoded<AuthorCount> {
+        return self.authors(|r| Ok(r.i64()? as i32));
+    }
+
+    fn perf(&mut self) -> Decoded<AuthorPerformance> {
+        return self.map(|r| r.author_count());
+    }
+
+    fn stats(&mut self) -> Decoded<AuthorStats> {
+        let authors = self.perf()?;
+        let docs = self.perf()?;
+        let excluded = self.map(|r| {
+            let reason = r.str()?;
+            let pattern = r.str()?;
+            let source = match r.u64()? {
+                0 => RuleSource::Builtin,
+                1 => RuleSource::Config,
+                2 => RuleSource::Cli,
+                _ => RuleSource::Gitattributes,
+            };
+            return Ok(SkipReason { reason, pattern, source });
+        })?;
+        let latest = match self.u64()? {
+            0 => None,
+            _ => {
+                let date = self.str()?;
+                let revision = self.str()?;
+                let files = (0..self.len()?).map(|_| Ok((self.str()?, self.author_count()?))).collect::<Decoded<_>>()?;
+                Some(FileOwnership { date, revision, files })
+            },
+        };
+        let groups = self.map(|r| r.perf())?;
+        let categories: HashMap<Date, AuthorCategories> = self.map(|r| r.authors(|r| {
+            let mut counts = CategoryCounts::default();
+            for count in counts.iter_mut() {
+                *count = r.i64()? as i32;
+            }
+            return Ok(counts);
+        }))?;
+        let fingerprint = self.map(|r| r.author_count())?;
+        let entropy = self.map(|r| Ok(EntropyTotal { sum: r.f64()?, files: r.u64()? as usize }))?;
+        let concentration = self.map(|r| Ok(Concentration { files: r.u64()? as usize, dominated: r.u64()? as usize }))?;
+        let staleness = self.map(|r| Ok(Staleness { lines: r.u64()? as usize, stale: r.u64()? as usize }))?;
+        let missing = self.strs()?;
+        let fallbacks = self.strs()?;
+        let ineligible = (0..self.len()?).map(|_| Ok((self.str()?, self.strs()?))).collect::<Decoded<_>>()?;
+        let estimates = self.map(|r| Ok(Estimate {
+            fraction: r.f64()?,
+            sampled: r.u64()? as usize,
+            files: r.u64()? as usize,
+            squares: r.authors(|r| r.f64())?,
+            total_squares: r.f64()?,
+        }))?;
+        return Ok(AuthorStats { authors, docs, excluded, latest, groups, categories, fingerprint, entropy, concentration, staleness, coverage: None, missing, fallbacks, ineligible, estimates });
+    }
+}
+
+fn write_config(w: &mut Writer, config: &Config) {
+    let Config { builtin_excludes, exclude, include, docs, teams, opt_out, health_weights, stale_after_days, labels, languages, generated, automated, domains, heuristics } = config;
+    w.u64(*builtin_excludes as u64);
+    w.strs(exclude);
+    w.strs(include);
+    w.strs(docs);
+    w.len(teams.len());
+    for (team, members) in teams {
+        w.str(team);
+        w.strs(members);
+    }
+    w.strs(opt_out);
+    let HealthWeights { bus_factor, entropy, stale, active_authors } = health_weights;
+    [bus_factor, entropy, stale, active_authors].into_iter().for_each(|&weight| w.f64(weight));
+    w.u64(*stale_after_days as u64);
+    let SpecialLabels { boundary, uncommitted, unknown } = labels;
+    [boundary, uncommitted, unknown].into_iter().for_each(|label| w.str(label));
+    for lists in [languages, generated, automated] {
+        w.len(lists.len());
+        for (name, items) in lists {
+            w.str(name);
+            w.strs(items);
+        }
+    }
+    w.len(domains.len());
+    for (from, to) in domains {
+        w.str(from);
+        w.str(to);
+    }
+    w.u64(*heuristics as u64);
+}
+
+fn read_config(r: &mut Reader) -> Decoded<Config> {
+    let builtin_excludes = r.u64()? != 0;
+    let exclude = r.strs()?;
+    let include = r.strs()?;
+    let docs = r.strs()?;
+    let teams = (0..r.len()?).map(|_| Ok((r.str()?, r.strs()?))).collect::<Decoded<_>>()?;
+    let opt_out = r.strs()?;
+    let health_weights = HealthWeights { bus_factor: r.f64()?, entropy: r.f64()?, stale: r.f64()?, active_authors: r.f64()? };
+    let stale_after_days = r.u64()? as u32;
+    let labels = SpecialLabels { boundary: r.str()?, uncommitted: r.str()?, unknown: r.str()? };
+    let languages = (0..r.len()?).map(|_| Ok((r.str()?, r.strs()?))).collect::<Decoded<_>>()?;
+    let generated = (0..r.len()?).map(|_| Ok((r.str()?, r.strs()?))).collect::<Decoded<_>>()?;
+    let automated = (0..r.len()?).map(|_| Ok((r.str()?, r.strs()?))).collect::<Decoded<_>>()?;
+    let domains = (0..r.len()?).map(|_| Ok((r.str()?, r.str()?))).collect::<Decoded<_>>()?;
+    let heuristics = r.u64()? != 0;
+    return Ok(Config { builtin_excludes, exclude, include, docs, teams, opt_out, health_weights, stale_after_days, labels, languages, generated, automated, domains, heuristics });
+}
+
+impl Model {
+    pub fn encode(&self) -> Vec<u8> {
+        let mut w = Writer { out: MAGIC.to_vec() };
+        w.out.extend_from_slice(&VERSION.to_le_bytes());
+        w.len(self.metrics.len());
+        for (metric, stats) in &self.metrics {
+            w.str(metric.name());
+            w.stats(stats);
+        }
+        w.stats(&self.active);
+        write_config(&mut w, &self.config);
+        w.str(&self.mailmap);
+        w.map(&self.activity, |w, last| w.authors(last, |w, &time| w.i64(time)));
+        w.len(self.coupling.len());
+        for pair in &self.coupling {
+            w.str(&pair.files.0);
+            w.str(&pair.files.1);
+            w.u64(pair.shared as u64);
+            w.u64(pair.revisions.0 as u64);
+            w.u64(pair.revisions.1 as u64);
+        }
+        return w.out;
+    }
+
+    pub fn decode(data: &[u8]) -> std::result::Result<Model, String> {
+        let Some(rest) = data.strip_prefix(MAGIC) else {
+            return Err("not a git-author-stats model".to_string());
+        };
+        let mut r = Reader { data: rest, pos: 0 };
+        let version = u32::from_le_bytes(r.bytes(4)?.try_into().unwrap());
+        if version != VERSION {
+            return Err(format!("model schema version {version} is not supported (expected {VERSION}); save it again"));
+        }
+        let mut metrics = Vec::new();
+        for _ in 0..r.len()? {
+            let name = r.str()?;
+            let metric = Metric::from_str(&name, false).map_err(|_| format!("unknown metric {name} in model"))?;
+            metrics.push((metric, r.stats()?));
+        }
+        let active = r.stats()?;
+        let config = read_config(&mut r)?;
+        let mailmap = r.str()?;
+        let activity = r.map(|r| r.authors(|r| r.i64()))?;
+        let coupling = (0..r.len()?)
+            .map(|_| Ok(Coupling { files: (r.str()?, r.str()?), shared: r.u64()? as usize, revisions: (r.u64()? as usize, r.u64()? as usize) }))
+            .collect::<Decoded<_>>()?;
+        return Ok(Model { metrics, active, config, mailmap, activity, coupling });
+    }
+
+    /// Write the model to `path`, encrypted if `at_rest` is given
+    pub fn save(&self, path: &Path, at_rest: Option<&AtRest>) -> Result<()> {
+        at_rest::write(path, self.encode(), at_rest)?;
+        return Ok(());
+    }
+
+    pub fn load(path: &Path, at_rest: Option<&AtRest>) -> Result<Model> {
+        let data = at_rest::read(path, at_rest)?;
+        return Model::decode(&data).map_err(|e| Error::Config(format!("{}: {e}", path.display())));
+    }
+
+    /// The counts of `metric`, if the model has them
+    pub fn stats(&self, metric: Metric) -> Option<&AuthorStats> {
+        return self.metrics.iter().find(|(m, _)| *m == metric).map(|(_, stats)| stats);
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::analysis::{blame_snapshots, SnapshotSpec};
+    use crate::cache::BlameCache;
+    use crate::cli_args::GroupBy;
+    use crate::filter::{DocFiles, FileFilter};
+    use crate::fixture::{Fixture, DATES};
+
+    #[test]
+    fn round_trip() {
+        let fixture = Fixture::new();
+        let spec = SnapshotSpec {
+            group_by: GroupBy::Dir,
+            keep_files: true,
+            categories: true,
+            entropy: true,
+            stale_after: Some(30),
+            docs: Some(DocFiles::new(&Config::default(), &[]).unwrap()),
+            ..SnapshotSpec::new(DATES.iter().map(|d| d.to_string()).collect())
+        };
+        let filter = FileFilter::new(&Config::default(), false, &["vendor/".to_string()], &[]).unwrap();
+        let mut stats = blame_snapshots(&fixture.backend(), &spec, &filter, &mut BlameCache::in_memory()).unwrap();
+        let mut estimate = Estimate::new(0.5, 1, 2);
+        estimate.add(&stats.authors["2020-03-01"], 1);
+        stats.estimates.insert("2020-03-01".to_string(), estimate);
+        let config = Config { teams: vec![("core".to_string(), vec!["Alice".to_string()])], ..Config::default() };
+        let coupling = vec![Coupling { files: ("a.rs".to_string(), "b.rs".to_string()), shared: 3, revisions: (4, 5) }];
+        let model = Model { metrics: vec![(Metric::Lines, stats)], config, mailmap: "A <a@x.com>".to_string(), coupling, ..Default::default() };
+
+        let encoded = model.encode();
+        let decoded = Model::decode(&encoded).unwrap();
+        assert_eq!(decoded.encode(), encoded);
+        let stats = decoded.stats(Metric::Lines).unwrap();
+        assert_eq!(stats.authors, model.metrics[0].1.authors);
+        assert_eq!(stats.docs, model.metrics[0].1.docs);
+        assert!(!stats.estimates.is_empty());
+        assert_eq!(stats.estimates, model.metrics[0].1.estimates);
+        assert_eq!(stats.missing, ["2020-01-01"]);
+        assert!(stats.excluded.contains_key("vendor/lib.js"));
+        assert_eq!(stats.latest.as_ref().unwrap().files.len(), 2);
+        assert_eq!(decoded.config.teams, model.config.teams);
+        assert_eq!(decoded.coupling, model.coupling);
+        assert!(decoded.stats(Metric::Commits).is_none());
+
+        assert!(Model::decode(&encoded[..encoded.len() - 3]).is_err());
+        let mut newer = encoded.clone();
+        newer[MAGIC.len()] += 1;
+        assert!(Model::decode(&newer).unwrap_err().contains("version"));
+        assert!(Model::decode(b"something else").is_err());
+    }
+}
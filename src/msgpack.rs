@@ -0,0 +1,104 @@
+use crate::json::{self, Value};
+
+//----
+// MessagePack output
+//
+// --format msgpack is the JSON document encoded as MessagePack, for ingesting
+// large multi-repo reports without the size and parse time of JSON text.
+// Maps keep the JSON's key order; whole numbers become the smallest integer
+// that holds them, the others 64-bit floats.
+
+/// The length prefix of a string, array or map: the `fix` marker with the
+/// length in its low bits when it fits in `fix_bits`, otherwise the 16 or 32
+/// bit marker and length
+fn length(out: &mut Vec<u8>, len: usize, fix: u8, fix_bits: u32, wide: [u8; 2]) {
+    if len < 1 << fix_bits {
+        out.push(fix | len as u8);
+    } else if len <= u16::MAX as usize {
+        out.push(wide[0]);
+        out.extend((len as u16).to_be_bytes());
+    } else {
+        out.push(wide[1]);
+        out.extend((len as u32).to_be_bytes());
+    }
+}
+
+fn string(out: &mut Vec<u8>, text: &str) {
+    match text.len() {
+        len @ 32..=255 => out.extend([0xd9, len as u8]),
+        len => length(out, len, 0xa0, 5, [0xda, 0xdb]),
+    }
+    out.extend(text.as_bytes());
+}
+
+fn integer(out: &mut Vec<u8>, n: i64) {
+    match n {
+        -32..=127 => out.push(n as i8 as u8),
+        128..=0xff => out.extend([0xcc, n as u8]),
+        0x100..=0xffff => out.extend([[0xcd].as_slice(), &(n as u16).to_be_bytes()].concat()),
+        0x1_0000..=0xffff_ffff => out.extend([[0xce].as_slice(), &(n as u32).to_be_bytes()].concat()),
+        0x1_0000_0000.. => out.extend([[0xcf].as_slice(), &(n as u64).to_be_bytes()].concat()),
+        -0x80..=-33 => out.extend([0xd0, n as i8 as u8]),
+        -0x8000..=-0x81 => out.extend([[0xd1].as_slice(), &(n as i16).to_be_bytes()].concat()),
+        -0x8000_0000..=-0x8001 => out.extend([[0xd2].as_slice(), &(n as i32).to_be_bytes()].concat()),
+        _ => out.extend([[0xd3].as_slice(), &n.to_be_bytes()].concat()),
+    }
+}
+
+fn encode(out: &mut Vec<u8>, value: &Value) {
+    match value {
+        Value::Null => out.push(0xc0),
+        Value::Bool(b) => out.push(0xc2 | *b as u8),
+        Value::Number(n) => match n.parse::<i64>() {
+            Ok(n) => integer(out, n),
+            Err(_) => {
+                out.push(0xcb);
+                out.extend(n.parse::<f64>().unwrap_or(f64::NAN).to_be_bytes());
+            },
+        },
+        Value::String(text) => string(out, text),
+        Value::Array(items) => {
+            length(out, items.len(), 0x90, 4, [0xdc, 0xdd]);
+            items.iter().for_each(|item| encode(out, item));
+        },
+        Value::Object(fields) => {
+            length(out, fields.len(), 0x80, 4, [0xde, 0xdf]);
+            for (key, value) in fields {
+                string(out, key);
+                encode(out, value);
+            }
+        },
+    }
+}
+
+/// The JSON document `text`, as render_json writes it, in MessagePack
+pub fn from_json(text: &str) -> Vec<u8> {
+    let value = json::parse(text).expect("the JSON output is well formed");
+    let mut out = Vec::new();
+    encode(&mut out, &value);
+    return out;
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn encodes_json_documents() {
+        let bytes = from_json(r#"{"dates": ["2020-01-01"], "authors": [{"author": "Alice", "counts": [5, -1, 300, 70000, -200]}], "entropy": [0.5], "risk": null}"#);
+        let expected = [
+            &[0x84][..],
+            b"\xa5dates", b"\x91\xaa2020-01-01",
+            b"\xa7authors", b"\x91\x82", b"\xa6author\xa5Alice",
+            b"\xa6counts\x95", b"\x05\xff\xcd\x01\x2c\xce\x00\x01\x11\x70\xd1\xff\x38",
+            b"\xa7entropy\x91\xcb", &0.5f64.to_be_bytes(),
+            b"\xa4risk\xc0",
+        ].concat();
+        assert_eq!(bytes, expected);
+
+        // Longer strings and lists get a length of their own
+        let long = "x".repeat(40);
+        assert_eq!(from_json(&format!("\"{long}\""))[..2], [0xd9, 40]);
+        assert_eq!(from_json(&format!("[{}]", vec!["1"; 20].join(",")))[..3], [0xdc, 0, 20]);
+    }
+}
@@ -0,0 +1,88 @@
+//----
+// Jupyter notebooks
+//
+// A .ipynb file is JSON holding its cells' outputs next to their code, so a
+// plain blame credits every base64 plot and table to whoever re-ran the
+// notebook last.  Blaming a notebook only counts the lines of its cells'
+// sources, as Jupyter writes them: one JSON string per line, between
+// `"source": [` and the closing `]`.  Notebooks written some other way (e.g.
+// on one line) keep all their lines.
+
+pub fn is_notebook(path: &str) -> bool {
+    return path.rsplit_once('.').is_some_and(|(_, ext)| ext.eq_ignore_ascii_case("ipynb"));
+}
+
+/// Which lines of a notebook are cell source lines
+pub fn source_lines<'a>(lines: impl IntoIterator<Item = &'a str>) -> Vec<bool> {
+    let mut mask = Vec::new();
+    let mut in_source = false;
+    let mut found = false;
+    for line in lines {
+        let line = line.trim();
+        if in_source {
+            in_source = !line.starts_with(']');
+            mask.push(in_source);
+            continue;
+        }
+        match line.strip_prefix("\"source\":").map(str::trim) {
+            Some("[") => {
+                in_source = true;
+                found = true;
+                mask.push(false);
+            },
+            // A one-line source, e.g. "source": "print(1)"
+            Some(value) => {
+                found = true;
+                mask.push(value.starts_with('"') && value != "\"\"" && value != "\"\",");
+            },
+            None => mask.push(false),
+        }
+    }
+    if !found {
+        mask.iter_mut().for_each(|keep| *keep = true);
+    }
+    return mask;
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn keeps_cell_sources_only() {
+        let notebook = r##"{
+ "cells": [
+  {
+   "cell_type": "code",
+   "outputs": [
+    {
+     "data": {
+      "image/png": "iVBORw0KGgo..."
+     }
+    }
+   ],
+   "source": [
+    "import numpy\n",
+    "numpy.zeros(3)"
+   ]
+  },
+  {
+   "cell_type": "markdown",
+   "source": "# Title"
+  },
+  {
+   "cell_type": "code",
+   "source": []
+  }
+ ]
+}"##;
+        let kept: Vec<&str> = notebook.lines().zip(source_lines(notebook.lines()))
+            .filter(|(_, keep)| *keep)
+            .map(|(line, _)| line.trim())
+            .collect();
+        assert_eq!(kept, ["\"import numpy\\n\",", "\"numpy.zeros(3)\"", "\"source\": \"# Title\""]);
+        assert_eq!(source_lines(["{\"cells\": []}"]), [true]);
+        assert!(is_notebook("analysis/Plots.IPYNB"));
+        assert!(!is_notebook("ipynb/readme.md"));
+    }
+}
@@ -0,0 +1,138 @@
+use hashbrown::HashMap;
+
+use crate::{AuthorCount, Count};
+
+//----
+// Ownership concentration
+//
+// The Shannon entropy (in bits) of a file's line counts per author is 0 when
+// one author owns every line, and log2(n) when n authors own equal shares.
+// Averaged over a snapshot's files it gives one number for how shared the
+// code's ownership is.
+//
+// --bus-factor looks at the risk side: the files where one author owns most
+// of the lines, and the fewest authors who together own half of them.
+
+/// Share of the lines the authors counted by the bus factor own together
+pub const BUS_FACTOR_SHARE: f64 = 0.5;
+
+/// Entropy in bits of a distribution given as counts
+pub fn entropy<I: Iterator<Item = Count>>(counts: I) -> f64 {
+    let counts: Vec<f64> = counts.filter(|&c| c > 0).map(|c| c as f64).collect();
+    let total: f64 = counts.iter().sum();
+    if total == 0.0 {
+        return 0.0;
+    }
+    return counts.iter().map(|c| {
+        let p = c / total;
+        -p * p.log2()
+    }).sum::<f64>().max(0.0);
+}
+
+/// A file's blame counts, merging raw identities that share a key
+fn by_key(acnt: &AuthorCount) -> HashMap<String, Count> {
+    let mut by_key: HashMap<String, Count> = HashMap::new();
+    for (author, count) in acnt {
+        *by_key.entry(author.key()).or_insert(0) += count;
+    }
+    return by_key;
+}
+
+/// Entropy of a file's blame, merging raw identities that share a key
+pub fn file_entropy(acnt: &AuthorCount) -> f64 {
+    return entropy(by_key(acnt).into_values());
+}
+
+/// Whether one author owns more than `threshold` (0..1) of a file's lines
+pub fn is_dominated(acnt: &AuthorCount, threshold: f64) -> bool {
+    let counts = by_key(acnt);
+    let total: Count = counts.values().sum();
+    let top = counts.values().copied().max().unwrap_or(0);
+    return total > 0 && top as f64 > threshold * total as f64;
+}
+
+/// Smallest number of authors owning at least `share` (0..1) of the lines
+pub fn bus_factor<I: Iterator<Item = Count>>(counts: I, share: f64) -> usize {
+    let mut counts: Vec<Count> = counts.filter(|&c| c > 0).collect();
+    counts.sort_by(|a, b| b.cmp(a));
+    let target = share * counts.iter().sum::<Count>() as f64;
+    let mut owned: Count = 0;
+    for (n, count) in counts.iter().enumerate() {
+        owned += count;
+        if owned as f64 >= target {
+            return n + 1;
+        }
+    }
+    return counts.len();
+}
+
+/// Sum of the entropy of a snapshot's files, for averaging
+#[derive(Debug, Clone, Copy, Default, PartialEq)]
+pub struct EntropyTotal {
+    pub sum: f64,
+    pub files: usize,
+}
+
+impl EntropyTotal {
+    pub fn add(&mut self, other: EntropyTotal) {
+        self.sum += other.sum;
+        self.files += other.files;
+    }
+
+    /// Average entropy per file, 0 without files
+    pub fn mean(&self) -> f64 {
+        match self.files {
+            0 => return 0.0,
+            n => return self.sum / n as f64,
+        }
+    }
+}
+
+/// Files of a snapshot, and how many of them have a dominant author
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq)]
+pub struct Concentration {
+    pub files: usize,
+    pub dominated: usize,
+}
+
+impl Concentration {
+    pub fn add(&mut self, other: Concentration) {
+        self.files += other.files;
+        self.dominated += other.dominated;
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::identity::Author;
+
+    #[test]
+    fn entropy_of_shares() {
+        assert_eq!(entropy([10].into_iter()), 0.0);
+        assert_eq!(entropy([5, 5].into_iter()), 1.0);
+        assert_eq!(entropy([1, 1, 1, 1].into_iter()), 2.0);
+        assert_eq!(entropy([0, 0].into_iter()), 0.0);
+        assert!((entropy([3, 1].into_iter()) - 0.8113).abs() < 1e-4);
+    }
+
+    #[test]
+    fn merges_identities_by_key() {
+        let acnt = AuthorCount::from([(Author::new("Jane", "jane@example.com"), 2), (Author::new("jroe", "JANE@example.com"), 2)]);
+        assert_eq!(file_entropy(&acnt), 0.0);
+        assert!(is_dominated(&acnt, 0.8));
+    }
+
+    #[test]
+    fn bus_factor_and_dominance() {
+        assert_eq!(bus_factor([6, 3, 1].into_iter(), 0.5), 1);
+        assert_eq!(bus_factor([4, 3, 3].into_iter(), 0.5), 2);
+        assert_eq!(bus_factor([1, 1, 1, 1].into_iter(), 0.5), 2);
+        assert_eq!(bus_factor([0, 0].into_iter(), 0.5), 0);
+
+        let (alice, bob) = (Author::new("alice", "a@x.com"), Author::new("bob", "b@x.com"));
+        assert!(!is_dominated(&AuthorCount::from([(alice.clone(), 8), (bob.clone(), 2)]), 0.8));
+        assert!(is_dominated(&AuthorCount::from([(alice.clone(), 9), (bob.clone(), 1)]), 0.8));
+        assert!(!is_dominated(&AuthorCount::new(), 0.8));
+    }
+}
@@ -0,0 +1,149 @@
+//----
+// Patches
+//
+// A unified diff, as `git diff` or `git format-patch` writes it, read for the
+// lines each file's change touches: the lines it removes, and where it adds
+// lines, both numbered as before the change, so they can be blamed at the
+// revision the patch applies to.  Anything outside the `---`/`+++` headers and
+// their hunks, such as commit messages or `diff --git` and `index` lines, is
+// skipped, and so are binary files.
+
+/// The change to one file
+#[derive(Debug, Clone, Default, PartialEq, Eq)]
+pub struct FilePatch {
+    /// Path after the change; before it for a deleted file
+    pub path: String,
+    /// Path before the change; none for a new file
+    pub old_path: Option<String>,
+    /// Line numbers, before the change, of the removed lines
+    pub removed: Vec<usize>,
+    /// For each added line, the number of the line before the change it
+    /// follows; 0 at the top of the file
+    pub added_after: Vec<usize>,
+}
+
+/// Path of a `---` or `+++` header; none for /dev/null
+fn header_path(text: &str, prefix: &str) -> Option<String> {
+    // diff -u follows the path with a tab and the file's time
+    let path = text.split('\t').next().unwrap_or(text).trim_end();
+    let path = path.strip_prefix('"').and_then(|path| path.strip_suffix('"')).unwrap_or(path);
+    match path {
+        "/dev/null" => return None,
+        _ => return Some(path.strip_prefix(prefix).unwrap_or(path).to_string()),
+    }
+}
+
+/// `-START[,COUNT] +START[,COUNT] @@` of a hunk header
+fn hunk_range(text: &str) -> Option<((usize, usize), (usize, usize))> {
+    let range = |part: &str| -> Option<(usize, usize)> {
+        let (start, count) = part.split_once(',').unwrap_or((part, "1"));
+        return Some((start.parse().ok()?, count.parse().ok()?));
+    };
+    let mut parts = text.split_whitespace();
+    let old = range(parts.next()?.strip_prefix('-')?)?;
+    let new = range(parts.next()?.strip_prefix('+')?)?;
+    return Some((old, new));
+}
+
+/// Each file's change in the unified diff `text`
+pub fn parse_patch(text: &str) -> Result<Vec<FilePatch>, String> {
+    let mut patches: Vec<FilePatch> = Vec::new();
+    let mut old_header: Option<Option<String>> = None;
+    // Where the hunk being read is, and what's left of it before and after
+    let (mut old_line, mut old_left, mut new_left) = (0, 0, 0);
+    for (i, line) in text.lines().enumerate() {
+        if old_left > 0 || new_left > 0 {
+            let patch = patches.last_mut().expect("hunks follow a file header");
+            match line.chars().next() {
+                // Editors strip the space of empty context lines
+                Some(' ') | None if old_left > 0 && new_left > 0 => {
+                    old_line += 1;
+                    old_left -= 1;
+                    new_left -= 1;
+                },
+                Some('-') if old_left > 0 => {
+                    patch.removed.push(old_line);
+                    old_line += 1;
+                    old_left -= 1;
+                },
+                Some('+') if new_left > 0 => {
+                    patch.added_after.push(old_line - 1);
+                    new_left -= 1;
+                },
+                Some('\\') => {},
+                _ => return Err(format!("line {}: the hunk ends too early, at {line:?}", i + 1)),
+            }
+            continue;
+        }
+        if let Some(path) = line.strip_prefix("--- ") {
+            old_header = Some(header_path(path, "a/"));
+        } else if let Some(path) = line.strip_prefix("+++ ") {
+            let old_path = old_header.take().ok_or_else(|| format!("line {}: +++ without a --- line before it", i + 1))?;
+            let path = header_path(path, "b/").or(old_path.clone())
+                .ok_or_else(|| format!("line {}: both sides of the change are /dev/null", i + 1))?;
+            patches.push(FilePatch { path, old_path, ..FilePatch::default() });
+        } else if let Some(range) = line.strip_prefix("@@ ") {
+            let bad = || format!("line {}: expected @@ -START,COUNT +START,COUNT @@, got {line:?}", i + 1);
+            let ((start, count), (_, new_count)) = hunk_range(range).ok_or_else(bad)?;
+            if patches.is_empty() {
+                return Err(format!("line {}: a hunk before any --- and +++ lines", i + 1));
+            }
+            // An empty side's start is the line before the change
+            old_line = if count == 0 { start + 1 } else { start.max(1) };
+            (old_left, new_left) = (count, new_count);
+        } else {
+            old_header = None;
+        }
+    }
+    if old_left > 0 || new_left > 0 {
+        return Err("the last hunk is cut short".to_string());
+    }
+    return Ok(patches);
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn reads_the_lines_a_patch_touches() {
+        let text = "\
+From 1234 Mon Sep 17 00:00:00 2001
+Subject: [PATCH] Tidy up
+
+--- a/src/main.rs
++++ b/src/main.rs
+@@ -2,3 +2,4 @@ fn main() {
+ keep
+-old
++new
++newer
+
+@@ -10,0 +12,1 @@
++appended
+diff --git a/docs/new.md b/docs/new.md
+new file mode 100644
+index 0000000..1111111
+--- /dev/null
++++ b/docs/new.md
+@@ -0,0 +1,2 @@
++# New
++Text
+\\ No newline at end of file
+Binary files a/logo.png and b/logo.png differ
+--- a/gone.txt\t2020-01-01 00:00:00
++++ /dev/null
+@@ -1 +0,0 @@
+-bye
+";
+        let patches = parse_patch(text).unwrap();
+        assert_eq!(patches, [
+            FilePatch { path: "src/main.rs".to_string(), old_path: Some("src/main.rs".to_string()), removed: vec![3], added_after: vec![3, 3, 10] },
+            FilePatch { path: "docs/new.md".to_string(), old_path: None, removed: vec![], added_after: vec![0, 0] },
+            FilePatch { path: "gone.txt".to_string(), old_path: Some("gone.txt".to_string()), removed: vec![1], added_after: vec![] },
+        ]);
+        assert!(parse_patch("@@ -1 +1 @@\n").unwrap_err().contains("before any"));
+        assert!(parse_patch("--- a/x\n+++ b/x\n@@ -1,2 +1,2 @@\n-one\n").unwrap_err().contains("cut short"));
+        assert!(parse_patch("--- a/x\n+++ b/x\n@@ -1,1 +1,1 @@\n+one\n+two\n").unwrap_err().starts_with("line 5: "));
+    }
+}
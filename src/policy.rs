@@ -0,0 +1,163 @@
+use regex::Regex;
+use std::fs;
+
+use crate::config::{self, parse_toml, TomlValue};
+use crate::filter::glob_to_regex;
+use crate::identity::FORMAT_FIELDS;
+
+//----
+// Policy file
+//
+// A shared service running git-author-stats for other people passes
+// --policy FILE to bound what they can ask for: which repositories may be
+// analyzed, which options and subcommands may be used (say, none that write
+// files or reach other hosts) and which author fields may be shown.  Unlike
+// the config file, a policy is strict: an unknown key is an error, so a typo
+// can't quietly allow everything.
+//
+//     [repositories]
+//     allow = ["/srv/git/*", "/srv/mirrors/**"]
+//
+//     [output]
+//     deny = ["--export-postgres", "--push-gateway", "self update", "annotate"]
+//     author-fields = ["name", "email_domain"]
+
+const KEYS: [&str; 3] = ["repositories.allow", "output.deny", "output.author-fields"];
+
+#[derive(Debug, Clone, Default)]
+pub struct Policy {
+    /// Globs over the top-level directories of the repositories that may be
+    /// analyzed; any repository when None
+    repositories: Option<Vec<Regex>>,
+    /// Options ("--save-model") and subcommands ("annotate", "cache gc") that
+    /// can't be used
+    denied: Vec<String>,
+    /// Author fields that may be shown; all of them when None
+    author_fields: Option<Vec<String>>,
+}
+
+/// Every key of `table`, dotted, and every empty table
+fn keys(table: &config::TomlTable, prefix: &str, out: &mut Vec<String>) {
+    for (key, value) in table {
+        let key = format!("{prefix}{key}");
+        match value {
+            TomlValue::Table(table) if !table.is_empty() => keys(table, &format!("{key}."), out),
+            _ => out.push(key),
+        }
+    }
+}
+
+impl Policy {
+    pub fn parse(text: &str) -> Result<Policy, String> {
+        let doc = parse_toml(text)?;
+        let mut found = Vec::new();
+        keys(&doc, "", &mut found);
+        found.sort();
+        if let Some(key) = found.iter().find(|key| !KEYS.contains(&key.as_str())) {
+            return Err(format!("unknown key '{key}', expected one of: {}", KEYS.join(", ")));
+        }
+
+        let repositories = match config::get(&doc, "repositories.allow") {
+            None => None,
+            Some(_) => Some(config::get_str_list(&doc, "repositories.allow")?.iter()
+                .map(|glob| Regex::new(&glob_to_regex(glob)).map_err(|e| format!("repositories.allow: {glob}: {e}")))
+                .collect::<Result<Vec<Regex>, String>>()?),
+        };
+        let author_fields = match config::get(&doc, "output.author-fields") {
+            None => None,
+            Some(_) => Some(config::get_str_list(&doc, "output.author-fields")?),
+        };
+        if let Some(field) = author_fields.iter().flatten().find(|field| !FORMAT_FIELDS.contains(&field.as_str())) {
+            return Err(format!("output.author-fields: unknown field {field}, expected one of: {}", FORMAT_FIELDS.join(", ")));
+        }
+        return Ok(Policy { repositories, denied: config::get_str_list(&doc, "output.deny")?, author_fields });
+    }
+
+    pub fn load(path: &str) -> Result<Policy, String> {
+        let text = fs::read_to_string(path).map_err(|e| format!("{path}: {e}"))?;
+        return Policy::parse(&text).map_err(|e| format!("{path}: {e}"));
+    }
+
+    /// Whether the repository at `root` (an absolute path) may be analyzed
+    pub fn check_repository(&self, root: &str) -> Result<(), String> {
+        let Some(allowed) = &self.repositories else {
+            return Ok(());
+        };
+        match allowed.iter().any(|re| re.is_match(root.trim_start_matches('/'))) {
+            true => return Ok(()),
+            false => return Err(format!("the policy doesn't allow analyzing {root}")),
+        }
+    }
+
+    /// Check that each denied option or subcommand is one of `known`, so a
+    /// misspelled one isn't quietly allowed
+    pub fn check_denied(&self, known: &[String]) -> Result<(), String> {
+        match self.denied.iter().find(|name| !known.contains(name)) {
+            Some(name) => return Err(format!("output.deny: unknown option or subcommand {name}")),
+            None => return Ok(()),
+        }
+    }
+
+    /// Whether the options and subcommands in `used` may be used; a denied
+    /// subcommand denies its own subcommands too
+    pub fn check_options(&self, used: &[String]) -> Result<(), String> {
+        for name in used {
+            let denied = self.denied.iter().any(|denied| name == denied || name.starts_with(&format!("{denied} ")));
+            if denied {
+                return Err(format!("the policy doesn't allow {name}"));
+            }
+        }
+        return Ok(());
+    }
+
+    /// Whether the author field `field` (see FORMAT_FIELDS) may be shown
+    pub fn allows_field(&self, field: &str) -> bool {
+        match &self.author_fields {
+            Some(fields) => return fields.iter().any(|allowed| allowed == field),
+            None => return true,
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn bounds_repositories_options_and_fields() {
+        let policy = Policy::parse("\
+[repositories]
+allow = [\"/srv/git/*\", \"/home/**/public\"]
+
+[output]
+deny = [\"--save-model\", \"cache\"]
+author-fields = [\"name\", \"email_domain\"]
+").unwrap();
+        assert!(policy.check_repository("/srv/git/web").is_ok());
+        assert!(policy.check_repository("/home/jo/src/public").is_ok());
+        assert!(policy.check_repository("/srv/other").is_err());
+        assert!(policy.check_repository("/srv/gitx/web").is_err());
+
+        let used = |names: &[&str]| names.iter().map(|name| name.to_string()).collect::<Vec<String>>();
+        assert!(policy.check_options(&used(&["--format", "at"])).is_ok());
+        assert_eq!(policy.check_options(&used(&["--format", "--save-model"])), Err("the policy doesn't allow --save-model".to_string()));
+        assert!(policy.check_options(&used(&["cache gc"])).is_err());
+        assert!(policy.allows_field("email_domain") && !policy.allows_field("email"));
+        assert!(policy.check_denied(&used(&["--save-model", "cache", "--format"])).is_ok());
+        assert!(policy.check_denied(&used(&["--save-model"])).is_err());
+
+        let open = Policy::parse("").unwrap();
+        assert!(open.check_repository("/anywhere").is_ok() && open.allows_field("email"));
+        assert!(Policy::parse("[repositories]\nallow = []\n").unwrap().check_repository("/srv/git/web").is_err());
+    }
+
+    #[test]
+    fn rejects_unknown_keys() {
+        assert_eq!(Policy::parse("[repository]\nallow = [\"/srv/*\"]\n").unwrap_err(),
+            "unknown key 'repository.allow', expected one of: repositories.allow, output.deny, output.author-fields");
+        assert_eq!(Policy::parse("[repositorys]\n").unwrap_err(),
+            "unknown key 'repositorys', expected one of: repositories.allow, output.deny, output.author-fields");
+        assert!(Policy::parse("[output]\nauthor-fields = [\"phone\"]\n").is_err());
+        assert!(Policy::parse("[output]\ndeny = \"--save-model\"\n").is_err());
+    }
+}
@@ -0,0 +1,184 @@
+//----
+// Blame porcelain
+//
+// `git blame --line-porcelain` prints a record per line of the file: a header
+// "<commit> <original line> <final line> [<lines in group>]", then "key value"
+// lines (author, author-mail, author-time, summary, boundary, ...) and the
+// line itself after a tab.  The parser is strict about what the counts rely
+// on: a header without a commit id and line numbers, a final line number out
+// of sequence, a missing or unreadable author, mail or time, text outside a
+// record or a record cut short is an error naming the offending line, rather
+// than a record silently dropped or merged into the next.  Keys it doesn't
+// use are let through, since git may add more.
+
+/// One blamed line
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct Record<'a> {
+    /// Commit that last changed the line
+    pub sha: &'a str,
+    /// Line number in the file, from 1
+    pub line: usize,
+    pub name: &'a str,
+    /// Email, without the angle brackets
+    pub mail: &'a str,
+    /// Author date, seconds since the epoch
+    pub time: i64,
+    pub summary: &'a str,
+    /// The commit is a boundary of the blame: the root, or a shallow one
+    pub boundary: bool,
+    /// The line itself
+    pub text: &'a str,
+}
+
+/// Whether `text` is a full SHA-1 or SHA-256 commit id
+fn is_sha(text: &str) -> bool {
+    return (text.len() == 40 || text.len() == 64) && text.bytes().all(|b| b.is_ascii_hexdigit());
+}
+
+/// The commit and final line number of a record's header
+fn header(line: &str) -> Option<(&str, usize)> {
+    let mut fields = line.split(' ');
+    let sha = fields.next().filter(|sha| is_sha(sha))?;
+    let numbers: Vec<usize> = fields.map(|field| field.parse().ok()).collect::<Option<_>>()?;
+    match numbers[..] {
+        [_, line] | [_, line, _] if line > 0 => return Some((sha, line)),
+        _ => return None,
+    }
+}
+
+/// Records of `git blame --line-porcelain` output, in line order, or what's
+/// wrong with it
+pub fn parse(text: &str) -> Result<Vec<Record<'_>>, String> {
+    let mut records = Vec::new();
+    let mut current: Option<(Record, [bool; 3])> = None;
+    for (i, x) in text.lines().enumerate() {
+        let error = |what: &str| format!("line {}: {what}: {x:?}", i + 1);
+        let Some((record, seen)) = current.as_mut() else {
+            let (sha, line) = header(x).ok_or_else(|| error("expected a commit header"))?;
+            if line != records.len() + 1 {
+                return Err(error(&format!("expected line {}", records.len() + 1)));
+            }
+            let record = Record { sha, line, name: "", mail: "", time: 0, summary: "", boundary: false, text: "" };
+            current = Some((record, [false; 3]));
+            continue;
+        };
+        if let Some(text) = x.strip_prefix('\t') {
+            if let Some(missing) = ["author", "author-mail", "author-time"].iter().zip(seen.iter()).find(|(_, seen)| !**seen) {
+                return Err(error(&format!("no {} before the line", missing.0)));
+            }
+            record.text = text;
+            records.push(current.take().unwrap().0);
+            continue;
+        }
+        let (key, value) = x.split_once(' ').unwrap_or((x, ""));
+        match key {
+            "author" => {
+                record.name = value;
+                seen[0] = true;
+            },
+            "author-mail" => {
+                record.mail = value.strip_prefix('<').and_then(|mail| mail.strip_suffix('>')).ok_or_else(|| error("bad author-mail"))?;
+                seen[1] = true;
+            },
+            "author-time" => {
+                record.time = value.parse().map_err(|_| error("bad author-time"))?;
+                seen[2] = true;
+            },
+            "summary" => record.summary = value,
+            "boundary" => record.boundary = true,
+            "" => return Err(error("expected a key")),
+            _ => {},
+        }
+    }
+    if let Some((record, _)) = current {
+        return Err(format!("line {} of the file has no text: the output ends in its record", record.line));
+    }
+    return Ok(records);
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn record(sha: &str, line: usize, author: &str) -> String {
+        return format!("{sha} {line} {line} 1\nauthor {author}\nauthor-mail <{}@example.com>\nauthor-time {line}00\n\
+            author-tz +0000\nsummary Change {line}\nfilename f\n\tline {line}\n", author.to_lowercase());
+    }
+
+    fn sample() -> String {
+        let (alice, bob) = ("a".repeat(40), "b".repeat(40));
+        return [record(&alice, 1, "Alice"), record(&bob, 2, "Bob"), record(&alice, 3, "Alice")].concat();
+    }
+
+    #[test]
+    fn parses_records() {
+        let text = sample();
+        let records = parse(&text).unwrap();
+        assert_eq!(records.len(), 3);
+        let bob = "b".repeat(40);
+        assert_eq!(records[1], Record {
+            sha: &bob,
+            line: 2,
+            name: "Bob",
+            mail: "bob@example.com",
+            time: 200,
+            summary: "Change 2",
+            boundary: false,
+            text: "line 2",
+        });
+        assert!(parse("").unwrap().is_empty());
+    }
+
+    #[test]
+    fn rejects_malformed_output() {
+        let text = sample();
+        let error = |text: &str| parse(text).unwrap_err();
+        // A record lost
+        assert!(error(&text.replacen(&record(&"b".repeat(40), 2, "Bob"), "", 1)).contains("expected line 2"));
+        // Cut short
+        assert!(error(&text[..text.len() - 8]).contains("line 3 of the file has no text"));
+        assert!(error(&text.replace("author-time 200", "author-time soon")).contains("bad author-time"));
+        assert!(error(&text.replace("author-mail <bob@example.com>", "author-mail bob")).contains("bad author-mail"));
+        assert!(error(&text.replace("author Bob\n", "")).contains("no author before the line"));
+        assert!(error(&text.replacen(&"a".repeat(40), "not-a-commit", 1)).starts_with("line 1: expected a commit header"));
+        assert!(error(&format!("stray text\n{text}")).starts_with("line 1:"));
+    }
+
+    /// Next number of a xorshift generator, for repeatable mutations
+    fn next(state: &mut u64) -> u64 {
+        *state ^= *state << 13;
+        *state ^= *state >> 7;
+        *state ^= *state << 17;
+        return *state;
+    }
+
+    #[test]
+    fn mutated_output_never_miscounts() {
+        let sample = sample();
+        let lines: Vec<&str> = sample.lines().collect();
+        let mut state = 0x9e37_79b9_7f4a_7c15;
+        for _ in 0..5000 {
+            let mut mutated: Vec<String> = lines.iter().map(|line| line.to_string()).collect();
+            for _ in 0..=next(&mut state) % 3 {
+                let i = (next(&mut state) % mutated.len() as u64) as usize;
+                let j = (next(&mut state) % mutated.len() as u64) as usize;
+                match next(&mut state) % 5 {
+                    0 => { mutated.remove(i); },
+                    1 => mutated.insert(i, mutated[i].clone()),
+                    2 => mutated[i].truncate((next(&mut state) % 8) as usize),
+                    3 => mutated.swap(i, j),
+                    _ => mutated[i].insert(0, ['\t', ' ', 'x'][(next(&mut state) % 3) as usize]),
+                }
+                if mutated.is_empty() {
+                    break;
+                }
+            }
+            let text = mutated.join("\n");
+            // Either an error, or a record for every line of text in order
+            if let Ok(records) = parse(&text) {
+                assert_eq!(records.len(), mutated.iter().filter(|line| line.starts_with('\t')).count(), "{text}");
+                assert!(records.iter().enumerate().all(|(i, record)| record.line == i + 1), "{text}");
+            }
+        }
+    }
+}
@@ -0,0 +1,275 @@
+use std::collections::HashMap;
+use std::io::Write;
+use std::process::{Command, Stdio};
+
+use crate::analysis::AuthorStats;
+use crate::cli_args::Metric;
+use crate::error::{Error, Result};
+use crate::identity::IdentityResolver;
+
+//----
+// PostgreSQL export
+//
+// --export-postgres URL upserts the counts into normalized tables, so
+// scheduled runs accumulate history in a warehouse without files in between:
+// a repository and an author table, a snapshot per repository, date and
+// metric, and each author's count in a snapshot.  A snapshot analyzed again
+// replaces its counts.  The tool creates and migrates the tables itself,
+// recording the applied steps in git_author_stats_migrations.  Authors are
+// keyed on their email, as in the reports, with their display name beside it.
+// The script runs in one transaction through `psql`, as git runs through
+// `git`, with any password in psql's environment rather than its arguments.
+
+/// Schema changes, in order: the Nth brings the tables to version N.  Add
+/// steps rather than editing the applied ones.
+const MIGRATIONS: [&str; 2] = ["
+    CREATE TABLE git_author_stats_repositories (
+        id serial PRIMARY KEY,
+        name text NOT NULL UNIQUE
+    );
+    CREATE TABLE git_author_stats_authors (
+        id serial PRIMARY KEY,
+        name text NOT NULL UNIQUE
+    );
+    CREATE TABLE git_author_stats_snapshots (
+        id serial PRIMARY KEY,
+        repository_id integer NOT NULL REFERENCES git_author_stats_repositories,
+        date date NOT NULL,
+        metric text NOT NULL,
+        exported_at timestamptz NOT NULL DEFAULT now(),
+        UNIQUE (repository_id, date, metric)
+    );
+    CREATE TABLE git_author_stats_counts (
+        snapshot_id integer NOT NULL REFERENCES git_author_stats_snapshots ON DELETE CASCADE,
+        author_id integer NOT NULL REFERENCES git_author_stats_authors,
+        value bigint NOT NULL,
+        PRIMARY KEY (snapshot_id, author_id)
+    );
+", "
+    -- Authors were keyed on the display name; the names stand in for the
+    -- keys of existing rows, and snapshots exported again move to the emails
+    ALTER TABLE git_author_stats_authors ADD COLUMN key text;
+    UPDATE git_author_stats_authors SET key = name;
+    ALTER TABLE git_author_stats_authors
+        ALTER COLUMN key SET NOT NULL,
+        ADD UNIQUE (key),
+        DROP CONSTRAINT git_author_stats_authors_name_key;
+"];
+
+fn sql_string(text: &str) -> String {
+    return format!("'{}'", text.replace('\'', "''"));
+}
+
+/// Create the tables, or bring them up to date.  A schema newer than this
+/// version knows is an error rather than something to write into blindly.
+fn migration_sql() -> String {
+    let mut sql = String::from("\
+        SET LOCAL client_min_messages = warning;\n\
+        CREATE TABLE IF NOT EXISTS git_author_stats_migrations (version integer PRIMARY KEY, applied_at timestamptz NOT NULL DEFAULT now());\n\
+        LOCK TABLE git_author_stats_migrations IN EXCLUSIVE MODE;\n");
+    sql.push_str(&format!("DO $migrate$ BEGIN\n\
+        IF (SELECT max(version) FROM git_author_stats_migrations) > {0} THEN\n\
+            RAISE EXCEPTION 'the tables are at schema version %, newer than this git-author-stats knows (%)', (SELECT max(version) FROM git_author_stats_migrations), {0};\n\
+        END IF;\n", MIGRATIONS.len()));
+    for (i, migration) in MIGRATIONS.iter().enumerate() {
+        let version = i + 1;
+        sql.push_str(&format!("IF NOT EXISTS (SELECT 1 FROM git_author_stats_migrations WHERE version = {version}) THEN{migration}\
+            INSERT INTO git_author_stats_migrations (version) VALUES ({version});\nEND IF;\n"));
+    }
+    sql.push_str("END $migrate$;\n");
+    return sql;
+}
+
+/// The script that migrates the tables and upserts the counts of each metric
+/// under `repository`, per resolved author
+pub fn export_sql(repository: &str, metrics: &[(Metric, &AuthorStats)], resolver: &IdentityResolver) -> String {
+    let repository = sql_string(repository);
+    let mut sql = migration_sql();
+    sql.push_str(&format!("INSERT INTO git_author_stats_repositories (name) VALUES ({repository}) ON CONFLICT (name) DO NOTHING;\n"));
+    for (metric, stats) in metrics {
+        let metric = sql_string(metric.name());
+        let mut dates: Vec<&String> = stats.authors.keys().collect();
+        if dates.is_empty() {
+            continue;
+        }
+        dates.sort();
+        let names = resolver.display_names(stats.authors.values().flat_map(|acnt| acnt.iter().map(|(a, c)| (a, *c))));
+        // Per canonical key, so two people sharing a name stay apart
+        let mut counts: HashMap<(&str, String), i64> = HashMap::new();
+        for (date, acnt) in &stats.authors {
+            for (author, count) in acnt {
+                *counts.entry((date.as_str(), resolver.resolve(author).author.key())).or_insert(0) += *count as i64;
+            }
+        }
+        let mut counts: Vec<((&str, String), i64)> = counts.into_iter().filter(|(_, count)| *count != 0).collect();
+        counts.sort();
+
+        let dates = dates.iter().map(|date| sql_string(date)).collect::<Vec<String>>();
+        sql.push_str(&format!("INSERT INTO git_author_stats_snapshots (repository_id, date, metric)\n\
+            SELECT r.id, d.date::date, {metric} FROM git_author_stats_repositories r, (VALUES ({})) AS d(date) WHERE r.name = {repository}\n\
+            ON CONFLICT (repository_id, date, metric) DO UPDATE SET exported_at = now();\n", dates.join("), (")));
+        sql.push_str(&format!("DELETE FROM git_author_stats_counts c USING git_author_stats_snapshots s, git_author_stats_repositories r\n\
+            WHERE c.snapshot_id = s.id AND s.repository_id = r.id AND r.name = {repository} AND s.metric = {metric} AND s.date::text IN ({});\n", dates.join(", ")));
+        if counts.is_empty() {
+            continue;
+        }
+        let mut authors: Vec<&str> = counts.iter().map(|((_, key), _)| key.as_str()).collect();
+        authors.sort();
+        authors.dedup();
+        let authors: Vec<String> = authors.iter()
+            .map(|key| format!("{}, {}", sql_string(key), sql_string(names.get(*key).map_or(*key, |name| name.as_str()))))
+            .collect();
+        sql.push_str(&format!("INSERT INTO git_author_stats_authors (key, name) VALUES ({}) ON CONFLICT (key) DO UPDATE SET name = excluded.name;\n", authors.join("), (")));
+        let rows: Vec<String> = counts.iter().map(|((date, key), count)| format!("({}, {}, {count})", sql_string(date), sql_string(key))).collect();
+        sql.push_str(&format!("INSERT INTO git_author_stats_counts (snapshot_id, author_id, value)\n\
+            SELECT s.id, a.id, v.value FROM (VALUES\n{}\n) AS v(date, author, value)\n\
+            JOIN git_author_stats_authors a ON a.key = v.author\n\
+            JOIN git_author_stats_repositories r ON r.name = {repository}\n\
+            JOIN git_author_stats_snapshots s ON s.repository_id = r.id AND s.date = v.date::date AND s.metric = {metric};\n", rows.join(",\n")));
+    }
+    return sql;
+}
+
+/// `url` without its password, and the password: from the user info or the
+/// `password` parameter of a postgresql:// URL, or the `password` keyword of
+/// a `host=... dbname=...` connection string
+pub fn split_password(url: &str) -> (String, Option<String>) {
+    let Some((scheme, rest)) = url.split_once("://") else {
+        let mut password = None;
+        let words: Vec<&str> = url.split_whitespace()
+            .filter(|word| match word.strip_prefix("password=") {
+                Some(value) => {
+                    password = Some(value.trim_matches('\'').to_string());
+                    false
+                },
+                None => true,
+            })
+            .collect();
+        return (words.join(" "), password);
+    };
+    let (authority, path) = rest.find(['/', '?']).map_or((rest, ""), |end| rest.split_at(end));
+    let mut password = None;
+    let authority = match authority.rsplit_once('@') {
+        Some((user_info, host)) => match user_info.split_once(':') {
+            Some((user, secret)) => {
+                password = Some(percent_decode(secret));
+                format!("{user}@{host}")
+            },
+            None => authority.to_string(),
+        },
+        None => authority.to_string(),
+    };
+    let path = match path.split_once('?') {
+        Some((path, query)) => {
+            let params: Vec<&str> = query.split('&')
+                .filter(|param| match param.strip_prefix("password=") {
+                    Some(value) => {
+                        password = Some(percent_decode(value));
+                        false
+                    },
+                    None => true,
+                })
+                .collect();
+            match params.is_empty() {
+                true => path.to_string(),
+                false => format!("{path}?{}", params.join("&")),
+            }
+        },
+        None => path.to_string(),
+    };
+    return (format!("{scheme}://{authority}{path}"), password);
+}
+
+/// Undo the %XX escapes of a URL part
+fn percent_decode(text: &str) -> String {
+    let bytes = text.as_bytes();
+    let mut decoded = Vec::with_capacity(bytes.len());
+    let mut i = 0;
+    while i < bytes.len() {
+        let hex = text.get(i + 1..i + 3).and_then(|hex| u8::from_str_radix(hex, 16).ok());
+        match (bytes[i], hex) {
+            (b'%', Some(byte)) => {
+                decoded.push(byte);
+                i += 3;
+            },
+            (byte, _) => {
+                decoded.push(byte);
+                i += 1;
+            },
+        }
+    }
+    return String::from_utf8_lossy(&decoded).to_string();
+}
+
+/// Run `sql` against the database at `url` in one transaction with psql.  A
+/// password in `url` goes to psql as PGPASSWORD, out of sight of `ps`.
+pub fn export(url: &str, sql: &str) -> Result<()> {
+    let failed = |message: String| Error::Export { target: "PostgreSQL".to_string(), message };
+    let (url, password) = split_password(url);
+    let mut command = Command::new("psql");
+    if let Some(password) = password {
+        command.env("PGPASSWORD", password);
+    }
+    let mut child = command
+        .arg("--no-psqlrc")
+        .arg("--quiet")
+        .arg("--single-transaction")
+        .arg("--set=ON_ERROR_STOP=1")
+        .arg(format!("--dbname={url}"))
+        .stdin(Stdio::piped())
+        .stdout(Stdio::null())
+        .stderr(Stdio::piped())
+        .spawn()
+        .map_err(|e| failed(format!("failed to start psql: {e}")))?;
+    let mut stdin = child.stdin.take().expect("stdin is piped");
+    let input = sql.to_string();
+    // Write from another thread so a full stderr pipe can't deadlock us
+    let writer = std::thread::spawn(move || stdin.write_all(input.as_bytes()));
+    let out = child.wait_with_output()?;
+    let _ = writer.join();
+    match out.status.success() {
+        true => return Ok(()),
+        false => return Err(failed(String::from_utf8_lossy(&out.stderr).trim().to_string())),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::identity::Author;
+    use crate::{AuthorCount, AuthorPerformance};
+
+    #[test]
+    fn writes_upserts() {
+        let mut authors = AuthorPerformance::new();
+        authors.insert("2020-02-01".to_string(), AuthorCount::from([(Author::new("Alice", "alice@example.com"), 5)]));
+        authors.insert("2020-03-01".to_string(), AuthorCount::from([
+            (Author::new("alice", "ALICE@example.com"), 4),
+            (Author::new("O'Brien", "ob@example.com"), 3),
+            (Author::new("Gone", "gone@example.com"), 0),
+            (Author::new("Alice", "alice@other.org"), 2),
+        ]));
+        let stats = AuthorStats { authors, ..Default::default() };
+        let sql = export_sql("web app", &[(Metric::Lines, &stats)], &IdentityResolver::default());
+
+        assert!(sql.contains("\nCREATE TABLE IF NOT EXISTS git_author_stats_migrations"));
+        assert!(sql.contains("IF NOT EXISTS (SELECT 1 FROM git_author_stats_migrations WHERE version = 1) THEN"));
+        assert!(sql.contains("IF NOT EXISTS (SELECT 1 FROM git_author_stats_migrations WHERE version = 2) THEN"));
+        assert!(sql.contains("VALUES ('web app') ON CONFLICT (name) DO NOTHING"));
+        assert!(sql.contains("(VALUES ('2020-02-01'), ('2020-03-01')) AS d(date)"));
+        // Keyed on the email: the two Alices stay apart
+        assert!(sql.contains("VALUES ('alice@example.com', 'Alice'), ('alice@other.org', 'Alice'), ('ob@example.com', 'O''Brien') ON CONFLICT (key) DO UPDATE"));
+        assert!(sql.contains("('2020-02-01', 'alice@example.com', 5),\n('2020-03-01', 'alice@example.com', 4),\n('2020-03-01', 'alice@other.org', 2),\n('2020-03-01', 'ob@example.com', 3)\n)"));
+        assert!(!sql.contains("Gone"));
+    }
+
+    #[test]
+    fn hides_passwords() {
+        assert_eq!(split_password("postgresql://app:s%40cret@db:5432/stats?sslmode=require"),
+            ("postgresql://app@db:5432/stats?sslmode=require".to_string(), Some("s@cret".to_string())));
+        assert_eq!(split_password("postgres://db/stats?password=pw&sslmode=require"),
+            ("postgres://db/stats?sslmode=require".to_string(), Some("pw".to_string())));
+        assert_eq!(split_password("postgresql://app@db/stats"), ("postgresql://app@db/stats".to_string(), None));
+        assert_eq!(split_password("host=db password='pw' dbname=stats"), ("host=db dbname=stats".to_string(), Some("pw".to_string())));
+    }
+}
@@ -0,0 +1,147 @@
+use std::io::Write;
+use std::process::{Command, Stdio};
+
+use crate::analysis::AuthorStats;
+use crate::activity;
+use crate::cli_args::Metric;
+use crate::error::{Error, Result};
+use crate::identity::IdentityResolver;
+
+//----
+// Prometheus Pushgateway
+//
+// --push-gateway URL pushes each author's count at the latest snapshot, one
+// gauge per metric, for CI runners that can't leave files for a scraper.
+// The push replaces the repository's group (job "git-author-stats", label
+// "repository"), so authors who dropped out don't linger.  It runs `curl`,
+// as git runs through `git`.
+
+const JOB: &str = "git-author-stats";
+
+fn help(metric: Metric) -> &'static str {
+    match metric {
+        Metric::Lines => return "Lines each author owns at the latest snapshot, per git blame",
+        Metric::Commits => return "Non-merge commits each author authored in the period before the latest snapshot",
+        Metric::Added => return "Lines each author added in the period before the latest snapshot",
+        Metric::Deleted => return "Lines each author deleted in the period before the latest snapshot",
+        Metric::Churn => return "Lines each author added plus deleted in the period before the latest snapshot",
+        Metric::Effort => return "Estimated hours each author worked in the period before the latest snapshot",
+    }
+}
+
+/// A label value, quoted and escaped
+fn label(value: &str) -> String {
+    return format!("\"{}\"", value.replace('\\', "\\\\").replace('"', "\\\"").replace('\n', "\\n"));
+}
+
+/// The text exposition of each metric's counts at its latest snapshot, and
+/// the time of that snapshot
+pub fn render(metrics: &[(Metric, &AuthorStats)], resolver: &IdentityResolver) -> String {
+    let mut out = String::new();
+    let mut snapshots = Vec::new();
+    for (metric, stats) in metrics {
+        let Some((date, acnt)) = stats.authors.iter().max_by_key(|(date, _)| *date) else {
+            continue;
+        };
+        let name = format!("git_author_stats_{}", metric.name());
+        let names = resolver.display_names(acnt.iter().map(|(a, c)| (a, *c)));
+        let mut counts: Vec<(String, i64)> = resolver.count_by_name(acnt, &names).into_iter().collect();
+        counts.sort();
+        out.push_str(&format!("# HELP {name} {}\n# TYPE {name} gauge\n", help(*metric)));
+        for (author, count) in counts {
+            out.push_str(&format!("{name}{{author={}}} {count}\n", label(&author)));
+        }
+        snapshots.push((metric.name(), activity::date_timestamp(date).unwrap_or(0)));
+    }
+    if !snapshots.is_empty() {
+        let name = "git_author_stats_snapshot_timestamp_seconds";
+        out.push_str(&format!("# HELP {name} Start of the latest snapshot's date, per metric\n# TYPE {name} gauge\n"));
+        for (metric, time) in snapshots {
+            out.push_str(&format!("{name}{{metric={}}} {time}\n", label(metric)));
+        }
+    }
+    return out;
+}
+
+/// URL-safe base64, for label values in a push URL
+fn base64(data: &[u8]) -> String {
+    const DIGITS: &[u8; 64] = b"ABCDEFGHIJKLMNOPQRSTUVWXYZabcdefghijklmnopqrstuvwxyz0123456789-_";
+    let mut out = String::new();
+    for chunk in data.chunks(3) {
+        let n = chunk.iter().enumerate().fold(0u32, |n, (i, &b)| n | (b as u32) << (16 - 8 * i));
+        for i in 0..=chunk.len() {
+            out.push(DIGITS[(n >> (18 - 6 * i) & 63) as usize] as char);
+        }
+        (chunk.len()..3).for_each(|_| out.push('='));
+    }
+    return out;
+}
+
+/// Where to push the metrics of `repository`
+pub fn push_url(gateway: &str, repository: &str) -> String {
+    // Base64, so any name fits in a path segment
+    return format!("{}/metrics/job/{JOB}/repository@base64/{}", gateway.trim_end_matches('/'), base64(repository.as_bytes()));
+}
+
+/// Replace the metrics of `repository` on the Pushgateway at `gateway`
+pub fn push(gateway: &str, repository: &str, body: &str) -> Result<()> {
+    let failed = |message: String| Error::Export { target: "the Pushgateway".to_string(), message };
+    let mut child = Command::new("curl")
+        .arg("--silent")
+        .arg("--show-error")
+        .arg("--fail")
+        .arg("--request").arg("PUT")
+        .arg("--header").arg("Content-Type: text/plain; version=0.0.4")
+        .arg("--data-binary").arg("@-")
+        .arg(push_url(gateway, repository))
+        .stdin(Stdio::piped())
+        .stdout(Stdio::null())
+        .stderr(Stdio::piped())
+        .spawn()
+        .map_err(|e| failed(format!("failed to start curl: {e}")))?;
+    let mut stdin = child.stdin.take().expect("stdin is piped");
+    let input = body.to_string();
+    // Write from another thread so a full stderr pipe can't deadlock us
+    let writer = std::thread::spawn(move || stdin.write_all(input.as_bytes()));
+    let out = child.wait_with_output()?;
+    let _ = writer.join();
+    match out.status.success() {
+        true => return Ok(()),
+        false => return Err(failed(String::from_utf8_lossy(&out.stderr).trim().to_string())),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::identity::Author;
+    use crate::{AuthorCount, AuthorPerformance};
+
+    #[test]
+    fn renders_latest_counts() {
+        let mut authors = AuthorPerformance::new();
+        authors.insert("2020-02-01".to_string(), AuthorCount::from([(Author::new("Alice", "alice@example.com"), 5)]));
+        authors.insert("2020-03-01".to_string(), AuthorCount::from([
+            (Author::new("Alice", "alice@example.com"), 4),
+            (Author::new("Bob \"B\"", "bob@example.com"), 3),
+        ]));
+        let stats = AuthorStats { authors, ..Default::default() };
+        let text = render(&[(Metric::Lines, &stats)], &IdentityResolver::default());
+        let samples: Vec<&str> = text.lines().filter(|line| !line.starts_with('#')).collect();
+        assert_eq!(samples, [
+            "git_author_stats_lines{author=\"Alice\"} 4",
+            "git_author_stats_lines{author=\"Bob \\\"B\\\"\"} 3",
+            &format!("git_author_stats_snapshot_timestamp_seconds{{metric=\"lines\"}} {}", activity::date_timestamp("2020-03-01").unwrap()),
+        ]);
+        assert!(text.starts_with("# HELP git_author_stats_lines Lines each author owns"));
+        assert!(text.contains("\n# TYPE git_author_stats_lines gauge\n"));
+    }
+
+    #[test]
+    fn encodes_repository_in_url() {
+        assert_eq!(base64(b"web"), "d2Vi");
+        assert_eq!(base64(b"a/b"), "YS9i");
+        assert_eq!(base64(b"ab"), "YWI=");
+        assert_eq!(push_url("http://gw:9091/", "org/app"), "http://gw:9091/metrics/job/git-author-stats/repository@base64/b3JnL2FwcA==");
+    }
+}
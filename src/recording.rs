@@ -0,0 +1,221 @@
+use std::env;
+use std::fs;
+use std::io::{self, Write};
+use std::path::{Path, PathBuf};
+use std::process::{Command, ExitStatus, Output, Stdio};
+use std::sync::OnceLock;
+
+use crate::at_rest::{self, AtRest};
+use crate::cache::fingerprint;
+
+//----
+// Recording git
+//
+// --record-git DIR saves every git command the run executes, with its
+// arguments, input and output, and the repo files read from disk (config,
+// .mailmap) to DIR; --replay-git DIR runs again with the same arguments from
+// the recording alone, without git or the repo.  That reproduces a user's
+// numbers on a maintainer's machine.  Each command is a file named after a
+// hash of its arguments and input, so commands can run in any order and
+// several processes (--isolate-snapshots) can share a recording.  The working
+// directory and the scratch directory don't count, being different every run.
+// With --encrypt-at-rest the recording is written through at_rest::write like
+// everything else, so replaying it needs the same key.
+
+const CWD_FILE: &str = "cwd";
+const HEADER: &[u8] = b"git-author-stats recording 1\n";
+
+#[derive(Debug)]
+enum Mode {
+    Record { dir: PathBuf, at_rest: Option<AtRest> },
+    Replay { dir: PathBuf, cwd: PathBuf, at_rest: Option<AtRest> },
+}
+
+static MODE: OnceLock<Mode> = OnceLock::new();
+
+/// Record the run's git commands to `dir`, encrypted if `at_rest` is given
+pub fn record(dir: &Path, at_rest: Option<&AtRest>) -> Result<(), String> {
+    let cwd = env::current_dir().map_err(|e| format!("--record-git: {e}"))?;
+    fs::create_dir_all(dir).map_err(|e| format!("--record-git {}: {e}", dir.display()))?;
+    write_atomically(&dir.join(CWD_FILE), cwd.to_string_lossy().as_bytes(), at_rest).map_err(|e| format!("--record-git {}: {e}", dir.display()))?;
+    let _ = MODE.set(Mode::Record { dir: dir.to_path_buf(), at_rest: at_rest.cloned() });
+    return Ok(());
+}
+
+/// Answer the run's git commands from the recording in `dir`, which needs
+/// `at_rest` if it was encrypted
+pub fn replay(dir: &Path, at_rest: Option<&AtRest>) -> Result<(), String> {
+    let cwd = match at_rest::read(&dir.join(CWD_FILE), at_rest) {
+        Ok(cwd) => String::from_utf8_lossy(&cwd).to_string(),
+        Err(e) if e.kind() == io::ErrorKind::NotFound => return Err(format!("--replay-git {}: not a recording made with --record-git", dir.display())),
+        Err(e) => return Err(format!("--replay-git {}: {e}", dir.display())),
+    };
+    let _ = MODE.set(Mode::Replay { dir: dir.to_path_buf(), cwd: PathBuf::from(cwd), at_rest: at_rest.cloned() });
+    return Ok(());
+}
+
+pub fn replaying() -> bool {
+    return matches!(MODE.get(), Some(Mode::Replay { .. }));
+}
+
+/// The working directory, or the recorded one when replaying
+pub fn current_dir() -> io::Result<PathBuf> {
+    match MODE.get() {
+        Some(Mode::Replay { cwd, .. }) => return Ok(cwd.clone()),
+        _ => return env::current_dir(),
+    }
+}
+
+/// The arguments identifying `cmd`, the program first, with paths in the
+/// scratch directory blanked
+fn arguments(cmd: &Command) -> Vec<String> {
+    let scratch = env::temp_dir().join("git-author-stats-").to_string_lossy().to_string();
+    return [cmd.get_program()].into_iter().chain(cmd.get_args())
+        .map(|arg| arg.to_string_lossy().to_string())
+        .map(|arg| match arg.starts_with(&scratch) {
+            true => "<scratch>".to_string(),
+            false => arg,
+        })
+        .collect();
+}
+
+fn section(out: &mut Vec<u8>, name: &str, data: &[u8]) {
+    out.extend_from_slice(format!("{name} {}\n", data.len()).as_bytes());
+    out.extend_from_slice(data);
+    out.push(b'\n');
+}
+
+/// The next `name` section of `data`, and the rest
+fn read_section<'a>(data: &'a [u8], name: &str) -> Option<(&'a [u8], &'a [u8])> {
+    let end = data.iter().position(|&b| b == b'\n')?;
+    let header = std::str::from_utf8(&data[..end]).ok()?;
+    let len: usize = header.strip_prefix(name)?.strip_prefix(' ')?.parse().ok()?;
+    let body = data.get(end + 1..end + 1 + len)?;
+    return Some((body, data.get(end + 2 + len..).unwrap_or(&[])));
+}
+
+fn encode(key: &[u8], code: i32, out: &Output) -> Vec<u8> {
+    let mut data = HEADER.to_vec();
+    section(&mut data, "key", key);
+    section(&mut data, "status", code.to_string().as_bytes());
+    section(&mut data, "stdout", &out.stdout);
+    section(&mut data, "stderr", &out.stderr);
+    return data;
+}
+
+/// The exit code and output recorded in `data`, if it's the recording of `key`
+fn decode(data: &[u8], key: &[u8]) -> Option<(i32, Vec<u8>, Vec<u8>)> {
+    let data = data.strip_prefix(HEADER)?;
+    let (recorded, data) = read_section(data, "key")?;
+    if recorded != key {
+        return None;
+    }
+    let (code, data) = read_section(data, "status")?;
+    let (stdout, data) = read_section(data, "stdout")?;
+    let (stderr, _) = read_section(data, "stderr")?;
+    return Some((std::str::from_utf8(code).ok()?.parse().ok()?, stdout.to_vec(), stderr.to_vec()));
+}
+
+#[cfg(unix)]
+fn exit_status(code: i32) -> ExitStatus {
+    use std::os::unix::process::ExitStatusExt;
+    return ExitStatus::from_raw(code << 8);
+}
+
+#[cfg(windows)]
+fn exit_status(code: i32) -> ExitStatus {
+    use std::os::windows::process::ExitStatusExt;
+    return ExitStatus::from_raw(code as u32);
+}
+
+/// Write through a temporary file, so processes sharing a recording never
+/// read half of one
+fn write_atomically(path: &Path, data: &[u8], at_rest: Option<&AtRest>) -> io::Result<()> {
+    let temp = path.with_extension(format!("tmp-{}", std::process::id()));
+    at_rest::write(&temp, data, at_rest)?;
+    return fs::rename(&temp, path);
+}
+
+/// Run `cmd` to completion, feeding it `stdin`, or replay its recorded output
+pub fn output(cmd: &mut Command, stdin: Option<Vec<u8>>) -> io::Result<Output> {
+    let key = match MODE.get() {
+        None => Vec::new(),
+        Some(_) => {
+            let mut key = arguments(cmd).join("\0").into_bytes();
+            key.push(b'\x01');
+            key.extend(stdin.iter().flatten());
+            key
+        },
+    };
+    if let Some(Mode::Replay { dir, at_rest, .. }) = MODE.get() {
+        let path = dir.join(format!("git-{}", fingerprint(&key)));
+        let not_recorded = || io::Error::new(io::ErrorKind::NotFound, format!("not in the recording {}: {}", dir.display(), arguments(cmd).join(" ")));
+        let data = match at_rest::read(&path, at_rest.as_ref()) {
+            Ok(data) => data,
+            Err(e) if e.kind() == io::ErrorKind::NotFound => return Err(not_recorded()),
+            Err(e) => return Err(e),
+        };
+        let (code, stdout, stderr) = decode(&data, &key).ok_or_else(not_recorded)?;
+        return Ok(Output { status: exit_status(code), stdout, stderr });
+    }
+
+    let out = match stdin {
+        None => cmd.stdin(Stdio::null()).output()?,
+        Some(input) => {
+            let mut child = cmd.stdin(Stdio::piped()).stdout(Stdio::piped()).stderr(Stdio::piped()).spawn()?;
+            let mut pipe = child.stdin.take().expect("stdin is piped");
+            // Write from another thread so a full stdout pipe can't deadlock us
+            let writer = std::thread::spawn(move || pipe.write_all(&input));
+            let out = child.wait_with_output()?;
+            let _ = writer.join();
+            out
+        },
+    };
+    if let Some(Mode::Record { dir, at_rest }) = MODE.get() {
+        let code = out.status.code().unwrap_or(-1);
+        write_atomically(&dir.join(format!("git-{}", fingerprint(&key))), &encode(&key, code, &out), at_rest.as_ref())?;
+    }
+    return Ok(out);
+}
+
+/// Read a file of the repo, recording or replaying it like git's output
+pub fn read_to_string(path: &Path) -> io::Result<String> {
+    let key = format!("file\0{}", path.display()).into_bytes();
+    let name = |dir: &Path| dir.join(format!("file-{}", fingerprint(&key)));
+    match MODE.get() {
+        Some(Mode::Replay { dir, at_rest, .. }) => {
+            let data = match at_rest::read(&name(dir), at_rest.as_ref()) {
+                Ok(data) => data,
+                Err(e) if e.kind() == io::ErrorKind::NotFound => return Err(io::Error::new(io::ErrorKind::NotFound, "not in the recording")),
+                Err(e) => return Err(e),
+            };
+            match decode(&data, &key) {
+                Some((0, text, _)) => return String::from_utf8(text).map_err(|e| io::Error::new(io::ErrorKind::InvalidData, e)),
+                _ => return Err(io::Error::new(io::ErrorKind::NotFound, "not in the recording")),
+            }
+        },
+        Some(Mode::Record { dir, at_rest }) => {
+            let text = fs::read_to_string(path);
+            let recorded = Output { status: exit_status(0), stdout: text.as_ref().map(|t| t.as_bytes().to_vec()).unwrap_or_default(), stderr: Vec::new() };
+            write_atomically(&name(dir), &encode(&key, if text.is_ok() { 0 } else { 1 }, &recorded), at_rest.as_ref())?;
+            return text;
+        },
+        None => return fs::read_to_string(path),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn round_trips_outputs() {
+        let key = b"git\0log\0-n1\x01input";
+        let out = Output { status: exit_status(3), stdout: b"a\nb\n".to_vec(), stderr: b"oops".to_vec() };
+        let data = encode(key, 3, &out);
+        assert_eq!(decode(&data, key), Some((3, b"a\nb\n".to_vec(), b"oops".to_vec())));
+        assert_eq!(decode(&data, b"git\0log\x01"), None);
+        assert_eq!(decode(&data[..data.len() - 3], key), None);
+        assert_eq!(exit_status(3).code(), Some(3));
+    }
+}
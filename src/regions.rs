@@ -0,0 +1,197 @@
+use hashbrown::{HashMap, HashSet};
+
+use crate::backend::Backend;
+use crate::error::Result;
+use crate::identity::{Author, IdentityResolver, OptOut};
+use crate::report::json_string;
+use crate::Count;
+
+//----
+// Region ownership
+//
+// `regions` reads `path:start-end` line ranges on stdin, as coverage and
+// static-analysis tools can list them, and tells who owns exactly those lines
+// at one revision, all regions together: "who owns our uncovered code".  Lines
+// are numbered from 1, ranges include both ends, and a line listed twice
+// counts once.  Owners are resolved like in the reports, opt-outs included.
+
+/// Lines `start` to `end` of `path`, relative to the repo's top level
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct Region {
+    pub path: String,
+    pub start: usize,
+    pub end: usize,
+}
+
+/// `path:start-end` or `path:line` lines, skipping blank ones and `#` comments
+pub fn parse_regions(text: &str) -> std::result::Result<Vec<Region>, String> {
+    let mut regions = Vec::new();
+    for (i, line) in text.lines().enumerate() {
+        let line = line.trim();
+        if line.is_empty() || line.starts_with('#') {
+            continue;
+        }
+        let bad = || format!("line {}: expected PATH:START-END, got {line:?}", i + 1);
+        let (path, range) = line.rsplit_once(':').ok_or_else(bad)?;
+        let (start, end) = range.split_once('-').unwrap_or((range, range));
+        let start: usize = start.trim().parse().map_err(|_| bad())?;
+        let end: usize = end.trim().parse().map_err(|_| bad())?;
+        if path.is_empty() || start == 0 || end < start {
+            return Err(bad());
+        }
+        regions.push(Region { path: path.to_string(), start, end });
+    }
+    return Ok(regions);
+}
+
+#[derive(Debug, Clone, PartialEq)]
+pub struct RegionOwner {
+    pub author: String,
+    /// Canonical email; empty for opted-out authors
+    pub email: String,
+    pub lines: Count,
+}
+
+#[derive(Debug, Clone, Default, PartialEq)]
+pub struct RegionOwnership {
+    /// Full commit id the regions were blamed at
+    pub revision: String,
+    /// Most lines first
+    pub owners: Vec<RegionOwner>,
+    pub lines: Count,
+    /// Files of the regions missing at the revision, or binary
+    pub missing: Vec<String>,
+    /// Lines of the regions past the end of their file
+    pub past_end: Count,
+    /// The regions reaching past the end of their file, with its line count
+    pub overruns: Vec<(Region, usize)>,
+}
+
+/// Blame the lines of `regions` at `revision`
+pub fn region_owners(backend: &dyn Backend, revision: &str, regions: &[Region], resolver: &IdentityResolver, opt_out: &OptOut) -> Result<RegionOwnership> {
+    let mut by_path: Vec<(&str, Vec<&Region>)> = Vec::new();
+    for region in regions {
+        match by_path.iter_mut().find(|(path, _)| *path == region.path) {
+            Some((_, of_path)) => of_path.push(region),
+            None => by_path.push((&region.path, vec![region])),
+        }
+    }
+
+    let paths: Vec<String> = by_path.iter().map(|(path, _)| path.to_string()).collect();
+    let present: HashSet<String> = backend.files(revision, &paths)?.into_iter().map(|entry| entry.path).collect();
+    let mut ownership = RegionOwnership { revision: revision.to_string(), ..RegionOwnership::default() };
+    let mut counts: HashMap<Author, Count> = HashMap::new();
+    for (path, of_path) in by_path {
+        let blamed = match present.contains(path) {
+            true => backend.blame_lines(revision, path)?,
+            false => Vec::new(),
+        };
+        if blamed.is_empty() {
+            ownership.missing.push(path.to_string());
+            continue;
+        }
+        let mut listed = vec![false; blamed.len()];
+        let mut past_end = 0;
+        for region in of_path {
+            if region.end > blamed.len() {
+                ownership.overruns.push(((*region).clone(), blamed.len()));
+            }
+            for line in region.start..=region.end {
+                match listed.get_mut(line - 1) {
+                    Some(flag) => *flag = true,
+                    None => past_end = past_end.max(line - blamed.len()),
+                }
+            }
+        }
+        ownership.past_end += past_end as Count;
+        for line in blamed.iter().zip(listed).filter(|(_, listed)| *listed).map(|(line, _)| line) {
+            let author = match opt_out.opted_out(&line.author, resolver) {
+                true => OptOut::aggregate(),
+                false => line.author.clone(),
+            };
+            *counts.entry(author).or_insert(0) += 1;
+        }
+    }
+
+    let names = resolver.display_names(counts.iter().map(|(author, count)| (author, *count)));
+    let mut by_key: HashMap<String, RegionOwner> = HashMap::new();
+    for (raw, count) in counts {
+        let canonical = resolver.resolve(&raw).author;
+        let key = canonical.key();
+        let owner = by_key.entry(key.clone()).or_insert_with(|| RegionOwner {
+            author: names.get(&key).cloned().unwrap_or(canonical.name.clone()),
+            email: canonical.email.clone(),
+            lines: 0,
+        });
+        owner.lines += count;
+        ownership.lines += count;
+    }
+    ownership.owners = by_key.into_values().collect();
+    ownership.owners.sort_by(|a, b| b.lines.cmp(&a.lines).then_with(|| a.author.cmp(&b.author)));
+    return Ok(ownership);
+}
+
+fn share(lines: Count, total: Count) -> f64 {
+    return if total > 0 { 100.0 * lines as f64 / total as f64 } else { 0.0 };
+}
+
+pub fn render_text(ownership: &RegionOwnership) -> String {
+    let width = ownership.owners.iter().map(|o| o.author.len()).max().unwrap_or(0).max("author".len());
+    let mut out = format!("{:<width$}  {:>8}  {:>6}\n", "author", "lines", "share");
+    for owner in &ownership.owners {
+        out.push_str(&format!("{:<width$}  {:>8}  {:5.1}%\n", owner.author, owner.lines, share(owner.lines, ownership.lines)));
+    }
+    out.push_str(&format!("{:<width$}  {:>8}\n", "total", ownership.lines));
+    return out;
+}
+
+pub fn render_json(ownership: &RegionOwnership) -> String {
+    let owners: Vec<String> = ownership.owners.iter().map(|o| {
+        format!("    {{\"author\": {}, \"email\": {}, \"lines\": {}, \"share\": {:.1}}}",
+            json_string(&o.author), json_string(&o.email), o.lines, share(o.lines, ownership.lines))
+    }).collect();
+    let missing: Vec<String> = ownership.missing.iter().map(|path| json_string(path)).collect();
+    let mut out = String::from("{\n");
+    out.push_str(&format!("  \"revision\": {},\n", json_string(&ownership.revision)));
+    out.push_str(&format!("  \"lines\": {},\n", ownership.lines));
+    out.push_str(&format!("  \"missing\": [{}],\n", missing.join(", ")));
+    out.push_str(&format!("  \"past_end\": {},\n", ownership.past_end));
+    match owners.is_empty() {
+        true => out.push_str("  \"owners\": []\n"),
+        false => out.push_str(&format!("  \"owners\": [\n{}\n  ]\n", owners.join(",\n"))),
+    }
+    out.push('}');
+    return out;
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::fixture::{Fixture, ALICE, BOB};
+
+    #[test]
+    fn owns_the_listed_lines() {
+        let regions = parse_regions("# uncovered\nsrc/main.rs:2-4\n\nsrc/main.rs:3\nsrc/main.rs:5-7\nno/such.rs:1-2\n").unwrap();
+        assert_eq!(regions[0], Region { path: "src/main.rs".to_string(), start: 2, end: 4 });
+        assert_eq!(regions[1], Region { path: "src/main.rs".to_string(), start: 3, end: 3 });
+        assert!(parse_regions("src/main.rs:4-2").unwrap_err().starts_with("line 1: "));
+        assert!(parse_regions("\nsrc/main.rs").unwrap_err().starts_with("line 2: "));
+
+        let fixture = Fixture::new();
+        let backend = fixture.backend();
+        let revision = backend.revision(&None, &None).unwrap().unwrap();
+        let resolver = IdentityResolver::default();
+        let ownership = region_owners(backend.as_ref(), &revision, &regions, &resolver, &OptOut::default()).unwrap();
+        // Alice owns lines 1-3 and Bob 4-5, so 2-5 is two lines each
+        let owners: Vec<(&str, Count)> = ownership.owners.iter().map(|o| (o.email.as_str(), o.lines)).collect();
+        assert_eq!(owners, [(ALICE.1, 2), (BOB.1, 2)]);
+        assert_eq!((ownership.lines, ownership.past_end), (4, 2));
+        assert_eq!(ownership.overruns, [(regions[2].clone(), 5)]);
+        assert_eq!(ownership.missing, ["no/such.rs"]);
+        assert!(render_json(&ownership).contains("\"owners\": [\n    {\"author\": \"Alice\", \"email\": \"alice@example.com\", \"lines\": 2, \"share\": 50.0},"));
+
+        let opt_out = OptOut::new(&[BOB.1.to_string()]).unwrap();
+        let ownership = region_owners(backend.as_ref(), &revision, &regions, &resolver, &opt_out).unwrap();
+        assert_eq!(ownership.owners[1].author, "Opted-out");
+    }
+}
@@ -0,0 +1,297 @@
+use std::cmp::Ordering;
+use std::env;
+use std::io;
+#[cfg(feature = "self-update")]
+use std::fs;
+use std::path::{Path, PathBuf};
+use std::process::Command;
+
+use crate::json::{self, Value};
+
+//----
+// Releases
+//
+// `self check` compares this binary with the latest GitHub release, and
+// `self update` (built with the self-update feature) installs it over this
+// binary, for build servers without cargo.  Each release carries a binary per
+// platform, `git-author-stats-<arch>-<os>`, with its SHA-256 in a `.sha256`
+// file next to it and, optionally, a signature in a `.sig` file.  curl
+// downloads and openssl checks them, as git runs through `git`.
+
+/// Where releases are published
+const REPO: &str = "bobsrentacow/git-author-stats";
+
+/// Environment variable overriding the URL of the latest release's JSON, for
+/// mirrors
+pub const RELEASES_ENV: &str = "GIT_AUTHOR_STATS_RELEASE_URL";
+
+pub const VERSION: &str = env!("CARGO_PKG_VERSION");
+
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct Release {
+    pub tag: String,
+    /// Name and download URL of each file
+    pub assets: Vec<(String, String)>,
+}
+
+impl Release {
+    /// The download URL of the file `name`
+    pub fn asset(&self, name: &str) -> io::Result<&str> {
+        return self.assets.iter().find(|(asset, _)| asset == name).map(|(_, url)| url.as_str())
+            .ok_or_else(|| io::Error::new(io::ErrorKind::NotFound, format!("release {} has no {name}", self.tag)));
+    }
+}
+
+/// The release in a GitHub API response
+pub fn parse_release(text: &str) -> Result<Release, String> {
+    let Value::Object(fields) = json::parse(text)? else {
+        return Err("expected a JSON object".to_string());
+    };
+    let field = |name: &str| fields.iter().find(|(key, _)| key == name).map(|(_, value)| value);
+    let Some(Value::String(tag)) = field("tag_name") else {
+        return Err("no tag_name".to_string());
+    };
+    let mut assets = Vec::new();
+    if let Some(Value::Array(items)) = field("assets") {
+        for item in items {
+            let Value::Object(asset) = item else {
+                continue;
+            };
+            let text = |name: &str| asset.iter().find_map(|(key, value)| match value {
+                Value::String(text) if key == name => Some(text.clone()),
+                _ => None,
+            });
+            if let (Some(name), Some(url)) = (text("name"), text("browser_download_url")) {
+                assets.push((name, url));
+            }
+        }
+    }
+    return Ok(Release { tag: tag.clone(), assets });
+}
+
+/// A version or tag such as v1.2.3 or v1.2.3-rc.1, ordered as semver orders
+/// them: a release comes after its pre-releases, and build metadata (+...)
+/// doesn't count
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct Version {
+    /// Major, minor and patch
+    pub numbers: (u64, u64, u64),
+    /// The dot-separated pre-release identifiers, empty for a release
+    pub pre: Vec<String>,
+}
+
+impl Ord for Version {
+    fn cmp(&self, other: &Self) -> Ordering {
+        let numbers = self.numbers.cmp(&other.numbers);
+        if numbers != Ordering::Equal {
+            return numbers;
+        }
+        match (self.pre.is_empty(), other.pre.is_empty()) {
+            (true, true) => return Ordering::Equal,
+            (true, false) => return Ordering::Greater,
+            (false, true) => return Ordering::Less,
+            (false, false) => {},
+        }
+        for (a, b) in self.pre.iter().zip(&other.pre) {
+            // Numeric identifiers compare as numbers and come before the rest
+            let order = match (a.parse::<u64>(), b.parse::<u64>()) {
+                (Ok(x), Ok(y)) => x.cmp(&y).then_with(|| a.cmp(b)),
+                (Ok(_), Err(_)) => Ordering::Less,
+                (Err(_), Ok(_)) => Ordering::Greater,
+                (Err(_), Err(_)) => a.cmp(b),
+            };
+            if order != Ordering::Equal {
+                return order;
+            }
+        }
+        return self.pre.len().cmp(&other.pre.len());
+    }
+}
+
+impl PartialOrd for Version {
+    fn partial_cmp(&self, other: &Self) -> Option<Ordering> {
+        return Some(self.cmp(other));
+    }
+}
+
+/// The version of a tag such as v1.2.3 or v1.2.3-rc.1+build
+pub fn version_of(tag: &str) -> Option<Version> {
+    let version = tag.trim_start_matches('v');
+    let version = version.split_once('+').map_or(version, |(version, _build)| version);
+    let (core, pre) = match version.split_once('-') {
+        Some((core, pre)) => (core, pre.split('.').map(str::to_string).collect()),
+        None => (version, Vec::new()),
+    };
+    let numbers: Vec<u64> = core.split('.').map(|n| n.parse().ok()).collect::<Option<_>>()?;
+    match numbers[..] {
+        [major, minor, patch] => return Some(Version { numbers: (major, minor, patch), pre }),
+        _ => return None,
+    }
+}
+
+/// Whether the release tagged `tag` is later than `current`
+pub fn is_newer(tag: &str, current: &str) -> bool {
+    match (version_of(tag), version_of(current)) {
+        (Some(tag), Some(current)) => return tag > current,
+        _ => return false,
+    }
+}
+
+/// The release binary for this platform
+pub fn asset_name() -> String {
+    return format!("git-author-stats-{}-{}", env::consts::ARCH, env::consts::OS);
+}
+
+/// The checksum of `name` in a sha256sum listing; a listing of one file is
+/// taken to be of `name`, whatever it calls it
+#[cfg_attr(not(feature = "self-update"), allow(dead_code))]
+fn checksum_of<'a>(listing: &'a str, name: &str) -> Option<&'a str> {
+    return listing.lines().find_map(|line| {
+        let (hash, file) = line.split_once(char::is_whitespace)?;
+        let file = file.trim_start().trim_start_matches('*');
+        (file == name || listing.lines().count() == 1).then_some(hash)
+    });
+}
+
+/// Output of a curl download of `url`
+fn download(url: &str) -> io::Result<Vec<u8>> {
+    let out = Command::new("curl")
+        .args(["--silent", "--show-error", "--fail", "--location"])
+        .args(["--header", "Accept: application/vnd.github+json"])
+        .arg(url)
+        .output()
+        .map_err(|e| io::Error::new(e.kind(), format!("failed to run curl: {e}")))?;
+    match out.status.success() {
+        true => return Ok(out.stdout),
+        false => return Err(io::Error::other(format!("{url}: {}", String::from_utf8_lossy(&out.stderr).trim()))),
+    }
+}
+
+/// The latest release
+pub fn latest() -> io::Result<Release> {
+    let url = env::var(RELEASES_ENV).unwrap_or(format!("https://api.github.com/repos/{REPO}/releases/latest"));
+    let text = download(&url)?;
+    return parse_release(&String::from_utf8_lossy(&text)).map_err(|e| io::Error::new(io::ErrorKind::InvalidData, format!("{url}: {e}")));
+}
+
+/// Run openssl, failing with `what` unless it succeeds
+#[cfg(feature = "self-update")]
+fn openssl(args: &[&str], what: &str) -> io::Result<String> {
+    let out = Command::new("openssl").args(args).output()
+        .map_err(|e| io::Error::new(e.kind(), format!("failed to run openssl: {e}")))?;
+    match out.status.success() {
+        true => return Ok(String::from_utf8_lossy(&out.stdout).to_string()),
+        false => {
+            // What `dgst -verify` says, or else the first line of the error
+            let output = [out.stdout, out.stderr].concat();
+            let output = String::from_utf8_lossy(&output);
+            return Err(io::Error::new(io::ErrorKind::InvalidData, format!("{what}: {}", output.lines().next().unwrap_or("").trim())));
+        },
+    }
+}
+
+/// Download this platform's binary of `release`, check its SHA-256 (and its
+/// signature, with `public_key`) and put it in place of the running binary
+#[cfg(feature = "self-update")]
+pub fn update(release: &Release, public_key: Option<&str>) -> io::Result<()> {
+    let name = asset_name();
+    let binary = download(release.asset(&name)?)?;
+    let listing = String::from_utf8_lossy(&download(release.asset(&format!("{name}.sha256"))?)?).to_string();
+    let expected = checksum_of(&listing, &name)
+        .ok_or_else(|| io::Error::new(io::ErrorKind::InvalidData, format!("{name}.sha256 has no checksum of {name}")))?
+        .to_lowercase();
+
+    // Next to the binary, so the rename replaces it in one step
+    let exe = env::current_exe()?;
+    let staged = exe.with_file_name(format!(".{name}.{}", release.tag));
+    fs::write(&staged, &binary)?;
+    let checked = (|| {
+        let staged_path = staged.to_string_lossy();
+        let digest = openssl(&["dgst", "-sha256", "-r", &staged_path], "checksum")?;
+        if digest.split_whitespace().next() != Some(expected.as_str()) {
+            return Err(io::Error::new(io::ErrorKind::InvalidData, format!("{name} doesn't match its SHA-256 in {name}.sha256")));
+        }
+        if let Some(key) = public_key {
+            let signature = signature_path(&staged);
+            fs::write(&signature, download(release.asset(&format!("{name}.sig"))?)?)?;
+            let verified = openssl(&["dgst", "-sha256", "-verify", key, "-signature", &signature.to_string_lossy(), &staged_path], "signature");
+            let _ = fs::remove_file(&signature);
+            verified?;
+        }
+        set_executable(&staged)?;
+        return fs::rename(&staged, &exe);
+    })();
+    if checked.is_err() {
+        let _ = fs::remove_file(&staged);
+    }
+    return checked;
+}
+
+/// Where the signature of the file at `path` is downloaded to: next to it,
+/// with `.sig` after its whole name, as the tag's dots aren't an extension
+#[cfg_attr(not(feature = "self-update"), allow(dead_code))]
+fn signature_path(path: &Path) -> PathBuf {
+    let mut name = path.file_name().unwrap_or_default().to_os_string();
+    name.push(".sig");
+    return path.with_file_name(name);
+}
+
+#[cfg(all(feature = "self-update", unix))]
+fn set_executable(path: &Path) -> io::Result<()> {
+    use std::os::unix::fs::PermissionsExt;
+    return fs::set_permissions(path, fs::Permissions::from_mode(0o755));
+}
+
+#[cfg(all(feature = "self-update", not(unix)))]
+fn set_executable(_path: &Path) -> io::Result<()> {
+    return Ok(());
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn reads_releases() {
+        let release = parse_release(r#"{"tag_name": "v0.2.0", "draft": false, "assets": [
+            {"name": "git-author-stats-x86_64-linux", "size": 10, "browser_download_url": "https://example.com/a"},
+            {"name": "git-author-stats-x86_64-linux.sha256", "browser_download_url": "https://example.com/a.sha256"}
+        ]}"#).unwrap();
+        assert_eq!(release.tag, "v0.2.0");
+        assert_eq!(release.asset("git-author-stats-x86_64-linux.sha256").unwrap(), "https://example.com/a.sha256");
+        assert!(release.asset("git-author-stats-aarch64-macos").is_err());
+        assert!(parse_release(r#"{"message": "Not Found"}"#).is_err());
+    }
+
+    #[test]
+    fn compares_versions() {
+        assert_eq!(version_of("v1.2.3"), Some(Version { numbers: (1, 2, 3), pre: Vec::new() }));
+        assert_eq!(version_of("0.10.0-rc.1+abc"), Some(Version { numbers: (0, 10, 0), pre: vec!["rc".to_string(), "1".to_string()] }));
+        assert_eq!(version_of("nightly"), None);
+        assert!(is_newer("v0.10.0", "0.9.1"));
+        assert!(!is_newer("v0.1.0", "0.1.0"));
+        assert!(!is_newer("nightly", "0.1.0"));
+        // Pre-releases come before their release, in semver's order
+        assert!(is_newer("v1.2.3", "1.2.3-rc1"));
+        assert!(!is_newer("1.2.3-rc1", "1.2.3"));
+        assert!(is_newer("1.2.3-rc2", "1.2.3-rc1"));
+        assert!(is_newer("1.2.3-rc.10", "1.2.3-rc.9"));
+        assert!(is_newer("1.2.3-rc.1", "1.2.3-rc"));
+        assert!(is_newer("1.2.3-beta", "1.2.3-1"));
+        assert!(!is_newer("1.2.3+build.2", "1.2.3+build.1"));
+    }
+
+    #[test]
+    fn names_signatures() {
+        let staged = Path::new("/usr/bin/.git-author-stats-x86_64-linux.v1.2.3");
+        assert_eq!(signature_path(staged), Path::new("/usr/bin/.git-author-stats-x86_64-linux.v1.2.3.sig"));
+    }
+
+    #[test]
+    fn finds_checksums() {
+        let listing = "abc123  git-author-stats-x86_64-linux\ndef456 *git-author-stats-aarch64-linux\n";
+        assert_eq!(checksum_of(listing, "git-author-stats-aarch64-linux"), Some("def456"));
+        assert_eq!(checksum_of(listing, "other"), None);
+        assert_eq!(checksum_of("abc123  renamed\n", "git-author-stats-x86_64-linux"), Some("abc123"));
+    }
+}
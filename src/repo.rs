@@ -0,0 +1,259 @@
+use std::env;
+use std::fs;
+use std::path::{Path, PathBuf};
+use std::process::{Command, Output};
+use std::sync::atomic::{AtomicUsize, Ordering};
+use std::sync::OnceLock;
+
+use crate::error::{Error, Result};
+use crate::recording;
+
+//----
+// Repository access
+//
+// The analyzed repo is never modified.  Every git command is built here so
+// that it runs with optional locks disabled (no opportunistic index
+// refreshes), and operations that need an index of their own get a private
+// one in a scratch directory via GIT_INDEX_FILE.  This also makes the tool
+// work on read-only repos such as shared mirrors.
+
+#[derive(Debug)]
+pub struct Repo {
+    /// Top level of the work tree (the git dir itself for bare repos)
+    pub root: String,
+    pub git_dir: PathBuf,
+    /// The git dir can't be written to by this user
+    pub read_only: bool,
+    /// Shallow clone: history before the shallow commits is missing
+    pub shallow: bool,
+    /// The commits a shallow clone's history is cut off at
+    pub shallow_commits: Vec<String>,
+    scratch: OnceLock<Scratch>,
+}
+
+/// Temporary directory removed when the run ends
+#[derive(Debug)]
+pub struct Scratch {
+    pub dir: PathBuf,
+    counter: AtomicUsize,
+}
+
+impl Scratch {
+    fn create() -> std::io::Result<Scratch> {
+        let base = env::temp_dir();
+        for attempt in 0..100 {
+            let dir = base.join(format!("git-author-stats-{}-{attempt}", std::process::id()));
+            match fs::create_dir(&dir) {
+                Ok(()) => return Ok(Scratch { dir, counter: AtomicUsize::new(0) }),
+                Err(e) if e.kind() == std::io::ErrorKind::AlreadyExists => continue,
+                Err(e) => return Err(e),
+            }
+        }
+        return Err(std::io::Error::new(std::io::ErrorKind::AlreadyExists, "no free scratch directory name"));
+    }
+
+    /// A fresh path inside the scratch directory
+    pub fn path(&self, prefix: &str) -> PathBuf {
+        let n = self.counter.fetch_add(1, Ordering::Relaxed);
+        return self.dir.join(format!("{prefix}-{n}"));
+    }
+}
+
+impl Drop for Scratch {
+    fn drop(&mut self) {
+        let _ = fs::remove_dir_all(&self.dir);
+    }
+}
+
+#[cfg(unix)]
+fn is_writable(path: &Path) -> bool {
+    use std::ffi::CString;
+    use std::os::unix::ffi::OsStrExt;
+    let Ok(cpath) = CString::new(path.as_os_str().as_bytes()) else {
+        return false;
+    };
+    // Covers both permissions and read-only mounts, without writing anything
+    return unsafe { libc::access(cpath.as_ptr(), libc::W_OK) } == 0;
+}
+
+#[cfg(not(unix))]
+fn is_writable(path: &Path) -> bool {
+    return fs::metadata(path).map(|m| !m.permissions().readonly()).unwrap_or(false);
+}
+
+/// Error for a git command that couldn't be started
+pub fn spawn_error(command: &str, e: std::io::Error) -> Error {
+    return Error::Git { command: command.to_string(), message: format!("failed to start: {e}") };
+}
+
+/// Run a git command, failing unless it exits successfully
+pub fn run(cmd: &mut Command, command: &str) -> Result<Output> {
+    let out = recording::output(cmd, None).map_err(|e| spawn_error(command, e))?;
+    if !out.status.success() {
+        return Err(Error::Git { command: command.to_string(), message: String::from_utf8_lossy(&out.stderr).trim().to_string() });
+    }
+    return Ok(out);
+}
+
+/// Run a git query in `dir`, returning its trimmed stdout, or None if git
+/// says no
+fn rev_parse(dir: &str, arg: &str) -> Result<Option<String>> {
+    let mut cmd = Command::new("git");
+    cmd.arg("rev-parse").arg(arg).current_dir(dir).env("GIT_OPTIONAL_LOCKS", "0");
+    let out = recording::output(&mut cmd, None).map_err(|e| spawn_error("rev-parse", e))?;
+    match out.status.success() {
+        false => return Ok(None),
+        true => return Ok(Some(String::from_utf8_lossy(&out.stdout).trim().to_string())),
+    }
+}
+
+/// Repository paths listed in a --repos-file: one per line, blank lines and
+/// `#` comments ignored.  Relative paths are relative to the file.
+pub fn parse_repo_list(text: &str, base: &Path) -> Vec<String> {
+    return text.lines()
+        .map(|line| line.split_once('#').map(|(path, _)| path).unwrap_or(line).trim())
+        .filter(|path| !path.is_empty())
+        .map(|path| base.join(path).to_string_lossy().to_string())
+        .collect();
+}
+
+pub fn read_repo_list(path: &str) -> Result<Vec<String>> {
+    let text = fs::read_to_string(path).map_err(|e| Error::Config(format!("{path}: {e}")))?;
+    let base = Path::new(path).parent().unwrap_or(Path::new(""));
+    return Ok(parse_repo_list(&text, base));
+}
+
+impl Repo {
+    /// Find the repo containing `path`
+    pub fn discover(path: &str) -> Result<Repo> {
+        // Start from the nearest existing directory, so files and deleted
+        // paths can be given too
+        let mut dir = PathBuf::from(path);
+        while !dir.is_dir() {
+            if !dir.pop() || dir.as_os_str().is_empty() {
+                dir = PathBuf::from(".");
+                break;
+            }
+        }
+        let dir = dir.to_string_lossy();
+        let git_dir = match rev_parse(&dir, "--absolute-git-dir")? {
+            Some(git_dir) => PathBuf::from(git_dir),
+            None => return Err(Error::NotARepo(path.to_string())),
+        };
+        let root = match rev_parse(&dir, "--show-toplevel")? {
+            Some(root) if !root.is_empty() => root,
+            // Bare repo: no work tree
+            _ => git_dir.to_string_lossy().to_string(),
+        };
+        let read_only = !is_writable(&git_dir);
+        let shallow = rev_parse(&dir, "--is-shallow-repository")?.as_deref() == Some("true");
+        let shallow_commits = match shallow {
+            true => fs::read_to_string(git_dir.join("shallow")).unwrap_or_default().lines().map(|line| line.trim().to_string()).collect(),
+            false => Vec::new(),
+        };
+        return Ok(Repo { root, git_dir, read_only, shallow, shallow_commits, scratch: OnceLock::new() });
+    }
+
+    /// Path of `path` relative to the top of the work tree, for use as a
+    /// pathspec.  Empty for the top itself.  The path doesn't have to exist
+    /// any more, so history of deleted directories can still be analyzed.
+    pub fn relative_path(&self, path: &str) -> Result<String> {
+        // A replayed recording's paths are those of the recording machine
+        let canonical = match recording::replaying() {
+            true => None,
+            false => fs::canonicalize(path).ok(),
+        };
+        let absolute = match canonical {
+            Some(p) => p,
+            None => {
+                let cwd = recording::current_dir()?;
+                let joined = cwd.join(path);
+                // Fold "." and ".." lexically, since the path may not exist
+                let mut folded = PathBuf::new();
+                for part in joined.components() {
+                    match part {
+                        std::path::Component::CurDir => {},
+                        std::path::Component::ParentDir => { folded.pop(); },
+                        part => folded.push(part),
+                    }
+                }
+                folded
+            },
+        };
+        let root = fs::canonicalize(&self.root).unwrap_or_else(|_| PathBuf::from(&self.root));
+        match absolute.strip_prefix(&root) {
+            Ok(rel) => return Ok(rel.to_string_lossy().to_string()),
+            Err(_) => return Err(Error::OutsideRepo { path: path.to_string(), root: self.root.clone() }),
+        }
+    }
+
+    /// A git command that runs in the repo without modifying it
+    pub fn git(&self) -> Command {
+        let mut cmd = Command::new("git");
+        cmd.current_dir(&self.root);
+        cmd.env("GIT_OPTIONAL_LOCKS", "0");
+        return cmd;
+    }
+
+    /// The run's scratch directory, created on first use
+    pub fn scratch(&self) -> std::io::Result<&Scratch> {
+        if let Some(scratch) = self.scratch.get() {
+            return Ok(scratch);
+        }
+        let scratch = Scratch::create()?;
+        return Ok(self.scratch.get_or_init(|| scratch));
+    }
+
+    /// A git command using a private index file populated from `treeish`, for
+    /// operations that would otherwise read or write the repo's own index
+    pub fn git_with_index(&self, treeish: &str) -> Result<Command> {
+        // --index-output would still lock the repo's own index, so point
+        // GIT_INDEX_FILE at the private one instead
+        let index = self.scratch()?.path("index");
+        run(self.git().env("GIT_INDEX_FILE", &index).arg("read-tree").arg(treeish), "read-tree")?;
+        let mut cmd = self.git();
+        cmd.env("GIT_INDEX_FILE", &index);
+        return Ok(cmd);
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::fixture::Fixture;
+
+    #[test]
+    fn discovers_from_subdirectory() {
+        let fixture = Fixture::new();
+        let repo = Repo::discover(&format!("{}/src", fixture.path())).unwrap();
+        assert_eq!(fs::canonicalize(&repo.root).unwrap(), fs::canonicalize(&fixture.dir).unwrap());
+        assert!(!repo.read_only);
+        assert!(!repo.shallow);
+    }
+
+    #[test]
+    fn relative_paths() {
+        let fixture = Fixture::new();
+        let repo = fixture.repo();
+        assert_eq!(repo.relative_path(fixture.path()).unwrap(), "");
+        assert_eq!(repo.relative_path(&format!("{}/src/main.rs", fixture.path())).unwrap(), "src/main.rs");
+        // Deleted paths still resolve
+        assert_eq!(repo.relative_path(&format!("{}/gone/../old", fixture.path())).unwrap(), "old");
+        assert!(matches!(repo.relative_path("/"), Err(Error::OutsideRepo { .. })));
+    }
+
+    #[test]
+    fn repo_lists() {
+        let text = "# team repos\n/src/app\n\n  lib  # shared code\n";
+        assert_eq!(parse_repo_list(text, Path::new("/work")), vec!["/src/app".to_string(), "/work/lib".to_string()]);
+    }
+
+    #[test]
+    fn not_a_repo() {
+        let dir = env::temp_dir().join(format!("git-author-stats-norepo-{}", std::process::id()));
+        fs::create_dir_all(&dir).unwrap();
+        let result = Repo::discover(dir.to_str().unwrap());
+        let _ = fs::remove_dir_all(&dir);
+        assert!(matches!(result, Err(Error::NotARepo(_))));
+    }
+}
@@ -0,0 +1,1329 @@
+use hashbrown::HashMap;
+use std::env;
+use std::io::{self, Write};
+
+use crate::chart;
+use crate::code_maat;
+use crate::cli_args::{Args, Metric, OutputFormat, SortOrder};
+use crate::filter::SkipReason;
+use crate::health::{Health, HealthWeights};
+use crate::html;
+use crate::i18n::{self, t, tf};
+use crate::msgpack;
+use crate::yaml;
+use crate::identity::{display_name, IdentityResolver};
+use crate::teams::{AuthorOrder, Teams};
+use crate::analysis::{AuthorStats, FileOwnership};
+use crate::breakdown::Fingerprint;
+use crate::ownership::{self, Concentration, EntropyTotal, BUS_FACTOR_SHARE};
+use crate::sampling::Estimate;
+use crate::category::{AuthorCategories, Category, CATEGORIES};
+use crate::coupling::Coupling;
+use crate::{csv_field, AuthorPerformance, Count, Date};
+
+//----
+// Reporting
+//
+// The per-date blame counts are turned into a table of one row per
+// canonical author and one column per date, sorted and ready to print.  With
+// --split-docs, each date's column is followed by one of the author's lines in
+// documentation files, which the first leaves out.
+
+#[derive(Debug, Clone)]
+pub struct Row {
+    pub author: String,
+    /// Identity keys (emails) merged into this row
+    pub keys: Vec<String>,
+    /// One count per date column
+    pub counts: Vec<Count>,
+    /// Lines in documentation files per date column, with --split-docs;
+    /// empty otherwise
+    pub docs: Vec<Count>,
+    /// Margin of the estimated counts per date column, with --sample; empty
+    /// otherwise
+    pub margins: Vec<f64>,
+}
+
+/// A run of consecutive rows shown under one heading, e.g. a team
+#[derive(Debug, Clone)]
+pub struct Group {
+    pub name: String,
+    pub rows: std::ops::Range<usize>,
+    /// Sum of the group's rows, per date column
+    pub totals: Vec<Count>,
+    pub doc_totals: Vec<Count>,
+}
+
+/// Owners of one file, by canonical author, most lines first
+#[derive(Debug, Clone)]
+pub struct FileRow {
+    pub path: String,
+    pub owners: Vec<(String, Count)>,
+    pub total: Count,
+    /// Ownership entropy over the owners, in bits
+    pub entropy: f64,
+}
+
+#[derive(Debug, Clone)]
+pub struct Table {
+    pub dates: Vec<Date>,
+    pub rows: Vec<Row>,
+    /// Sum of every row, per date column
+    pub totals: Vec<Count>,
+    /// Sum of every row's docs, per date column; empty without --split-docs
+    pub doc_totals: Vec<Count>,
+    /// Row groups; empty when the rows aren't grouped
+    pub groups: Vec<Group>,
+    /// Per-file owners at the latest date
+    pub files: Vec<FileRow>,
+    /// Date and revision the files were blamed at, if they were
+    pub files_at: Option<(Date, String)>,
+    /// Average ownership entropy of the files per date column, with --entropy
+    pub entropy: Vec<f64>,
+    /// Ownership concentration per date column, with --bus-factor
+    pub bus_factor: Vec<BusFactor>,
+    /// Health score per date column, with --health-score
+    pub health: Vec<Health>,
+    /// Whether every file was skipped, per date column; empty if none was
+    pub ineligible: Vec<bool>,
+    /// Margin of the estimated total per date column, with --sample; empty
+    /// when the counts are exact
+    pub margins: Vec<f64>,
+}
+
+/// Row label of the --entropy trend
+pub const ENTROPY_LABEL: &str = "(entropy)";
+
+/// Row labels of the --bus-factor trend
+pub const BUS_FACTOR_LABEL: &str = "(bus factor)";
+pub const DOMINATED_LABEL: &str = "(dominated files)";
+
+/// Row label of the --health-score trend
+pub const HEALTH_LABEL: &str = "(health)";
+
+/// Row label marking the dates whose every file was skipped
+pub const INELIGIBLE_LABEL: &str = "(no eligible files)";
+
+/// Row label of the --sample total's margins
+pub const MARGIN_LABEL: &str = "(margin, 95%)";
+
+/// A summary row's label in the run's language; CSV and JSON keep the consts
+pub fn summary_label(label: &str) -> String {
+    let id = match label {
+        ENTROPY_LABEL => "entropy-row",
+        BUS_FACTOR_LABEL => "bus-factor-row",
+        DOMINATED_LABEL => "dominated-row",
+        HEALTH_LABEL => "health-row",
+        INELIGIBLE_LABEL => "ineligible-row",
+        MARGIN_LABEL => "margin-row",
+        _ => return label.to_string(),
+    };
+    return t(id);
+}
+
+/// Ownership concentration at one date
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq)]
+pub struct BusFactor {
+    /// Fewest canonical authors owning half the lines
+    pub authors: usize,
+    /// Files one author owns more than --dominance of
+    pub dominated: usize,
+    pub files: usize,
+}
+
+/// Counts per date, per canonical author display name
+type NamedPerformance = HashMap<Date, HashMap<String, Count>>;
+
+/// The overall table, plus one per --group-by group
+#[derive(Debug, Clone)]
+pub struct Report {
+    pub table: Table,
+    pub groups: Vec<(String, Table)>,
+    /// Author x category x date cube, with --categories
+    pub categories: Vec<CategoryRow>,
+    /// Each author's lines per --fingerprint group at the latest date
+    pub fingerprint: Vec<FingerprintRow>,
+    /// Files that change together, with their owners, with --coupling
+    pub coupling: Vec<CouplingRow>,
+}
+
+/// Two files that change together, and their top owners at the latest date
+#[derive(Debug, Clone)]
+pub struct CouplingRow {
+    pub coupling: Coupling,
+    pub owners: (String, String),
+    /// The owners' teams, if teams are configured
+    pub teams: Option<(String, String)>,
+}
+
+impl CouplingRow {
+    /// Why the pair needs coordination, if it does
+    pub fn risk(&self) -> Option<&'static str> {
+        match &self.teams {
+            Some((a, b)) if a != b => return Some("different teams"),
+            _ if self.owners.0 != self.owners.1 => return Some("different owners"),
+            _ => return None,
+        }
+    }
+}
+
+/// An author's count at the last two dates
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct Mover {
+    pub author: String,
+    pub before: Count,
+    pub after: Count,
+}
+
+impl Mover {
+    pub fn change(&self) -> i64 {
+        return self.after as i64 - self.before as i64;
+    }
+
+    /// Change relative to the earlier count; None for authors new at the latest date
+    pub fn percent(&self) -> Option<f64> {
+        match self.before {
+            0 => return None,
+            before => return Some(100.0 * self.change() as f64 / before as f64),
+        }
+    }
+}
+
+/// The authors who changed most from the second-to-last date to the last
+#[derive(Debug, Clone, Default)]
+pub struct Movers {
+    pub gained: Vec<Mover>,
+    pub lost: Vec<Mover>,
+    pub grew: Vec<Mover>,
+    pub shrank: Vec<Mover>,
+}
+
+impl Movers {
+    pub fn is_empty(&self) -> bool {
+        return self.gained.is_empty() && self.lost.is_empty() && self.grew.is_empty() && self.shrank.is_empty();
+    }
+}
+
+/// Up to `n` authors per heading, largest change first.  Nothing with fewer
+/// than two dates.
+pub fn movers(table: &Table, n: usize) -> Movers {
+    let columns = table.dates.len();
+    if columns < 2 {
+        return Movers::default();
+    }
+    let all: Vec<Mover> = table.rows.iter()
+        .map(|row| Mover { author: row.author.clone(), before: row.counts[columns - 2], after: row.counts[columns - 1] })
+        .filter(|mover| mover.change() != 0)
+        .collect();
+    let top = |mut movers: Vec<Mover>, key: &dyn Fn(&Mover) -> f64| {
+        movers.sort_by(|a, b| key(b).total_cmp(&key(a)).then(a.author.cmp(&b.author)));
+        movers.truncate(n);
+        movers
+    };
+    let by = |keep: fn(&Mover) -> bool| all.iter().filter(|mover| keep(mover)).cloned().collect::<Vec<Mover>>();
+    return Movers {
+        gained: top(by(|m| m.change() > 0), &|m| m.change() as f64),
+        lost: top(by(|m| m.change() < 0), &|m| -m.change() as f64),
+        grew: top(by(|m| m.percent().is_some() && m.change() > 0), &|m| m.percent().unwrap_or(0.0)),
+        shrank: top(by(|m| m.percent().is_some() && m.change() < 0), &|m| -m.percent().unwrap_or(0.0)),
+    };
+}
+
+/// Share of an author's lines per group, largest first
+#[derive(Debug, Clone)]
+pub struct FingerprintRow {
+    pub author: String,
+    pub total: Count,
+    pub groups: Vec<(String, Count)>,
+}
+
+/// Groups listed by name in a fingerprint; the rest are summed as "other"
+const FINGERPRINT_GROUPS: usize = 4;
+
+impl FingerprintRow {
+    /// The shares as text, e.g. "60% .rs, 30% .py, 10% other"
+    pub fn summary(&self) -> String {
+        let percent = |count: Count| if self.total == 0 { 0.0 } else { 100.0 * count as f64 / self.total as f64 };
+        let mut parts: Vec<String> = self.groups.iter().take(FINGERPRINT_GROUPS)
+            .map(|(group, count)| format!("{:.0}% {group}", percent(*count)))
+            .collect();
+        let rest: Count = self.groups.iter().skip(FINGERPRINT_GROUPS).map(|(_, count)| count).sum();
+        if rest != 0 {
+            parts.push(format!("{:.0}% other", percent(rest)));
+        }
+        return parts.join(", ");
+    }
+}
+
+/// Lines of one author from commits of one category, per date column
+#[derive(Debug, Clone)]
+pub struct CategoryRow {
+    pub author: String,
+    pub category: Category,
+    pub counts: Vec<Count>,
+}
+
+/// Merge the per-category counts into canonical authors, one row per author
+/// and category that has any lines, in the table's date columns
+pub fn category_rows(perf: &AuthorPerformance, categories: &HashMap<Date, AuthorCategories>, resolver: &IdentityResolver, dates: &[Date])
+    -> Vec<CategoryRow>
+{
+    let names = resolver.display_names(perf.values().flat_map(|acnt| acnt.iter().map(|(a, c)| (a, *c))));
+    let mut cube: HashMap<(String, Category), Vec<Count>> = HashMap::new();
+    for (i, date) in dates.iter().enumerate() {
+        let Some(acat) = categories.get(date) else {
+            continue;
+        };
+        for (author, counts) in acat {
+            let key = resolver.resolve(author).author.key();
+            let name = names.get(&key).cloned().unwrap_or_else(|| display_name(&author.name));
+            for category in CATEGORIES {
+                if counts[category as usize] != 0 {
+                    cube.entry((name.clone(), category)).or_insert_with(|| vec![0; dates.len()])[i] += counts[category as usize];
+                }
+            }
+        }
+    }
+    let mut rows: Vec<CategoryRow> = cube.into_iter().map(|((author, category), counts)| CategoryRow { author, category, counts }).collect();
+    rows.sort_by(|a, b| (&a.author, a.category).cmp(&(&b.author, b.category)));
+    return rows;
+}
+
+/// Merge the fingerprint counts into canonical authors, most lines first
+pub fn fingerprint_rows(fingerprint: &Fingerprint, resolver: &IdentityResolver) -> Vec<FingerprintRow> {
+    let names = resolver.display_names(fingerprint.values().flat_map(|acnt| acnt.iter().map(|(a, c)| (a, *c))));
+    let mut by_author: HashMap<String, HashMap<String, Count>> = HashMap::new();
+    for (group, acnt) in fingerprint {
+        for (author, count) in acnt {
+            let key = resolver.resolve(author).author.key();
+            let name = names.get(&key).cloned().unwrap_or_else(|| display_name(&author.name));
+            *by_author.entry(name).or_default().entry_ref(group).or_insert(0) += count;
+        }
+    }
+    let mut rows: Vec<FingerprintRow> = by_author.into_iter().map(|(author, groups)| {
+        let mut groups: Vec<(String, Count)> = groups.into_iter().filter(|(_, count)| *count != 0).collect();
+        groups.sort_by(|a, b| b.1.cmp(&a.1).then(a.0.cmp(&b.0)));
+        let total = groups.iter().map(|(_, count)| count).sum();
+        FingerprintRow { author, total, groups }
+    }).filter(|row| row.total != 0).collect();
+    rows.sort_by(|a, b| b.total.cmp(&a.total).then(a.author.cmp(&b.author)));
+    return rows;
+}
+
+/// The coupled pairs of which both files are in the table's file list, with
+/// their top owners and, if there are teams, the owners' teams
+pub fn coupling_rows(coupling: &[Coupling], table: &Table, teams: &Teams) -> Vec<CouplingRow> {
+    let owners: HashMap<&str, &str> = table.files.iter()
+        .filter_map(|file| file.owners.first().map(|(owner, _)| (file.path.as_str(), owner.as_str())))
+        .collect();
+    let keys: HashMap<&str, &[String]> = table.rows.iter().map(|row| (row.author.as_str(), row.keys.as_slice())).collect();
+    let team = |owner: &str| -> String {
+        let team = teams.team_of(owner, keys.get(owner).copied().unwrap_or_default());
+        return team.map(|i| teams.names[i].as_str()).unwrap_or(teams.unassigned()).to_string();
+    };
+    return coupling.iter().filter_map(|pair| {
+        let owners = (owners.get(pair.files.0.as_str())?.to_string(), owners.get(pair.files.1.as_str())?.to_string());
+        let teams = match teams.is_empty() {
+            true => None,
+            false => Some((team(&owners.0), team(&owners.1))),
+        };
+        Some(CouplingRow { coupling: pair.clone(), owners, teams })
+    }).collect();
+}
+
+/// Average ownership entropy per date column
+pub fn entropy_trend(entropy: &HashMap<Date, EntropyTotal>, dates: &[Date]) -> Vec<f64> {
+    return dates.iter().map(|date| entropy.get(date).map(|total| total.mean()).unwrap_or(0.0)).collect();
+}
+
+/// Bus factor of the canonical authors and dominated files per date column
+pub fn bus_factor_trend(perf: &AuthorPerformance, concentration: &HashMap<Date, Concentration>, resolver: &IdentityResolver, dates: &[Date])
+    -> Vec<BusFactor>
+{
+    let names = resolver.display_names(perf.values().flat_map(|acnt| acnt.iter().map(|(a, c)| (a, *c))));
+    let (perf, _) = resolve_authors(perf, resolver, names);
+    return dates.iter().map(|date| {
+        let files = concentration.get(date).copied().unwrap_or_default();
+        BusFactor {
+            authors: perf.get(date).map(|acnt| ownership::bus_factor(acnt.values().copied(), BUS_FACTOR_SHARE)).unwrap_or(0),
+            dominated: files.dominated,
+            files: files.files,
+        }
+    }).collect();
+}
+
+/// Health score per date column, from the canonical authors' lines, the files'
+/// entropy and staleness, and the authors with commits in each period
+pub fn health_trend(stats: &AuthorStats, active: &AuthorPerformance, weights: &HealthWeights, resolver: &IdentityResolver, dates: &[Date])
+    -> Vec<Health>
+{
+    let canonical = |perf: &AuthorPerformance| {
+        let names = resolver.display_names(perf.values().flat_map(|acnt| acnt.iter().map(|(a, c)| (a, *c))));
+        return resolve_authors(perf, resolver, names).0;
+    };
+    let (lines, active) = (canonical(&stats.authors), canonical(active));
+    let mut before = None;
+    return dates.iter().map(|date| {
+        let bus_factor = lines.get(date).map(|acnt| ownership::bus_factor(acnt.values().copied(), BUS_FACTOR_SHARE)).unwrap_or(0);
+        let entropy = stats.entropy.get(date).map(|total| total.mean()).unwrap_or(0.0);
+        let stale = stats.staleness.get(date).map(|lines| lines.share()).unwrap_or(0.0);
+        let authors = active.get(date).map(|acnt| acnt.values().filter(|&&c| c > 0).count()).unwrap_or(0);
+        let health = Health::new(weights, bus_factor, entropy, stale, authors, before);
+        before = Some(authors);
+        health
+    }).collect();
+}
+
+/// Merge raw identities into canonical authors, keyed by display name.  Also
+/// returns the identity keys behind each display name.
+fn resolve_authors(perf: &AuthorPerformance, resolver: &IdentityResolver, names: HashMap<String, String>)
+    -> (NamedPerformance, HashMap<String, Vec<String>>)
+{
+    let mut resolved = HashMap::new();
+    perf.iter().for_each(|(date, acnt_in)| {
+        let acnt_out: &mut HashMap<String, Count> = resolved.entry_ref(date).or_insert(HashMap::new());
+        for (author, cnt_in) in acnt_in {
+            let key = resolver.resolve(author).author.key();
+            *acnt_out.entry_ref(&names[&key]).or_insert(0) += cnt_in;
+        }
+    });
+
+    let mut keys: HashMap<String, Vec<String>> = HashMap::new();
+    for (key, name) in names {
+        keys.entry(name).or_default().push(key);
+    }
+    return (resolved, keys);
+}
+
+fn file_rows(latest: &FileOwnership, resolver: &IdentityResolver, names: &HashMap<String, String>) -> Vec<FileRow> {
+    let mut rows: Vec<FileRow> = latest.files.iter().map(|(path, acnt)| {
+        let mut owners: HashMap<String, Count> = HashMap::new();
+        for (author, count) in acnt {
+            let key = resolver.resolve(author).author.key();
+            let name = names.get(&key).cloned().unwrap_or_else(|| display_name(&author.name));
+            *owners.entry(name).or_insert(0) += count;
+        }
+        let mut owners: Vec<(String, Count)> = owners.into_iter().collect();
+        owners.sort_by(|a, b| b.1.cmp(&a.1).then(a.0.cmp(&b.0)));
+        let total = owners.iter().map(|(_, count)| count).sum();
+        let entropy = ownership::entropy(owners.iter().map(|(_, count)| *count));
+        FileRow { path: path.clone(), owners, total, entropy }
+    }).collect();
+    rows.sort_by(|a, b| a.path.cmp(&b.path));
+    return rows;
+}
+
+fn column_totals(rows: &[Row], columns: usize) -> Vec<Count> {
+    return (0..columns).map(|i| rows.iter().map(|row| row.counts[i]).sum()).collect();
+}
+
+/// Sum of the rows' docs, per date column, if they have any
+fn doc_totals(rows: &[Row], columns: usize, split: bool) -> Vec<Count> {
+    match split {
+        true => return (0..columns).map(|i| rows.iter().map(|row| row.docs[i]).sum()).collect(),
+        false => return Vec::new(),
+    }
+}
+
+/// Share of `total`, in percent
+fn share(count: Count, total: Count) -> f64 {
+    return if total == 0 { 0.0 } else { 100.0 * count as f64 / total as f64 };
+}
+
+/// Text of a cell: the count, its share of the total with --percent, or both
+/// with --with-percent
+fn cell_text(opt: &Args, count: Count, total: Count, separator: &str) -> String {
+    match (opt.as_percent, opt.with_percent) {
+        (true, _) => return format!("{:.1}%", share(count, total)),
+        (false, true) => return format!("{} ({:.0}%)", group_digits(count as i64, separator), share(count, total)),
+        (false, false) => return group_digits(count as i64, separator),
+    }
+}
+
+/// Fields of a cell in csv and json: the count, or its share with --percent,
+/// then the share with --with-percent
+fn cell_fields(opt: &Args, count: Count, total: Count) -> Vec<String> {
+    match (opt.as_percent, opt.with_percent) {
+        (true, _) => return vec![format!("{:.1}", share(count, total))],
+        (false, true) => return vec![count.to_string(), format!("{:.1}", share(count, total))],
+        (false, false) => return vec![count.to_string()],
+    }
+}
+
+impl Table {
+    /// The table of `perf`, with `docs` columns beside it if given
+    #[allow(clippy::too_many_arguments)]
+    pub fn build(opt: &Args, perf: &AuthorPerformance, docs: Option<&AuthorPerformance>, resolver: &IdentityResolver, teams: &Teams, order: &AuthorOrder,
+        latest: Option<&FileOwnership>) -> Table {
+        let names = resolver.display_names(perf.values().chain(docs.into_iter().flat_map(|docs| docs.values())).flat_map(|acnt| acnt.iter().map(|(a, c)| (a, *c))));
+        let files = latest.map(|latest| file_rows(latest, resolver, &names)).unwrap_or_default();
+        let split = docs.is_some();
+        let docs = docs.map(|docs| resolve_authors(docs, resolver, names.clone()).0).unwrap_or_default();
+        let (perf, mut keys) = resolve_authors(perf, resolver, names);
+
+        let mut dates = perf.keys().map(|x| x.to_string()).collect::<Vec<String>>();
+        dates.sort();
+
+        let mut authors = Vec::new();
+        for date in &dates {
+            if let Some(acnt) = perf.get(date) {
+                authors.extend(acnt.keys().map(|x| x.to_string()));
+            }
+            if let Some(acnt) = docs.get(date) {
+                authors.extend(acnt.keys().map(|x| x.to_string()));
+            }
+        }
+        authors.sort();
+        authors.dedup();
+
+        let mut rows: Vec<Row> = authors.into_iter().map(|author| {
+            let counts = dates.iter().map(|date| *perf[date].get(&author).unwrap_or(&0)).collect();
+            let docs = match split {
+                true => dates.iter().map(|date| docs.get(date).and_then(|acnt| acnt.get(&author)).copied().unwrap_or(0)).collect(),
+                false => Vec::new(),
+            };
+            let keys = keys.remove(&author).unwrap_or_default();
+            Row { author, keys, counts, docs, margins: Vec::new() }
+        }).collect();
+
+        // Rows are already alphabetical; stable sorts keep that order for ties
+        let sort = match opt.alphabetical {
+            true => SortOrder::Alphabetical,
+            false => opt.sort,
+        };
+        let skip = dates.len().saturating_sub(opt.recent);
+        let size = |row: &Row| -> i64 {
+            match sort {
+                SortOrder::Latest | SortOrder::Alphabetical => return row.counts.last().copied().unwrap_or(0) as i64,
+                SortOrder::Total => return row.counts.iter().map(|&c| c as i64).sum(),
+                SortOrder::Recent => return row.counts[skip..].iter().map(|&c| c as i64).sum(),
+            }
+        };
+        if sort != SortOrder::Alphabetical {
+            rows.sort_by_key(|row| std::cmp::Reverse(size(row)));
+        }
+        let pinned = match order.is_empty() {
+            true => 0,
+            false => {
+                rows.sort_by_key(|row| order.position(&row.author, &row.keys).unwrap_or(usize::MAX));
+                rows.iter().take_while(|row| order.position(&row.author, &row.keys).is_some()).count()
+            },
+        };
+
+        // Roll everyone past the N largest into one row; pinned authors stay
+        if let Some(top) = opt.top {
+            if rows.len() > pinned + top + 1 {
+                let mut unpinned = rows.split_off(pinned);
+                unpinned.sort_by_key(|row| std::cmp::Reverse(size(row)));
+                let rest = unpinned.split_off(top);
+                if sort == SortOrder::Alphabetical {
+                    unpinned.sort_by(|a, b| a.author.cmp(&b.author));
+                }
+                rows.extend(unpinned);
+                let counts = column_totals(&rest, dates.len());
+                let docs = doc_totals(&rest, dates.len(), split);
+                rows.push(Row { author: format!("Other ({} authors)", rest.len()), keys: Vec::new(), counts, docs, margins: Vec::new() });
+            }
+        }
+
+        // Group by team, keeping the sort order within each team
+        let mut groups = Vec::new();
+        if !teams.is_empty() {
+            let team_of = |row: &Row| teams.team_of(&row.author, &row.keys).unwrap_or(teams.names.len());
+            rows.sort_by_key(team_of);
+            let mut start = 0;
+            while start < rows.len() {
+                let team = team_of(&rows[start]);
+                let end = start + rows[start..].iter().take_while(|row| team_of(row) == team).count();
+                groups.push(Group {
+                    name: teams.names.get(team).map(|x| x.as_str()).unwrap_or(teams.unassigned()).to_string(),
+                    rows: start..end,
+                    totals: column_totals(&rows[start..end], dates.len()),
+                    doc_totals: doc_totals(&rows[start..end], dates.len(), split),
+                });
+                start = end;
+            }
+        }
+
+        let totals = column_totals(&rows, dates.len());
+        let doc_totals = doc_totals(&rows, dates.len(), split);
+        let files_at = latest.map(|latest| (latest.date.clone(), latest.revision.clone()));
+        return Table { dates, rows, totals, doc_totals, groups, files, files_at, entropy: Vec::new(), bus_factor: Vec::new(), health: Vec::new(), ineligible: Vec::new(),
+            margins: Vec::new() };
+    }
+
+    /// Mark the counts as --sample estimates, with the 95% margins of each
+    /// row and of the total.  A row of several identities treats their
+    /// estimates as independent; identities rolled into --top's "Other" row
+    /// count towards it.
+    pub fn add_margins(&mut self, estimates: &HashMap<Date, Estimate>, resolver: &IdentityResolver) {
+        let mut rows: HashMap<String, usize> = HashMap::new();
+        for (i, row) in self.rows.iter().enumerate() {
+            rows.extend(row.keys.iter().map(|key| (key.clone(), i)));
+        }
+        let other = self.rows.iter().position(|row| row.keys.is_empty());
+        let mut squares = vec![vec![0.0; self.dates.len()]; self.rows.len()];
+        for (i, date) in self.dates.iter().enumerate() {
+            let Some(estimate) = estimates.get(date) else {
+                continue;
+            };
+            for (author, margin) in estimate.margins() {
+                if let Some(row) = rows.get(&resolver.resolve(author).author.key()).copied().or(other) {
+                    squares[row][i] += margin * margin;
+                }
+            }
+        }
+        for (row, squares) in self.rows.iter_mut().zip(squares) {
+            row.margins = squares.into_iter().map(f64::sqrt).collect();
+        }
+        self.margins = self.dates.iter().map(|date| estimates.get(date).map(|estimate| estimate.total_margin()).unwrap_or(0.0)).collect();
+    }
+
+    /// Whether the counts are --sample estimates
+    pub fn estimated(&self) -> bool {
+        return !self.margins.is_empty();
+    }
+
+    /// Row groups to display, or a single unnamed group of every row
+    pub fn sections(&self) -> Vec<Group> {
+        match self.groups.is_empty() {
+            false => return self.groups.clone(),
+            true => return vec![Group { name: String::new(), rows: 0..self.rows.len(), totals: self.totals.clone(), doc_totals: self.doc_totals.clone() }],
+        }
+    }
+
+    /// Whether each date column is followed by a --split-docs one
+    pub fn splits_docs(&self) -> bool {
+        return !self.doc_totals.is_empty();
+    }
+
+    /// Column headings: each date, then its docs column with --split-docs
+    pub fn column_labels(&self) -> Vec<String> {
+        let mut labels = Vec::new();
+        for date in &self.dates {
+            let label = i18n::date_label(date);
+            if self.splits_docs() {
+                labels.push(label.clone());
+                labels.push(tf("docs-column", &[("date", &label)]));
+            } else {
+                labels.push(label);
+            }
+        }
+        return labels;
+    }
+
+    /// Text of the cells of a row's or group's counts and docs, in column order
+    pub fn cells(&self, opt: &Args, counts: &[Count], docs: &[Count], separator: &str) -> Vec<String> {
+        let mut cells = Vec::new();
+        for i in 0..self.dates.len() {
+            cells.push(self.cell(opt, counts, i, separator));
+            if self.splits_docs() {
+                cells.push(self.doc_cell(opt, docs, i, separator));
+            }
+        }
+        return cells;
+    }
+
+    /// A summary row's cells, one per date, under the date columns
+    pub fn spread(&self, cells: Vec<String>) -> Vec<String> {
+        match self.splits_docs() {
+            true => return cells.into_iter().flat_map(|cell| [cell, String::new()]).collect(),
+            false => return cells,
+        }
+    }
+
+    /// The --bus-factor and --health-score rows, formatted, and the row
+    /// marking dates with no eligible files
+    pub fn summary_rows(&self) -> Vec<(&'static str, Vec<String>)> {
+        let mut rows = Vec::new();
+        if !self.bus_factor.is_empty() {
+            rows.push((BUS_FACTOR_LABEL, self.bus_factor.iter().map(|b| b.authors.to_string()).collect()));
+            rows.push((DOMINATED_LABEL, self.bus_factor.iter().map(|b| b.dominated.to_string()).collect()));
+        }
+        if !self.health.is_empty() {
+            rows.push((HEALTH_LABEL, self.health.iter().map(|h| format!("{:.1}", h.score)).collect()));
+        }
+        if !self.ineligible.is_empty() {
+            rows.push((INELIGIBLE_LABEL, self.ineligible.iter().map(|&none| if none { "x" } else { "" }.to_string()).collect()));
+        }
+        if self.estimated() {
+            rows.push((MARGIN_LABEL, self.margins.iter().map(|margin| format!("\u{b1}{margin:.0}")).collect()));
+        }
+        return rows;
+    }
+
+    /// Share of the date's total, in percent
+    pub fn percent(&self, counts: &[Count], i: usize) -> f64 {
+        return share(counts[i], self.totals[i]);
+    }
+
+    /// Text of a cell: the count, its share of the date's total with
+    /// --percent, or both with --with-percent
+    pub fn cell(&self, opt: &Args, counts: &[Count], i: usize, separator: &str) -> String {
+        return cell_text(opt, counts[i], self.totals[i], separator);
+    }
+
+    /// The same of a row's docs, against the date's doc lines
+    pub fn doc_cell(&self, opt: &Args, docs: &[Count], i: usize, separator: &str) -> String {
+        return cell_text(opt, docs[i], self.doc_totals[i], separator);
+    }
+
+    /// Fields of a cell in csv and json: the count, or its share with
+    /// --percent, then the share with --with-percent
+    fn fields(&self, opt: &Args, counts: &[Count], i: usize) -> Vec<String> {
+        return cell_fields(opt, counts[i], self.totals[i]);
+    }
+
+    /// The same of a row's docs
+    fn doc_fields(&self, opt: &Args, docs: &[Count], i: usize) -> Vec<String> {
+        return cell_fields(opt, docs[i], self.doc_totals[i]);
+    }
+}
+
+/// Digit group separator the locale uses, from LC_ALL / LC_NUMERIC / LANG
+fn locale_separator() -> String {
+    let locale = ["LC_ALL", "LC_NUMERIC", "LANG"].iter()
+        .filter_map(|var| env::var(var).ok())
+        .find(|value| !value.is_empty())
+        .unwrap_or_default();
+    let (lang, region) = match locale.split(['.', '@']).next().unwrap_or("").split_once('_') {
+        Some((lang, region)) => (lang.to_string(), region.to_string()),
+        None => (locale.clone(), String::new()),
+    };
+    let separator = match (lang.as_str(), region.as_str()) {
+        ("de", "CH") | ("it", "CH") | ("fr", "CH") => "'",
+        ("en", _) | ("ja", _) | ("zh", _) | ("ko", _) | ("he", _) | ("th", _) | ("hi", _) => ",",
+        ("de", _) | ("nl", _) | ("it", _) | ("es", _) | ("pt", _) | ("da", _) | ("id", _) | ("tr", _) | ("el", _) => ".",
+        ("fr", _) | ("ru", _) | ("pl", _) | ("cs", _) | ("sk", _) | ("sv", _) | ("fi", _) | ("nb", _) | ("uk", _) | ("hu", _) => " ",
+        // C, POSIX, unset or unknown
+        _ => "",
+    };
+    return separator.to_string();
+}
+
+/// Separator to group digits with in human readable formats; machine
+/// readable formats always get raw numbers
+pub fn digit_separator(opt: &Args) -> String {
+    if matches!(opt.format, OutputFormat::Csv | OutputFormat::Json | OutputFormat::Yaml | OutputFormat::Msgpack | OutputFormat::CodeMaat) {
+        return String::new();
+    }
+    match opt.thousands_separator.as_str() {
+        "auto" => return locale_separator(),
+        "none" => return String::new(),
+        sep => return sep.to_string(),
+    }
+}
+
+/// Format a number with `separator` between groups of three digits
+pub fn group_digits(n: i64, separator: &str) -> String {
+    let digits = n.unsigned_abs().to_string();
+    if separator.is_empty() {
+        return n.to_string();
+    }
+    let mut out = String::new();
+    for (i, c) in digits.chars().enumerate() {
+        if i > 0 && (digits.len() - i).is_multiple_of(3) {
+            out.push_str(separator);
+        }
+        out.push(c);
+    }
+    if n < 0 {
+        out.insert(0, '-');
+    }
+    return out;
+}
+
+/// Print the report of each metric, labelled by metric when there are several.
+/// HTML only shows the first.
+pub fn display_results(opt: &Args, reports: &[(Metric, Report)], excluded: &HashMap<String, SkipReason>) {
+    let separator = digit_separator(opt);
+    let several = reports.len() > 1;
+    match opt.format {
+        // These documents include the breakdown and excluded files themselves
+        OutputFormat::Html => return print!("{}", html::render(opt, &reports[0].1, excluded, &separator, html::PageAssets::default())),
+        OutputFormat::Json if several => return print!("{}", render_metrics_json(opt, reports)),
+        OutputFormat::Json => return print!("{}", render_json(opt, &reports[0].1)),
+        OutputFormat::Yaml if several => return print!("{}", yaml::from_json(&render_metrics_json(opt, reports))),
+        OutputFormat::Yaml => return print!("{}", yaml::from_json(&render_json(opt, &reports[0].1))),
+        OutputFormat::CodeMaat => {
+            let files = reports.iter().find(|(metric, _)| *metric == Metric::Lines).map(|(_, report)| &report.table.files[..]).unwrap_or_default();
+            return print!("{}", code_maat::render_main_dev(files));
+        },
+        OutputFormat::Msgpack => {
+            let json = match several {
+                true => render_metrics_json(opt, reports),
+                false => render_json(opt, &reports[0].1),
+            };
+            return io::stdout().write_all(&msgpack::from_json(&json)).expect("failed printing to stdout");
+        },
+        OutputFormat::Csv => {
+            // With several metrics, a block per metric, its rows prefixed by
+            // the metric: snapshots missing for blame still count activity
+            for (i, (metric, report)) in reports.iter().enumerate() {
+                let mut lines = csv_lines(opt, report).into_iter();
+                let header = lines.next().unwrap_or_default();
+                if !several {
+                    println!("{header}");
+                    lines.for_each(|line| println!("{line}"));
+                    continue;
+                }
+                if i > 0 {
+                    println!();
+                }
+                println!("metric,{header}");
+                lines.for_each(|line| println!("{},{line}", metric.name()));
+            }
+        },
+        OutputFormat::Table | OutputFormat::Markdown => {
+            for (metric, report) in reports {
+                match (opt.format, several) {
+                    (_, false) => {},
+                    (OutputFormat::Markdown, true) => println!("## {}\n", metric.name()),
+                    (_, true) => println!("=== {} ===", metric.name()),
+                }
+                display_tables(opt, report, &separator);
+                if opt.format == OutputFormat::Table && opt.movers > 0 {
+                    display_movers(&report.table, opt.movers, &separator);
+                }
+                if several && opt.format == OutputFormat::Markdown && report.groups.is_empty() {
+                    println!();
+                }
+            }
+        },
+    }
+    for (_, report) in reports {
+        if !report.fingerprint.is_empty() {
+            display_fingerprint(opt, report);
+        }
+        if !report.coupling.is_empty() {
+            display_coupling(opt, report);
+        }
+    }
+    if opt.show_excluded {
+        display_excluded(excluded);
+    }
+}
+
+/// The biggest gains and losses at the latest date, after the terminal table
+fn display_movers(table: &Table, n: usize, separator: &str) {
+    let movers = movers(table, n);
+    if movers.is_empty() {
+        return;
+    }
+    let signed = |change: i64| match change > 0 {
+        true => format!("+{}", group_digits(change, separator)),
+        false => group_digits(change, separator),
+    };
+    let absolute = |m: &Mover| format!("{} {}", m.author, signed(m.change()));
+    let relative = |m: &Mover| format!("{} {:+.0}% ({} -> {})", m.author, m.percent().unwrap_or(0.0),
+        group_digits(m.before as i64, separator), group_digits(m.after as i64, separator));
+    println!("== {} ==", tf("movers-since", &[("date", &i18n::date_label(&table.dates[table.dates.len() - 2]))]));
+    let lines = [
+        (t("gained"), movers.gained.iter().map(absolute).collect::<Vec<String>>()),
+        (t("lost"), movers.lost.iter().map(absolute).collect()),
+        (t("grew"), movers.grew.iter().map(relative).collect()),
+        (t("shrank"), movers.shrank.iter().map(relative).collect()),
+    ];
+    let width = lines.iter().map(|(label, _)| label.chars().count()).max().unwrap_or(0).max(7);
+    for (label, list) in lines.iter().filter(|(_, list)| !list.is_empty()) {
+        println!("{label:<width$} {}", list.join(", "));
+    }
+    println!();
+}
+
+/// Coupled file pairs with their owners, one line per pair
+fn display_coupling(opt: &Args, report: &Report) {
+    let date = i18n::date_label(report.table.dates.last().map(|d| d.as_str()).unwrap_or_default());
+    let risk = |row: &CouplingRow| row.risk().map(|risk| t(&risk.replace(' ', "-"))).unwrap_or_default();
+    let owners = |row: &CouplingRow| match &row.teams {
+        Some((a, b)) => format!("{} ({a}) / {} ({b})", row.owners.0, row.owners.1),
+        None => format!("{} / {}", row.owners.0, row.owners.1),
+    };
+    match opt.format {
+        OutputFormat::Markdown => {
+            println!("### {}\n", tf("coupled-files-heading", &[("date", &date)]));
+            println!("| {} | {} | {} | {} | {} |", t("files"), t("coupling"), t("commits"), t("owners"), t("risk"));
+            println!("|---|---:|---:|---|---|");
+            for row in &report.coupling {
+                let (a, b) = &row.coupling.files;
+                println!("| `{}` <-> `{}` | {:.0}% | {} | {} | {} |", a.replace('|', "\\|"), b.replace('|', "\\|"), 100.0 * row.coupling.degree(),
+                    row.coupling.shared, owners(row).replace('|', "\\|"), risk(row));
+            }
+            println!();
+        },
+        OutputFormat::Csv => {
+            println!();
+            println!("file,coupled_file,degree,shared_commits,owner,coupled_owner,risk");
+            for row in &report.coupling {
+                let (a, b) = &row.coupling.files;
+                println!("{},{},{:.4},{},{},{},{}", csv_field(a), csv_field(b), row.coupling.degree(), row.coupling.shared,
+                    csv_field(&row.owners.0), csv_field(&row.owners.1), row.risk().unwrap_or(""));
+            }
+        },
+        _ => {
+            println!("== {} ==", tf("coupled-files-at", &[("date", &date)]));
+            for row in &report.coupling {
+                let (a, b) = &row.coupling.files;
+                let risk = row.risk().map(|_| format!(", {}", risk(row))).unwrap_or_default();
+                let together = tf("coupled-together", &[("degree", &format!("{:.0}", 100.0 * row.coupling.degree())), ("commits", &row.coupling.shared.to_string())]);
+                println!("{a} <-> {b}: {together}, {}{risk}", owners(row));
+            }
+            println!();
+        },
+    }
+}
+
+/// The authors of a single-date table as a numbered list with their share,
+/// for `at`
+pub fn display_ranking(opt: &Args, table: &Table, target: &str) {
+    let separator = digit_separator(opt);
+    let date = table.dates.first().map(|d| d.as_str()).unwrap_or_default();
+    match target == date {
+        true => println!("{}", tf("lines-at", &[("date", &i18n::date_label(date))])),
+        false => println!("{}", tf("lines-at-target", &[("target", target), ("date", &i18n::date_label(date))])),
+    }
+    let total = table.totals.first().copied().unwrap_or(0);
+    let long_auth = table.rows.iter().map(|x| x.author.len()).max().unwrap_or(0);
+    let mut rank = 0;
+    for group in &table.sections() {
+        if !table.groups.is_empty() {
+            println!("[{}]", group.name);
+        }
+        for row in &table.rows[group.rows.clone()] {
+            rank += 1;
+            let count = row.counts.first().copied().unwrap_or(0);
+            let share = match total {
+                0 => 0.0,
+                total => 100.0 * count as f64 / total as f64,
+            };
+            println!("{rank:>4}. {:<long_auth$}  {:>10}  {share:>5.1}%", row.author, group_digits(count as i64, &separator));
+        }
+    }
+    if let Some(entropy) = table.entropy.first() {
+        println!("{} {entropy:.2}", summary_label(ENTROPY_LABEL));
+    }
+    for (label, cells) in table.summary_rows() {
+        println!("{} {}", summary_label(label), cells.first().map(|c| c.as_str()).unwrap_or_default());
+    }
+}
+
+/// The author table, or one per group, as a table or markdown
+pub fn display_tables(opt: &Args, report: &Report, separator: &str) {
+    let tables = match report.groups.is_empty() {
+        true => vec![(String::new(), report.table.clone())],
+        false => report.groups.clone(),
+    };
+    for (name, table) in &tables {
+        match (opt.format, name.is_empty()) {
+            (_, true) => {},
+            (OutputFormat::Markdown, false) => println!("### {name}\n"),
+            (_, false) => println!("== {name} =="),
+        }
+        match opt.format {
+            _ if opt.chart => chart::display_sparklines(opt, table, separator),
+            OutputFormat::Markdown => {
+                display_markdown(opt, table, separator);
+                if !name.is_empty() {
+                    println!();
+                }
+            },
+            _ => display_table(opt, table, separator),
+        }
+    }
+}
+
+/// Each author's share of lines per group, one line per author
+fn display_fingerprint(opt: &Args, report: &Report) {
+    let date = i18n::date_label(report.table.dates.last().map(|d| d.as_str()).unwrap_or_default());
+    match opt.format {
+        OutputFormat::Markdown => {
+            println!("### {}\n", tf("fingerprint-heading", &[("date", &date)]));
+            println!("| {} | {} | {} |", t("author"), t("lines"), t("groups"));
+            println!("|---|---:|---|");
+            for row in &report.fingerprint {
+                println!("| {} | {} | {} |", row.author.replace('|', "\\|"), row.total, row.summary().replace('|', "\\|"));
+            }
+            println!();
+        },
+        OutputFormat::Csv => {
+            println!();
+            println!("author,group,lines");
+            for row in &report.fingerprint {
+                for (group, count) in &row.groups {
+                    println!("{},{},{count}", csv_field(&row.author), csv_field(group));
+                }
+            }
+        },
+        _ => {
+            println!("== {} ==", tf("fingerprint-at", &[("date", &date)]));
+            let long_auth = report.fingerprint.iter().map(|row| row.author.len()).max().unwrap_or(0);
+            for row in &report.fingerprint {
+                println!("{:<long_auth$}  {}", row.author, row.summary());
+            }
+            println!();
+        },
+    }
+}
+
+fn display_table(opt: &Args, table: &Table, separator: &str) {
+    let sections = table.sections();
+    let subtotal = |group: &Group| tf("group-total", &[("group", &group.name)]);
+    let dates = table.column_labels();
+    let long_auth = table.rows.iter().map(|x| x.author.len())
+        .chain(table.groups.iter().map(|g| subtotal(g).chars().count()))
+        .chain(table.entropy.first().map(|_| summary_label(ENTROPY_LABEL).chars().count()))
+        .chain(table.summary_rows().iter().map(|(label, _)| summary_label(label).chars().count()))
+        .max().unwrap_or(0).max(t("date").chars().count());
+
+    // Room for "1,234,567 (100%)", or a longer date label
+    let width = dates.iter().map(|date| date.chars().count()).max().unwrap_or(0).max(if opt.with_percent { 16 } else { 10 });
+    print!("{:<long_auth$}, ", t("date"));
+    for date in &dates {
+       print!("{:>width$}, ", date);
+    }
+    println!();
+
+    for group in &sections {
+        if !table.groups.is_empty() {
+            println!("[{}]", group.name);
+        }
+        for row in &table.rows[group.rows.clone()] {
+            print!("{:<long_auth$}, ", row.author);
+            for cell in table.cells(opt, &row.counts, &row.docs, separator) {
+                print!("{:>width$}, ", cell);
+            }
+            println!();
+        };
+        if !table.groups.is_empty() {
+            print!("{:<long_auth$}, ", subtotal(group));
+            for cell in table.cells(opt, &group.totals, &group.doc_totals, separator) {
+                print!("{:>width$}, ", cell);
+            }
+            println!();
+            println!();
+        }
+    }
+    if !table.entropy.is_empty() {
+        print!("{:<long_auth$}, ", summary_label(ENTROPY_LABEL));
+        for value in table.spread(table.entropy.iter().map(|value| format!("{value:.2}")).collect()) {
+            print!("{:>width$}, ", value);
+        }
+        println!();
+    }
+    for (label, cells) in table.summary_rows() {
+        print!("{:<long_auth$}, ", summary_label(label));
+        for cell in table.spread(cells) {
+            print!("{:>width$}, ", cell);
+        }
+        println!();
+    }
+    if table.groups.is_empty() {
+        println!();
+    }
+}
+
+fn display_markdown(opt: &Args, table: &Table, separator: &str) {
+    let dates = table.column_labels();
+    let columns = dates.len();
+    println!("| {} | {} |", t("author"), dates.join(" | "));
+    println!("|---|{}", "---:|".repeat(columns));
+    for group in &table.sections() {
+        if !table.groups.is_empty() {
+            println!("| **{}** |{}", group.name.replace('|', "\\|"), " |".repeat(columns));
+        }
+        for row in &table.rows[group.rows.clone()] {
+            let cells = table.cells(opt, &row.counts, &row.docs, separator);
+            println!("| {} | {} |", row.author.replace('|', "\\|"), cells.join(" | "));
+        }
+        if !table.groups.is_empty() {
+            let cells: Vec<String> = table.cells(opt, &group.totals, &group.doc_totals, separator).iter().map(|cell| format!("*{cell}*")).collect();
+            println!("| *{}* | {} |", tf("group-total", &[("group", &group.name)]).replace('|', "\\|"), cells.join(" | "));
+        }
+    }
+    let emphasized = |cell: String| if cell.is_empty() { cell } else { format!("*{cell}*") };
+    if !table.entropy.is_empty() {
+        let cells: Vec<String> = table.spread(table.entropy.iter().map(|value| format!("{value:.2}")).collect()).into_iter().map(emphasized).collect();
+        println!("| *{}* | {} |", summary_label(ENTROPY_LABEL), cells.join(" | "));
+    }
+    for (label, cells) in table.summary_rows() {
+        let cells: Vec<String> = table.spread(cells).into_iter().map(emphasized).collect();
+        println!("| *{}* | {} |", summary_label(label), cells.join(" | "));
+    }
+}
+
+/// The header and rows of the CSV output
+pub fn csv_lines(opt: &Args, report: &Report) -> Vec<String> {
+    // One flat table; with --group-by, each row is prefixed by its group
+    let mut lines = Vec::new();
+    let tables = match report.groups.is_empty() {
+        true => vec![(None, &report.table)],
+        false => report.groups.iter().map(|(name, table)| (Some(name), table)).collect(),
+    };
+    let prefix = |name: Option<&String>| name.map(|x| format!("{},", csv_field(x))).unwrap_or_default();
+    let group_column = if report.groups.is_empty() { "" } else { "group," };
+    // With --with-percent, each date's count is followed by its share, and
+    // with --split-docs by its docs columns, which the summary rows leave empty
+    let split = report.table.splits_docs();
+    let mut columns: Vec<String> = Vec::new();
+    for date in &report.table.dates {
+        let dated = match split {
+            true => vec![date.clone(), format!("{date}_docs")],
+            false => vec![date.clone()],
+        };
+        for column in dated {
+            columns.push(column.clone());
+            if opt.with_percent {
+                columns.push(format!("{column}_percent"));
+            }
+        }
+    }
+    let blanks = ",".repeat(columns.len() / report.table.dates.len().max(1) - 1);
+    let summary = |cells: Vec<String>| cells.iter().map(|cell| format!("{cell}{blanks}")).collect::<Vec<String>>().join(",");
+    lines.push(format!("{group_column}author,{}", columns.join(",")));
+    for (name, table) in tables {
+        for row in &table.rows {
+            let cells: Vec<String> = (0..table.dates.len()).flat_map(|i| {
+                let mut fields = table.fields(opt, &row.counts, i);
+                if split {
+                    fields.extend(table.doc_fields(opt, &row.docs, i));
+                }
+                fields
+            }).collect();
+            lines.push(format!("{}{},{}", prefix(name), csv_field(&row.author), cells.join(",")));
+        }
+    }
+    if !report.table.entropy.is_empty() {
+        let cells: Vec<String> = report.table.entropy.iter().map(|value| format!("{value:.4}")).collect();
+        lines.push(format!("{}{ENTROPY_LABEL},{}", if report.groups.is_empty() { "" } else { "," }, summary(cells)));
+    }
+    for (label, cells) in report.table.summary_rows() {
+        lines.push(format!("{}{label},{}", if report.groups.is_empty() { "" } else { "," }, summary(cells)));
+    }
+    return lines;
+}
+
+/// Quote and escape a JSON string
+pub fn json_string(text: &str) -> String {
+    let mut out = String::from("\"");
+    for c in text.chars() {
+        match c {
+            '"' => out.push_str("\\\""),
+            '\\' => out.push_str("\\\\"),
+            '\n' => out.push_str("\\n"),
+            '\t' => out.push_str("\\t"),
+            c if (c as u32) < 0x20 => out.push_str(&format!("\\u{:04x}", c as u32)),
+            c => out.push(c),
+        }
+    }
+    out.push('"');
+    return out;
+}
+
+/// Rows of a table as a JSON array of {"author", "counts"} objects, plus
+/// "percents" with --with-percent and "docs" with --split-docs
+fn json_rows(opt: &Args, table: &Table, indent: &str) -> String {
+    let series = |name: &str, fields: &[Vec<String>]| {
+        let cells: Vec<&str> = fields.iter().map(|f| f[0].as_str()).collect();
+        let percents = match opt.with_percent {
+            true => format!(", \"{name}percents\": [{}]", fields.iter().map(|f| f[1].as_str()).collect::<Vec<&str>>().join(", ")),
+            false => String::new(),
+        };
+        return format!("[{}]{percents}", cells.join(", "));
+    };
+    let rows: Vec<String> = table.rows.iter().map(|row| {
+        let fields: Vec<Vec<String>> = (0..table.dates.len()).map(|i| table.fields(opt, &row.counts, i)).collect();
+        let docs = match table.splits_docs() {
+            true => format!(", \"docs\": {}", series("docs_", &(0..table.dates.len()).map(|i| table.doc_fields(opt, &row.docs, i)).collect::<Vec<_>>())),
+            false => String::new(),
+        };
+        let margins = match row.margins.is_empty() {
+            true => String::new(),
+            false => format!(", \"margins\": [{}]", row.margins.iter().map(|margin| format!("{margin:.1}")).collect::<Vec<String>>().join(", ")),
+        };
+        format!("{indent}  {{\"author\": {}, \"counts\": {}{docs}{margins}}}", json_string(&row.author), series("", &fields))
+    }).collect();
+    match rows.is_empty() {
+        true => return "[]".to_string(),
+        false => return format!("[\n{}\n{indent}]", rows.join(",\n")),
+    }
+}
+
+pub fn render_json(opt: &Args, report: &Report) -> String {
+    let dates: Vec<String> = report.table.dates.iter().map(|d| json_string(d)).collect();
+    let mut out = String::from("{\n");
+    out.push_str(&format!("  \"dates\": [{}],\n", dates.join(", ")));
+    out.push_str(&format!("  \"estimated\": {},\n", report.table.estimated()));
+    out.push_str(&format!("  \"authors\": {}", json_rows(opt, &report.table, "  ")));
+    if report.table.estimated() {
+        let values: Vec<String> = report.table.margins.iter().map(|margin| format!("{margin:.1}")).collect();
+        out.push_str(&format!(",\n  \"margins\": [{}]", values.join(", ")));
+    }
+    if !report.groups.is_empty() {
+        let groups: Vec<String> = report.groups.iter()
+            .map(|(name, table)| format!("    {{\"group\": {}, \"authors\": {}}}", json_string(name), json_rows(opt, table, "    ")))
+            .collect();
+        out.push_str(&format!(",\n  \"groups\": [\n{}\n  ]", groups.join(",\n")));
+    }
+    if !report.table.entropy.is_empty() {
+        let values: Vec<String> = report.table.entropy.iter().map(|value| format!("{value:.4}")).collect();
+        out.push_str(&format!(",\n  \"entropy\": [{}]", values.join(", ")));
+    }
+    if !report.table.bus_factor.is_empty() {
+        let series = |value: fn(&BusFactor) -> usize| report.table.bus_factor.iter().map(|b| value(b).to_string()).collect::<Vec<String>>().join(", ");
+        out.push_str(&format!(",\n  \"bus_factor\": {{\"authors\": [{}], \"dominated_files\": [{}], \"files\": [{}]}}",
+            series(|b| b.authors), series(|b| b.dominated), series(|b| b.files)));
+        let owners: Vec<String> = report.table.files.iter().filter_map(|file| {
+            let (author, count) = file.owners.first()?;
+            let share = *count as f64 / file.total as f64;
+            (100.0 * share > opt.dominance).then(|| format!("    {{\"path\": {}, \"author\": {}, \"share\": {share:.4}}}", json_string(&file.path), json_string(author)))
+        }).collect();
+        if !owners.is_empty() {
+            out.push_str(&format!(",\n  \"dominant_owners\": [\n{}\n  ]", owners.join(",\n")));
+        }
+    }
+    if !report.table.health.is_empty() {
+        let series = |value: fn(&Health) -> String| report.table.health.iter().map(value).collect::<Vec<String>>().join(", ");
+        out.push_str(&format!(",\n  \"health\": {{\"score\": [{}], \"bus_factor\": [{}], \"entropy\": [{}], \"stale_share\": [{}], \"active_authors\": [{}]}}",
+            series(|h| format!("{:.1}", h.score)), series(|h| h.bus_factor.to_string()), series(|h| format!("{:.4}", h.entropy)),
+            series(|h| format!("{:.4}", h.stale_share)), series(|h| h.active_authors.to_string())));
+    }
+    if !report.table.ineligible.is_empty() {
+        let dates: Vec<String> = report.table.dates.iter().zip(&report.table.ineligible).filter(|(_, &none)| none).map(|(date, _)| json_string(date)).collect();
+        out.push_str(&format!(",\n  \"no_eligible_files\": [{}]", dates.join(", ")));
+    }
+    if !report.categories.is_empty() {
+        let rows: Vec<String> = report.categories.iter().map(|row| {
+            let counts: Vec<String> = row.counts.iter().map(|c| c.to_string()).collect();
+            format!("    {{\"author\": {}, \"category\": \"{}\", \"counts\": [{}]}}", json_string(&row.author), row.category, counts.join(", "))
+        }).collect();
+        out.push_str(&format!(",\n  \"categories\": [\n{}\n  ]", rows.join(",\n")));
+    }
+    if !report.fingerprint.is_empty() {
+        let rows: Vec<String> = report.fingerprint.iter().map(|row| {
+            let groups: Vec<String> = row.groups.iter().map(|(group, count)| format!("{}: {count}", json_string(group))).collect();
+            format!("    {{\"author\": {}, \"lines\": {}, \"groups\": {{{}}}}}", json_string(&row.author), row.total, groups.join(", "))
+        }).collect();
+        out.push_str(&format!(",\n  \"fingerprint\": [\n{}\n  ]", rows.join(",\n")));
+    }
+    if !report.coupling.is_empty() {
+        let rows: Vec<String> = report.coupling.iter().map(|row| {
+            let (a, b) = &row.coupling.files;
+            let teams = match &row.teams {
+                Some((team_a, team_b)) => format!(", \"teams\": [{}, {}]", json_string(team_a), json_string(team_b)),
+                None => String::new(),
+            };
+            let risk = row.risk().map(json_string).unwrap_or("null".to_string());
+            format!("    {{\"files\": [{}, {}], \"degree\": {:.4}, \"shared_commits\": {}, \"owners\": [{}, {}]{teams}, \"risk\": {risk}}}",
+                json_string(a), json_string(b), row.coupling.degree(), row.coupling.shared, json_string(&row.owners.0), json_string(&row.owners.1))
+        }).collect();
+        out.push_str(&format!(",\n  \"coupling\": [\n{}\n  ]", rows.join(",\n")));
+    }
+    out.push_str("\n}\n");
+    return out;
+}
+
+/// One JSON document holding the report of each metric, by metric name
+pub fn render_metrics_json(opt: &Args, reports: &[(Metric, Report)]) -> String {
+    let metrics: Vec<String> = reports.iter().map(|(metric, report)| {
+        let document = render_json(opt, report).trim_end().replace('\n', "\n    ");
+        format!("    \"{}\": {document}", metric.name())
+    }).collect();
+    return format!("{{\n  \"metrics\": {{\n{}\n  }}\n}}\n", metrics.join(",\n"));
+}
+
+/// List the files left out of the analysis, with the reason they were skipped
+fn display_excluded(excluded: &HashMap<String, SkipReason>) {
+    let mut paths: Vec<&String> = excluded.keys().collect();
+    paths.sort();
+
+    println!("{}", tf("excluded-files", &[("count", &paths.len().to_string())]));
+    for path in paths {
+        let skip = &excluded[path];
+        match skip.pattern.is_empty() {
+            true => println!("{:<20} {path}", skip.reason),
+            false => println!("{:<20} {path}  ({} {})", skip.reason, skip.source, skip.pattern),
+        }
+    }
+    println!();
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use clap::Parser;
+    use crate::identity::Author;
+    use crate::AuthorCount;
+
+    fn table(rows: &[(&str, [Count; 2])]) -> Table {
+        let rows: Vec<Row> = rows.iter()
+            .map(|(author, counts)| Row { author: author.to_string(), keys: Vec::new(), counts: counts.to_vec(), docs: Vec::new(), margins: Vec::new() })
+            .collect();
+        let totals = column_totals(&rows, 2);
+        return Table {
+            dates: vec!["2020-01-01".to_string(), "2020-02-01".to_string()],
+            rows,
+            totals,
+            doc_totals: Vec::new(),
+            groups: Vec::new(),
+            files: Vec::new(),
+            files_at: None,
+            entropy: Vec::new(),
+            bus_factor: Vec::new(),
+            health: Vec::new(),
+            ineligible: Vec::new(),
+            margins: Vec::new(),
+        };
+    }
+
+    #[test]
+    fn finds_movers() {
+        let table = table(&[("Alice", [100, 150]), ("Bob", [10, 30]), ("Carol", [50, 20]), ("Dan", [0, 5]), ("Eve", [7, 7])]);
+        let movers = movers(&table, 2);
+        let authors = |list: &[Mover]| list.iter().map(|m| m.author.clone()).collect::<Vec<String>>();
+        assert_eq!(authors(&movers.gained), ["Alice", "Bob"]);
+        assert_eq!(authors(&movers.lost), ["Carol"]);
+        assert_eq!(authors(&movers.grew), ["Bob", "Alice"]);
+        assert_eq!(movers.shrank[0].percent(), Some(-60.0));
+        assert!(super::movers(&table, 0).is_empty());
+    }
+
+    #[test]
+    fn pins_listed_authors_to_the_top() {
+        let counts = [("Alice", 10), ("Bob", 5), ("Carol", 3), ("Dan", 1), ("Eve", 2)];
+        let acnt: AuthorCount = counts.iter()
+            .map(|&(name, count)| (Author::new(name, &format!("{}@example.com", name.to_lowercase())), count))
+            .collect();
+        let perf = AuthorPerformance::from([("2020-01-01".to_string(), acnt)]);
+        let order = AuthorOrder::parse("# current team\nCarol\n\nDan <DAN@example.com>\nnobody@example.com\n");
+        let authors = |args: &[&str]| {
+            let opt = Args::parse_from(["git-author-stats"].iter().chain(args));
+            let table = Table::build(&opt, &perf, None, &IdentityResolver::default(), &Teams::default(), &order, None);
+            table.rows.into_iter().map(|row| row.author).collect::<Vec<String>>()
+        };
+        assert_eq!(authors(&[]), ["Carol", "Dan", "Alice", "Bob", "Eve"]);
+        assert_eq!(authors(&["--top", "1"]), ["Carol", "Dan", "Alice", "Other (2 authors)"]);
+        assert_eq!(authors(&["--sort", "alphabetical"]), ["Carol", "Dan", "Alice", "Bob", "Eve"]);
+    }
+
+    #[test]
+    fn adds_sample_margins() {
+        let (alice, bob, carol) = (Author::new("Alice", "alice@example.com"), Author::new("Bob", "bob@example.com"), Author::new("Carol", "carol@example.com"));
+        let mut estimate = Estimate::new(0.5, 2, 4);
+        estimate.add(&AuthorCount::from([(alice.clone(), 4), (bob.clone(), 2)]), 1);
+        estimate.add(&AuthorCount::from([(carol.clone(), 1)]), 1);
+        let acnt = AuthorCount::from([(alice.clone(), 8), (bob.clone(), 4), (carol.clone(), 2)]);
+        let perf = AuthorPerformance::from([("2020-01-01".to_string(), acnt)]);
+        let opt = Args::parse_from(["git-author-stats", "--top", "1", "--format", "json"]);
+        let mut table = Table::build(&opt, &perf, None, &IdentityResolver::default(), &Teams::default(), &AuthorOrder::default(), None);
+        assert!(!table.estimated());
+        table.add_margins(&HashMap::from([("2020-01-01".to_string(), estimate.clone())]), &IdentityResolver::default());
+        assert!(table.estimated());
+        assert_eq!(table.rows[0].margins, [estimate.margin(&alice)]);
+        // Bob and Carol are rolled into "Other"
+        let other = (estimate.margin(&bob).powi(2) + estimate.margin(&carol).powi(2)).sqrt();
+        assert!((table.rows[1].margins[0] - other).abs() < 1e-9);
+        assert_eq!(table.margins, [estimate.total_margin()]);
+        let report = Report { table, groups: Vec::new(), categories: Vec::new(), fingerprint: Vec::new(), coupling: Vec::new() };
+        assert!(render_json(&opt, &report).contains("\"estimated\": true"));
+    }
+}
@@ -0,0 +1,211 @@
+use hashbrown::HashMap;
+
+use crate::analysis::FileOwnership;
+use crate::backend::BlameLine;
+use crate::identity::{Author, IdentityResolver, OptOut, Special};
+use crate::patch::FilePatch;
+use crate::report::json_string;
+use crate::Count;
+
+//----
+// Reviewer suggestions
+//
+// `suggest-reviewers --file PATH` ranks who should review a change to some
+// files: the people owning most of their lines, and of the directories they're
+// in (subdirectories included), at one revision.  The file's own lines weigh
+// more, FILE_WEIGHT against DIR_WEIGHT of the score, so a directory's owner
+// comes right after the file's.  The change's author is left out with
+// --exclude, as are bots, opted-out authors and lines not committed yet.
+//
+// With --patch, the files come from a unified diff, and each reviewer gets an
+// estimate of the time the review takes them: the lines the patch touches in
+// code they own go at OWNED_LINES_PER_HOUR, the rest at UNOWNED_LINES_PER_HOUR,
+// for bots that balance review load across a team.  An added line belongs to
+// the owner of the line it follows, and the lines of a new file to no-one.
+
+const FILE_WEIGHT: f64 = 0.7;
+const DIR_WEIGHT: f64 = 0.3;
+
+/// Review pace in code the reviewer knows and in code they don't; careful
+/// reviews go at a few hundred lines an hour
+const OWNED_LINES_PER_HOUR: f64 = 400.0;
+const UNOWNED_LINES_PER_HOUR: f64 = 150.0;
+
+/// How much of a patch one reviewer owns, and how long reviewing it takes
+#[derive(Debug, Clone, PartialEq)]
+pub struct Burden {
+    /// Lines the patch touches in code the reviewer owns
+    pub owned_lines: Count,
+    pub unowned_lines: Count,
+    pub minutes: f64,
+}
+
+#[derive(Debug, Clone, PartialEq)]
+pub struct Candidate {
+    pub author: String,
+    pub email: String,
+    /// Canonical identity key
+    pub key: String,
+    /// Lines owned in the changed files
+    pub file_lines: Count,
+    /// Lines owned in their directories
+    pub dir_lines: Count,
+    /// Weighted percent of both
+    pub score: f64,
+    /// With --patch
+    pub burden: Option<Burden>,
+}
+
+/// The directory of `file` whose files count for it, "" for the top level
+pub fn dir_of(file: &str) -> &str {
+    return file.rsplit_once('/').map(|(dir, _)| dir).unwrap_or("");
+}
+
+fn within(dir: &str, path: &str) -> bool {
+    return dir.is_empty() || path.strip_prefix(dir).is_some_and(|rest| rest.starts_with('/'));
+}
+
+/// Candidates to review a change to `changed`, best first, from the owners in
+/// `files`; `keep` decides who may review
+pub fn rank(files: &FileOwnership, changed: &[String], resolver: &IdentityResolver, keep: &dyn Fn(&Author) -> bool) -> Vec<Candidate> {
+    let mut dirs: Vec<&str> = changed.iter().map(|file| dir_of(file)).collect();
+    dirs.sort();
+    dirs.dedup();
+    let mut file_lines: HashMap<Author, Count> = HashMap::new();
+    let mut dir_lines: HashMap<Author, Count> = HashMap::new();
+    for (path, acnt) in &files.files {
+        let in_file = changed.contains(path);
+        let in_dir = dirs.iter().any(|dir| within(dir, path));
+        for (author, &count) in acnt {
+            if in_file {
+                *file_lines.entry(author.clone()).or_insert(0) += count;
+            }
+            if in_dir {
+                *dir_lines.entry(author.clone()).or_insert(0) += count;
+            }
+        }
+    }
+    let file_total: Count = file_lines.values().sum();
+    let dir_total: Count = dir_lines.values().sum();
+
+    let names = resolver.display_names(dir_lines.iter().map(|(author, count)| (author, *count)));
+    let mut by_key: HashMap<String, Candidate> = HashMap::new();
+    for (raw, &dir_count) in &dir_lines {
+        if !keep(raw) || Special::of(raw).is_some() || *raw == OptOut::aggregate() {
+            continue;
+        }
+        let canonical = resolver.resolve(raw).author;
+        let key = canonical.key();
+        let candidate = by_key.entry(key.clone()).or_insert_with(|| Candidate {
+            author: names.get(&key).cloned().unwrap_or(canonical.name.clone()),
+            email: canonical.email.clone(),
+            key,
+            file_lines: 0,
+            dir_lines: 0,
+            score: 0.0,
+            burden: None,
+        });
+        candidate.file_lines += file_lines.get(raw).copied().unwrap_or(0);
+        candidate.dir_lines += dir_count;
+    }
+    let share = |count: Count, total: Count| if total > 0 { 100.0 * count as f64 / total as f64 } else { 0.0 };
+    let mut candidates: Vec<Candidate> = by_key.into_values().map(|mut candidate| {
+        candidate.score = FILE_WEIGHT * share(candidate.file_lines, file_total) + DIR_WEIGHT * share(candidate.dir_lines, dir_total);
+        candidate
+    }).collect();
+    candidates.sort_by(|a, b| b.score.total_cmp(&a.score).then_with(|| a.author.cmp(&b.author)));
+    return candidates;
+}
+
+/// The canonical owner of each line `patch` touches, from `blamed`, the
+/// file's lines before the change; none for lines of no-one's
+pub fn touched_owners(patch: &FilePatch, blamed: &[BlameLine], resolver: &IdentityResolver) -> Vec<Option<String>> {
+    let owner = |line: usize| blamed.get(line.max(1) - 1).map(|blamed| resolver.resolve(&blamed.author).author.key());
+    return patch.removed.iter().chain(&patch.added_after).map(|&line| owner(line)).collect();
+}
+
+/// The review burden of `candidate` for a patch touching lines of `owners`
+pub fn burden(candidate: &Candidate, owners: &[Option<String>]) -> Burden {
+    let owned = owners.iter().filter(|owner| owner.as_ref() == Some(&candidate.key)).count() as Count;
+    let unowned = owners.len() as Count - owned;
+    let hours = owned as f64 / OWNED_LINES_PER_HOUR + unowned as f64 / UNOWNED_LINES_PER_HOUR;
+    return Burden { owned_lines: owned, unowned_lines: unowned, minutes: 60.0 * hours };
+}
+
+pub fn render_text(candidates: &[Candidate]) -> String {
+    let width = candidates.iter().map(|c| c.author.len()).max().unwrap_or(0).max("reviewer".len());
+    let burdens = candidates.iter().any(|c| c.burden.is_some());
+    let mut out = format!("{:<width$}  score  file lines  dir lines", "reviewer");
+    out.push_str(if burdens { "  owned  unowned  minutes\n" } else { "\n" });
+    for candidate in candidates {
+        out.push_str(&format!("{:<width$}  {:5.1}  {:>10}  {:>9}", candidate.author, candidate.score, candidate.file_lines, candidate.dir_lines));
+        match &candidate.burden {
+            Some(b) => out.push_str(&format!("  {:>5}  {:>7}  {:7.1}\n", b.owned_lines, b.unowned_lines, b.minutes)),
+            None => out.push('\n'),
+        }
+    }
+    return out;
+}
+
+pub fn render_json(revision: &str, files: &[String], candidates: &[Candidate]) -> String {
+    let files: Vec<String> = files.iter().map(|file| json_string(file)).collect();
+    let reviewers: Vec<String> = candidates.iter().map(|c| {
+        let burden = match &c.burden {
+            Some(b) => format!(", \"owned_lines\": {}, \"unowned_lines\": {}, \"minutes\": {:.1}", b.owned_lines, b.unowned_lines, b.minutes),
+            None => String::new(),
+        };
+        format!("    {{\"author\": {}, \"email\": {}, \"score\": {:.1}, \"file_lines\": {}, \"dir_lines\": {}{burden}}}",
+            json_string(&c.author), json_string(&c.email), c.score, c.file_lines, c.dir_lines)
+    }).collect();
+    let mut out = String::from("{\n");
+    out.push_str(&format!("  \"revision\": {},\n", json_string(revision)));
+    out.push_str(&format!("  \"files\": [{}],\n", files.join(", ")));
+    match reviewers.is_empty() {
+        true => out.push_str("  \"reviewers\": []\n"),
+        false => out.push_str(&format!("  \"reviewers\": [\n{}\n  ]\n", reviewers.join(",\n"))),
+    }
+    out.push('}');
+    return out;
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::analysis::{blame_snapshots, SnapshotSpec};
+    use crate::cache::BlameCache;
+    use crate::filter::FileFilter;
+    use crate::fixture::{Fixture, ALICE, BOB};
+
+    #[test]
+    fn ranks_owners_of_the_file_then_the_directory() {
+        let fixture = Fixture::new();
+        fixture.commit_files(&[("src/util.rs", b"1\n2\n3\n4\n5\n6\n7\n8\n9\n10\n")], BOB, "2020-03-10T12:00:00", "Add util");
+        let spec = SnapshotSpec { keep_files: true, ..SnapshotSpec::new(vec!["2020-04-01".to_string()]) };
+        let files = blame_snapshots(&fixture.backend(), &spec, &FileFilter::default(), &mut BlameCache::in_memory()).unwrap().latest.unwrap();
+        let changed = vec!["src/main.rs".to_string()];
+        let resolver = IdentityResolver::default();
+
+        let candidates = rank(&files, &changed, &resolver, &|_| true);
+        let owners: Vec<(&str, Count, Count)> = candidates.iter().map(|c| (c.author.as_str(), c.file_lines, c.dir_lines)).collect();
+        // Bob: 0.7 * 40% + 0.3 * 80%; Alice: 0.7 * 60% + 0.3 * 20%
+        assert_eq!(owners, [("Bob", 2, 12), ("Alice", 3, 3)]);
+        assert!((candidates[0].score - 52.0).abs() < 1e-9 && (candidates[1].score - 48.0).abs() < 1e-9);
+
+        let without_bob = rank(&files, &changed, &resolver, &|author| author.email != BOB.1);
+        assert_eq!(without_bob.iter().map(|c| c.email.as_str()).collect::<Vec<&str>>(), [ALICE.1]);
+        assert!(render_json("abc", &changed, &without_bob).contains("\"files\": [\"src/main.rs\"],\n  \"reviewers\": [\n    {\"author\": \"Alice\", \"email\": \"alice@example.com\", \"score\": 48.0, \"file_lines\": 3, \"dir_lines\": 3}\n  ]"));
+
+        // main.rs is Alice's lines 1-3 and Bob's 4-5: one line of each changed, one added after Bob's
+        let patch = crate::patch::parse_patch("--- a/src/main.rs\n+++ b/src/main.rs\n@@ -3,2 +3,3 @@\n-c\n-d\n+x\n+y\n+z\n").unwrap();
+        let blamed = fixture.backend().blame_lines("HEAD", "src/main.rs").unwrap();
+        let owners = touched_owners(&patch[0], &blamed, &resolver);
+        assert_eq!(owners.len(), 5);
+        let alice = burden(&candidates[1], &owners);
+        assert_eq!((alice.owned_lines, alice.unowned_lines), (1, 4));
+        assert!((alice.minutes - 60.0 * (1.0 / 400.0 + 4.0 / 150.0)).abs() < 1e-9);
+        assert_eq!(burden(&candidates[0], &owners).owned_lines, 4);
+        assert_eq!(touched_owners(&crate::patch::FilePatch { added_after: vec![0], ..Default::default() }, &[], &resolver), [None]);
+        assert_eq!(dir_of("src/main.rs"), "src");
+        assert_eq!(dir_of("README.md"), "");
+    }
+}
@@ -0,0 +1,135 @@
+use hashbrown::HashMap;
+
+use crate::cache::fingerprint;
+use crate::identity::Author;
+use crate::{AuthorCount, Count};
+
+//----
+// Sampling
+//
+// --sample P% blames a pseudo-random P% of the files at each snapshot and
+// scales the counts up by 100/P, for a quick estimate of a huge repo before a
+// full run.  A file is picked by a hash of its path, so every run, and every
+// snapshot of one run, picks the same files, and changes between snapshots
+// aren't sampling noise.  Each file is picked independently, which makes the
+// scaled count the Horvitz-Thompson estimate, with variance (1-p)/p² Σ y² for
+// per-file counts y; the margins are the 95% confidence interval.
+
+/// z-score of a two-sided 95% confidence interval
+const Z_95: f64 = 1.96;
+
+/// `P%` or `P` as a fraction from 0 (excluded) to 1
+pub fn parse_sample(text: &str) -> Result<f64, String> {
+    let number = text.strip_suffix('%').unwrap_or(text);
+    match number.trim().parse::<f64>() {
+        Ok(percent) if percent > 0.0 && percent <= 100.0 => return Ok(percent / 100.0),
+        _ => return Err(format!("expected a percentage above 0 and at most 100%, got {text:?}")),
+    }
+}
+
+/// Whether `path` is in the sample of `fraction` of the files
+pub fn sampled(path: &str, fraction: f64) -> bool {
+    let hash = u64::from_str_radix(&fingerprint(path.as_bytes()), 16).expect("fingerprints are hex");
+    // FNV's high bits mix best
+    let position = (hash >> 11) as f64 / (1u64 << 53) as f64;
+    return position < fraction;
+}
+
+/// The estimate of one snapshot's counts from the sampled files
+#[derive(Debug, Clone, Default, PartialEq)]
+pub struct Estimate {
+    pub fraction: f64,
+    /// Files blamed, of `files` after the excludes
+    pub sampled: usize,
+    pub files: usize,
+    /// Sums of the squares of each author's per-file counts, as blamed
+    pub squares: HashMap<Author, f64>,
+    pub total_squares: f64,
+}
+
+impl Estimate {
+    pub fn new(fraction: f64, sampled: usize, files: usize) -> Estimate {
+        return Estimate { fraction, sampled, files, ..Estimate::default() };
+    }
+
+    /// Count a sampled file whose lines are `counts`
+    pub fn add(&mut self, counts: &AuthorCount, weight: Count) {
+        let mut total = 0.0;
+        for (author, &count) in counts {
+            let count = (count * weight) as f64;
+            *self.squares.entry(author.clone()).or_insert(0.0) += count * count;
+            total += count;
+        }
+        self.total_squares += total * total;
+    }
+
+    /// Count the sampled files of another repository's estimate, of the same
+    /// fraction
+    pub fn add_estimate(&mut self, other: &Estimate) {
+        self.sampled += other.sampled;
+        self.files += other.files;
+        for (author, squares) in &other.squares {
+            *self.squares.entry(author.clone()).or_insert(0.0) += squares;
+        }
+        self.total_squares += other.total_squares;
+    }
+
+    /// Scale a count of the sampled files up to an estimate of all the files'
+    pub fn scale(&self, count: Count) -> Count {
+        return (count as f64 / self.fraction).round() as Count;
+    }
+
+    pub fn extrapolate(&self, counts: &mut AuthorCount) {
+        counts.values_mut().for_each(|count| *count = self.scale(*count));
+    }
+
+    fn margin_of(&self, squares: f64) -> f64 {
+        let p = self.fraction;
+        return Z_95 * ((1.0 - p) / (p * p) * squares).sqrt();
+    }
+
+    /// Half the width of the 95% confidence interval of `author`'s estimate
+    pub fn margin(&self, author: &Author) -> f64 {
+        return self.margin_of(self.squares.get(author).copied().unwrap_or(0.0));
+    }
+
+    /// The margin of each blamed author's estimate
+    pub fn margins(&self) -> impl Iterator<Item = (&Author, f64)> + '_ {
+        return self.squares.iter().map(|(author, &squares)| (author, self.margin_of(squares)));
+    }
+
+    /// Half the width of the 95% confidence interval of the total's estimate
+    pub fn total_margin(&self) -> f64 {
+        return self.margin_of(self.total_squares);
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn estimates_from_a_stable_sample() {
+        assert_eq!(parse_sample("10%"), Ok(0.1));
+        assert_eq!(parse_sample("100"), Ok(1.0));
+        assert!(parse_sample("0%").is_err() && parse_sample("150%").is_err() && parse_sample("ten").is_err());
+
+        let paths: Vec<String> = (0..10000).map(|i| format!("src/module{i}/file.rs")).collect();
+        let picked: Vec<&String> = paths.iter().filter(|path| sampled(path, 0.1)).collect();
+        assert!((900..1100).contains(&picked.len()), "{}", picked.len());
+        assert_eq!(picked, paths.iter().filter(|path| sampled(path, 0.1)).collect::<Vec<_>>());
+        assert!(paths.iter().all(|path| sampled(path, 1.0)));
+
+        let alice = Author::new("Alice", "alice@example.com");
+        let mut estimate = Estimate::new(0.25, 2, 8);
+        estimate.add(&AuthorCount::from([(alice.clone(), 3)]), 1);
+        estimate.add(&AuthorCount::from([(alice.clone(), 4)]), 1);
+        let mut counts = AuthorCount::from([(alice.clone(), 7)]);
+        estimate.extrapolate(&mut counts);
+        assert_eq!(counts[&alice], 28);
+        // 1.96 * sqrt(0.75 / 0.0625 * 25)
+        assert!((estimate.margin(&alice) - 33.948).abs() < 1e-3);
+        assert_eq!(estimate.margin(&Author::new("Bob", "bob@example.com")), 0.0);
+        assert_eq!(Estimate::new(1.0, 8, 8).total_margin(), 0.0);
+    }
+}
@@ -0,0 +1,198 @@
+use chrono::{Datelike, Local, NaiveDate, TimeZone};
+use hashbrown::HashMap;
+use std::fmt::Write;
+
+use crate::backend::CommitInfo;
+use crate::chart::PALETTE;
+use crate::filter::FileFilter;
+use crate::html::escape;
+use crate::identity::{Author, IdentityResolver};
+use crate::report::Table;
+use crate::{csv_field, Count, Date};
+
+//----
+// Seniority curve
+//
+// --seniority-csv and --seniority-svg put each author's surviving lines
+// against their tenure: the whole months from their first commit to each
+// sampled date, as a share of the team's lines then.  Lined up by tenure
+// rather than by date, the curves show how quickly people grow into owning
+// code, whenever they joined.  The first commit is the earliest touching a
+// file the filters keep.
+
+const WIDTH: f64 = 800.0;
+const HEIGHT: f64 = 300.0;
+const MARGIN: f64 = 40.0;
+const LEGEND_WIDTH: f64 = 200.0;
+
+/// Earliest commit time per raw author
+pub type FirstCommits = HashMap<Author, i64>;
+
+pub fn first_commits(commits: &[CommitInfo], filter: &FileFilter) -> FirstCommits {
+    let mut first = FirstCommits::new();
+    for commit in commits.iter().filter(|commit| commit.files.iter().any(|f| filter.reason_to_skip(&f.path).is_none())) {
+        let time = first.entry(commit.author.clone()).or_insert(commit.time);
+        *time = (*time).min(commit.time);
+    }
+    return first;
+}
+
+/// The earliest of both, per author
+pub fn merge(into: &mut FirstCommits, other: FirstCommits) {
+    for (author, time) in other {
+        let first = into.entry(author).or_insert(time);
+        *first = (*first).min(time);
+    }
+}
+
+#[derive(Debug, Clone, PartialEq)]
+pub struct Point {
+    pub date: Date,
+    /// Whole months from the first commit to the date
+    pub months: i32,
+    pub lines: Count,
+    /// Percent of the date's lines
+    pub share: f64,
+}
+
+#[derive(Debug, Clone, PartialEq)]
+pub struct Curve {
+    pub author: String,
+    /// Local date of the first commit
+    pub first_commit: Date,
+    /// The sampled dates after the first commit, oldest first
+    pub points: Vec<Point>,
+}
+
+fn months_between(from: NaiveDate, to: NaiveDate) -> i32 {
+    let months = (to.year() - from.year()) * 12 + to.month() as i32 - from.month() as i32;
+    return months - (to.day() < from.day()) as i32;
+}
+
+/// A curve per row of the lines `table` with a first commit, by author
+pub fn curves(table: &Table, first: &FirstCommits, resolver: &IdentityResolver) -> Vec<Curve> {
+    let mut by_key: HashMap<String, i64> = HashMap::new();
+    for (raw, &time) in first {
+        let time_of = by_key.entry(resolver.resolve(raw).author.key()).or_insert(time);
+        *time_of = (*time_of).min(time);
+    }
+    let mut curves = Vec::new();
+    for row in &table.rows {
+        let Some(start) = row.keys.iter().filter_map(|key| by_key.get(key)).min() else {
+            continue;
+        };
+        let Some(start) = Local.timestamp_opt(*start, 0).single().map(|time| time.date_naive()) else {
+            continue;
+        };
+        let mut points = Vec::new();
+        for (i, date) in table.dates.iter().enumerate() {
+            let Ok(day) = NaiveDate::parse_from_str(date, "%Y-%m-%d") else {
+                continue;
+            };
+            if day <= start {
+                continue;
+            }
+            let share = match table.totals[i] {
+                0 => 0.0,
+                total => 100.0 * row.counts[i] as f64 / total as f64,
+            };
+            points.push(Point { date: date.clone(), months: months_between(start, day), lines: row.counts[i], share });
+        }
+        curves.push(Curve { author: row.author.clone(), first_commit: start.format("%Y-%m-%d").to_string(), points });
+    }
+    curves.sort_by(|a, b| a.author.cmp(&b.author));
+    return curves;
+}
+
+/// An `author,first_commit,date,months,lines,share` row per curve point
+pub fn render_csv(curves: &[Curve]) -> String {
+    let mut out = String::from("author,first_commit,date,months,lines,share\n");
+    for curve in curves {
+        for point in &curve.points {
+            writeln!(out, "{},{},{},{},{},{:.2}", csv_field(&curve.author), curve.first_commit, point.date, point.months, point.lines, point.share).unwrap();
+        }
+    }
+    return out;
+}
+
+/// A line per author of their share over months of tenure, as a standalone
+/// SVG document
+pub fn render_svg(curves: &[Curve]) -> String {
+    let last = curves.iter().flat_map(|curve| &curve.points).map(|point| point.months).max().unwrap_or(0).max(1);
+    let max = curves.iter().flat_map(|curve| &curve.points).map(|point| point.share).fold(0.0, f64::max);
+    let x = |months: i32| MARGIN + (WIDTH - 2.0 * MARGIN) * months as f64 / last as f64;
+    let y = |share: f64| HEIGHT - MARGIN - if max > 0.0 { (HEIGHT - 2.0 * MARGIN) * share / max } else { 0.0 };
+
+    let mut out = String::new();
+    writeln!(out, "<svg xmlns=\"http://www.w3.org/2000/svg\" width=\"{}\" height=\"{HEIGHT}\" font-family=\"sans-serif\" font-size=\"11\">",
+        WIDTH + LEGEND_WIDTH).unwrap();
+    for (i, curve) in curves.iter().enumerate() {
+        let points: Vec<String> = curve.points.iter().map(|point| format!("{:.1},{:.1}", x(point.months), y(point.share))).collect();
+        writeln!(out, "<polyline fill=\"none\" stroke=\"{}\" stroke-width=\"2\" points=\"{}\"><title>{}</title></polyline>",
+            PALETTE[i % PALETTE.len()], points.join(" "), escape(&curve.author)).unwrap();
+    }
+
+    // Axes, with the scale's top share and longest tenure
+    writeln!(out, "<line x1=\"{MARGIN}\" y1=\"{0}\" x2=\"{1}\" y2=\"{0}\" stroke=\"#333\"/>", HEIGHT - MARGIN, WIDTH - MARGIN).unwrap();
+    writeln!(out, "<line x1=\"{MARGIN}\" y1=\"{MARGIN}\" x2=\"{MARGIN}\" y2=\"{}\" stroke=\"#333\"/>", HEIGHT - MARGIN).unwrap();
+    writeln!(out, "<text x=\"{}\" y=\"{}\" text-anchor=\"end\">{max:.0}%</text>", MARGIN - 4.0, MARGIN + 4.0).unwrap();
+    writeln!(out, "<text x=\"{MARGIN}\" y=\"{}\">0 months</text>", HEIGHT - MARGIN + 16.0).unwrap();
+    writeln!(out, "<text x=\"{}\" y=\"{}\" text-anchor=\"end\">{last} months</text>", WIDTH - MARGIN, HEIGHT - MARGIN + 16.0).unwrap();
+
+    for (i, curve) in curves.iter().enumerate() {
+        let top = MARGIN + 16.0 * i as f64;
+        writeln!(out, "<rect x=\"{WIDTH}\" y=\"{}\" width=\"10\" height=\"10\" fill=\"{}\"/>", top - 9.0, PALETTE[i % PALETTE.len()]).unwrap();
+        writeln!(out, "<text x=\"{}\" y=\"{top}\">{}</text>", WIDTH + 16.0, escape(&curve.author)).unwrap();
+    }
+    writeln!(out, "</svg>").unwrap();
+    return out;
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::report::Row;
+
+    #[test]
+    fn lines_up_authors_by_tenure() {
+        let table = Table {
+            dates: vec!["2020-02-01".to_string(), "2020-03-01".to_string(), "2020-04-01".to_string()],
+            rows: vec![
+                Row { author: "Jane".to_string(), keys: vec!["jane@example.com".to_string()], counts: vec![10, 10, 5], docs: Vec::new(), margins: Vec::new() },
+                Row { author: "Bob".to_string(), keys: vec!["bob@example.com".to_string()], counts: vec![0, 10, 15], docs: Vec::new(), margins: Vec::new() },
+                Row { author: "Other (2 authors)".to_string(), keys: Vec::new(), counts: vec![0, 0, 5], docs: Vec::new(), margins: Vec::new() },
+            ],
+            totals: vec![10, 20, 25],
+            doc_totals: Vec::new(),
+            groups: Vec::new(),
+            files: Vec::new(),
+            files_at: None,
+            entropy: Vec::new(),
+            bus_factor: Vec::new(),
+            health: Vec::new(),
+            ineligible: Vec::new(),
+            margins: Vec::new(),
+        };
+        let noon = |date: &str| Local.from_local_datetime(&NaiveDate::parse_from_str(date, "%Y-%m-%d").unwrap().and_hms_opt(12, 0, 0).unwrap()).unwrap().timestamp();
+        let first = FirstCommits::from([
+            (Author::new("Jane", "jane@example.com"), noon("2020-01-15")),
+            (Author::new("Bob", "bob@example.com"), noon("2020-02-10")),
+        ]);
+        let curves = curves(&table, &first, &IdentityResolver::default());
+
+        assert_eq!(curves.len(), 2);
+        assert_eq!(curves[0].points, [
+            Point { date: "2020-03-01".to_string(), months: 0, lines: 10, share: 50.0 },
+            Point { date: "2020-04-01".to_string(), months: 1, lines: 15, share: 60.0 },
+        ]);
+        assert_eq!(render_csv(&curves), "\
+author,first_commit,date,months,lines,share
+Bob,2020-02-10,2020-03-01,0,10,50.00
+Bob,2020-02-10,2020-04-01,1,15,60.00
+Jane,2020-01-15,2020-02-01,0,10,100.00
+Jane,2020-01-15,2020-03-01,1,10,50.00
+Jane,2020-01-15,2020-04-01,2,5,20.00
+");
+        assert!(render_svg(&curves).contains("<polyline fill=\"none\" stroke=\"#4e79a7\" stroke-width=\"2\" points=\"40.0,150.0 400.0,128.0\"><title>Bob</title>"));
+    }
+}
@@ -0,0 +1,350 @@
+use hashbrown::HashMap;
+use std::fs;
+use std::io::{self, BufRead, BufReader, Read, Write};
+use std::net::{SocketAddr, TcpListener, TcpStream};
+use std::sync::{Arc, Mutex};
+use std::time::{Duration, Instant};
+use threadpool::ThreadPool;
+
+use crate::report::json_string;
+use crate::{info, warn};
+
+//----
+// HTTP API
+//
+// `serve` answers GET requests for the JSON reports over HTTP, so dashboards
+// and bots can ask a shared instance rather than each cloning and blaming
+// the repo.  It's plain HTTP/1.1 on std's TcpListener, one request per
+// connection; put it behind a TLS-terminating proxy to expose it widely.
+//
+// Each analysis can blame the whole history, so callers are bounded twice:
+// with --tokens every request needs `Authorization: Bearer TOKEN`, one of the
+// tokens in the file, and --rate-limit caps the requests of each client (its
+// token's name, or its address without tokens) per minute.  Without tokens
+// only loopback addresses may be listened on.
+
+/// Longest request line and headers read
+const MAX_HEAD: u64 = 16 * 1024;
+/// How long a client has to send its request
+const READ_TIMEOUT: Duration = Duration::from_secs(10);
+
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct Request {
+    pub method: String,
+    pub path: String,
+    /// Query parameters, percent-decoded, in order
+    pub query: Vec<(String, String)>,
+    pub headers: Vec<(String, String)>,
+}
+
+impl Request {
+    /// The last value of the query parameter `name`
+    pub fn param(&self, name: &str) -> Option<&str> {
+        return self.query.iter().rev().find(|(key, _)| key == name).map(|(_, value)| value.as_str());
+    }
+
+    /// The value of the header `name`, which is case-insensitive
+    pub fn header(&self, name: &str) -> Option<&str> {
+        return self.headers.iter().find(|(key, _)| key.eq_ignore_ascii_case(name)).map(|(_, value)| value.as_str());
+    }
+}
+
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct Response {
+    pub status: u16,
+    pub headers: Vec<(String, String)>,
+    pub body: String,
+}
+
+impl Response {
+    pub fn json(status: u16, body: String) -> Response {
+        return Response { status, headers: vec![("Content-Type".to_string(), "application/json".to_string())], body };
+    }
+
+    /// `{"error": message}`
+    pub fn error(status: u16, message: &str) -> Response {
+        return Response::json(status, format!("{{\"error\": {}}}\n", json_string(message)));
+    }
+
+    fn reason(&self) -> &'static str {
+        match self.status {
+            200 => return "OK",
+            400 => return "Bad Request",
+            401 => return "Unauthorized",
+            404 => return "Not Found",
+            405 => return "Method Not Allowed",
+            429 => return "Too Many Requests",
+            500 => return "Internal Server Error",
+            _ => return "",
+        }
+    }
+
+    fn write_to(&self, out: &mut impl Write) -> io::Result<()> {
+        let mut head = format!("HTTP/1.1 {} {}\r\nContent-Length: {}\r\nConnection: close\r\n", self.status, self.reason(), self.body.len());
+        for (name, value) in &self.headers {
+            head.push_str(&format!("{name}: {value}\r\n"));
+        }
+        head.push_str("\r\n");
+        out.write_all(head.as_bytes())?;
+        out.write_all(self.body.as_bytes())?;
+        return out.flush();
+    }
+}
+
+/// `text` with %XX escapes, and + for spaces, decoded
+fn percent_decode(text: &str) -> Result<String, String> {
+    let bytes = text.as_bytes();
+    let mut out = Vec::with_capacity(bytes.len());
+    let mut i = 0;
+    while i < bytes.len() {
+        match bytes[i] {
+            b'+' => out.push(b' '),
+            b'%' => {
+                let hex = text.get(i + 1..i + 3).and_then(|hex| u8::from_str_radix(hex, 16).ok());
+                out.push(hex.ok_or_else(|| format!("bad escape in {text}"))?);
+                i += 2;
+            },
+            b => out.push(b),
+        }
+        i += 1;
+    }
+    return String::from_utf8(out).map_err(|_| format!("{text} isn't UTF-8"));
+}
+
+/// Read the request line and headers from `input`
+pub fn read_request(input: impl Read) -> Result<Request, String> {
+    let mut reader = BufReader::new(input.take(MAX_HEAD));
+    let mut lines = Vec::new();
+    loop {
+        let mut line = String::new();
+        match reader.read_line(&mut line) {
+            Ok(0) => return Err("the request ended early".to_string()),
+            Ok(_) if line.trim_end().is_empty() => break,
+            Ok(_) => lines.push(line.trim_end().to_string()),
+            Err(e) => return Err(e.to_string()),
+        }
+    }
+    let Some(first) = lines.first() else {
+        return Err("no request line".to_string());
+    };
+    let mut words = first.split(' ');
+    let (Some(method), Some(target), Some(_version), None) = (words.next(), words.next(), words.next(), words.next()) else {
+        return Err(format!("bad request line: {first}"));
+    };
+    let (path, query) = target.split_once('?').unwrap_or((target, ""));
+    let query = query.split('&').filter(|pair| !pair.is_empty()).map(|pair| {
+        let (key, value) = pair.split_once('=').unwrap_or((pair, ""));
+        return Ok((percent_decode(key)?, percent_decode(value)?));
+    }).collect::<Result<Vec<(String, String)>, String>>()?;
+    let headers = lines[1..].iter().map(|line| match line.split_once(':') {
+        Some((name, value)) => Ok((name.trim().to_string(), value.trim().to_string())),
+        None => Err(format!("bad header: {line}")),
+    }).collect::<Result<Vec<(String, String)>, String>>()?;
+    return Ok(Request { method: method.to_string(), path: percent_decode(path)?, query, headers });
+}
+
+//----
+// Access control
+
+/// Client name and token of each client allowed in
+#[derive(Debug, Clone, Default)]
+pub struct Tokens {
+    clients: Vec<(String, String)>,
+}
+
+/// Whether `a` and `b` are equal, taking as long wherever they differ
+fn same_secret(a: &str, b: &str) -> bool {
+    let (a, b) = (a.as_bytes(), b.as_bytes());
+    let diff = (0..a.len().max(b.len())).fold(0u8, |diff, i| diff | (a.get(i).unwrap_or(&0) ^ b.get(i).unwrap_or(&0)));
+    return diff == 0 && a.len() == b.len();
+}
+
+impl Tokens {
+    /// A `name token` line per client; blank lines and # comments are ignored
+    pub fn parse(text: &str) -> Result<Tokens, String> {
+        let mut clients = Vec::new();
+        for (n, line) in text.lines().enumerate() {
+            let line = line.trim();
+            if line.is_empty() || line.starts_with('#') {
+                continue;
+            }
+            match line.split_whitespace().collect::<Vec<&str>>()[..] {
+                [name, token] if token.len() >= 16 => clients.push((name.to_string(), token.to_string())),
+                [_, _] => return Err(format!("line {}: tokens should be at least 16 characters", n + 1)),
+                _ => return Err(format!("line {}: expected a client name and its token", n + 1)),
+            }
+        }
+        return Ok(Tokens { clients });
+    }
+
+    pub fn load(path: &str) -> Result<Tokens, String> {
+        let text = fs::read_to_string(path).map_err(|e| format!("{path}: {e}"))?;
+        return Tokens::parse(&text).map_err(|e| format!("{path}: {e}"));
+    }
+
+    /// The name of the client whose token is `token`
+    pub fn client(&self, token: &str) -> Option<&str> {
+        // Every token is compared, so the time taken doesn't tell which matched
+        return self.clients.iter().fold(None, |found, (name, known)| match same_secret(token, known) {
+            true => Some(name.as_str()),
+            false => found,
+        });
+    }
+}
+
+/// Up to `per_minute` requests per client, refilled evenly over the minute
+#[derive(Debug)]
+pub struct RateLimiter {
+    per_minute: u32,
+    /// Requests each client may still make, and when that was counted
+    buckets: HashMap<String, (f64, Instant)>,
+}
+
+impl RateLimiter {
+    pub fn new(per_minute: u32) -> RateLimiter {
+        return RateLimiter { per_minute, buckets: HashMap::new() };
+    }
+
+    /// Count a request by `client` at `now`, or say how long until it may
+    /// make one
+    pub fn admit(&mut self, client: &str, now: Instant) -> Result<(), Duration> {
+        let capacity = self.per_minute as f64;
+        let per_second = capacity / 60.0;
+        let (left, since) = self.buckets.entry_ref(client).or_insert((capacity, now));
+        *left = (*left + now.saturating_duration_since(*since).as_secs_f64() * per_second).min(capacity);
+        *since = now;
+        if *left < 1.0 {
+            return Err(Duration::from_secs_f64(((1.0 - *left) / per_second).ceil()));
+        }
+        *left -= 1.0;
+        // A minute refills any bucket, so idle clients' can go
+        self.buckets.retain(|_, (_, since)| now.saturating_duration_since(*since) < Duration::from_secs(60));
+        return Ok(());
+    }
+}
+
+/// How `serve` admits requests
+#[derive(Debug)]
+pub struct Access {
+    /// Without tokens, anyone who can connect
+    pub tokens: Option<Tokens>,
+    pub limiter: Mutex<RateLimiter>,
+}
+
+impl Access {
+    /// The client making `request` from `peer`, or the response refusing it
+    fn admit(&self, request: &Request, peer: SocketAddr) -> Result<String, Response> {
+        let client = match &self.tokens {
+            None => peer.ip().to_string(),
+            Some(tokens) => {
+                let token = request.header("Authorization").and_then(|value| value.strip_prefix("Bearer "));
+                match token.and_then(|token| tokens.client(token.trim())) {
+                    Some(name) => name.to_string(),
+                    None => {
+                        let mut refusal = Response::error(401, "a valid bearer token is required");
+                        refusal.headers.push(("WWW-Authenticate".to_string(), "Bearer".to_string()));
+                        return Err(refusal);
+                    },
+                }
+            },
+        };
+        let admitted = self.limiter.lock().unwrap().admit(&client, Instant::now());
+        match admitted {
+            Ok(()) => return Ok(client),
+            Err(wait) => {
+                let mut refusal = Response::error(429, &format!("{client} is over the rate limit"));
+                refusal.headers.push(("Retry-After".to_string(), wait.as_secs().to_string()));
+                return Err(refusal);
+            },
+        }
+    }
+}
+
+fn handle<F>(mut stream: TcpStream, access: &Access, handler: &F) -> io::Result<()>
+where
+    F: Fn(&Request) -> Response,
+{
+    let peer = stream.peer_addr()?;
+    stream.set_read_timeout(Some(READ_TIMEOUT))?;
+    let response = match read_request(&stream) {
+        Err(e) => Response::error(400, &e),
+        Ok(request) if request.method != "GET" => Response::error(405, "only GET is supported"),
+        Ok(request) => match access.admit(&request, peer) {
+            Err(refusal) => refusal,
+            Ok(client) => {
+                let response = handler(&request);
+                info!("{client} {} {} {}", request.method, request.path, response.status);
+                response
+            },
+        },
+    };
+    return response.write_to(&mut stream);
+}
+
+/// Answer the requests on `listener` with `handler`, `workers` at a time
+pub fn serve<F>(listener: TcpListener, access: Access, workers: usize, handler: F) -> io::Result<()>
+where
+    F: Fn(&Request) -> Response + Send + Sync + 'static,
+{
+    let pool = ThreadPool::new(workers.max(1));
+    let access = Arc::new(access);
+    let handler = Arc::new(handler);
+    for stream in listener.incoming() {
+        let stream = match stream {
+            Ok(stream) => stream,
+            Err(e) => {
+                warn!("failed to accept a connection: {e}");
+                continue;
+            },
+        };
+        let (access, handler) = (access.clone(), handler.clone());
+        pool.execute(move || {
+            if let Err(e) = handle(stream, &access, handler.as_ref()) {
+                warn!("failed to answer a request: {e}");
+            }
+        });
+    }
+    return Ok(());
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn reads_requests() {
+        let request = read_request("GET /ownership?at=v1.0&path=src%2Fmain.rs&x=a+b HTTP/1.1\r\nHost: x\r\nAuthorization: Bearer abc\r\n\r\n".as_bytes()).unwrap();
+        assert_eq!(request.method, "GET");
+        assert_eq!(request.path, "/ownership");
+        assert_eq!(request.param("path"), Some("src/main.rs"));
+        assert_eq!(request.param("x"), Some("a b"));
+        assert_eq!(request.header("authorization"), Some("Bearer abc"));
+        assert!(read_request("GET /\r\n\r\n".as_bytes()).is_err());
+        assert!(read_request("\r\n".as_bytes()).is_err());
+        assert!(read_request("GET /?a=%zz HTTP/1.1\r\n\r\n".as_bytes()).is_err());
+        assert!(read_request("GET / HTTP/1.1\r\nHost: x\r\n".as_bytes()).is_err());
+    }
+
+    #[test]
+    fn checks_tokens() {
+        let tokens = Tokens::parse("# CI and the dashboard\nci 0123456789abcdef\n\ndashboard fedcba9876543210\n").unwrap();
+        assert_eq!(tokens.client("fedcba9876543210"), Some("dashboard"));
+        assert_eq!(tokens.client("fedcba987654321"), None);
+        assert_eq!(tokens.client(""), None);
+        assert!(Tokens::parse("ci short\n").is_err());
+        assert!(Tokens::parse("ci\n").is_err());
+    }
+
+    #[test]
+    fn limits_each_client() {
+        let mut limiter = RateLimiter::new(2);
+        let start = Instant::now();
+        assert!(limiter.admit("ci", start).is_ok());
+        assert!(limiter.admit("ci", start).is_ok());
+        assert_eq!(limiter.admit("ci", start), Err(Duration::from_secs(30)));
+        assert!(limiter.admit("dashboard", start).is_ok());
+        // One request back every 30 seconds
+        assert!(limiter.admit("ci", start + Duration::from_secs(30)).is_ok());
+        assert!(limiter.admit("ci", start + Duration::from_secs(31)).is_err());
+    }
+}
@@ -0,0 +1,38 @@
+use hashbrown::HashMap;
+use std::fs;
+use std::path::Path;
+
+use crate::at_rest::{self, AtRest};
+use crate::cli_args::Args;
+use crate::error::Result;
+use crate::filter::SkipReason;
+use crate::html::{self, PageAssets};
+use crate::report::{digit_separator, render_json, Report};
+
+//----
+// Static site
+//
+// --emit-static-site writes the report as a directory that can be published
+// as is, e.g. to GitHub Pages from CI:
+//
+//   index.html         the HTML report
+//   data.json          the same data as --format json, for dashboards
+//   assets/style.css
+//
+// With --encrypt-at-rest the page and data are encrypted, the stylesheet isn't.
+
+const DATA_FILE: &str = "data.json";
+const STYLESHEET: &str = "assets/style.css";
+
+pub fn emit_static_site(dir: &Path, opt: &Args, report: &Report, excluded: &HashMap<String, SkipReason>, at_rest: Option<&AtRest>)
+    -> Result<()>
+{
+    fs::create_dir_all(dir.join("assets"))?;
+    fs::write(dir.join(STYLESHEET), html::STYLE)?;
+    at_rest::write(&dir.join(DATA_FILE), render_json(opt, report), at_rest)?;
+
+    let assets = PageAssets { stylesheet: Some(STYLESHEET), data: Some(DATA_FILE) };
+    let page = html::render(opt, report, excluded, &digit_separator(opt), assets);
+    at_rest::write(&dir.join("index.html"), page, at_rest)?;
+    return Ok(());
+}
@@ -0,0 +1,198 @@
+use std::collections::VecDeque;
+use std::sync::{Arc, Condvar, Mutex};
+use std::task::{Context, Poll, Waker};
+use std::thread::{self, JoinHandle};
+
+use crate::analysis::{blame_snapshots, AuthorStats, SnapshotSpec};
+use crate::backend::Backend;
+use crate::cache::BlameCache;
+use crate::error::Result;
+use crate::filter::FileFilter;
+use crate::Date;
+
+//----
+// Streaming snapshots
+//
+// For programs that want each date's counts as soon as they're known (a
+// server, a bot) rather than all of them at the end: `analyze_stream` blames
+// the dates one after the other on a thread of its own, handing over each
+// finished snapshot.  It runs at most `buffer` snapshots ahead of the
+// consumer, so a slow consumer holds the analysis back instead of piling up
+// results.
+//
+// The stream is an `Iterator` for blocking code, and has `poll_next` for async
+// code; `futures::stream::poll_fn(|cx| stream.poll_next(cx))` makes it a
+// `futures::Stream` without this crate depending on an async runtime.
+
+/// The counts of one date
+#[derive(Debug, Clone)]
+pub struct Snapshot {
+    pub date: Date,
+    /// Only this date's counts
+    pub stats: AuthorStats,
+}
+
+#[derive(Default)]
+struct Queue {
+    ready: VecDeque<Result<Snapshot>>,
+    /// The analysis ended, or failed
+    done: bool,
+    /// The stream was dropped
+    closed: bool,
+    /// Task waiting in poll_next
+    waker: Option<Waker>,
+}
+
+#[derive(Default)]
+struct Shared {
+    queue: Mutex<Queue>,
+    /// Signalled when the consumer takes a snapshot or goes away
+    taken: Condvar,
+    /// Signalled when a snapshot is ready, or the analysis ends
+    ready: Condvar,
+}
+
+/// Snapshots of an analysis running in the background, oldest date first.
+/// The first error ends it.
+pub struct SnapshotStream {
+    shared: Arc<Shared>,
+    worker: Option<JoinHandle<BlameCache>>,
+}
+
+/// Blame the files at each of `spec.dates`, like blame_snapshots, yielding
+/// each date's counts as soon as they're known.  Unchanged files are only
+/// blamed once, through `cache`.
+pub fn analyze_stream(backend: Arc<dyn Backend>, spec: SnapshotSpec, filter: FileFilter, mut cache: BlameCache, buffer: usize) -> SnapshotStream {
+    let shared = Arc::new(Shared::default());
+    let producer = shared.clone();
+    let worker = thread::spawn(move || {
+        for date in &spec.dates {
+            let one = SnapshotSpec { dates: vec![date.clone()], ..spec.clone() };
+            let result = blame_snapshots(&backend, &one, &filter, &mut cache)
+                .map(|stats| Snapshot { date: date.clone(), stats });
+            let failed = result.is_err();
+            let mut queue = producer.queue.lock().unwrap();
+            while queue.ready.len() >= buffer.max(1) && !queue.closed {
+                queue = producer.taken.wait(queue).unwrap();
+            }
+            if queue.closed {
+                break;
+            }
+            queue.ready.push_back(result);
+            if let Some(waker) = queue.waker.take() {
+                waker.wake();
+            }
+            producer.ready.notify_one();
+            if failed {
+                break;
+            }
+        }
+        let mut queue = producer.queue.lock().unwrap();
+        queue.done = true;
+        if let Some(waker) = queue.waker.take() {
+            waker.wake();
+        }
+        producer.ready.notify_one();
+        return cache;
+    });
+    return SnapshotStream { shared, worker: Some(worker) };
+}
+
+impl SnapshotStream {
+    /// The next snapshot, Pending until it's ready, or None at the end
+    pub fn poll_next(&mut self, cx: &mut Context<'_>) -> Poll<Option<Result<Snapshot>>> {
+        let mut queue = self.shared.queue.lock().unwrap();
+        match queue.ready.pop_front() {
+            Some(snapshot) => {
+                self.shared.taken.notify_one();
+                return Poll::Ready(Some(snapshot));
+            },
+            None if queue.done => return Poll::Ready(None),
+            None => {
+                queue.waker = Some(cx.waker().clone());
+                return Poll::Pending;
+            },
+        }
+    }
+
+    /// Stop the analysis after the date it's on and take back the cache,
+    /// with what it blamed so far, e.g. to save it
+    pub fn into_cache(mut self) -> BlameCache {
+        return self.finish().expect("the analysis thread panicked");
+    }
+
+    fn finish(&mut self) -> Option<BlameCache> {
+        self.shared.queue.lock().unwrap().closed = true;
+        self.shared.taken.notify_one();
+        return self.worker.take()?.join().ok();
+    }
+}
+
+impl Iterator for SnapshotStream {
+    type Item = Result<Snapshot>;
+
+    fn next(&mut self) -> Option<Result<Snapshot>> {
+        let mut queue = self.shared.queue.lock().unwrap();
+        loop {
+            if let Some(snapshot) = queue.ready.pop_front() {
+                self.shared.taken.notify_one();
+                return Some(snapshot);
+            }
+            if queue.done {
+                return None;
+            }
+            queue = self.shared.ready.wait(queue).unwrap();
+        }
+    }
+}
+
+impl Drop for SnapshotStream {
+    fn drop(&mut self) {
+        // The analysis stops after the date it's on
+        self.finish();
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::fixture::{Fixture, ALICE, BOB, DATES};
+    use crate::identity::Author;
+
+    fn spec() -> SnapshotSpec {
+        return SnapshotSpec { jobs: 2, ..SnapshotSpec::new(DATES.iter().map(|d| d.to_string()).collect()) };
+    }
+
+    fn lines(snapshot: &Snapshot, (name, email): (&str, &str)) -> i32 {
+        let authors = snapshot.stats.authors.get(&snapshot.date);
+        return authors.and_then(|acnt| acnt.get(&Author::new(name, email))).copied().unwrap_or(0);
+    }
+
+    #[test]
+    fn streams_snapshots() {
+        let fixture = Fixture::new();
+        let stream = analyze_stream(fixture.backend(), spec(), FileFilter::default(), BlameCache::in_memory(), 1);
+        let snapshots: Vec<Snapshot> = stream.map(|snapshot| snapshot.unwrap()).collect();
+        let dates: Vec<&str> = snapshots.iter().map(|snapshot| snapshot.date.as_str()).collect();
+        assert_eq!(dates, DATES);
+        // Before the first commit
+        assert_eq!(snapshots[0].stats.missing, [DATES[0]]);
+        assert_eq!(lines(&snapshots[1], ALICE), 5);
+        assert_eq!(lines(&snapshots[3], ALICE), 4);
+        assert_eq!(lines(&snapshots[3], BOB), 3);
+
+        // Polled from async code
+        let mut stream = analyze_stream(fixture.backend(), spec(), FileFilter::default(), BlameCache::in_memory(), 1);
+        let mut cx = Context::from_waker(Waker::noop());
+        let mut polled = Vec::new();
+        loop {
+            match stream.poll_next(&mut cx) {
+                Poll::Ready(Some(snapshot)) => polled.push(snapshot.unwrap().date),
+                Poll::Ready(None) => break,
+                Poll::Pending => thread::yield_now(),
+            }
+        }
+        assert_eq!(polled, DATES);
+        assert!(stream.into_cache().misses > 0);
+    }
+}
@@ -0,0 +1,124 @@
+use std::fs;
+
+use crate::config::Config;
+use crate::directory::Directory;
+use crate::identity::{normalize_name, parse_identity};
+
+//----
+// Teams
+//
+// The config's [teams] table assigns authors to teams, by email or by name:
+//
+//   [teams]
+//   firmware = ["jdoe@example.com", "Alice Smith"]
+//   web = ["bob@example.com"]
+//
+// --group-by title, location or cost-center builds the teams from those
+// attributes of the --directory instead.
+//
+// --author-order pins the authors listed in a file to the top of the tables,
+// in the file's order, one per line as a name, an email or "Name <email>"
+// (`#` comments allowed): the current team first, say, then alumni.  Everyone
+// else follows in the --sort order, and within teams the pinned authors come
+// first too.
+
+/// Heading for the authors that aren't in any team
+pub const NO_TEAM: &str = "(no team)";
+
+#[derive(Debug, Clone, Default)]
+pub struct Teams {
+    /// Team names, in display order
+    pub names: Vec<String>,
+    /// (team index, lowercased email or normalized name)
+    members: Vec<(usize, String)>,
+    /// Heading for everyone else, instead of NO_TEAM
+    unassigned: Option<String>,
+}
+
+impl Teams {
+    pub fn from_config(config: &Config) -> Teams {
+        let mut teams = Teams::default();
+        for (name, members) in &config.teams {
+            let index = teams.names.len();
+            teams.names.push(name.clone());
+            for member in members {
+                let member = match member.contains('@') {
+                    true => member.trim().to_lowercase(),
+                    false => normalize_name(member),
+                };
+                teams.members.push((index, member));
+            }
+        }
+        return teams;
+    }
+
+    /// One team per value of a directory column, e.g. "title"
+    pub fn from_directory(directory: &Directory, column: &str) -> Teams {
+        let mut teams = Teams { unassigned: Some(format!("(no {})", column.replace('_', " "))), ..Teams::default() };
+        let mut values: Vec<&str> = directory.values(column).into_iter().map(|(_, value)| value).collect();
+        values.sort();
+        values.dedup();
+        teams.names = values.iter().map(|value| value.to_string()).collect();
+        for (email, value) in directory.values(column) {
+            let index = values.binary_search(&value).unwrap();
+            teams.members.push((index, email.to_string()));
+        }
+        return teams;
+    }
+
+    /// Heading for the authors that aren't in any team
+    pub fn unassigned(&self) -> &str {
+        return self.unassigned.as_deref().unwrap_or(NO_TEAM);
+    }
+
+    pub fn is_empty(&self) -> bool {
+        return self.names.is_empty();
+    }
+
+    /// Index of the team of an author, given its display name and identity keys
+    pub fn team_of(&self, name: &str, keys: &[String]) -> Option<usize> {
+        let name = normalize_name(name);
+        return self.members.iter()
+            .find(|(_, member)| *member == name || keys.contains(member))
+            .map(|(index, _)| *index);
+    }
+}
+
+/// Authors pinned to the top of the tables by --author-order, in order
+#[derive(Debug, Clone, Default)]
+pub struct AuthorOrder {
+    /// (normalized name, lowercased email), either one empty
+    authors: Vec<(String, String)>,
+}
+
+impl AuthorOrder {
+    pub fn parse(text: &str) -> AuthorOrder {
+        let authors = text.lines()
+            .map(|line| line.trim())
+            .filter(|line| !line.is_empty() && !line.starts_with('#'))
+            .map(|line| {
+                let (name, email) = parse_identity(line);
+                (normalize_name(&name), email.to_lowercase())
+            })
+            .collect();
+        return AuthorOrder { authors };
+    }
+
+    pub fn load(path: &str) -> Result<AuthorOrder, String> {
+        let text = fs::read_to_string(path).map_err(|e| format!("{path}: {e}"))?;
+        return Ok(AuthorOrder::parse(&text));
+    }
+
+    pub fn is_empty(&self) -> bool {
+        return self.authors.is_empty();
+    }
+
+    /// Position of an author in the order, given its display name and
+    /// identity keys; none if it isn't pinned
+    pub fn position(&self, name: &str, keys: &[String]) -> Option<usize> {
+        let name = normalize_name(name);
+        return self.authors.iter().position(|(pinned_name, email)| {
+            (!pinned_name.is_empty() && *pinned_name == name) || (!email.is_empty() && keys.contains(email))
+        });
+    }
+}
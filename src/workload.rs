@@ -0,0 +1,145 @@
+use chrono::NaiveDate;
+use hashbrown::HashMap;
+use std::fs;
+
+use crate::analysis::AuthorStats;
+use crate::cli_args::Metric;
+use crate::directory::{column_name, split_csv};
+use crate::identity::{Author, IdentityResolver};
+use crate::{csv_field, AuthorPerformance, Count, Date};
+
+//----
+// Workload export
+//
+// --issues FILE reads per-author issue counts exported from an issue
+// tracker, and --workload-csv OUT writes them next to each metric's counts,
+// a row per date and author, so the two can be compared without joining
+// identities by hand.  The issues CSV's header names its columns: `email`,
+// `date` (YYYY-MM-DD) and `issues`, plus an optional `name`:
+//
+//   email,date,issues
+//   jdoe@example.com,2020-01-20,4
+//
+// Emails go through the mailmap and aliases like commit authors do, so an
+// author's issues land on their row.  Like the activity metrics, a date's
+// issues are those dated after the previous sampled date and before it.
+
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct Issues {
+    pub author: Author,
+    pub date: Date,
+    pub count: Count,
+}
+
+pub fn parse_issues(text: &str) -> Result<Vec<Issues>, String> {
+    let mut lines = text.lines().enumerate().filter(|(_, line)| !line.trim().is_empty());
+    let header: Vec<String> = split_csv(lines.next().map(|(_, line)| line).unwrap_or("")).iter().map(|name| column_name(name)).collect();
+    let column = |name: &str| header.iter().position(|column| column == name).ok_or(format!("no {name} column"));
+    let (email, date, issues) = (column("email")?, column("date")?, column("issues")?);
+    let name = header.iter().position(|column| column == "name");
+    let mut rows = Vec::new();
+    for (n, line) in lines {
+        let mut fields = split_csv(line);
+        fields.resize(header.len(), String::new());
+        if NaiveDate::parse_from_str(&fields[date], "%Y-%m-%d").is_err() {
+            return Err(format!("line {}: expected a YYYY-MM-DD date, not {}", n + 1, fields[date]));
+        }
+        let count = fields[issues].parse::<Count>().map_err(|_| format!("line {}: expected a number of issues, not {}", n + 1, fields[issues]))?;
+        let author = Author::new(name.map(|i| fields[i].as_str()).unwrap_or(""), &fields[email]);
+        rows.push(Issues { author, date: fields[date].clone(), count });
+    }
+    return Ok(rows);
+}
+
+pub fn load_issues(path: &str) -> Result<Vec<Issues>, String> {
+    let text = fs::read_to_string(path).map_err(|e| format!("--issues {path}: {e}"))?;
+    return parse_issues(&text).map_err(|e| format!("--issues {path}: {e}"));
+}
+
+/// The issues counted at each of the sorted `dates`: those dated from the
+/// previous date up to the day before.  Issues after the last date are left out.
+pub fn issues_by_date(issues: &[Issues], dates: &[Date]) -> AuthorStats {
+    let mut authors = AuthorPerformance::new();
+    for date in dates {
+        authors.insert(date.clone(), HashMap::new());
+    }
+    for row in issues {
+        if let Some(date) = dates.get(dates.partition_point(|date| *date <= row.date)) {
+            *authors.get_mut(date).unwrap().entry(row.author.clone()).or_insert(0) += row.count;
+        }
+    }
+    return AuthorStats { authors, ..Default::default() };
+}
+
+/// A `date,author,issues,<metric>...` row per date and author with anything
+/// to show, by date and then author
+pub fn render_csv(issues: &AuthorStats, metrics: &[(Metric, &AuthorStats)], resolver: &IdentityResolver) -> String {
+    let all: Vec<&AuthorStats> = [issues].into_iter().chain(metrics.iter().map(|(_, stats)| *stats)).collect();
+    let names = resolver.display_names(all.iter().flat_map(|stats| stats.authors.values()).flat_map(|acnt| acnt.iter().map(|(a, c)| (a, *c))));
+    // Authors known only by an email, e.g. with issues but no commits
+    let names: HashMap<String, String> = names.into_iter().map(|(key, name)| match name.is_empty() {
+        true => (key.clone(), key),
+        false => (key, name),
+    }).collect();
+
+    let mut rows: HashMap<(Date, String), Vec<i64>> = HashMap::new();
+    for (column, stats) in all.iter().enumerate() {
+        for (date, acnt) in &stats.authors {
+            for (name, count) in resolver.count_by_name(acnt, &names) {
+                rows.entry((date.clone(), name)).or_insert_with(|| vec![0; all.len()])[column] += count;
+            }
+        }
+    }
+    let mut rows: Vec<((Date, String), Vec<i64>)> = rows.into_iter().filter(|(_, counts)| counts.iter().any(|&c| c != 0)).collect();
+    rows.sort();
+
+    let mut out = String::from("date,author,issues");
+    for (metric, _) in metrics {
+        out.push_str(&format!(",{}", metric.name()));
+    }
+    out.push('\n');
+    for ((date, author), counts) in rows {
+        let counts: Vec<String> = counts.iter().map(|count| count.to_string()).collect();
+        out.push_str(&format!("{date},{},{}\n", csv_field(&author), counts.join(",")));
+    }
+    return out;
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::identity::Mailmap;
+    use crate::AuthorCount;
+
+    #[test]
+    fn joins_issues_with_counts() {
+        let issues = parse_issues("Email,Date,Issues,Name\n\
+            jane@old.example.com,2020-01-20,4,\n\
+            jane@example.com,2020-02-01,1,Jane\n\
+            bob@example.com,2020-02-10,2,\n\
+            bob@example.com,2020-03-01,7,\n").unwrap();
+        assert_eq!(issues[1], Issues { author: Author::new("Jane", "jane@example.com"), date: "2020-02-01".to_string(), count: 1 });
+
+        let dates = vec!["2020-02-01".to_string(), "2020-03-01".to_string()];
+        let issues = issues_by_date(&issues, &dates);
+        let mut authors = AuthorPerformance::new();
+        authors.insert("2020-02-01".to_string(), AuthorCount::from([(Author::new("Jane Doe", "jane@example.com"), 10)]));
+        authors.insert("2020-03-01".to_string(), AuthorCount::from([(Author::new("Jane Doe", "jane@example.com"), 12)]));
+        let lines = AuthorStats { authors, ..Default::default() };
+        let resolver = IdentityResolver { mailmap: Mailmap::parse("<jane@example.com> <jane@old.example.com>\n"), ..Default::default() };
+
+        assert_eq!(render_csv(&issues, &[(Metric::Lines, &lines)], &resolver), "\
+date,author,issues,lines
+2020-02-01,Jane Doe,4,10
+2020-03-01,Jane Doe,1,12
+2020-03-01,bob@example.com,2,0
+");
+    }
+
+    #[test]
+    fn rejects_bad_rows() {
+        assert_eq!(parse_issues("email,issues\n").unwrap_err(), "no date column");
+        assert_eq!(parse_issues("email,date,issues\na@example.com,2020-13-01,1\n").unwrap_err(), "line 2: expected a YYYY-MM-DD date, not 2020-13-01");
+        assert!(parse_issues("email,date,issues\na@example.com,2020-01-01,many\n").is_err());
+    }
+}
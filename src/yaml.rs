@@ -0,0 +1,130 @@
+use crate::json::{self, Value};
+use crate::report::json_string;
+
+//----
+// YAML output
+//
+// --format yaml is the JSON document in YAML's block style, converted from
+// the JSON itself so the two can't drift apart: same keys, same order, same
+// numbers.  Lists of plain values stay on one line, in flow style.  Strings
+// are left unquoted only when YAML can't read them as anything else; the
+// others keep their JSON quoting, which YAML reads the same.
+
+/// A value written on the line of its key or dash
+fn is_inline(value: &Value) -> bool {
+    match value {
+        Value::Array(items) => return items.iter().all(|item| !matches!(item, Value::Array(_) | Value::Object(_))),
+        Value::Object(fields) => return fields.is_empty(),
+        _ => return true,
+    }
+}
+
+/// Whether YAML reads `text` back as this string when left unquoted
+fn is_plain(text: &str) -> bool {
+    const RESERVED: [&str; 10] = ["true", "false", "yes", "no", "on", "off", "null", "y", "n", "~"];
+    let Some(first) = text.chars().next() else {
+        return false;
+    };
+    return (first.is_alphabetic() || first == '_' || first == '/')
+        && text.chars().all(|c| c.is_alphanumeric() || " _./()@+-".contains(c))
+        && !text.ends_with(' ')
+        && !RESERVED.contains(&text.to_lowercase().as_str());
+}
+
+fn scalar(value: &Value) -> String {
+    match value {
+        Value::Null => return "null".to_string(),
+        Value::Bool(b) => return b.to_string(),
+        Value::Number(n) => return n.clone(),
+        Value::String(s) if is_plain(s) => return s.clone(),
+        Value::String(s) => return json_string(s),
+        Value::Array(items) => return format!("[{}]", items.iter().map(scalar).collect::<Vec<_>>().join(", ")),
+        Value::Object(_) => return "{}".to_string(),
+    }
+}
+
+/// `value` as block lines indented by `indent` spaces
+fn emit(value: &Value, indent: usize, out: &mut String) {
+    let pad = " ".repeat(indent);
+    match value {
+        Value::Object(fields) if !fields.is_empty() => {
+            for (key, value) in fields {
+                let key = scalar(&Value::String(key.clone()));
+                match is_inline(value) {
+                    true => out.push_str(&format!("{pad}{key}: {}\n", scalar(value))),
+                    false => {
+                        out.push_str(&format!("{pad}{key}:\n"));
+                        emit(value, indent + 2, out);
+                    },
+                }
+            }
+        },
+        Value::Array(items) if !is_inline(value) => {
+            for item in items {
+                if is_inline(item) {
+                    out.push_str(&format!("{pad}- {}\n", scalar(item)));
+                    continue;
+                }
+                // The item's block, its first indent taken by the dash
+                let mut block = String::new();
+                emit(item, indent + 2, &mut block);
+                out.push_str(&format!("{pad}- {}", &block[indent + 2..]));
+            }
+        },
+        value => out.push_str(&format!("{pad}{}\n", scalar(value))),
+    }
+}
+
+/// The JSON document `json`, as render_json writes it, in YAML
+pub fn from_json(text: &str) -> String {
+    let value = json::parse(text).expect("the JSON output is well formed");
+    let mut out = String::new();
+    emit(&value, 0, &mut out);
+    return out;
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn converts_json_documents() {
+        let json = r#"{
+  "dates": ["2020-01-01", "2020-02-01"],
+  "authors": [
+    {"author": "Alice", "counts": [5, 4], "percents": [100.0, 80.0]},
+    {"author": "yes", "counts": []}
+  ],
+  "groups": [{"group": "src/", "authors": [{"author": "Bob \"B\"\n", "counts": [0, -1]}]}],
+  "fingerprint": [{"author": "Alice", "lines": 4, "groups": {}}],
+  "risk": null
+}"#;
+        assert_eq!(from_json(json), "\
+dates: [\"2020-01-01\", \"2020-02-01\"]
+authors:
+  - author: Alice
+    counts: [5, 4]
+    percents: [100.0, 80.0]
+  - author: \"yes\"
+    counts: []
+groups:
+  - group: src/
+    authors:
+      - author: \"Bob \\\"B\\\"\\n\"
+        counts: [0, -1]
+fingerprint:
+  - author: Alice
+    lines: 4
+    groups: {}
+risk: null
+");
+    }
+
+    #[test]
+    fn quotes_what_yaml_would_misread() {
+        assert!(is_plain("Proper Alice") && is_plain("src/main.rs") && is_plain("jane@example.com"));
+        for text in ["", "Dependabot[Bot]", "2020-01-01", "no", "Null", "-x", "a: b", "a #b", "*x", "C++ "] {
+            assert!(!is_plain(text), "{text}");
+        }
+    }
+}
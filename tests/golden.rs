@@ -0,0 +1,136 @@
+//! Golden-file tests of the output formats: the binary runs on a fixture repo
+//! with a fixed history and its output must match `tests/golden/<name>` byte
+//! for byte.  After an intended change to the output, rewrite the files with
+//!
+//!   UPDATE_GOLDEN=1 cargo test --test golden
+//!
+//! and review the diff.
+
+#![allow(clippy::needless_return)]
+
+use std::fs;
+use std::path::{Path, PathBuf};
+use std::process::Command;
+
+/// The fixture repo:
+///
+///   2020-01-15  Alice  src/main.rs (3 lines), README.md (2 lines)
+///   2020-02-15  Bob    src/main.rs +2 lines, vendor/lib.js (1 line)
+///   2020-03-15  Bob    README.md -1 line
+///   2020-04-15  Carol  src/lib.rs (4 lines), src/main.rs 1 line changed
+struct Fixture {
+    dir: PathBuf,
+}
+
+const ALICE: (&str, &str) = ("Alice", "alice@example.com");
+const BOB: (&str, &str) = ("Bob", "bob@example.com");
+const CAROL: (&str, &str) = ("Carol", "carol@example.com");
+
+impl Fixture {
+    fn new(name: &str) -> Fixture {
+        let dir = std::env::temp_dir().join(format!("git-author-stats-golden-{}-{name}", std::process::id()));
+        let _ = fs::remove_dir_all(&dir);
+        fs::create_dir_all(&dir).unwrap();
+        let fixture = Fixture { dir };
+        fixture.git(&["init", "-q", "-b", "main"], None);
+        fixture.commit(&[("src/main.rs", "a\nb\nc\n"), ("README.md", "x\ny\n")], ALICE, "2020-01-15", "Initial commit");
+        fixture.commit(&[("src/main.rs", "a\nb\nc\nd\ne\n"), ("vendor/lib.js", "v\n")], BOB, "2020-02-15", "Add vendored lib");
+        fixture.commit(&[("README.md", "x\n")], BOB, "2020-03-15", "Trim readme");
+        fixture.commit(&[("src/lib.rs", "1\n2\n3\n4\n"), ("src/main.rs", "a\nB\nc\nd\ne\n")], CAROL, "2020-04-15", "Fix main, add lib");
+        return fixture;
+    }
+
+    fn git(&self, args: &[&str], author: Option<((&str, &str), &str)>) {
+        let mut cmd = Command::new("git");
+        cmd.current_dir(&self.dir).args(args);
+        // Keep the user's and the system's git config out of it
+        cmd.env("GIT_CONFIG_NOSYSTEM", "1").env("GIT_CONFIG_GLOBAL", "/dev/null");
+        if let Some(((name, email), date)) = author {
+            let date = format!("{date}T12:00:00+0000");
+            cmd.env("GIT_AUTHOR_NAME", name).env("GIT_AUTHOR_EMAIL", email).env("GIT_AUTHOR_DATE", &date)
+                .env("GIT_COMMITTER_NAME", name).env("GIT_COMMITTER_EMAIL", email).env("GIT_COMMITTER_DATE", &date);
+        }
+        assert!(cmd.output().unwrap().status.success(), "git {args:?} failed");
+    }
+
+    fn commit(&self, files: &[(&str, &str)], author: (&str, &str), date: &str, message: &str) {
+        for (path, text) in files {
+            let path = self.dir.join(path);
+            fs::create_dir_all(path.parent().unwrap()).unwrap();
+            fs::write(path, text).unwrap();
+        }
+        self.git(&["add", "-A"], None);
+        self.git(&["commit", "-q", "-m", message], Some((author, date)));
+    }
+
+    /// Standard output of the binary run in the repo with `args`
+    fn run(&self, args: &[&str]) -> String {
+        let path = self.dir.to_str().unwrap();
+        return String::from_utf8(self.run_binary(args)).unwrap().replace(path, "<repo>");
+    }
+
+    /// Likewise for binary formats, left as they are
+    fn run_binary(&self, args: &[&str]) -> Vec<u8> {
+        let output = Command::new(env!("CARGO_BIN_EXE_git-author-stats"))
+            .current_dir(&self.dir)
+            .env("GIT_CONFIG_NOSYSTEM", "1")
+            .env("GIT_CONFIG_GLOBAL", "/dev/null")
+            .args(args)
+            .output()
+            .unwrap();
+        assert!(output.status.success(), "{args:?} failed: {}", String::from_utf8_lossy(&output.stderr));
+        return output.stdout;
+    }
+}
+
+impl Drop for Fixture {
+    fn drop(&mut self) {
+        let _ = fs::remove_dir_all(&self.dir);
+    }
+}
+
+/// Compare `actual` with the golden file, or rewrite it with UPDATE_GOLDEN
+fn check<T: AsRef<[u8]>>(name: &str, actual: T) {
+    let actual = actual.as_ref();
+    let path = Path::new(env!("CARGO_MANIFEST_DIR")).join("tests/golden").join(name);
+    if std::env::var_os("UPDATE_GOLDEN").is_some() {
+        fs::create_dir_all(path.parent().unwrap()).unwrap();
+        fs::write(&path, actual).unwrap();
+        return;
+    }
+    let expected = fs::read(&path).unwrap_or_else(|e| panic!("{}: {e}; run with UPDATE_GOLDEN=1 to write it", path.display()));
+    if actual != expected {
+        // Binary formats show as lossy text
+        let (expected, actual) = (String::from_utf8_lossy(&expected), String::from_utf8_lossy(actual));
+        let line = expected.lines().zip(actual.lines()).position(|(e, a)| e != a)
+            .unwrap_or(expected.lines().count().min(actual.lines().count()));
+        panic!("{name} differs from {} from line {}:\n--- expected\n{}\n--- actual\n{}\nrun with UPDATE_GOLDEN=1 to accept the new output",
+            path.display(), line + 1,
+            expected.lines().skip(line).take(5).collect::<Vec<_>>().join("\n"),
+            actual.lines().skip(line).take(5).collect::<Vec<_>>().join("\n"));
+    }
+}
+
+/// The first of each month from January through May 2020
+const DATES: [&str; 4] = ["--since", "2020-01-01", "--date", "2020-05-01"];
+
+#[test]
+fn output_formats() {
+    let fixture = Fixture::new("formats");
+    for (format, name) in [("table", "table.txt"), ("markdown", "markdown.md"), ("csv", "lines.csv"), ("json", "lines.json"), ("yaml", "lines.yaml"), ("html", "report.html")] {
+        let args = [&DATES[..], &["--format", format]].concat();
+        check(name, fixture.run(&args));
+    }
+    check("lines.msgpack", fixture.run_binary(&[&DATES[..], &["--format", "msgpack"]].concat()));
+    check("main_dev.csv", fixture.run(&[&DATES[..], &["--format", "code-maat"]].concat()));
+}
+
+#[test]
+fn output_options() {
+    let fixture = Fixture::new("options");
+    let run = |extra: &[&str]| fixture.run(&[&DATES[..], extra].concat());
+    check("metrics.csv", run(&["--metric", "lines,commits,churn", "--format", "csv"]));
+    check("group_by_dir.json", run(&["--group-by", "dir", "--format", "json"]));
+    check("with_percent.txt", run(&["--with-percent", "--exclude", "vendor/"]));
+    check("ranking.txt", fixture.run(&["at", "HEAD"]));
+}